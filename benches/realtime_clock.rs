@@ -29,15 +29,21 @@ macro_rules! with_realtime_clocks {
                 .expect("could not spawn upkeep thread");
             $group.bench_with_input(BenchmarkId::new($name, "QuantaUpkeepClock"), &clock, |$b, $clock| $closure);
         }
+        {
+            let clock = clock::CoarseClock::from_interval(Duration::from_micros(40))
+                .expect("could not spawn update thread");
+            $group.bench_with_input(BenchmarkId::new($name, "CoarseClock"), &clock, |$b, $clock| $closure);
+        }
     };
 }
 
+#[allow(deprecated)]
 fn bench_mostly_allow(c: &mut Criterion) {
     let mut group = c.benchmark_group("realtime_clock");
     group.throughput(Throughput::Elements(1));
     with_realtime_clocks! {("mostly_allow", group) |b, clock| {
         let rl = RateLimiter::direct_with_clock(
-            Quota::new(nonzero!(u32::max_value()), Duration::from_nanos(1)).unwrap(),
+            Quota::new(nonzero!(u32::MAX), Duration::from_nanos(1)).unwrap(),
             clock
         );
         b.iter(|| {
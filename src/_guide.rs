@@ -167,3 +167,27 @@
 //! # } #[cfg(not(feature = "std"))] fn main() {}
 //! ```
 //!
+//! # Asynchronous waits and timers
+//!
+//! The asynchronous methods on rate limiters (like
+//! [`until_ready`][crate::RateLimiter::until_ready]) and the sink and stream combinators
+//! wait for the rate limiter's permission using a timer. By default, that timer is provided by
+//! the [`futures-timer`](https://docs.rs/futures-timer) crate, which runs on a thread of its
+//! own and works with any executor.
+//!
+//! If your program runs on [`tokio`](https://docs.rs/tokio), you can enable governor's `tokio`
+//! feature: Waits that happen within a tokio runtime then use `tokio::time::sleep`, so that no
+//! separate timer thread is necessary.
+//!
+//! In tests, tokio's time can be paused. To have the rate limiter's notion of time advance
+//! along with tokio's, construct the rate limiter with a
+//! [`TokioClock`](https://docs.rs/governor/latest/governor/clock/struct.TokioClock.html).
+//!
+//! # WebAssembly
+//!
+//! To use governor in browsers, web workers or on Cloudflare Workers (that is, on the
+//! `wasm32-unknown-unknown` target), disable the default features and enable the `wasm`
+//! feature (along with `jitter` and `dashmap`, if you need them). The default clock then is the
+//! [`WasmClock`](https://docs.rs/governor/latest/governor/clock/struct.WasmClock.html), which
+//! reads `performance.now()`, and asynchronous waits use JavaScript timers (`setTimeout`)
+//! instead of a timer thread.
@@ -0,0 +1,280 @@
+//! Rate limiters that adapt their quota to feedback from the rate-limited service.
+
+use std::prelude::v1::*;
+
+use std::hash::Hash;
+use std::ops::Deref;
+use std::time::Duration;
+
+use crate::nanos::Nanos;
+use crate::state::keyed::DefaultKeyedStateStore;
+use crate::state::{InMemoryState, NotKeyed, StateStore};
+use crate::{clock, Quota, RateLimiter};
+
+const NANOS_PER_SEC: f64 = 1_000_000_000.0;
+
+/// An additive-increase/multiplicative-decrease (AIMD) policy for an [`AdaptiveRateLimiter`].
+///
+/// The policy describes a range of rates (from a minimum quota to a maximum quota) within which
+/// the rate limiter may let cells through. Every reported success increases the rate by a fixed
+/// number of cells per second, and every reported throttling event multiplies the rate by a
+/// factor smaller than 1.
+///
+/// The time it takes to replenish the maximum quota's burst capacity stays constant: As the rate
+/// shrinks, so does the number of cells that can be let through in one burst.
+///
+/// # Example
+/// ```rust
+/// # use governor::{Aimd, Quota};
+/// # use nonzero_ext::nonzero;
+/// // Allow at most 100 cells per second, but never throttle below 5 cells per second. Grow by
+/// // 2 cells per second on success, and halve the rate when throttled:
+/// let policy = Aimd::new(Quota::per_second(nonzero!(100u32)))
+///     .with_minimum(Quota::per_second(nonzero!(5u32)))
+///     .additive_increase(2.0)
+///     .multiplicative_decrease(0.5);
+/// assert_eq!(policy.maximum(), Quota::per_second(nonzero!(100u32)));
+/// ```
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Aimd {
+    max: Quota,
+    min: Quota,
+    increase: f64,
+    decrease: f64,
+}
+
+impl Aimd {
+    /// Constructs a policy that allows at most the given quota.
+    ///
+    /// By default, the rate of cells grows by one cell per second whenever a success is reported,
+    /// is halved whenever throttling is reported, and does not drop below a single cell per
+    /// replenishment period of the maximum quota's burst size.
+    pub fn new(max: Quota) -> Aimd {
+        let min = Quota {
+            max_burst: nonzero_ext::nonzero!(1u32),
+            replenish_1_per: max.burst_size_replenished_in(),
+            ..max
+        };
+        Aimd {
+            max,
+            min,
+            increase: 1.0,
+            decrease: 0.5,
+        }
+    }
+
+    /// Sets the minimum rate that the rate limiter will allow, no matter how much throttling
+    /// is reported.
+    ///
+    /// If the minimum quota allows more cells than the maximum quota, the maximum is used.
+    pub fn with_minimum(self, min: Quota) -> Aimd {
+        Aimd { min, ..self }
+    }
+
+    /// Sets the number of cells per second that the rate grows by when a success is reported.
+    ///
+    /// # Panics
+    /// Panics if `cells_per_second` is negative or not a finite number.
+    pub fn additive_increase(self, cells_per_second: f64) -> Aimd {
+        assert!(
+            cells_per_second.is_finite() && cells_per_second >= 0.0,
+            "additive increase must be a finite, non-negative number"
+        );
+        Aimd {
+            increase: cells_per_second,
+            ..self
+        }
+    }
+
+    /// Sets the factor that the rate is multiplied with when throttling is reported.
+    ///
+    /// # Panics
+    /// Panics if `factor` does not lie between 0 (exclusive) and 1 (inclusive).
+    pub fn multiplicative_decrease(self, factor: f64) -> Aimd {
+        assert!(
+            factor > 0.0 && factor <= 1.0,
+            "multiplicative decrease must lie in the interval (0, 1]"
+        );
+        Aimd {
+            decrease: factor,
+            ..self
+        }
+    }
+
+    /// The highest quota that the policy allows.
+    pub fn maximum(&self) -> Quota {
+        self.max
+    }
+
+    /// The lowest quota that the policy allows.
+    pub fn minimum(&self) -> Quota {
+        self.min
+    }
+
+    fn max_rate(&self) -> f64 {
+        rate_of(self.max.replenish_1_per)
+    }
+
+    fn min_rate(&self) -> f64 {
+        self.max_rate().min(rate_of(self.min.replenish_1_per))
+    }
+}
+
+/// Returns the rate (in cells per second) at which a quota replenishes cells.
+fn rate_of(replenish_1_per: Duration) -> f64 {
+    NANOS_PER_SEC / replenish_1_per.as_nanos() as f64
+}
+
+/// Returns the replenishment interval of a single cell at the given rate.
+fn interval_of(rate: f64) -> Nanos {
+    let ns = (NANOS_PER_SEC / rate) as u64;
+    Nanos::from(ns.max(1))
+}
+
+/// Applies a function to the rate that corresponds to a replenishment interval.
+fn adjust_rate(t: Nanos, f: impl Fn(f64) -> f64) -> Nanos {
+    interval_of(f(rate_of(t.into())))
+}
+
+/// A rate limiter whose quota adapts to feedback about the rate-limited service.
+///
+/// The adaptive rate limiter starts out allowing the [maximum][Aimd::maximum] quota of its
+/// [`Aimd`] policy. Callers report the outcome of their operations using
+/// [`report_success`](#method.report_success) and
+/// [`report_throttled`](#method.report_throttled) (e.g. when the service responds with
+/// HTTP 429 status codes), which adjusts the rate at which the underlying [`RateLimiter`]
+/// lets cells through.
+///
+/// `AdaptiveRateLimiter` dereferences to the underlying [`RateLimiter`], so all its checking
+/// methods (including the `async` ones, and the sink & stream combinators) are available.
+///
+/// # Example
+/// ```rust
+/// # use governor::{clock::FakeRelativeClock, AdaptiveRateLimiter, Aimd, Quota};
+/// # use nonzero_ext::nonzero;
+/// let clock = FakeRelativeClock::default();
+/// let policy = Aimd::new(Quota::per_second(nonzero!(10u32)));
+/// let lim = AdaptiveRateLimiter::direct_with_clock(policy, &clock);
+/// assert_eq!(lim.current_quota(), Quota::per_second(nonzero!(10u32)));
+///
+/// lim.report_throttled();
+/// assert_eq!(lim.current_quota(), Quota::per_second(nonzero!(5u32)));
+/// ```
+#[derive(Debug)]
+pub struct AdaptiveRateLimiter<K, S, C>
+where
+    S: StateStore<Key = K>,
+    C: clock::Clock,
+{
+    limiter: RateLimiter<K, S, C>,
+    policy: Aimd,
+}
+
+/// # Adaptive rate limiters - Constructors
+impl<K, S, C> AdaptiveRateLimiter<K, S, C>
+where
+    S: StateStore<Key = K>,
+    C: clock::Clock,
+{
+    /// Creates a new adaptive rate limiter from components.
+    ///
+    /// This is the most generic way to construct an adaptive rate limiter; most users should
+    /// prefer [`direct`](#method.direct) or other methods instead.
+    pub fn new(policy: Aimd, state: S, clock: &C) -> Self {
+        AdaptiveRateLimiter {
+            limiter: RateLimiter::new(policy.max, state, clock),
+            policy,
+        }
+    }
+
+    /// Consumes the adaptive rate limiter and returns the underlying rate limiter, with its
+    /// current quota.
+    pub fn into_inner(self) -> RateLimiter<K, S, C> {
+        self.limiter
+    }
+}
+
+#[cfg(feature = "std")]
+impl AdaptiveRateLimiter<NotKeyed, InMemoryState, clock::DefaultClock> {
+    /// Constructs a new in-memory direct adaptive rate limiter with the default real-time clock.
+    pub fn direct(policy: Aimd) -> Self {
+        let clock = clock::DefaultClock::default();
+        Self::direct_with_clock(policy, &clock)
+    }
+}
+
+impl<C> AdaptiveRateLimiter<NotKeyed, InMemoryState, C>
+where
+    C: clock::Clock,
+{
+    /// Constructs a new direct adaptive rate limiter with a custom clock.
+    pub fn direct_with_clock(policy: Aimd, clock: &C) -> Self {
+        Self::new(policy, InMemoryState::default(), clock)
+    }
+}
+
+impl<K> AdaptiveRateLimiter<K, DefaultKeyedStateStore<K>, clock::DefaultClock>
+where
+    K: Clone + Hash + Eq,
+{
+    /// Constructs a new keyed adaptive rate limiter backed by the
+    /// [`DefaultKeyedStateStore`].
+    ///
+    /// All keys share the same adaptive quota.
+    pub fn keyed(policy: Aimd) -> Self {
+        let clock = clock::DefaultClock::default();
+        Self::new(policy, DefaultKeyedStateStore::default(), &clock)
+    }
+}
+
+/// # Adaptive rate limiters - Feedback
+impl<K, S, C> AdaptiveRateLimiter<K, S, C>
+where
+    S: StateStore<Key = K>,
+    C: clock::Clock,
+{
+    /// Reports that an operation was successful, additively increasing the rate (up to the
+    /// policy's maximum).
+    pub fn report_success(&self) {
+        let increase = self.policy.increase;
+        let max = self.policy.max_rate();
+        self.limiter
+            .gcra()
+            .update_t(|t| adjust_rate(t, |rate| (rate + increase).min(max)));
+    }
+
+    /// Reports that an operation was throttled by the rate-limited service, multiplicatively
+    /// decreasing the rate (down to the policy's minimum).
+    pub fn report_throttled(&self) {
+        let decrease = self.policy.decrease;
+        let min = self.policy.min_rate();
+        self.limiter
+            .gcra()
+            .update_t(|t| adjust_rate(t, |rate| (rate * decrease).max(min)));
+    }
+
+    /// Returns the quota that the rate limiter currently enforces.
+    ///
+    /// Since the burst capacity is not adjusted in whole cells, the returned quota's burst size
+    /// is rounded down (but is never less than 1).
+    pub fn current_quota(&self) -> Quota {
+        self.limiter.gcra().quota()
+    }
+
+    /// Returns the policy that the rate limiter adapts its quota by.
+    pub fn policy(&self) -> &Aimd {
+        &self.policy
+    }
+}
+
+impl<K, S, C> Deref for AdaptiveRateLimiter<K, S, C>
+where
+    S: StateStore<Key = K>,
+    C: clock::Clock,
+{
+    type Target = RateLimiter<K, S, C>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.limiter
+    }
+}
@@ -0,0 +1,372 @@
+//! Rate limiting for web services built with [`axum`].
+//!
+//! The [`RateLimitLayer`] is a middleware that makes a keyed rate limiting decision for each
+//! request it sees. The key of a request is computed by a [`KeyExtractor`]; this module comes
+//! with extractors that key requests by the client's IP address ([`PeerIp`], which can also
+//! look through trusted reverse proxies), by the value of a header ([`HeaderKey`]), and by the
+//! user that an authentication middleware attached to the request ([`AuthenticatedUser`]).
+//!
+//! Requests that exceed the rate limit are answered with `429 Too Many Requests`, without
+//! reaching the wrapped service; the response carries the rate limiting headers (see
+//! [`headers`][crate::headers]), including a `Retry-After` header that tells the client in how
+//! many seconds it may try again.
+//!
+//! # Example
+//! ```rust
+//! # use axum::{routing::get, Router};
+//! # use governor::{axum::{PeerIp, RateLimitLayer}, Quota, RateLimiter};
+//! # use nonzero_ext::nonzero;
+//! # use std::net::SocketAddr;
+//! let lim = RateLimiter::keyed(Quota::per_minute(nonzero!(60u32)));
+//! let app = Router::new()
+//!     .route("/", get(|| async { "Hello!" }))
+//!     .layer(RateLimitLayer::new(lim, PeerIp::new()));
+//! // `PeerIp` needs to know the address of each connection's peer:
+//! let service = app.into_make_service_with_connect_info::<SocketAddr>();
+//! ```
+
+use std::prelude::v1::*;
+
+use crate::headers::RateLimitHeaders;
+use crate::state::keyed::KeyedStateStore;
+use crate::{clock, NotUntil, RateLimiterHandle};
+use axum::extract::ConnectInfo;
+use axum::http::{HeaderName, HeaderValue, Request, StatusCode};
+use axum::response::{IntoResponse, Response};
+use futures::future::{ready, Either, Ready};
+use std::fmt;
+use std::hash::Hash;
+use std::marker::PhantomData;
+use std::mem;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tower_layer::Layer;
+use tower_service::Service;
+
+/// Computes the key under which a request is rate limited.
+pub trait KeyExtractor {
+    /// The type of the keys.
+    type Key: Hash + Eq + Clone;
+
+    /// Returns the key of `request`.
+    fn extract<B>(&self, request: &Request<B>) -> Self::Key;
+}
+
+/// A [`KeyExtractor`] that keys requests by the IP address of the client.
+///
+/// By default, the client's address is the address of the connection's peer, which axum
+/// records when the app is served with
+/// [`into_make_service_with_connect_info::<SocketAddr>`](axum::Router::into_make_service_with_connect_info).
+/// All requests without that information are limited under one shared key, the unspecified
+/// address `0.0.0.0`.
+///
+/// Behind a reverse proxy, the connection's peer is always the proxy; use
+/// [`behind_proxies`](PeerIp::behind_proxies) to key requests by the address that the proxies
+/// report in the `X-Forwarded-For` header instead.
+#[derive(Clone, Default)]
+pub struct PeerIp {
+    trusted: Option<TrustedProxies>,
+}
+
+/// Decides whether an address belongs to a trusted proxy.
+type TrustedProxies = Arc<dyn Fn(&IpAddr) -> bool + Send + Sync>;
+
+impl PeerIp {
+    /// Constructs an extractor that keys requests by the address of the connection's peer,
+    /// ignoring any `X-Forwarded-For` headers.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Constructs an extractor that trusts the proxies whose addresses match `trusted` to
+    /// report their client's address in the `X-Forwarded-For` header.
+    ///
+    /// Starting with the connection's peer, the extractor walks the `X-Forwarded-For` entries
+    /// from the last (the one added by the peer) to the first, for as long as the address it
+    /// arrived at is a trusted proxy. Clients can put any addresses into the header, but
+    /// entries that weren't added by a trusted proxy are never used, so clients can't pick
+    /// their key. Should an entry not be a valid IP address, the address of the proxy that
+    /// added it is used.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use axum::{extract::ConnectInfo, http::Request};
+    /// # use governor::axum::{KeyExtractor, PeerIp};
+    /// # use std::net::{IpAddr, SocketAddr};
+    /// let extractor = PeerIp::behind_proxies(|ip| ip.is_loopback());
+    /// let mut request = Request::builder()
+    ///     .header("X-Forwarded-For", "203.0.113.7, 127.0.0.2")
+    ///     .body(())
+    ///     .unwrap();
+    /// request
+    ///     .extensions_mut()
+    ///     .insert(ConnectInfo("127.0.0.1:4711".parse::<SocketAddr>().unwrap()));
+    /// assert_eq!(extractor.extract(&request), "203.0.113.7".parse::<IpAddr>().unwrap());
+    /// ```
+    pub fn behind_proxies<F>(trusted: F) -> Self
+    where
+        F: Fn(&IpAddr) -> bool + Send + Sync + 'static,
+    {
+        PeerIp {
+            trusted: Some(Arc::new(trusted)),
+        }
+    }
+}
+
+impl fmt::Debug for PeerIp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PeerIp")
+            .field("behind_proxies", &self.trusted.is_some())
+            .finish()
+    }
+}
+
+impl KeyExtractor for PeerIp {
+    type Key = IpAddr;
+
+    fn extract<B>(&self, request: &Request<B>) -> IpAddr {
+        let mut ip = match request.extensions().get::<ConnectInfo<SocketAddr>>() {
+            Some(ConnectInfo(peer)) => peer.ip(),
+            None => return IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        };
+        if let Some(trusted) = &self.trusted {
+            let forwarded: Vec<&str> = request
+                .headers()
+                .get_all("x-forwarded-for")
+                .iter()
+                .filter_map(|value| value.to_str().ok())
+                .flat_map(|value| value.split(','))
+                .collect();
+            for entry in forwarded.into_iter().rev() {
+                if !trusted(&ip) {
+                    break;
+                }
+                match entry.trim().parse() {
+                    Ok(forwarded) => ip = forwarded,
+                    Err(_) => break,
+                }
+            }
+        }
+        ip
+    }
+}
+
+/// A [`KeyExtractor`] that keys requests by the value of a header (e.g., an API token).
+///
+/// All requests that lack the header (or whose header value isn't valid ASCII) are limited
+/// under one shared key, the empty string.
+#[derive(Debug, Clone, Copy)]
+pub struct HeaderKey {
+    name: &'static str,
+}
+
+impl HeaderKey {
+    /// Constructs an extractor that keys requests by the value of the header `name`.
+    pub fn new(name: &'static str) -> Self {
+        HeaderKey { name }
+    }
+}
+
+impl KeyExtractor for HeaderKey {
+    type Key = String;
+
+    fn extract<B>(&self, request: &Request<B>) -> String {
+        request
+            .headers()
+            .get(self.name)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+            .to_string()
+    }
+}
+
+/// A [`KeyExtractor`] that keys requests by the authenticated user, as recorded by an
+/// authentication middleware in the request's [extensions](axum::http::Request::extensions).
+///
+/// The key is the extension of type `T` (e.g., a user ID), or `None` for requests that don't
+/// have one, so that all unauthenticated requests share one key. The authentication middleware
+/// must run before the rate limiter, i.e., its layer must be added after the
+/// [`RateLimitLayer`].
+pub struct AuthenticatedUser<T> {
+    user: PhantomData<fn() -> T>,
+}
+
+impl<T> AuthenticatedUser<T> {
+    /// Constructs an extractor that keys requests by their extension of type `T`.
+    pub fn new() -> Self {
+        AuthenticatedUser { user: PhantomData }
+    }
+}
+
+impl<T> Default for AuthenticatedUser<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Clone for AuthenticatedUser<T> {
+    fn clone(&self) -> Self {
+        Self::new()
+    }
+}
+
+impl<T> fmt::Debug for AuthenticatedUser<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AuthenticatedUser").finish()
+    }
+}
+
+impl<T: Hash + Eq + Clone + Send + Sync + 'static> KeyExtractor for AuthenticatedUser<T> {
+    type Key = Option<T>;
+
+    fn extract<B>(&self, request: &Request<B>) -> Option<T> {
+        request.extensions().get::<T>().cloned()
+    }
+}
+
+/// A tower layer that rate limits requests to axum services, keyed by a [`KeyExtractor`].
+///
+/// Use it with [`Router::layer`](axum::Router::layer) (or
+/// [`route_layer`](axum::Router::route_layer), to only limit requests that match a route).
+pub struct RateLimitLayer<X, S, C>
+where
+    X: KeyExtractor,
+    S: KeyedStateStore<X::Key>,
+    C: clock::Clock,
+{
+    limiter: RateLimiterHandle<X::Key, S, C>,
+    extractor: X,
+}
+
+impl<X, S, C> RateLimitLayer<X, S, C>
+where
+    X: KeyExtractor,
+    S: KeyedStateStore<X::Key>,
+    C: clock::Clock,
+{
+    /// Constructs a layer that rate limits requests using `limiter`, keyed by `extractor`.
+    pub fn new(limiter: impl Into<RateLimiterHandle<X::Key, S, C>>, extractor: X) -> Self {
+        RateLimitLayer {
+            limiter: limiter.into(),
+            extractor,
+        }
+    }
+
+    /// Returns the rate limiter that the layer uses.
+    pub fn limiter(&self) -> &RateLimiterHandle<X::Key, S, C> {
+        &self.limiter
+    }
+}
+
+impl<X, S, C> Clone for RateLimitLayer<X, S, C>
+where
+    X: KeyExtractor + Clone,
+    S: KeyedStateStore<X::Key>,
+    C: clock::Clock,
+{
+    fn clone(&self) -> Self {
+        RateLimitLayer {
+            limiter: self.limiter.clone(),
+            extractor: self.extractor.clone(),
+        }
+    }
+}
+
+impl<Svc, X, S, C> Layer<Svc> for RateLimitLayer<X, S, C>
+where
+    X: KeyExtractor + Clone,
+    S: KeyedStateStore<X::Key>,
+    C: clock::Clock,
+{
+    type Service = RateLimit<Svc, X, S, C>;
+
+    fn layer(&self, inner: Svc) -> Self::Service {
+        RateLimit {
+            inner,
+            limiter: self.limiter.clone(),
+            extractor: self.extractor.clone(),
+        }
+    }
+}
+
+/// The service that a [`RateLimitLayer`] wraps around an axum service.
+pub struct RateLimit<Svc, X, S, C>
+where
+    X: KeyExtractor,
+    S: KeyedStateStore<X::Key>,
+    C: clock::Clock,
+{
+    inner: Svc,
+    limiter: RateLimiterHandle<X::Key, S, C>,
+    extractor: X,
+}
+
+impl<Svc, X, S, C> Clone for RateLimit<Svc, X, S, C>
+where
+    Svc: Clone,
+    X: KeyExtractor + Clone,
+    S: KeyedStateStore<X::Key>,
+    C: clock::Clock,
+{
+    fn clone(&self) -> Self {
+        RateLimit {
+            inner: self.inner.clone(),
+            limiter: self.limiter.clone(),
+            extractor: self.extractor.clone(),
+        }
+    }
+}
+
+/// The service only forwards the requests that the rate limiter lets through. A readied inner
+/// service stays in place until a request is forwarded to it, so that requests which are
+/// rejected don't use up the capacity that it reserved in `poll_ready`.
+impl<Svc, B, X, S, C> Service<Request<B>> for RateLimit<Svc, X, S, C>
+where
+    Svc: Service<Request<B>, Response = Response> + Clone,
+    X: KeyExtractor,
+    S: KeyedStateStore<X::Key>,
+    C: clock::Clock,
+{
+    type Response = Response;
+    type Error = Svc::Error;
+    type Future = Either<Ready<Result<Response, Svc::Error>>, Svc::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        let key = self.extractor.extract(&request);
+        match self.limiter.check_key(&key) {
+            Ok(()) => {
+                // Forward to the inner service that was readied, and keep a fresh clone for the
+                // next request:
+                let clone = self.inner.clone();
+                let mut inner = mem::replace(&mut self.inner, clone);
+                Either::Right(inner.call(request))
+            }
+            Err(negative) => Either::Left(ready(Ok(too_many_requests(&negative)))),
+        }
+    }
+}
+
+/// Returns a `429 Too Many Requests` response for a negative rate limiting decision, carrying
+/// the rate limiting headers.
+pub fn too_many_requests<P: clock::Reference>(not_until: &NotUntil<P>) -> Response {
+    let headers = RateLimitHeaders::from_not_until(not_until);
+    let retry_after = headers.retry_after_value().unwrap_or_default();
+    let mut response = (
+        StatusCode::TOO_MANY_REQUESTS,
+        format!("rate limit exceeded, retry after {} seconds", retry_after),
+    )
+        .into_response();
+    for (name, value) in headers.header_values() {
+        let name = HeaderName::from_bytes(name.as_bytes());
+        let value = HeaderValue::from_str(&value);
+        if let (Ok(name), Ok(value)) = (name, value) {
+            response.headers_mut().insert(name, value);
+        }
+    }
+    response
+}
@@ -0,0 +1,147 @@
+use std::prelude::v1::*;
+
+use crate::state::{DirectStateStore, NotKeyed};
+use crate::{clock, NotUntil, RateLimiterHandle};
+use std::cell::Cell;
+use std::cmp;
+use std::fmt;
+use std::num::NonZeroU32;
+
+/// A view of a direct rate limiter that lets cells through in batches, trading accuracy for
+/// fewer updates to the shared rate limiting state.
+///
+/// Checking a direct rate limiter updates its state with an atomic compare-and-swap, which
+/// becomes a bottleneck when many threads check the same rate limiter at a very high rate. A
+/// batched limiter instead buys up to `batch_size` cells from the rate limiter at once, and lets
+/// its own caller through from that local stock until it runs out, so that it updates the shared
+/// state only once per `batch_size` checks. When the rate limiter can't let a whole batch
+/// through, the batched limiter asks for a single cell instead.
+///
+/// Batched limiters are meant to be used by one thread each: They're cheap to clone, and each
+/// clone keeps its own stock of cells (a fresh clone has none), while sharing the rate limiter.
+///
+/// # Accuracy
+/// Over time, batched limiters let exactly as many cells through as the rate limiter does, but
+/// cells may be let through later than the rate limiter made room for them. Every batched
+/// limiter can hold up to `batch_size - 1` cells in stock, so in any given period, the batched
+/// limiters of a rate limiter may let up to `batch_size` × (the number of batched limiters) more
+/// cells through than the quota allows. Cells in stock when a batched limiter is dropped are
+/// lost. This makes batched limiters a good fit for cases like sampling log messages, where
+/// neither is a problem, and a poor fit for enforcing a third party's rate limit.
+///
+/// Decision callbacks (like [`on_allowed`](struct.RateLimiter.html#method.on_allowed)) are
+/// called for the decisions on each batch, not for each cell. When the rate limiter can't let a
+/// whole batch through, only the decision on the single cell is reported.
+///
+/// # Example
+/// ```rust
+/// # use governor::{clock::FakeRelativeClock, BatchedRateLimiter, Quota, RateLimiter};
+/// # use nonzero_ext::nonzero;
+/// let clock = FakeRelativeClock::default();
+/// let lim = RateLimiter::direct_with_clock(Quota::per_second(nonzero!(100u32)), &clock);
+/// let batched = BatchedRateLimiter::new(lim, nonzero!(10u32));
+/// for _ in 0..100 {
+///     assert_eq!(Ok(()), batched.check());
+/// }
+/// assert!(batched.check().is_err());
+/// ```
+pub struct BatchedRateLimiter<S, C>
+where
+    S: DirectStateStore,
+    C: clock::Clock,
+{
+    limiter: RateLimiterHandle<NotKeyed, S, C>,
+    batch_size: NonZeroU32,
+    stock: Cell<u32>,
+}
+
+impl<S, C> BatchedRateLimiter<S, C>
+where
+    S: DirectStateStore,
+    C: clock::Clock,
+{
+    /// Constructs a batched limiter that buys up to `batch_size` cells at once from `limiter`
+    /// (a rate limiter or a handle to a shared one).
+    ///
+    /// Batches that exceed the rate limiter's burst size are reduced to the burst size.
+    pub fn new(
+        limiter: impl Into<RateLimiterHandle<NotKeyed, S, C>>,
+        batch_size: NonZeroU32,
+    ) -> Self {
+        BatchedRateLimiter {
+            limiter: limiter.into(),
+            batch_size,
+            stock: Cell::new(0),
+        }
+    }
+
+    /// Returns the rate limiter that the batched limiter buys its cells from.
+    pub fn rate_limiter(&self) -> &RateLimiterHandle<NotKeyed, S, C> {
+        &self.limiter
+    }
+
+    /// Returns the number of cells that the batched limiter buys at once.
+    pub fn batch_size(&self) -> NonZeroU32 {
+        self.batch_size
+    }
+
+    /// Returns the number of cells that the batched limiter holds in stock, and can let through
+    /// without consulting the rate limiter.
+    pub fn stock(&self) -> u32 {
+        self.stock.get()
+    }
+
+    /// Allow a single cell through, from the local stock if possible, or else by buying a new
+    /// batch of cells from the rate limiter.
+    ///
+    /// If the rate limiter lets neither a batch nor a single cell through, `check` returns the
+    /// rate limiter's information about the earliest time that a cell might be allowed through
+    /// again.
+    pub fn check(&self) -> Result<(), NotUntil<C::Instant>> {
+        let stock = self.stock.get();
+        if stock > 0 {
+            self.stock.set(stock - 1);
+            return Ok(());
+        }
+        let burst_size = self.limiter.gcra().quota().burst_size();
+        let batch_size = cmp::min(self.batch_size, burst_size);
+        // Only the decision that lets the cell through is reported (to the state store, and to
+        // the callbacks), so a batch that doesn't fit doesn't count as a denial:
+        let now = self.limiter.clock().now();
+        if batch_size.get() > 1
+            && self
+                .limiter
+                .probe_key_n_at(&NotKeyed::NonKey, batch_size, now)
+                .is_ok()
+        {
+            self.stock.set(batch_size.get() - 1);
+            return Ok(());
+        }
+        self.limiter.check()
+    }
+}
+
+/// Clones start out without any cells in stock.
+impl<S, C> Clone for BatchedRateLimiter<S, C>
+where
+    S: DirectStateStore,
+    C: clock::Clock,
+{
+    fn clone(&self) -> Self {
+        BatchedRateLimiter::new(self.limiter.clone(), self.batch_size)
+    }
+}
+
+impl<S, C> fmt::Debug for BatchedRateLimiter<S, C>
+where
+    S: DirectStateStore + fmt::Debug,
+    C: clock::Clock + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        f.debug_struct("BatchedRateLimiter")
+            .field("limiter", &self.limiter)
+            .field("batch_size", &self.batch_size)
+            .field("stock", &self.stock.get())
+            .finish()
+    }
+}
@@ -69,9 +69,18 @@ impl Add<Nanos> for Duration {
 /// # Thread safety
 /// The mock time is represented as an atomic u64 count of nanoseconds, behind an [`Arc`].
 /// Clones of this clock will all show the same time, even if the original advances.
+///
+/// # Asynchronous waits
+/// In `std` mode, this clock can be used with the asynchronous methods of rate limiters (like
+/// [`until_ready`][crate::RateLimiter::until_ready]): Instead of waiting in real time, these
+/// wait until the clock gets advanced far enough. To advance the clock whenever all waits are
+/// pending, use [`block_on_auto_advance`](#method.block_on_auto_advance).
 #[derive(Debug, Clone, Default)]
 pub struct FakeRelativeClock {
     now: Arc<AtomicU64>,
+
+    #[cfg(feature = "std")]
+    timers: Arc<crate::timer::FakeTimers>,
 }
 
 impl FakeRelativeClock {
@@ -91,6 +100,14 @@ impl FakeRelativeClock {
             prev = next_prev;
             next = prev + by;
         }
+
+        #[cfg(feature = "std")]
+        self.timers.wake_due(next.into());
+    }
+
+    #[cfg(feature = "std")]
+    pub(crate) fn timers(&self) -> &crate::timer::FakeTimers {
+        &self.timers
     }
 }
 
@@ -113,11 +130,37 @@ mod with_std;
 #[cfg(feature = "std")]
 pub use with_std::*;
 
+#[cfg(feature = "std")]
+pub use crate::timer::Delay;
+
+#[cfg(feature = "std")]
+mod coarse;
+#[cfg(feature = "std")]
+pub use self::coarse::*;
+
 #[cfg(all(feature = "std", feature = "quanta"))]
 mod quanta;
 #[cfg(all(feature = "std", feature = "quanta"))]
 pub use self::quanta::*;
 
+#[cfg(any(feature = "chrono", feature = "time"))]
+mod civil;
+#[cfg(any(feature = "chrono", feature = "time"))]
+pub use self::civil::*;
+
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+mod wasm;
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+pub use self::wasm::*;
+
+#[cfg(feature = "tokio")]
+mod tokio;
+#[cfg(feature = "tokio")]
+pub use self::tokio::*;
+
+mod tick;
+pub use tick::*;
+
 mod default;
 
 pub use default::*;
@@ -0,0 +1,266 @@
+// Clocks that read the civil (wall-clock) time through the `chrono` and `time` crates.
+//
+// The system's clock can jump backwards, but rate limiters expect their clock to never run
+// backwards, so these clocks hold still at their latest reading until the system's clock has
+// caught up again.
+
+use std::prelude::v1::*;
+
+use crate::clock::{Clock, ReasonablyRealtime, Reference};
+use crate::nanos::Nanos;
+use std::fmt;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
+
+/// The latest reading of a civil clock, in nanoseconds since the UNIX epoch, shared by all its
+/// clones.
+#[derive(Debug, Clone)]
+struct Latest(Arc<AtomicI64>);
+
+impl Default for Latest {
+    fn default() -> Self {
+        Latest(Arc::new(AtomicI64::new(i64::MIN)))
+    }
+}
+
+impl Latest {
+    /// Records a reading, returning the latest reading if it lies after it.
+    fn observe(&self, reading: i64) -> Option<i64> {
+        let latest = self.0.fetch_max(reading, Ordering::AcqRel);
+        if latest > reading {
+            Some(latest)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(feature = "chrono")]
+mod with_chrono {
+    use super::*;
+    use chrono::{DateTime, TimeDelta, Utc};
+    use std::ops::Add;
+
+    impl Reference for DateTime<Utc> {
+        /// Returns the time between the two readings, or the zero duration if `earlier` is
+        /// later.
+        fn duration_since(&self, earlier: Self) -> Nanos {
+            (*self - earlier)
+                .to_std()
+                .map(Nanos::from)
+                .unwrap_or_else(|_| Nanos::new(0))
+        }
+
+        fn saturating_sub(&self, duration: Nanos) -> Self {
+            TimeDelta::from_std(duration.into())
+                .ok()
+                .and_then(|duration| self.checked_sub_signed(duration))
+                .unwrap_or(*self)
+        }
+    }
+
+    impl Add<Nanos> for DateTime<Utc> {
+        type Output = DateTime<Utc>;
+
+        /// Adds the duration to the reading, saturating at the latest representable date.
+        fn add(self, other: Nanos) -> DateTime<Utc> {
+            TimeDelta::from_std(other.into())
+                .ok()
+                .and_then(|other| self.checked_add_signed(other))
+                .unwrap_or(DateTime::<Utc>::MAX_UTC)
+        }
+    }
+
+    /// A clock that reads the civil time in UTC as a [`chrono::DateTime<Utc>`].
+    ///
+    /// Unlike the [`MonotonicClock`][crate::clock::MonotonicClock], this clock follows
+    /// adjustments of the system's clock, so rate limiters can be aligned with calendar days
+    /// (by starting them at midnight with [`RateLimiter::new_const`][crate::RateLimiter::new_const]).
+    ///
+    /// When the system's clock jumps backwards (e.g., when it gets corrected), this clock
+    /// returns its latest reading again until the system's clock has caught up, so that the
+    /// rate limiter's time never runs backwards. Clones of the clock share their latest
+    /// reading.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use chrono::Utc;
+    /// # use governor::{clock::ChronoClock, state::InMemoryState, Quota, RateLimiter};
+    /// # use nonzero_ext::nonzero;
+    /// # use std::time::Duration;
+    /// // 1000 cells per day on average, with the day starting at midnight (UTC):
+    /// let clock = ChronoClock::default();
+    /// let midnight = Utc::now().date_naive().and_hms_opt(0, 0, 0).unwrap().and_utc();
+    /// let quota = Quota::with_period(Duration::from_millis(86_400))
+    ///     .unwrap()
+    ///     .allow_burst(nonzero!(1000u32));
+    /// let lim = RateLimiter::new_const(quota, InMemoryState::default(), clock, midnight);
+    /// ```
+    #[derive(Clone)]
+    pub struct ChronoClock {
+        source: Arc<dyn Fn() -> DateTime<Utc> + Send + Sync>,
+        latest: Latest,
+    }
+
+    impl ChronoClock {
+        /// Constructs a clock that reads the civil time from `source`, e.g. to simulate jumps
+        /// of the system's clock.
+        pub fn from_fn<F>(source: F) -> Self
+        where
+            F: Fn() -> DateTime<Utc> + Send + Sync + 'static,
+        {
+            ChronoClock {
+                source: Arc::new(source),
+                latest: Latest::default(),
+            }
+        }
+    }
+
+    /// The default `ChronoClock` reads the system's clock, with [`Utc::now`].
+    impl Default for ChronoClock {
+        fn default() -> Self {
+            ChronoClock::from_fn(Utc::now)
+        }
+    }
+
+    impl fmt::Debug for ChronoClock {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_struct("ChronoClock")
+                .field("latest", &self.latest)
+                .finish()
+        }
+    }
+
+    impl Clock for ChronoClock {
+        type Instant = DateTime<Utc>;
+
+        fn now(&self) -> Self::Instant {
+            let reading = (self.source)();
+            // Readings outside of the range of i64 nanoseconds (before 1677 or after 2262) are
+            // passed through:
+            reading
+                .timestamp_nanos_opt()
+                .and_then(|nanos| self.latest.observe(nanos))
+                .map(DateTime::from_timestamp_nanos)
+                .unwrap_or(reading)
+        }
+    }
+
+    impl ReasonablyRealtime for ChronoClock {}
+}
+
+#[cfg(feature = "chrono")]
+pub use with_chrono::ChronoClock;
+
+#[cfg(feature = "time")]
+mod with_time {
+    use super::*;
+    use std::convert::TryFrom;
+    use std::ops::Add;
+    use time::{OffsetDateTime, PrimitiveDateTime};
+
+    impl Reference for OffsetDateTime {
+        /// Returns the time between the two readings, or the zero duration if `earlier` is
+        /// later.
+        fn duration_since(&self, earlier: Self) -> Nanos {
+            std::time::Duration::try_from(*self - earlier)
+                .map(Nanos::from)
+                .unwrap_or_else(|_| Nanos::new(0))
+        }
+
+        fn saturating_sub(&self, duration: Nanos) -> Self {
+            time::Duration::try_from(std::time::Duration::from(duration))
+                .ok()
+                .and_then(|duration| self.checked_sub(duration))
+                .unwrap_or(*self)
+        }
+    }
+
+    impl Add<Nanos> for OffsetDateTime {
+        type Output = OffsetDateTime;
+
+        /// Adds the duration to the reading, saturating at the latest representable date.
+        fn add(self, other: Nanos) -> OffsetDateTime {
+            time::Duration::try_from(std::time::Duration::from(other))
+                .ok()
+                .and_then(|other| self.checked_add(other))
+                .unwrap_or_else(|| PrimitiveDateTime::MAX.assume_offset(self.offset()))
+        }
+    }
+
+    /// A clock that reads the civil time in UTC as a [`time::OffsetDateTime`].
+    ///
+    /// This clock follows adjustments of the system's clock, like the `ChronoClock` does for
+    /// the `chrono` crate. Should the system's clock fall behind a reading that the clock (or
+    /// one of its clones) already returned, the clock holds still at that reading until the
+    /// system's clock has caught up.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use governor::{clock::TimeClock, state::InMemoryState, Quota, RateLimiter};
+    /// # use nonzero_ext::nonzero;
+    /// # use std::time::Duration;
+    /// # use time::{OffsetDateTime, Time};
+    /// // 1000 cells per day on average, with the day starting at midnight (UTC):
+    /// let midnight = OffsetDateTime::now_utc().replace_time(Time::MIDNIGHT);
+    /// let quota = Quota::with_period(Duration::from_millis(86_400))
+    ///     .unwrap()
+    ///     .allow_burst(nonzero!(1000u32));
+    /// let lim = RateLimiter::new_const(quota, InMemoryState::default(), TimeClock::default(), midnight);
+    /// ```
+    #[derive(Clone)]
+    pub struct TimeClock {
+        source: Arc<dyn Fn() -> OffsetDateTime + Send + Sync>,
+        latest: Latest,
+    }
+
+    impl TimeClock {
+        /// Constructs a clock that reads the civil time from `source`, e.g. to simulate jumps
+        /// of the system's clock.
+        pub fn from_fn<F>(source: F) -> Self
+        where
+            F: Fn() -> OffsetDateTime + Send + Sync + 'static,
+        {
+            TimeClock {
+                source: Arc::new(source),
+                latest: Latest::default(),
+            }
+        }
+    }
+
+    /// The default `TimeClock` reads the system's clock, with [`OffsetDateTime::now_utc`].
+    impl Default for TimeClock {
+        fn default() -> Self {
+            TimeClock::from_fn(OffsetDateTime::now_utc)
+        }
+    }
+
+    impl fmt::Debug for TimeClock {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_struct("TimeClock")
+                .field("latest", &self.latest)
+                .finish()
+        }
+    }
+
+    impl Clock for TimeClock {
+        type Instant = OffsetDateTime;
+
+        fn now(&self) -> Self::Instant {
+            let reading = (self.source)();
+            // Readings outside of the range of i64 nanoseconds (before 1677 or after 2262) are
+            // passed through:
+            i64::try_from(reading.unix_timestamp_nanos())
+                .ok()
+                .and_then(|nanos| self.latest.observe(nanos))
+                .and_then(|nanos| OffsetDateTime::from_unix_timestamp_nanos(i128::from(nanos)).ok())
+                .map(|latest| latest.to_offset(reading.offset()))
+                .unwrap_or(reading)
+        }
+    }
+
+    impl ReasonablyRealtime for TimeClock {}
+}
+
+#[cfg(feature = "time")]
+pub use with_time::TimeClock;
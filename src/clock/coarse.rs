@@ -0,0 +1,114 @@
+use std::prelude::v1::*;
+
+use crate::clock::{Clock, Delay, ReasonablyRealtime};
+use crate::nanos::Nanos;
+use std::convert::TryInto;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// A low-overhead clock, which reads a timestamp that a background thread updates with a fixed
+/// resolution.
+///
+/// Reading the time from this clock is a single atomic load, which makes it cheaper than
+/// [`Instant::now`] (and even [`QuantaClock`][crate::clock::QuantaClock]s) on hot paths. It
+/// reports [`Instant`]s, so it can be used in place of the
+/// [`MonotonicClock`][crate::clock::MonotonicClock].
+///
+/// The background thread is stopped as soon as the last clone of the clock is dropped.
+///
+/// # Accuracy
+///
+/// The time reported by this clock lags behind the real time by up to its resolution. For the
+/// GCRA, this means that rate limiting decisions are made as if they happened up to one
+/// resolution earlier than they did: The clock never lets more cells through than the quota
+/// allows, but it may reject cells that would have conformed, and the wait times it reports may
+/// be up to one resolution too long. This is only noticeable if the resolution isn't
+/// significantly smaller than the quota's replenishment interval; a resolution of 1ms, for
+/// example, works well for quotas of up to a few hundred cells per second.
+///
+/// To make sure that asynchronous waits (like [`until_ready`][crate::RateLimiter::until_ready])
+/// observe the time they waited for, they wait an additional resolution.
+///
+/// ```rust
+/// # use governor::{clock::CoarseClock, Quota, RateLimiter};
+/// # use nonzero_ext::nonzero;
+/// # use std::time::Duration;
+/// let clock = CoarseClock::from_interval(Duration::from_millis(1)).unwrap();
+/// let lim = RateLimiter::direct_with_clock(Quota::per_second(nonzero!(50u32)), &clock);
+/// assert!(lim.check().is_ok());
+/// ```
+#[derive(Clone)]
+pub struct CoarseClock {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    base: Instant,
+    offset: AtomicU64,
+    resolution: Duration,
+}
+
+impl Shared {
+    fn update(&self) {
+        let offset: u64 = self
+            .base
+            .elapsed()
+            .as_nanos()
+            .try_into()
+            .expect("Can not represent times past ~584 years");
+        self.offset.store(offset, Ordering::Relaxed);
+    }
+}
+
+impl CoarseClock {
+    /// Returns a new `CoarseClock` with a background thread that updates its time once in
+    /// `interval`.
+    pub fn from_interval(interval: Duration) -> Result<CoarseClock, std::io::Error> {
+        let shared = Arc::new(Shared {
+            base: Instant::now(),
+            offset: AtomicU64::new(0),
+            resolution: interval,
+        });
+        let weak: Weak<Shared> = Arc::downgrade(&shared);
+        thread::Builder::new()
+            .name("governor-coarse-clock".to_string())
+            .spawn(move || loop {
+                thread::sleep(interval);
+                match weak.upgrade() {
+                    Some(shared) => shared.update(),
+                    None => return,
+                }
+            })?;
+        Ok(CoarseClock { shared })
+    }
+
+    /// Returns the interval in which the clock's time gets updated.
+    pub fn resolution(&self) -> Duration {
+        self.shared.resolution
+    }
+}
+
+impl fmt::Debug for CoarseClock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        f.debug_struct("CoarseClock")
+            .field("resolution", &self.shared.resolution)
+            .finish()
+    }
+}
+
+impl Clock for CoarseClock {
+    type Instant = Instant;
+
+    fn now(&self) -> Self::Instant {
+        self.shared.base + Nanos::from(self.shared.offset.load(Ordering::Relaxed))
+    }
+}
+
+impl ReasonablyRealtime for CoarseClock {
+    fn delay(&self, duration: Duration) -> Delay {
+        Delay::new(duration + self.shared.resolution)
+    }
+}
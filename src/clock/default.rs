@@ -1,8 +1,16 @@
-#[cfg(all(feature = "std", not(feature = "quanta")))]
+#[cfg(all(
+    feature = "std",
+    not(feature = "quanta"),
+    not(all(feature = "wasm", target_arch = "wasm32"))
+))]
 /// The default clock that reports [`Instant`][std::time::Instant]s.
 pub type DefaultClock = crate::clock::MonotonicClock;
 
-#[cfg(all(feature = "std", feature = "quanta"))]
+#[cfg(all(
+    feature = "std",
+    feature = "quanta",
+    not(all(feature = "wasm", target_arch = "wasm32"))
+))]
 /// The default clock using [`quanta`] for extremely fast timekeeping (at a 100ns resolution).
 pub type DefaultClock = crate::clock::QuantaClock;
 
@@ -10,3 +18,7 @@ pub type DefaultClock = crate::clock::QuantaClock;
 /// The default `no_std` clock that reports [`Durations`][core::time::Duration] must be advanced by the
 /// program.
 pub type DefaultClock = crate::clock::FakeRelativeClock;
+
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+/// The default clock in JavaScript environments, using `performance.now()`.
+pub type DefaultClock = crate::clock::WasmClock;
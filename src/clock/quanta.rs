@@ -3,7 +3,7 @@ use std::prelude::v1::*;
 use crate::clock::{Clock, ReasonablyRealtime, Reference};
 use crate::nanos::Nanos;
 use std::ops::Add;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex, OnceLock, Weak};
 use std::time::Duration;
 
 /// A clock using the default [`quanta::Clock`] structure.
@@ -12,12 +12,137 @@ use std::time::Duration;
 /// clock that uses a quanta background upkeep thread (which allows retrieving the time with an
 /// atomic read, but requires a background thread that wakes up continually),
 /// see [`QuantaUpkeepClock`].
+///
+/// Creating a quanta clock can involve calibrating it against the system's clock, which takes
+/// time. To avoid paying that cost for every rate limiter, all `QuantaClock`s created with
+/// [`default`](#method.default) share one calibrated clock. Use a [`QuantaClockBuilder`] to
+/// control this.
 #[derive(Debug, Clone)]
 pub struct QuantaClock(quanta::Clock);
 
+impl QuantaClock {
+    /// Returns a builder for configuring a `QuantaClock` (or a [`QuantaUpkeepClock`]).
+    pub fn builder() -> QuantaClockBuilder {
+        QuantaClockBuilder::default()
+    }
+
+    /// Returns a `QuantaClock` on top of a [mocked][quanta::Clock::mock] quanta clock, and the
+    /// handle that controls the mocked time.
+    pub fn mock() -> (QuantaClock, Arc<quanta::Mock>) {
+        let (clock, mock) = quanta::Clock::mock();
+        (
+            QuantaClock::builder().with_quanta_clock(clock).build(),
+            mock,
+        )
+    }
+}
+
 impl Default for QuantaClock {
     fn default() -> Self {
-        QuantaClock(Default::default())
+        QuantaClock::builder().build()
+    }
+}
+
+/// Returns the quanta clock that this process shares between quanta-based clocks by default,
+/// calibrating it on first use.
+fn shared_quanta_clock() -> quanta::Clock {
+    static SHARED: OnceLock<quanta::Clock> = OnceLock::new();
+    SHARED.get_or_init(quanta::Clock::new).clone()
+}
+
+/// The upkeep thread shared by [`QuantaUpkeepClock`]s that were created from a
+/// [`QuantaClockBuilder`], along with the interval it updates the time in.
+static SHARED_UPKEEP: Mutex<Option<(Duration, Weak<quanta::Handle>)>> = Mutex::new(None);
+
+/// A builder for quanta-based clocks.
+///
+/// Quanta clocks come in two modes, which the builder's two build methods construct:
+/// * [`build`](#method.build) returns a [`QuantaClock`], which reads the current time whenever
+///   it's asked.
+/// * [`build_with_upkeep`](#method.build_with_upkeep) returns a [`QuantaUpkeepClock`], which
+///   reads the "recent" time that a background thread updates.
+///
+/// # Example
+/// ```rust
+/// # use governor::clock::QuantaClock;
+/// # use std::time::Duration;
+/// // Calibrate a quanta clock once, and share it between a clock reading the current time and
+/// // one reading the recent time:
+/// let quanta = quanta::Clock::new();
+/// let now_clock = QuantaClock::builder()
+///     .with_quanta_clock(quanta.clone())
+///     .build();
+/// let recent_clock = QuantaClock::builder()
+///     .with_quanta_clock(quanta)
+///     .build_with_upkeep(Duration::from_millis(1))
+///     .unwrap();
+/// ```
+#[derive(Debug, Default)]
+pub struct QuantaClockBuilder {
+    clock: Option<quanta::Clock>,
+    recalibrate: bool,
+}
+
+impl QuantaClockBuilder {
+    /// Uses the given quanta clock, e.g. one that was already calibrated, or a
+    /// [mocked][quanta::Clock::mock] clock.
+    ///
+    /// Note that a mocked clock must not be used with
+    /// [`build_with_upkeep`](#method.build_with_upkeep): The upkeep thread would report the
+    /// mocked time as the recent time of all quanta clocks in the process. A mocked clock's
+    /// recent time is always its mocked time, so use [`build`](#method.build) instead.
+    pub fn with_quanta_clock(self, clock: quanta::Clock) -> Self {
+        QuantaClockBuilder {
+            clock: Some(clock),
+            ..self
+        }
+    }
+
+    /// Controls whether a new quanta clock should be created and calibrated, instead of
+    /// sharing the process-wide one. Defaults to `false`.
+    ///
+    /// Has no effect if a clock was given with
+    /// [`with_quanta_clock`](#method.with_quanta_clock).
+    pub fn recalibrate(self, recalibrate: bool) -> Self {
+        QuantaClockBuilder {
+            recalibrate,
+            ..self
+        }
+    }
+
+    fn quanta_clock(self) -> quanta::Clock {
+        match self.clock {
+            Some(clock) => clock,
+            None if self.recalibrate => quanta::Clock::new(),
+            None => shared_quanta_clock(),
+        }
+    }
+
+    /// Returns a [`QuantaClock`], which reads the current time from the quanta clock.
+    pub fn build(self) -> QuantaClock {
+        QuantaClock(self.quanta_clock())
+    }
+
+    /// Returns a [`QuantaUpkeepClock`], which reads the recent time that an upkeep thread
+    /// updates at least once in `interval`.
+    ///
+    /// Since quanta only keeps one recent time per process, only one upkeep thread is needed:
+    /// If a clock built by this method is still alive, and its upkeep thread updates the time
+    /// at least as often as `interval`, the new clock shares that thread.
+    pub fn build_with_upkeep(
+        self,
+        interval: Duration,
+    ) -> Result<QuantaUpkeepClock, std::io::Error> {
+        let clock = self.quanta_clock();
+        let mut shared = SHARED_UPKEEP.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some((shared_interval, handle)) = &*shared {
+            if let Some(handle) = handle.upgrade().filter(|_| *shared_interval <= interval) {
+                return Ok(QuantaUpkeepClock(clock, handle));
+            }
+        }
+        let handle = Arc::new(quanta::Builder::new_with_clock(interval, clock.clone()).start()?);
+        *shared = Some((interval, Arc::downgrade(&handle)));
+        Ok(QuantaUpkeepClock(clock, handle))
     }
 }
 
@@ -71,10 +196,13 @@ impl Reference for QuantaInstant {
 /// and the upkeep interval that you pick; you should measure and compare performance before
 /// picking one or the other.
 #[derive(Debug, Clone)]
-pub struct QuantaUpkeepClock(quanta::Clock, Arc<quanta::Handle>);
+pub struct QuantaUpkeepClock(quanta::Clock, #[allow(dead_code)] Arc<quanta::Handle>);
 
 impl QuantaUpkeepClock {
     /// Returns a new `QuantaUpkeepClock` with an upkeep thread that wakes up once in `interval`.
+    ///
+    /// This always starts a new upkeep thread; to share upkeep threads between clocks, use
+    /// [`QuantaClockBuilder::build_with_upkeep`].
     pub fn from_interval(interval: Duration) -> Result<QuantaUpkeepClock, std::io::Error> {
         let builder = quanta::Builder::new(interval);
         Self::from_builder(builder)
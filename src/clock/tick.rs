@@ -0,0 +1,95 @@
+use std::prelude::v1::*;
+
+use crate::clock::Clock;
+use crate::nanos::Nanos;
+use std::convert::TryInto;
+use std::fmt;
+
+/// A monotonically increasing counter of ticks, like a hardware timer or a cycle counter (e.g.
+/// the Cortex-M DWT's `CYCCNT`).
+///
+/// The counter must not wrap around during the lifetime of the rate limiter: If the hardware
+/// counter is narrower than 64 bits, implementations should extend it (e.g. by counting
+/// overflows in an interrupt handler).
+///
+/// This trait is implemented for all cloneable closures returning a `u64`.
+pub trait TickSource: Clone {
+    /// Returns the current tick count.
+    fn ticks(&self) -> u64;
+}
+
+impl<F> TickSource for F
+where
+    F: Fn() -> u64 + Clone,
+{
+    fn ticks(&self) -> u64 {
+        self()
+    }
+}
+
+/// A clock driven by a [`TickSource`] that ticks at a frequency of `HZ` ticks per second.
+///
+/// This clock works without `std`, and is intended for bare-metal targets, where the time is kept
+/// by a hardware timer. It reports the time elapsed since tick zero as [`Nanos`], so, like the
+/// [`FakeRelativeClock`][crate::clock::FakeRelativeClock], it can not be used to determine
+/// wall-clock times.
+///
+/// # Example
+/// ```rust
+/// # use governor::{clock::TickClock, Quota, RateLimiter};
+/// # use nonzero_ext::nonzero;
+/// # use std::sync::atomic::{AtomicU64, Ordering};
+/// // A 32kHz timer, e.g. incremented by an interrupt handler:
+/// static TICKS: AtomicU64 = AtomicU64::new(0);
+///
+/// let clock = TickClock::<_, 32_768>::new(|| TICKS.load(Ordering::Relaxed));
+/// let lim = RateLimiter::direct_with_clock(Quota::per_second(nonzero!(1u32)), &clock);
+/// assert!(lim.check().is_ok());
+/// assert!(lim.check().is_err());
+///
+/// TICKS.fetch_add(32_768, Ordering::Relaxed);
+/// assert!(lim.check().is_ok());
+/// ```
+#[derive(Clone)]
+pub struct TickClock<T: TickSource, const HZ: u64> {
+    source: T,
+}
+
+impl<T: TickSource, const HZ: u64> TickClock<T, HZ> {
+    /// Constructs a clock that reads its ticks from `source`.
+    ///
+    /// # Panics
+    /// Panics if `HZ` is 0.
+    pub const fn new(source: T) -> Self {
+        assert!(HZ > 0, "A tick clock must tick at least once per second");
+        TickClock { source }
+    }
+
+    /// Returns the frequency of the clock's ticks, in ticks per second.
+    pub const fn frequency(&self) -> u64 {
+        HZ
+    }
+
+    /// Converts a number of ticks into the duration they take at the clock's frequency.
+    pub fn ticks_to_nanos(ticks: u64) -> Nanos {
+        let nanos = u128::from(ticks) * 1_000_000_000 / u128::from(HZ);
+        nanos
+            .try_into()
+            .map(Nanos::new)
+            .expect("Can not represent times past ~584 years")
+    }
+}
+
+impl<T: TickSource, const HZ: u64> fmt::Debug for TickClock<T, HZ> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        f.debug_struct("TickClock").field("frequency", &HZ).finish()
+    }
+}
+
+impl<T: TickSource, const HZ: u64> Clock for TickClock<T, HZ> {
+    type Instant = Nanos;
+
+    fn now(&self) -> Self::Instant {
+        Self::ticks_to_nanos(self.source.ticks())
+    }
+}
@@ -0,0 +1,68 @@
+use std::prelude::v1::*;
+
+use crate::clock::{Clock, ReasonablyRealtime, Reference};
+use crate::nanos::Nanos;
+use std::ops::Add;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// The monotonic clock of the tokio runtime, implemented by [`tokio::time::Instant`].
+///
+/// Unlike the [`MonotonicClock`][crate::clock::MonotonicClock], this clock respects tokio's
+/// time controls: If time is paused (using `tokio::time::pause`, or the `start_paused` option
+/// of the `#[tokio::test]` macro), the clock only advances when tokio's time does. Together with
+/// the `tokio` feature's timer, which tokio advances automatically while the runtime is idle,
+/// this makes it possible to test asynchronous code that uses rate limiters without waiting in
+/// real time. (With other clocks, waits on a paused runtime would end immediately, without the
+/// rate limiter's clock having advanced.)
+///
+/// ```rust
+/// # use governor::{clock::TokioClock, Quota, RateLimiter};
+/// # use nonzero_ext::nonzero;
+/// # use std::time::Duration;
+/// let rt = tokio::runtime::Builder::new_current_thread()
+///     .enable_time()
+///     .start_paused(true)
+///     .build()
+///     .unwrap();
+/// rt.block_on(async {
+///     let clock = TokioClock;
+///     let lim = RateLimiter::direct_with_clock(Quota::per_hour(nonzero!(1u32)), &clock);
+///     let start = tokio::time::Instant::now();
+///     lim.until_ready().await;
+///     lim.until_ready().await;
+///     // An hour passed, but only in tokio's paused time:
+///     assert_eq!(start.elapsed(), Duration::from_secs(60 * 60));
+/// });
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct TokioClock;
+
+impl Add<Nanos> for Instant {
+    type Output = Instant;
+
+    fn add(self, other: Nanos) -> Instant {
+        let other: Duration = other.into();
+        self + other
+    }
+}
+
+impl Reference for Instant {
+    fn duration_since(&self, earlier: Self) -> Nanos {
+        self.saturating_duration_since(earlier).into()
+    }
+
+    fn saturating_sub(&self, duration: Nanos) -> Self {
+        self.checked_sub(duration.into()).unwrap_or(*self)
+    }
+}
+
+impl Clock for TokioClock {
+    type Instant = Instant;
+
+    fn now(&self) -> Self::Instant {
+        Instant::now()
+    }
+}
+
+impl ReasonablyRealtime for TokioClock {}
@@ -0,0 +1,61 @@
+use std::prelude::v1::*;
+
+use crate::clock::{Clock, ReasonablyRealtime, Reference};
+use crate::nanos::Nanos;
+use std::ops::Add;
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen]
+extern "C" {
+    // `performance` is a global in browser windows and web workers, as well as in Node.js and
+    // Cloudflare Workers.
+    #[wasm_bindgen(js_namespace = performance, js_name = now)]
+    fn performance_now() -> f64;
+}
+
+/// The monotonic clock of JavaScript environments, implemented by
+/// [`performance.now()`](https://developer.mozilla.org/en-US/docs/Web/API/Performance/now).
+///
+/// On `wasm32-unknown-unknown`, [`Instant`][std::time::Instant] isn't available and the
+/// [`QuantaClock`][crate::clock::QuantaClock] can't read the CPU's time stamp counter, so this
+/// is the default clock there when the `wasm` feature is enabled.
+///
+/// Note that browsers may reduce the precision of `performance.now()` (to e.g. 100μs), and
+/// Cloudflare Workers only advance it on I/O; rate limits with very short emission intervals may
+/// not be enforced precisely in these environments.
+#[derive(Clone, Debug, Default)]
+pub struct WasmClock;
+
+/// A nanosecond-scale opaque instant, relative to the time origin of the JavaScript environment,
+/// returned from a [`WasmClock`].
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct WasmInstant(Nanos);
+
+impl Add<Nanos> for WasmInstant {
+    type Output = WasmInstant;
+
+    fn add(self, other: Nanos) -> WasmInstant {
+        WasmInstant(self.0 + other)
+    }
+}
+
+impl Reference for WasmInstant {
+    fn duration_since(&self, earlier: Self) -> Nanos {
+        self.0.duration_since(earlier.0)
+    }
+
+    fn saturating_sub(&self, duration: Nanos) -> Self {
+        WasmInstant(self.0.saturating_sub(duration))
+    }
+}
+
+impl Clock for WasmClock {
+    type Instant = WasmInstant;
+
+    fn now(&self) -> Self::Instant {
+        // `performance.now()` returns (fractional) milliseconds:
+        WasmInstant(Nanos::new((performance_now() * 1_000_000.0) as u64))
+    }
+}
+
+impl ReasonablyRealtime for WasmClock {}
@@ -1,9 +1,14 @@
-use super::{Clock, Reference};
+use super::{Clock, Delay, FakeRelativeClock, Reference};
 
 use std::prelude::v1::*;
 
 use crate::nanos::Nanos;
+use futures::task::{waker, ArcWake, Context, Poll};
+use futures::Future;
 use std::ops::Add;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, Thread};
 use std::time::{Duration, Instant, SystemTime};
 
 /// The monotonic clock implemented by [`Instant`].
@@ -87,8 +92,96 @@ pub trait ReasonablyRealtime: Clock {
     fn reference_point(&self) -> Self::Instant {
         self.now()
     }
+
+    /// Returns a future that resolves once `duration` has passed on this clock.
+    ///
+    /// The default implementation waits in real time, which is appropriate for clocks that
+    /// follow the real time.
+    fn delay(&self, duration: Duration) -> Delay {
+        Delay::new(duration)
+    }
 }
 
 impl ReasonablyRealtime for MonotonicClock {}
 
 impl ReasonablyRealtime for SystemClock {}
+
+/// Waits on the fake clock resolve when the clock gets advanced past their deadline, rather than
+/// in real time.
+impl ReasonablyRealtime for FakeRelativeClock {
+    fn delay(&self, duration: Duration) -> Delay {
+        Delay::fake(self, duration)
+    }
+}
+
+/// Wakes up the thread running [`FakeRelativeClock::block_on_auto_advance`].
+struct ThreadWaker {
+    woken: AtomicBool,
+    thread: Thread,
+}
+
+impl ArcWake for ThreadWaker {
+    fn wake_by_ref(arc_self: &Arc<Self>) {
+        arc_self.woken.store(true, Ordering::Release);
+        arc_self.thread.unpark();
+    }
+}
+
+impl FakeRelativeClock {
+    /// Runs a future to completion on the current thread, advancing the clock whenever the
+    /// future can't make progress otherwise.
+    ///
+    /// Whenever the future is pending without having woken itself up, the clock is advanced to
+    /// the earliest deadline of the asynchronous waits (e.g. in
+    /// [`until_ready`][crate::RateLimiter::until_ready]) pending on it. This makes tests of
+    /// asynchronous code that uses rate limiters - even with jitter - deterministic and fast: No
+    /// time passes in reality, and the clock only ever advances by as much as the waits require.
+    ///
+    /// Only the tasks that are part of `future` (e.g. joined with
+    /// [`join_all`][futures::future::join_all]) are considered; if there is no pending wait on
+    /// the clock, this blocks until the future gets woken up by something else.
+    ///
+    /// ```rust
+    /// # use governor::{clock::{Clock, FakeRelativeClock}, Quota, RateLimiter};
+    /// # use nonzero_ext::nonzero;
+    /// # use std::time::Duration;
+    /// let clock = FakeRelativeClock::default();
+    /// let lim = RateLimiter::direct_with_clock(Quota::per_hour(nonzero!(2u32)), &clock);
+    /// clock.block_on_auto_advance(async {
+    ///     for _ in 0..4 {
+    ///         lim.until_ready().await;
+    ///     }
+    /// });
+    /// assert_eq!(Duration::from(clock.now()), Duration::from_secs(60 * 60));
+    /// ```
+    pub fn block_on_auto_advance<F: Future>(&self, future: F) -> F::Output {
+        let mut future = Box::pin(future);
+        let thread_waker = Arc::new(ThreadWaker {
+            woken: AtomicBool::new(false),
+            thread: thread::current(),
+        });
+        let waker = waker(thread_waker.clone());
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            thread_waker.woken.store(false, Ordering::Release);
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+            if thread_waker.woken.swap(false, Ordering::Acquire) {
+                // Something made progress while polling; poll again.
+                continue;
+            }
+            match self.timers().next_deadline() {
+                Some(deadline) => {
+                    // This also wakes up waits whose deadline has already passed:
+                    self.advance(deadline.duration_since(self.now()).into());
+                }
+                None => {
+                    while !thread_waker.woken.swap(false, Ordering::Acquire) {
+                        thread::park();
+                    }
+                }
+            }
+        }
+    }
+}
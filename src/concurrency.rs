@@ -0,0 +1,301 @@
+use std::prelude::v1::*;
+
+use crate::state::{DirectStateStore, NotKeyed};
+use crate::{clock, Jitter, RateLimiterHandle};
+use futures::Future;
+use parking_lot::Mutex;
+use std::collections::VecDeque;
+use std::fmt;
+use std::num::NonZeroU32;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Waker};
+
+/// A limit on the number of operations that may be in flight at the same time (a semaphore).
+///
+/// Each operation acquires a [`ConcurrencyPermit`] before it starts, and releases it by dropping
+/// the permit when it finishes. Tasks that [`acquire`](#method.acquire) a permit while the limit
+/// is reached wait in line, and get their permits in the order in which they started waiting.
+///
+/// Limiters are cheap to clone; clones share the same limit.
+///
+/// To enforce a concurrency limit together with a rate limit, see
+/// [`RateAndConcurrencyLimiter`].
+///
+/// # Example
+/// ```rust
+/// # use governor::ConcurrencyLimiter;
+/// # use nonzero_ext::nonzero;
+/// # futures::executor::block_on(async {
+/// let lim = ConcurrencyLimiter::new(nonzero!(2u32));
+/// let first = lim.acquire().await;
+/// let _second = lim.acquire().await;
+/// assert!(lim.try_acquire().is_none());
+///
+/// drop(first);
+/// assert!(lim.try_acquire().is_some());
+/// # });
+/// ```
+#[derive(Clone)]
+pub struct ConcurrencyLimiter {
+    inner: Arc<Semaphore>,
+}
+
+struct Semaphore {
+    max: u32,
+    state: Mutex<SemaphoreState>,
+}
+
+#[derive(Default)]
+struct SemaphoreState {
+    in_flight: u32,
+    waiters: VecDeque<(u64, Waker)>,
+    next_waiter: u64,
+}
+
+impl SemaphoreState {
+    /// Wakes the task that waits for a permit the longest, if there is a permit for it.
+    fn wake_next(&self, max: u32) {
+        if self.in_flight < max {
+            if let Some((_, waker)) = self.waiters.front() {
+                waker.wake_by_ref();
+            }
+        }
+    }
+}
+
+impl ConcurrencyLimiter {
+    /// Constructs a limiter that allows at most `max_in_flight` operations at the same time.
+    pub fn new(max_in_flight: NonZeroU32) -> Self {
+        ConcurrencyLimiter {
+            inner: Arc::new(Semaphore {
+                max: max_in_flight.get(),
+                state: Mutex::new(SemaphoreState::default()),
+            }),
+        }
+    }
+
+    /// Returns the maximum number of operations allowed in flight at the same time.
+    pub fn max_in_flight(&self) -> NonZeroU32 {
+        NonZeroU32::new(self.inner.max).unwrap()
+    }
+
+    /// Returns the number of permits that are currently held.
+    pub fn in_flight(&self) -> u32 {
+        self.inner.state.lock().in_flight
+    }
+
+    /// Returns a permit if one is available right away, and no other task is waiting for one.
+    pub fn try_acquire(&self) -> Option<ConcurrencyPermit> {
+        let mut state = self.inner.state.lock();
+        if state.in_flight < self.inner.max && state.waiters.is_empty() {
+            state.in_flight += 1;
+            Some(self.permit())
+        } else {
+            None
+        }
+    }
+
+    /// Asynchronously resolves to a permit as soon as one is available.
+    ///
+    /// Dropping the returned future before it resolves gives up the task's place in line.
+    pub async fn acquire(&self) -> ConcurrencyPermit {
+        Acquire {
+            limiter: self,
+            waiter: None,
+        }
+        .await
+    }
+
+    fn permit(&self) -> ConcurrencyPermit {
+        ConcurrencyPermit {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl fmt::Debug for ConcurrencyLimiter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        let state = self.inner.state.lock();
+        f.debug_struct("ConcurrencyLimiter")
+            .field("max_in_flight", &self.inner.max)
+            .field("in_flight", &state.in_flight)
+            .field("waiting", &state.waiters.len())
+            .finish()
+    }
+}
+
+/// The future that waits for a permit, keeping the task's place in line.
+struct Acquire<'a> {
+    limiter: &'a ConcurrencyLimiter,
+    waiter: Option<u64>,
+}
+
+impl<'a> Future for Acquire<'a> {
+    type Output = ConcurrencyPermit;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let limiter = self.limiter;
+        let max = limiter.inner.max;
+        let mut state = limiter.inner.state.lock();
+        let first_in_line = match (self.waiter, state.waiters.front()) {
+            (_, None) => true,
+            (Some(waiter), Some((front, _))) => waiter == *front,
+            (None, Some(_)) => false,
+        };
+        if first_in_line && state.in_flight < max {
+            state.in_flight += 1;
+            if self.waiter.take().is_some() {
+                state.waiters.pop_front();
+                state.wake_next(max);
+            }
+            return Poll::Ready(limiter.permit());
+        }
+        match self.waiter {
+            Some(waiter) => {
+                if let Some((_, waker)) = state.waiters.iter_mut().find(|(id, _)| *id == waiter) {
+                    waker.clone_from(cx.waker());
+                }
+            }
+            None => {
+                let waiter = state.next_waiter;
+                state.next_waiter += 1;
+                state.waiters.push_back((waiter, cx.waker().clone()));
+                self.waiter = Some(waiter);
+            }
+        }
+        Poll::Pending
+    }
+}
+
+impl<'a> Drop for Acquire<'a> {
+    fn drop(&mut self) {
+        if let Some(waiter) = self.waiter {
+            let mut state = self.limiter.inner.state.lock();
+            state.waiters.retain(|(id, _)| *id != waiter);
+            state.wake_next(self.limiter.inner.max);
+        }
+    }
+}
+
+/// Permission for one operation to be in flight, from a [`ConcurrencyLimiter`].
+///
+/// The permit is released when it is dropped.
+#[must_use = "the permit is released as soon as it is dropped"]
+pub struct ConcurrencyPermit {
+    inner: Arc<Semaphore>,
+}
+
+impl Drop for ConcurrencyPermit {
+    fn drop(&mut self) {
+        let mut state = self.inner.state.lock();
+        state.in_flight -= 1;
+        state.wake_next(self.inner.max);
+    }
+}
+
+impl fmt::Debug for ConcurrencyPermit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        f.debug_struct("ConcurrencyPermit").finish()
+    }
+}
+
+/// A combination of a direct rate limiter and a [`ConcurrencyLimiter`], enforcing both a rate
+/// limit (e.g. "at most 100 requests per second") and a concurrency limit (e.g. "at most 10
+/// requests in flight").
+///
+/// [`acquire`](#method.acquire) first waits for a concurrency permit, and then for the rate
+/// limiter to allow a cell through. This way, no cell is used up by an operation that then has
+/// to wait for a permit (by which time it may no longer conform).
+///
+/// # Example
+/// ```rust
+/// # use governor::{ConcurrencyLimiter, Quota, RateAndConcurrencyLimiter, RateLimiter};
+/// # use nonzero_ext::nonzero;
+/// # futures::executor::block_on(async {
+/// let lim = RateAndConcurrencyLimiter::new(
+///     RateLimiter::direct(Quota::per_second(nonzero!(100u32))),
+///     ConcurrencyLimiter::new(nonzero!(10u32)),
+/// );
+/// let permit = lim.acquire().await;
+/// // ...perform the request...
+/// drop(permit);
+/// # });
+/// ```
+pub struct RateAndConcurrencyLimiter<S, C>
+where
+    S: DirectStateStore,
+    C: clock::Clock,
+{
+    rate: RateLimiterHandle<NotKeyed, S, C>,
+    concurrency: ConcurrencyLimiter,
+}
+
+impl<S, C> RateAndConcurrencyLimiter<S, C>
+where
+    S: DirectStateStore,
+    C: clock::ReasonablyRealtime,
+{
+    /// Combines a rate limiter (or a handle to a shared one) with a concurrency limiter.
+    pub fn new(
+        rate: impl Into<RateLimiterHandle<NotKeyed, S, C>>,
+        concurrency: ConcurrencyLimiter,
+    ) -> Self {
+        RateAndConcurrencyLimiter {
+            rate: rate.into(),
+            concurrency,
+        }
+    }
+
+    /// Returns the rate limiter.
+    pub fn rate_limiter(&self) -> &RateLimiterHandle<NotKeyed, S, C> {
+        &self.rate
+    }
+
+    /// Returns the concurrency limiter.
+    pub fn concurrency_limiter(&self) -> &ConcurrencyLimiter {
+        &self.concurrency
+    }
+
+    /// Asynchronously resolves to a concurrency permit as soon as both a permit is available and
+    /// the rate limiter allows a cell through.
+    pub async fn acquire(&self) -> ConcurrencyPermit {
+        self.acquire_with_jitter(Jitter::NONE).await
+    }
+
+    /// Asynchronously resolves to a concurrency permit as soon as both a permit is available and
+    /// the rate limiter allows a cell through, waiting for the rate limiter with a randomized
+    /// wait period (see
+    /// [`until_ready_with_jitter`](struct.RateLimiter.html#method.until_ready_with_jitter)).
+    pub async fn acquire_with_jitter(&self, jitter: Jitter) -> ConcurrencyPermit {
+        let permit = self.concurrency.acquire().await;
+        self.rate.until_ready_with_jitter(jitter).await;
+        permit
+    }
+}
+
+impl<S, C> Clone for RateAndConcurrencyLimiter<S, C>
+where
+    S: DirectStateStore,
+    C: clock::Clock,
+{
+    fn clone(&self) -> Self {
+        RateAndConcurrencyLimiter {
+            rate: self.rate.clone(),
+            concurrency: self.concurrency.clone(),
+        }
+    }
+}
+
+impl<S, C> fmt::Debug for RateAndConcurrencyLimiter<S, C>
+where
+    S: DirectStateStore + fmt::Debug,
+    C: clock::Clock + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        f.debug_struct("RateAndConcurrencyLimiter")
+            .field("rate", &self.rate)
+            .field("concurrency", &self.concurrency)
+            .finish()
+    }
+}
@@ -0,0 +1,174 @@
+use std::prelude::v1::*;
+
+use crate::clock::Reference;
+use crate::gcra::Gcra;
+use crate::nanos::Nanos;
+use crate::Quota;
+
+#[cfg(feature = "std")]
+use crate::clock::{self, Delay};
+#[cfg(feature = "std")]
+use futures::task::{Context, Poll};
+#[cfg(feature = "std")]
+use futures::{Future, Stream};
+#[cfg(feature = "std")]
+use std::pin::Pin;
+
+/// An iterator over the earliest times at which a rate limiter with a given quota lets
+/// consecutive cells through.
+///
+/// See [`Quota::conforming_times`].
+#[derive(Debug, Clone)]
+pub struct ConformingTimes<P: Reference> {
+    start: P,
+    t: Nanos,
+    tau: Nanos,
+    tat: Nanos,
+}
+
+impl<P: Reference> Iterator for ConformingTimes<P> {
+    type Item = P;
+
+    fn next(&mut self) -> Option<P> {
+        if self.tau == Nanos::from(0) {
+            // The quota lets no cells through.
+            return None;
+        }
+        let earliest = self.tat.saturating_sub(self.tau);
+        self.tat = self.tat + self.t;
+        Some(self.start + earliest)
+    }
+}
+
+/// # Computing conforming times up front
+impl Quota {
+    /// Returns the earliest times at which a fresh rate limiting state with this quota, first
+    /// used at `start`, lets consecutive cells through. For quotas that
+    /// [start empty][Quota::starting_empty], `start` is the time at which the state begins to
+    /// fill up.
+    ///
+    /// A rate limiter that checks one cell at each of these times allows all of them through,
+    /// so this is useful for tools (like load generators or traffic replay tools) that
+    /// schedule their work up front instead of checking a rate limiter as they go. The
+    /// iterator is infinite (unless the quota [lets no cells through][Quota::none]); use
+    /// [`take`][Iterator::take] to compute the times for a number of cells.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use governor::{clock::{Clock, FakeRelativeClock, Reference}, Quota, RateLimiter};
+    /// # use nonzero_ext::nonzero;
+    /// # use std::time::Duration;
+    /// let clock = FakeRelativeClock::default();
+    /// let quota = Quota::per_second(nonzero!(4u32)).allow_burst(nonzero!(2u32));
+    /// let start = clock.now();
+    /// let times: Vec<Duration> = quota
+    ///     .conforming_times(start)
+    ///     .take(4)
+    ///     .map(|time| time.duration_since(start).into())
+    ///     .collect();
+    /// assert_eq!(
+    ///     times,
+    ///     vec![
+    ///         Duration::from_millis(0),
+    ///         Duration::from_millis(0),
+    ///         Duration::from_millis(250),
+    ///         Duration::from_millis(500)
+    ///     ]
+    /// );
+    ///
+    /// // A rate limiter lets a cell through at each of these times:
+    /// let lim = RateLimiter::direct_with_clock(quota, &clock);
+    /// for time in quota.conforming_times(start).take(10) {
+    ///     clock.advance(time.duration_since(clock.now()).into());
+    ///     assert_eq!(Ok(()), lim.check());
+    /// }
+    /// ```
+    pub fn conforming_times<P: Reference>(&self, start: P) -> ConformingTimes<P> {
+        let gcra = Gcra::new(*self);
+        let (t, tau) = (gcra.t(), gcra.tau());
+        let tat = if self.start_empty { tau + t } else { t };
+        ConformingTimes { start, t, tau, tat }
+    }
+
+    /// Returns a stream that yields the [conforming times][Quota::conforming_times] of a fresh
+    /// rate limiting state with this quota, first used now, as each of them is reached on
+    /// `clock`.
+    ///
+    /// This paces work at the rate that the quota allows, without a rate limiter: Each item is
+    /// the time at which the tick was scheduled. If the stream isn't polled for a while, the
+    /// ticks that were due in the meantime are yielded right away.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use futures::StreamExt;
+    /// # use governor::{clock::DefaultClock, Quota};
+    /// # use nonzero_ext::nonzero;
+    /// # futures::executor::block_on(async {
+    /// let clock = DefaultClock::default();
+    /// let mut ticks = Quota::per_second(nonzero!(50u32)).conforming_ticks(&clock);
+    /// for _ in 0..3 {
+    ///     let _scheduled_at = ticks.next().await.unwrap();
+    ///     // ...send a request...
+    /// }
+    /// # });
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn conforming_ticks<C: clock::ReasonablyRealtime>(&self, clock: &C) -> ConformingTicks<C> {
+        ConformingTicks {
+            times: self.conforming_times(clock.now()),
+            clock: clock.clone(),
+            next: None,
+            delay: None,
+        }
+    }
+}
+
+/// A stream that yields the conforming times of a quota as they are reached.
+///
+/// See [`Quota::conforming_ticks`].
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct ConformingTicks<C: clock::ReasonablyRealtime> {
+    times: ConformingTimes<C::Instant>,
+    clock: C,
+    next: Option<C::Instant>,
+    delay: Option<Delay>,
+}
+
+// The ticks stream never pins any of its fields:
+#[cfg(feature = "std")]
+impl<C: clock::ReasonablyRealtime> Unpin for ConformingTicks<C> {}
+
+#[cfg(feature = "std")]
+impl<C: clock::ReasonablyRealtime> Stream for ConformingTicks<C> {
+    type Item = C::Instant;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            let next = match this.next {
+                Some(next) => next,
+                None => match this.times.next() {
+                    Some(next) => {
+                        this.next = Some(next);
+                        next
+                    }
+                    None => return Poll::Ready(None),
+                },
+            };
+            if let Some(delay) = &mut this.delay {
+                match Pin::new(delay).poll(cx) {
+                    Poll::Pending => return Poll::Pending,
+                    Poll::Ready(()) => this.delay = None,
+                }
+            }
+            let now = this.clock.now();
+            if now >= next {
+                this.next = None;
+                return Poll::Ready(Some(next));
+            }
+            let delay = this.clock.delay(next.duration_since(now).into());
+            this.delay = Some(delay);
+        }
+    }
+}
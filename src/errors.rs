@@ -1,4 +1,6 @@
+use crate::Quota;
 use std::fmt;
+use std::num::NonZeroU32;
 
 /// Gives additional information about the negative outcome of a batch
 /// cell decision.
@@ -22,9 +24,162 @@ pub enum NegativeMultiDecision<E: fmt::Display> {
     /// simultaneous decisions).
     BatchNonConforming(u32, E),
 
-    /// The number of cells tested (the first argument) is larger than
-    /// the bucket's capacity, which means the decision can never have
-    /// a conforming result. The argument gives the maximum number of
-    /// cells that could ever have a conforming result.
-    InsufficientCapacity(u32),
+    /// The number of cells tested is larger than the bucket's
+    /// capacity, which means the decision can never have a conforming
+    /// result. The argument gives the number of cells tested, and the
+    /// maximum number of cells that could ever have a conforming
+    /// result.
+    InsufficientCapacity(InsufficientCapacity),
+}
+
+impl<E: fmt::Display> fmt::Display for NegativeMultiDecision<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            NegativeMultiDecision::BatchNonConforming(n, not_until) => {
+                write!(f, "batch of {} cells is non-conforming: {}", n, not_until)
+            }
+            NegativeMultiDecision::InsufficientCapacity(insufficient) => insufficient.fmt(f),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<E: std::error::Error + 'static> std::error::Error for NegativeMultiDecision<E> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            NegativeMultiDecision::BatchNonConforming(_, not_until) => Some(not_until),
+            NegativeMultiDecision::InsufficientCapacity(_) => None,
+        }
+    }
+}
+
+/// An error that occurs when the number of cells tested in a batch exceeds the maximum burst
+/// size of the rate limiter, so that the batch can never conform.
+///
+/// Besides the number of cells that were tested, it carries the rate limiter's maximum burst
+/// size and quota, so that callers can split oversized batches into ones that fit.
+///
+/// # Example
+/// ```rust
+/// # #[cfg(feature = "std")] fn main() {
+/// # use governor::{NegativeMultiDecision, Quota, RateLimiter};
+/// # use nonzero_ext::nonzero;
+/// # use std::num::NonZeroU32;
+/// let lim = RateLimiter::direct(Quota::per_second(nonzero!(5u32)));
+/// match lim.check_n(nonzero!(12u32)) {
+///     Err(NegativeMultiDecision::InsufficientCapacity(insufficient)) => {
+///         assert_eq!(insufficient.requested().get(), 12);
+///         assert_eq!(insufficient.max_burst(), 5);
+///         // Split the batch into pieces that fit:
+///         let batch = NonZeroU32::new(insufficient.max_burst()).unwrap();
+///         assert_eq!(Ok(()), lim.check_n(batch));
+///     }
+///     other => panic!("unexpected outcome {:?}", other),
+/// }
+/// # }
+/// # #[cfg(not(feature = "std"))] fn main() {}
+/// ```
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct InsufficientCapacity {
+    requested: NonZeroU32,
+    max_burst: u32,
+    quota: Quota,
+}
+
+impl InsufficientCapacity {
+    pub(crate) fn new(requested: NonZeroU32, max_burst: u32, quota: Quota) -> Self {
+        InsufficientCapacity {
+            requested,
+            max_burst,
+            quota,
+        }
+    }
+
+    /// Returns the number of cells that were tested.
+    pub fn requested(&self) -> NonZeroU32 {
+        self.requested
+    }
+
+    /// Returns the maximum number of cells that could ever conform at once: The rate limiter's
+    /// burst size, or 0 if its quota [lets no cells through][Quota::none].
+    pub fn max_burst(&self) -> u32 {
+        self.max_burst
+    }
+
+    /// Returns the quota of the rate limiter that the cells were tested against.
+    pub fn quota(&self) -> Quota {
+        self.quota
+    }
+}
+
+impl fmt::Display for InsufficientCapacity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(
+            f,
+            "required number of {} cells exceeds the capacity of {} cells",
+            self.requested, self.max_burst
+        )
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for InsufficientCapacity {}
+
+/// The reasons why a [`Quota`][crate::Quota] can not be constructed.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum QuotaError {
+    /// The period of time in which the cells are replenished is zero.
+    ZeroPeriod,
+
+    /// The period of time in which the cells are replenished is too long: The time that it takes
+    /// to replenish all cells can't be represented in 64 bits of nanoseconds (about 584 years).
+    PeriodTooLong,
+}
+
+impl fmt::Display for QuotaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            QuotaError::ZeroPeriod => write!(f, "quota period must not be zero"),
+            QuotaError::PeriodTooLong => write!(f, "quota period is too long to represent"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for QuotaError {}
+
+/// The negative outcome of a rate limiting decision that is made at a given time (e.g., with
+/// [`check_at`](struct.RateLimiter.html#method.check_at)).
+#[derive(Debug, PartialEq)]
+pub enum CheckAtError<E, P> {
+    /// The decision was made, and was negative. The argument gives more information about it.
+    NonConforming(E),
+
+    /// No decision was made, since decisions must be made in chronological order: The given
+    /// time lies before that of an earlier decision made at a given time (the argument), or
+    /// before the rate limiter was constructed.
+    OutOfOrder(P),
+}
+
+impl<E: fmt::Display, P: fmt::Debug> fmt::Display for CheckAtError<E, P> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            CheckAtError::NonConforming(negative) => negative.fmt(f),
+            CheckAtError::OutOfOrder(latest) => write!(
+                f,
+                "decision time lies before that of an earlier decision, at {:?}",
+                latest
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<E: std::error::Error + 'static, P: fmt::Debug> std::error::Error for CheckAtError<E, P> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CheckAtError::NonConforming(negative) => Some(negative),
+            CheckAtError::OutOfOrder(_) => None,
+        }
+    }
 }
@@ -1,27 +1,47 @@
 use std::prelude::v1::*;
 
 use crate::nanos::Nanos;
+use crate::quota::Limit;
 use crate::state::StateStore;
-use crate::{clock, NegativeMultiDecision, Quota};
+use crate::{clock, InsufficientCapacity, NegativeMultiDecision, Quota};
+use std::convert::Infallible;
 use std::num::NonZeroU32;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::Duration;
 use std::{cmp, fmt};
 
-#[cfg(feature = "std")]
-use crate::Jitter;
-
 /// A negative rate-limiting outcome.
 ///
 /// `NotUntil`'s methods indicate when a caller can expect the next positive
 /// rate-limiting result.
-#[derive(Debug, PartialEq)]
-pub struct NotUntil<'a, P: clock::Reference> {
-    limiter: &'a Gcra,
+///
+/// In `std` mode, `NotUntil` implements [`Error`][std::error::Error], so it can be propagated
+/// with the `?` operator:
+///
+/// ```rust
+/// # #[cfg(feature = "std")] fn main() {
+/// # use governor::{Quota, RateLimiter};
+/// # use nonzero_ext::nonzero;
+/// # use std::error::Error;
+/// fn send_email(lim: &RateLimiter<governor::state::NotKeyed, governor::state::InMemoryState, governor::clock::DefaultClock>) -> Result<(), Box<dyn Error>> {
+///     lim.check()?;
+///     // ...send the email...
+///     Ok(())
+/// }
+///
+/// let lim = RateLimiter::direct(Quota::per_hour(nonzero!(1u32)));
+/// assert!(send_email(&lim).is_ok());
+/// assert!(send_email(&lim).is_err());
+/// # } #[cfg(not(feature = "std"))] fn main() {}
+/// ```
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct NotUntil<P: clock::Reference> {
+    state: StateSnapshot,
     tat: Nanos,
     start: P,
 }
 
-impl<'a, P: clock::Reference> NotUntil<'a, P> {
+impl<P: clock::Reference> NotUntil<P> {
     /// Returns the earliest time at which a decision could be
     /// conforming (excluding conforming decisions made by the Decider
     /// that are made in the meantime).
@@ -41,70 +61,371 @@ impl<'a, P: clock::Reference> NotUntil<'a, P> {
         earliest.duration_since(earliest.min(from)).into()
     }
 
+    /// Returns the amount of time that must pass after the decision was made, before a
+    /// decision can be conforming.
+    ///
+    /// Unlike [`wait_time_from`](#method.wait_time_from), this does not require access to the
+    /// rate limiter's clock, which makes it suitable for e.g. computing the value of a
+    /// `Retry-After` header.
+    pub fn retry_after(&self) -> Duration {
+        self.tat
+            .saturating_sub(self.state.time_of_measurement)
+            .into()
+    }
+
+    /// Returns the time at which the decision was made.
+    pub fn time_of_decision(&self) -> P {
+        self.start + self.state.time_of_measurement
+    }
+
+    /// Returns the time it takes to replenish a single cell (the GCRA's emission interval).
+    pub fn emission_interval(&self) -> Duration {
+        self.state.emission_interval()
+    }
+
+    /// Returns the capacity of the rate limiter's bucket in units of time (the GCRA's
+    /// tolerance): the emission interval times the maximum burst size.
+    pub fn tolerance(&self) -> Duration {
+        self.state.tolerance()
+    }
+
+    /// Returns a snapshot of the rate-limiting state as of the time of the decision.
+    pub fn state_snapshot(&self) -> StateSnapshot {
+        self.state
+    }
+
     #[cfg(feature = "std")] // not used unless we use Instant-compatible clocks.
-    pub(crate) fn earliest_possible_with_offset(&self, jitter: Jitter) -> P {
-        let tat = jitter + self.tat;
+    pub(crate) fn earliest_possible_with_offset(&self, offset: Nanos) -> P {
+        let tat = self.tat + offset;
         self.start + tat
     }
 
     #[cfg(feature = "std")] // not used unless we use Instant-compatible clocks.
-    pub(crate) fn wait_time_with_offset(&self, from: P, jitter: Jitter) -> Duration {
-        let earliest = self.earliest_possible_with_offset(jitter);
+    pub(crate) fn wait_time_with_offset(&self, from: P, offset: Nanos) -> Duration {
+        let earliest = self.earliest_possible_with_offset(offset);
         earliest.duration_since(earliest.min(from)).into()
     }
 }
 
-impl<'a, P: clock::Reference> fmt::Display for NotUntil<'a, P> {
+impl<P: clock::Reference> fmt::Display for NotUntil<P> {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
         write!(f, "rate-limited until {:?}", self.start + self.tat)
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[cfg(feature = "std")]
+impl<P: clock::Reference> std::error::Error for NotUntil<P> {}
+
+/// A snapshot of a rate-limiting state and the parameters of the rate limiter it belongs to.
+///
+/// Snapshots are useful for observing and debugging the pacing behavior of rate limiters. Times
+/// (the theoretical arrival time and the time of measurement) are given as [`Nanos`] that passed
+/// since the rate limiter was constructed.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct StateSnapshot {
+    t: Nanos,
+    tau: Nanos,
+    tat: Option<Nanos>,
+    time_of_measurement: Nanos,
+    remaining: u32,
+}
+
+impl StateSnapshot {
+    /// Returns the time it takes to replenish a single cell (the GCRA's emission interval).
+    pub fn emission_interval(&self) -> Duration {
+        self.t.into()
+    }
+
+    /// Returns the capacity of the rate limiter's bucket in units of time (the GCRA's
+    /// tolerance): the emission interval times the maximum burst size.
+    pub fn tolerance(&self) -> Duration {
+        self.tau.into()
+    }
+
+    /// Returns the state's theoretical arrival time (the time at which the state would be
+    /// fully replenished, plus one emission interval), or `None` if the state was not used yet.
+    pub fn theoretical_arrival_time(&self) -> Option<Nanos> {
+        self.tat
+    }
+
+    /// Returns the time at which the snapshot was taken.
+    pub fn time_of_measurement(&self) -> Nanos {
+        self.time_of_measurement
+    }
+
+    /// Returns the number of cells that could have been let through at the time of
+    /// measurement.
+    pub fn remaining_burst_capacity(&self) -> u32 {
+        self.remaining
+    }
+}
+
+/// The parameters of the GCRA.
+///
+/// These live in atomic integers, so that rate limiters that adjust their quota at runtime
+/// (e.g. the [`AdaptiveRateLimiter`][crate::AdaptiveRateLimiter]) can do so without requiring
+/// exclusive access.
 pub(crate) struct Gcra {
     // The "weight" of a single packet in units of time.
-    t: Nanos,
+    t: AtomicU64,
 
     // The "capacity" of the bucket.
-    tau: Nanos,
+    tau: AtomicU64,
+
+    // Whether new states start with an empty bucket.
+    start_empty: bool,
 }
 
 impl Gcra {
-    pub(crate) fn new(quota: Quota) -> Self {
-        let tau: Nanos = (quota.replenish_1_per * quota.max_burst.get()).into();
-        let t: Nanos = quota.replenish_1_per.into();
-        Gcra { tau, t }
+    pub(crate) const fn new(quota: Quota) -> Self {
+        let (t, tau) = Self::params(quota);
+        Gcra {
+            t: AtomicU64::new(t),
+            tau: AtomicU64::new(tau),
+            start_empty: quota.start_empty,
+        }
+    }
+
+    /// Computes the weight of a single cell and the capacity of the bucket for `quota`.
+    ///
+    /// The quota that lets every cell through is represented by a weight of zero, and the one
+    /// that lets no cells through by a capacity of zero; neither can occur for other quotas,
+    /// whose replenishment intervals are at least one nanosecond.
+    const fn params(quota: Quota) -> (u64, u64) {
+        if quota.is_unlimited() {
+            (0, u64::MAX)
+        } else if quota.is_none() {
+            (u64::MAX, 0)
+        } else {
+            let t = quota.replenish_1_per.as_nanos();
+            let tau = t * quota.max_burst.get() as u128;
+            assert!(tau <= u64::MAX as u128, "Duration is longer than 584 years");
+            (t as u64, tau as u64)
+        }
+    }
+
+    /// The weight of a single cell.
+    pub(crate) fn t(&self) -> Nanos {
+        self.t.load(Ordering::Relaxed).into()
+    }
+
+    /// The capacity of the bucket.
+    pub(crate) fn tau(&self) -> Nanos {
+        self.tau.load(Ordering::Relaxed).into()
+    }
+
+    /// Returns the quota corresponding to the current parameters.
+    ///
+    /// If the bucket's capacity is not a whole multiple of a single cell's weight, the burst
+    /// size is rounded down (but never to less than 1 cell).
+    pub(crate) fn quota(&self) -> Quota {
+        let (t, tau) = (self.t(), self.tau());
+        let sentinel = if is_unlimited(t) {
+            Some(Quota::unlimited())
+        } else if is_none(tau) {
+            Some(Quota::none())
+        } else {
+            None
+        };
+        if let Some(quota) = sentinel {
+            return Quota {
+                start_empty: self.start_empty,
+                ..quota
+            };
+        }
+        let burst = (tau / t).clamp(1, u32::MAX as u64) as u32;
+        Quota {
+            max_burst: NonZeroU32::new(burst).unwrap(),
+            replenish_1_per: t.into(),
+            start_empty: self.start_empty,
+            limit: Limit::Rate,
+        }
+    }
+
+    /// Takes a snapshot of a state with the theoretical arrival time `tat`, at time `t0`.
+    pub(crate) fn snapshot(&self, tat: Option<Nanos>, t0: Nanos) -> StateSnapshot {
+        self.snapshot_with(self.t(), self.tau(), tat, t0)
+    }
+
+    fn snapshot_with(&self, t: Nanos, tau: Nanos, tat: Option<Nanos>, t0: Nanos) -> StateSnapshot {
+        StateSnapshot {
+            t,
+            tau,
+            tat,
+            time_of_measurement: t0,
+            remaining: self.remaining_cells_with(t, tau, tat, t0),
+        }
+    }
+
+    /// Returns the number of cells that could be let through at time `t0` (measured from the
+    /// rate limiter's start), given the theoretical arrival time `tat` of a state.
+    pub(crate) fn remaining_cells(&self, tat: Option<Nanos>, t0: Nanos) -> u32 {
+        self.remaining_cells_with(self.t(), self.tau(), tat, t0)
+    }
+
+    fn remaining_cells_with(&self, t: Nanos, tau: Nanos, tat: Option<Nanos>, t0: Nanos) -> u32 {
+        if is_unlimited(t) {
+            return u32::MAX;
+        } else if is_none(tau) {
+            return 0;
+        }
+        let burst = tau / t;
+        let tat = tat.unwrap_or_else(|| self.starting_state(t0, t, tau));
+        let backlog = tat.saturating_sub(t0);
+        let remaining = if backlog > tau {
+            0
+        } else {
+            (tau.saturating_sub(backlog) / t + 1).min(burst)
+        };
+        remaining.min(u32::MAX as u64) as u32
+    }
+
+    /// Adjusts the weight of a single cell using the given function, leaving the bucket's
+    /// capacity as it is.
+    ///
+    /// Decisions that are currently in progress may still use the previous weight.
+    pub(crate) fn update_t(&self, f: impl Fn(Nanos) -> Nanos) {
+        let mut prev = self.t.load(Ordering::Acquire);
+        while let Err(next_prev) = self.t.compare_exchange_weak(
+            prev,
+            f(prev.into()).into(),
+            Ordering::Release,
+            Ordering::Relaxed,
+        ) {
+            prev = next_prev;
+        }
+    }
+
+    /// Replaces the parameters with the ones computed from `quota`, if they differ.
+    ///
+    /// Whether new states start out empty is determined when the rate limiter is constructed,
+    /// and is not changed by this method.
+    ///
+    /// Decisions that are currently in progress may still use the previous parameters, or a
+    /// mix of the previous and new parameters.
+    pub(crate) fn set_quota(&self, quota: Quota) {
+        let (t, tau) = Self::params(quota);
+        if self.tau.load(Ordering::Relaxed) != tau {
+            self.tau.store(tau, Ordering::Relaxed);
+        }
+        if self.t.load(Ordering::Relaxed) != t {
+            self.t.store(t, Ordering::Relaxed);
+        }
+    }
+
+    /// Rolls the state at `key` back by the weight of `cells` cells, as of `t0`, so that they
+    /// can be let through on top of the cells that replenished.
+    ///
+    /// The state is rolled back at most to that of a full bucket (which a fresh state has), so
+    /// the bucket's capacity is never exceeded.
+    pub(crate) fn grant_bonus<K>(
+        &self,
+        key: &K,
+        state: &impl StateStore<Key = K>,
+        t0: Nanos,
+        cells: NonZeroU32,
+    ) {
+        let (t, tau) = (self.t(), self.tau());
+        if is_unlimited(t) || is_none(tau) {
+            return;
+        }
+        let weight = Nanos::new(t.as_u64().saturating_mul(u64::from(cells.get())));
+        let full = t0 + t;
+        let _ = state.measure_and_replace(key, |tat| {
+            let tat = tat.unwrap_or_else(|| self.starting_state(t0, t, tau));
+            let rolled_back = cmp::max(tat.saturating_sub(weight), full);
+            Ok::<_, Infallible>(((), cmp::min(tat, rolled_back)))
+        });
     }
 
     /// Computes and returns a new ratelimiter state if none exists yet.
-    fn starting_state(&self, t0: Nanos) -> Nanos {
-        t0 + self.t
+    ///
+    /// An empty state's theoretical arrival time lies so far in the future that the first cell
+    /// conforms only after a single cell's weight has passed.
+    fn starting_state(&self, t0: Nanos, t: Nanos, tau: Nanos) -> Nanos {
+        if self.start_empty {
+            t0 + tau + t
+        } else {
+            t0 + t
+        }
+    }
+
+    /// Returns the negative outcome for cells that can never conform: those of a rate limiter
+    /// that lets no cells through, and those that the state store [rejects](reject).
+    fn never<P: clock::Reference>(&self, start: P, t: Nanos, tau: Nanos, t0: Nanos) -> NotUntil<P> {
+        NotUntil {
+            state: StateSnapshot {
+                t,
+                tau,
+                tat: None,
+                time_of_measurement: t0,
+                remaining: 0,
+            },
+            tat: t0 + NEVER,
+            start,
+        }
+    }
+
+    /// Returns a negative outcome, as of `t0`, for cells that can never conform.
+    pub(crate) fn never_at<P: clock::Reference>(&self, start: P, t0: P) -> NotUntil<P> {
+        self.never(start, self.t(), self.tau(), t0.duration_since(start))
     }
 
     /// Tests a single cell against the rate limiter state and updates it at the given key.
+    ///
+    /// Also returns whether the state store held no state for the key before the decision.
     pub(crate) fn test_and_update<K, P: clock::Reference>(
         &self,
         start: P,
         key: &K,
         state: &impl StateStore<Key = K>,
         t0: P,
-    ) -> Result<(), NotUntil<P>> {
+    ) -> (Result<(), NotUntil<P>>, bool) {
         let t0 = t0.duration_since(start);
-        let tau = self.tau;
-        let t = self.t;
-        state.measure_and_replace(key, |tat| {
-            let tat = tat.unwrap_or_else(|| self.starting_state(t0));
-            let earliest_time = tat.saturating_sub(tau);
-            if t0 < earliest_time {
-                Err(NotUntil {
-                    limiter: self,
-                    tat: earliest_time,
-                    start,
-                })
-            } else {
-                Ok(((), cmp::max(tat, t0) + t))
-            }
-        })
+        let (decision, fresh) = self.decide(start, key, state, t0);
+        state.record_decision(key, decision.is_ok(), t0);
+        (decision, fresh)
+    }
+
+    fn decide<K, P: clock::Reference>(
+        &self,
+        start: P,
+        key: &K,
+        state: &impl StateStore<Key = K>,
+        t0: Nanos,
+    ) -> (Result<(), NotUntil<P>>, bool) {
+        let tau = self.tau();
+        let t = self.t();
+        if is_unlimited(t) {
+            return (Ok(()), false);
+        } else if is_none(tau) {
+            return (Err(self.never(start, t, tau, t0)), false);
+        }
+        state
+            .measure_and_replace(key, |tat| {
+                if tat == Some(REJECTED) {
+                    return Err(self.never(start, t, tau, t0));
+                }
+                let fresh = tat.is_none();
+                let tat = tat.unwrap_or_else(|| self.starting_state(t0, t, tau));
+                let earliest_time = tat.saturating_sub(tau);
+                if t0 < earliest_time {
+                    let negative = NotUntil {
+                        state: self.snapshot_with(t, tau, Some(tat), t0),
+                        tat: earliest_time,
+                        start,
+                    };
+                    if fresh {
+                        // Remember the (empty) starting state, so that it can fill up:
+                        Ok(((Err(negative), true), tat))
+                    } else {
+                        Err(negative)
+                    }
+                } else {
+                    Ok(((Ok(()), fresh), cmp::max(tat, t0) + t))
+                }
+            })
+            .unwrap_or_else(|negative| (Err(negative), false))
     }
 
     /// Tests whether all `n` cells could be accommodated and updates the rate limiter state, if so.
@@ -117,32 +438,114 @@ impl Gcra {
         t0: P,
     ) -> Result<(), NegativeMultiDecision<NotUntil<P>>> {
         let t0 = t0.duration_since(start);
-        let tau = self.tau;
-        let t = self.t;
+        let decision = self.decide_n(start, key, n, state, t0);
+        state.record_decision(key, decision.is_ok(), t0);
+        decision
+    }
+
+    /// Tests whether all `n` cells could be accommodated and updates the rate limiter state, if
+    /// so, without recording the decision with the state store.
+    pub(crate) fn decide_n<K, P: clock::Reference>(
+        &self,
+        start: P,
+        key: &K,
+        n: NonZeroU32,
+        state: &impl StateStore<Key = K>,
+        t0: Nanos,
+    ) -> Result<(), NegativeMultiDecision<NotUntil<P>>> {
+        let tau = self.tau();
+        let t = self.t();
+        if is_unlimited(t) {
+            return Ok(());
+        } else if is_none(tau) {
+            return Err(NegativeMultiDecision::InsufficientCapacity(
+                InsufficientCapacity::new(n, 0, self.quota()),
+            ));
+        }
         let additional_weight = t * (n.get() - 1) as u64;
 
         // check that we can allow enough cells through. Note that `additional_weight` is the
         // value of the cells *in addition* to the first cell - so add that first cell back.
         if additional_weight + t > tau {
             return Err(NegativeMultiDecision::InsufficientCapacity(
-                (tau.as_u64() / t.as_u64()) as u32,
+                InsufficientCapacity::new(n, (tau.as_u64() / t.as_u64()) as u32, self.quota()),
             ));
         }
-        state.measure_and_replace(key, |tat| {
-            let tat = tat.unwrap_or_else(|| self.starting_state(t0));
-            let earliest_time = (tat + additional_weight).saturating_sub(tau);
-            if t0 < earliest_time {
-                Err(NegativeMultiDecision::BatchNonConforming(
-                    n.get(),
-                    NotUntil {
-                        limiter: self,
-                        tat: earliest_time,
-                        start,
-                    },
-                ))
-            } else {
-                Ok(((), cmp::max(tat, t0) + t + additional_weight))
-            }
-        })
+        state
+            .measure_and_replace(key, |tat| {
+                if tat == Some(REJECTED) {
+                    return Err(NegativeMultiDecision::BatchNonConforming(
+                        n.get(),
+                        self.never(start, t, tau, t0),
+                    ));
+                }
+                let fresh = tat.is_none();
+                let tat = tat.unwrap_or_else(|| self.starting_state(t0, t, tau));
+                let earliest_time = (tat + additional_weight).saturating_sub(tau);
+                if t0 < earliest_time {
+                    let negative = NegativeMultiDecision::BatchNonConforming(
+                        n.get(),
+                        NotUntil {
+                            state: self.snapshot_with(t, tau, Some(tat), t0),
+                            tat: earliest_time,
+                            start,
+                        },
+                    );
+                    if fresh {
+                        // Remember the (empty) starting state, so that it can fill up:
+                        Ok((Err(negative), tat))
+                    } else {
+                        Err(negative)
+                    }
+                } else {
+                    Ok((Ok(()), cmp::max(tat, t0) + t + additional_weight))
+                }
+            })
+            .and_then(|decision| decision)
+    }
+}
+
+/// How far in the future the negative outcomes for cells that can never conform indicate the
+/// next conforming decision.
+const NEVER: Nanos = Nanos::new(u64::MAX / 2);
+
+/// The state that [`reject`] hands to decisions. No state that was measured can reach it.
+const REJECTED: Nanos = Nanos::new(u64::MAX);
+
+/// Rejects the decision `f` for a key that the state store can't hold a state for, without
+/// remembering anything.
+///
+/// The rate limiter's decisions recognize the state that they get handed, and reject their
+/// cells as if the quota let no cells through, regardless of the bucket's capacity.
+pub(crate) fn reject<T, F, E>(f: F) -> Result<T, E>
+where
+    F: Fn(Option<Nanos>) -> Result<(T, Nanos), E>,
+{
+    f(Some(REJECTED)).map(|(result, _)| result)
+}
+
+/// Whether the weight of a single cell is that of the quota that lets every cell through.
+fn is_unlimited(t: Nanos) -> bool {
+    t == Nanos::new(0)
+}
+
+/// Whether the capacity of the bucket is that of the quota that lets no cells through.
+fn is_none(tau: Nanos) -> bool {
+    tau == Nanos::new(0)
+}
+
+impl fmt::Debug for Gcra {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        f.debug_struct("Gcra")
+            .field("t", &self.t())
+            .field("tau", &self.tau())
+            .field("start_empty", &self.start_empty)
+            .finish()
+    }
+}
+
+impl PartialEq for Gcra {
+    fn eq(&self, other: &Self) -> bool {
+        self.t() == other.t() && self.tau() == other.tau() && self.start_empty == other.start_empty
     }
 }
@@ -0,0 +1,170 @@
+//! Cheaply cloneable, owned handles to rate limiters.
+
+use std::prelude::v1::*;
+
+use crate::clock;
+use crate::state::{RateLimiter, StateStore};
+use std::fmt;
+use std::ops::Deref;
+use std::sync::Arc;
+
+/// An owned, cheaply cloneable handle to a [`RateLimiter`].
+///
+/// Rate limiters are meant to be shared: All clones of a handle make their rate limiting
+/// decisions against the same state. Handles are `'static` (as long as the rate limiter's
+/// components are), so they can be moved into spawned tasks or threads, or stored in long-lived
+/// service structs, without wrapping the rate limiter in an [`Arc`] by hand.
+///
+/// Handles dereference to the rate limiter they point to, so all of the rate limiter's methods
+/// can be called on them directly.
+///
+/// # Example
+/// ```rust
+/// # #[cfg(feature = "std")] fn main() {
+/// # use governor::{Quota, RateLimiter};
+/// # use nonzero_ext::nonzero;
+/// # use std::thread;
+/// let lim = RateLimiter::keyed(Quota::per_second(nonzero!(5u32))).into_handle();
+/// let handles: Vec<_> = (0..5)
+///     .map(|_| {
+///         let lim = lim.clone();
+///         thread::spawn(move || lim.check_key(&"customer").is_ok())
+///     })
+///     .collect();
+/// for h in handles {
+///     assert!(h.join().unwrap());
+/// }
+/// assert!(lim.check_key(&"customer").is_err());
+/// # } #[cfg(not(feature = "std"))] fn main() {}
+/// ```
+pub struct RateLimiterHandle<K, S, C>
+where
+    S: StateStore<Key = K>,
+    C: clock::Clock,
+{
+    limiter: Arc<RateLimiter<K, S, C>>,
+}
+
+impl<K, S, C> RateLimiterHandle<K, S, C>
+where
+    S: StateStore<Key = K>,
+    C: clock::Clock,
+{
+    /// Returns `true` if both handles point to the same rate limiter.
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.limiter, &other.limiter)
+    }
+
+    /// Consumes the handle, returning the [`Arc`] that holds the rate limiter.
+    pub fn into_arc(self) -> Arc<RateLimiter<K, S, C>> {
+        self.limiter
+    }
+}
+
+impl<K, S, C> Clone for RateLimiterHandle<K, S, C>
+where
+    S: StateStore<Key = K>,
+    C: clock::Clock,
+{
+    fn clone(&self) -> Self {
+        RateLimiterHandle {
+            limiter: Arc::clone(&self.limiter),
+        }
+    }
+}
+
+impl<K, S, C> Deref for RateLimiterHandle<K, S, C>
+where
+    S: StateStore<Key = K>,
+    C: clock::Clock,
+{
+    type Target = RateLimiter<K, S, C>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.limiter
+    }
+}
+
+impl<K, S, C> AsRef<RateLimiter<K, S, C>> for RateLimiterHandle<K, S, C>
+where
+    S: StateStore<Key = K>,
+    C: clock::Clock,
+{
+    fn as_ref(&self) -> &RateLimiter<K, S, C> {
+        &self.limiter
+    }
+}
+
+impl<K, S, C> From<RateLimiter<K, S, C>> for RateLimiterHandle<K, S, C>
+where
+    S: StateStore<Key = K>,
+    C: clock::Clock,
+{
+    fn from(limiter: RateLimiter<K, S, C>) -> Self {
+        RateLimiterHandle {
+            limiter: Arc::new(limiter),
+        }
+    }
+}
+
+impl<K, S, C> From<Arc<RateLimiter<K, S, C>>> for RateLimiterHandle<K, S, C>
+where
+    S: StateStore<Key = K>,
+    C: clock::Clock,
+{
+    fn from(limiter: Arc<RateLimiter<K, S, C>>) -> Self {
+        RateLimiterHandle { limiter }
+    }
+}
+
+impl<K, S, C> fmt::Debug for RateLimiterHandle<K, S, C>
+where
+    S: StateStore<Key = K> + fmt::Debug,
+    C: clock::Clock + fmt::Debug,
+    K: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        f.debug_tuple("RateLimiterHandle")
+            .field(&self.limiter)
+            .finish()
+    }
+}
+
+impl<K, S, C> RateLimiter<K, S, C>
+where
+    S: StateStore<Key = K>,
+    C: clock::Clock,
+{
+    /// Moves the rate limiter into a cheaply cloneable [`RateLimiterHandle`].
+    pub fn into_handle(self) -> RateLimiterHandle<K, S, C> {
+        RateLimiterHandle::from(self)
+    }
+}
+
+/// A rate limiter that is either borrowed or held through a handle, as used by the combinators
+/// that come in both borrowing and owning variants.
+#[cfg(feature = "std")]
+pub(crate) enum LimiterRef<'a, K, S, C>
+where
+    S: StateStore<Key = K>,
+    C: clock::Clock,
+{
+    Borrowed(&'a RateLimiter<K, S, C>),
+    Owned(RateLimiterHandle<K, S, C>),
+}
+
+#[cfg(feature = "std")]
+impl<'a, K, S, C> Deref for LimiterRef<'a, K, S, C>
+where
+    S: StateStore<Key = K>,
+    C: clock::Clock,
+{
+    type Target = RateLimiter<K, S, C>;
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            LimiterRef::Borrowed(limiter) => limiter,
+            LimiterRef::Owned(handle) => handle,
+        }
+    }
+}
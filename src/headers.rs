@@ -0,0 +1,191 @@
+//! Helpers for communicating rate limits to HTTP clients.
+//!
+//! Web services commonly tell their clients about the rate limits they enforce by way of
+//! response headers. This module computes the values of the most common of these headers from
+//! the outcome of a rate limiting decision, so that every web framework integration built on
+//! governor reports them the same way:
+//!
+//! * [`Retry-After`](https://tools.ietf.org/html/rfc7231#section-7.1.3), in seconds (only on
+//!   negative outcomes),
+//! * `X-RateLimit-Limit`, the maximum burst size,
+//! * `X-RateLimit-Remaining`, the number of cells that could be let through right now,
+//! * `X-RateLimit-Reset`, the number of seconds until the full burst capacity is available
+//!   again, and
+//! * `RateLimit-Policy`, in the format of the IETF
+//!   [RateLimit header fields draft](https://datatracker.ietf.org/doc/draft-ietf-httpapi-ratelimit-headers/).
+//!
+//! All durations are rounded up to the next full second, so that clients that wait the
+//! advertised amount of time don't get rate-limited again.
+//!
+//! # Example
+//!
+//! ```rust
+//! # use governor::{Quota, RateLimiter, clock::FakeRelativeClock, headers::RateLimitHeaders};
+//! # use nonzero_ext::nonzero;
+//! # use std::time::Duration;
+//! let clock = FakeRelativeClock::default();
+//! let lim = RateLimiter::direct_with_clock(Quota::per_minute(nonzero!(2u32)), &clock);
+//!
+//! lim.check().unwrap();
+//! let headers = RateLimitHeaders::from_snapshot(&lim.state_snapshot());
+//! assert_eq!(headers.remaining(), 1);
+//! assert_eq!(headers.retry_after(), None);
+//!
+//! lim.check().unwrap();
+//! let headers = RateLimitHeaders::from_not_until(&lim.check().unwrap_err());
+//! assert_eq!(
+//!     headers.header_values(),
+//!     vec![
+//!         ("Retry-After", "30".to_string()),
+//!         ("X-RateLimit-Limit", "2".to_string()),
+//!         ("X-RateLimit-Remaining", "0".to_string()),
+//!         ("X-RateLimit-Reset", "60".to_string()),
+//!         ("RateLimit-Policy", "2;w=60".to_string()),
+//!     ]
+//! );
+//! ```
+
+use std::prelude::v1::*;
+
+use crate::{clock, NotUntil, StateSnapshot};
+use std::time::Duration;
+
+/// The name of the `Retry-After` header.
+pub const RETRY_AFTER: &str = "Retry-After";
+
+/// The name of the `X-RateLimit-Limit` header.
+pub const X_RATELIMIT_LIMIT: &str = "X-RateLimit-Limit";
+
+/// The name of the `X-RateLimit-Remaining` header.
+pub const X_RATELIMIT_REMAINING: &str = "X-RateLimit-Remaining";
+
+/// The name of the `X-RateLimit-Reset` header.
+pub const X_RATELIMIT_RESET: &str = "X-RateLimit-Reset";
+
+/// The name of the `RateLimit-Policy` header.
+pub const RATELIMIT_POLICY: &str = "RateLimit-Policy";
+
+/// The rate limiting information to communicate to a client, computed from the outcome of a
+/// rate limiting decision.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct RateLimitHeaders {
+    limit: u32,
+    remaining: u32,
+    reset: Duration,
+    window: Duration,
+    retry_after: Option<Duration>,
+}
+
+impl RateLimitHeaders {
+    /// Computes the headers from a snapshot of a rate limiting state, e.g. one taken right
+    /// after a positive decision.
+    ///
+    /// Since the snapshot doesn't indicate a negative outcome, no `Retry-After` value is
+    /// reported.
+    pub fn from_snapshot(snapshot: &StateSnapshot) -> RateLimitHeaders {
+        let t = snapshot.emission_interval();
+        let tau = snapshot.tolerance();
+        let limit = (tau.as_nanos() / t.as_nanos().max(1)).clamp(1, u32::MAX as u128) as u32;
+        // The state is fully replenished once the theoretical arrival time, minus the
+        // emission interval of the cell it would admit, has passed:
+        let reset = snapshot
+            .theoretical_arrival_time()
+            .map(|tat| {
+                let full = Duration::from(tat).checked_sub(t).unwrap_or_default();
+                full.checked_sub(snapshot.time_of_measurement().into())
+                    .unwrap_or_default()
+            })
+            .unwrap_or_default();
+        RateLimitHeaders {
+            limit,
+            remaining: snapshot.remaining_burst_capacity(),
+            reset,
+            window: tau,
+            retry_after: None,
+        }
+    }
+
+    /// Computes the headers from a negative rate limiting outcome.
+    pub fn from_not_until<P: clock::Reference>(not_until: &NotUntil<P>) -> RateLimitHeaders {
+        RateLimitHeaders {
+            retry_after: Some(not_until.retry_after()),
+            ..RateLimitHeaders::from_snapshot(&not_until.state_snapshot())
+        }
+    }
+
+    /// Returns the maximum number of cells that can be let through in a burst.
+    pub fn limit(&self) -> u32 {
+        self.limit
+    }
+
+    /// Returns the number of cells that could be let through at the time of the decision.
+    pub fn remaining(&self) -> u32 {
+        self.remaining
+    }
+
+    /// Returns the time that must pass after the decision until the full burst capacity is
+    /// available again.
+    pub fn reset(&self) -> Duration {
+        self.reset
+    }
+
+    /// Returns the time it takes to replenish the full burst capacity: The policy's "window".
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    /// Returns the time that the client must wait before its next request can be let through,
+    /// if the decision was negative.
+    pub fn retry_after(&self) -> Option<Duration> {
+        self.retry_after
+    }
+
+    /// Returns the value of the `Retry-After` header, if the decision was negative.
+    pub fn retry_after_value(&self) -> Option<String> {
+        self.retry_after.map(|d| ceil_secs(d).to_string())
+    }
+
+    /// Returns the value of the `X-RateLimit-Limit` header.
+    pub fn limit_value(&self) -> String {
+        self.limit.to_string()
+    }
+
+    /// Returns the value of the `X-RateLimit-Remaining` header.
+    pub fn remaining_value(&self) -> String {
+        self.remaining.to_string()
+    }
+
+    /// Returns the value of the `X-RateLimit-Reset` header, in seconds.
+    pub fn reset_value(&self) -> String {
+        ceil_secs(self.reset).to_string()
+    }
+
+    /// Returns the value of the `RateLimit-Policy` header, e.g. `10;w=60` for a quota of
+    /// 10 cells per minute.
+    pub fn policy_value(&self) -> String {
+        format!("{};w={}", self.limit, ceil_secs(self.window))
+    }
+
+    /// Returns all applicable headers as pairs of header name and value, in a stable order.
+    /// The `Retry-After` header is only included if the decision was negative.
+    pub fn header_values(&self) -> Vec<(&'static str, String)> {
+        let mut headers = Vec::with_capacity(5);
+        if let Some(retry_after) = self.retry_after_value() {
+            headers.push((RETRY_AFTER, retry_after));
+        }
+        headers.push((X_RATELIMIT_LIMIT, self.limit_value()));
+        headers.push((X_RATELIMIT_REMAINING, self.remaining_value()));
+        headers.push((X_RATELIMIT_RESET, self.reset_value()));
+        headers.push((RATELIMIT_POLICY, self.policy_value()));
+        headers
+    }
+}
+
+/// Rounds a duration up to the next full second.
+fn ceil_secs(d: Duration) -> u64 {
+    if d.subsec_nanos() > 0 {
+        d.as_secs() + 1
+    } else {
+        d.as_secs()
+    }
+}
@@ -6,8 +6,11 @@ use rand::distributions::uniform::{SampleBorrow, SampleUniform, UniformInt, Unif
 #[cfg(feature = "jitter")]
 use rand::distributions::{Distribution, Uniform};
 #[cfg(feature = "jitter")]
-use rand::{thread_rng, Rng};
+use rand::{thread_rng, Rng, RngCore};
+use std::fmt;
 use std::ops::Add;
+#[cfg(feature = "jitter")]
+use std::sync::Arc;
 use std::time::Duration;
 
 #[cfg(feature = "std")]
@@ -57,27 +60,184 @@ use std::time::Instant;
 /// # }
 /// # #[cfg(any(not(feature = "jitter"), not(feature = "std")))] fn main() {}
 /// ```
-#[derive(Debug, PartialEq, Default, Clone, Copy)]
+///
+/// # Jitter strategies
+///
+/// Besides a uniformly distributed amount of jitter, a few strategies commonly used to spread
+/// out retries are available. These depend on how often a wait had to be retried: Whenever
+/// a task waiting on a rate limiter (e.g. in
+/// [`until_ready_with_jitter`](struct.RateLimiter.html#method.until_ready_with_jitter))
+/// wakes up and gets rate-limited again, it is one attempt further along:
+///
+/// * [`exponential`](#method.exponential) backoff waits `base * 2^attempt` (at most `cap`),
+/// * [`full`](#method.full) jitter waits a uniformly random duration between zero and the
+///   exponential backoff,
+/// * [`equal`](#method.equal) jitter waits half the exponential backoff, plus a uniformly random
+///   duration of up to the other half,
+/// * [`decorrelated`](#method.decorrelated) jitter waits a uniformly random duration between
+///   `base` and three times the previous wait (at most `cap`).
+///
+/// In all cases, the jitter is added to the time the rate limiter indicates the task must
+/// wait. Arbitrary random distributions can be used via
+/// [`from_distribution`](#method.from_distribution).
+///
+/// ```rust
+/// # #[cfg(feature = "jitter")]
+/// # fn main() {
+/// # use governor::Jitter;
+/// # use std::time::Duration;
+/// let reference = Duration::from_secs(24);
+/// let jitter = Jitter::equal(Duration::from_millis(100), Duration::from_secs(5));
+/// // The first attempt gets between 50ms and 100ms of jitter:
+/// let result = jitter + reference;
+/// assert!(result >= reference + Duration::from_millis(50));
+/// assert!(result <= reference + Duration::from_millis(100));
+/// # }
+/// # #[cfg(not(feature = "jitter"))]
+/// # fn main() {}
+/// ```
+#[derive(Debug, Clone, PartialEq, Default)]
 #[cfg_attr(feature = "docs", doc(cfg(jitter)))]
 pub struct Jitter {
-    min: Nanos,
-    max: Nanos,
+    kind: Kind,
+}
+
+#[derive(Clone)]
+enum Kind {
+    Uniform {
+        min: Nanos,
+        max: Nanos,
+    },
+
+    #[cfg(feature = "jitter")]
+    Exponential {
+        base: Nanos,
+        cap: Nanos,
+    },
+
+    #[cfg(feature = "jitter")]
+    Full {
+        base: Nanos,
+        cap: Nanos,
+    },
+
+    #[cfg(feature = "jitter")]
+    Equal {
+        base: Nanos,
+        cap: Nanos,
+    },
+
+    #[cfg(feature = "jitter")]
+    Decorrelated {
+        base: Nanos,
+        cap: Nanos,
+    },
+
+    #[cfg(feature = "jitter")]
+    Distribution(Arc<dyn DynDistribution>),
+}
+
+impl Default for Kind {
+    fn default() -> Self {
+        Kind::Uniform {
+            min: Nanos::from(0),
+            max: Nanos::from(0),
+        }
+    }
+}
+
+impl fmt::Debug for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            Kind::Uniform { min, max } => f
+                .debug_struct("Uniform")
+                .field("min", min)
+                .field("max", max)
+                .finish(),
+            #[cfg(feature = "jitter")]
+            Kind::Exponential { base, cap } => f
+                .debug_struct("Exponential")
+                .field("base", base)
+                .field("cap", cap)
+                .finish(),
+            #[cfg(feature = "jitter")]
+            Kind::Full { base, cap } => f
+                .debug_struct("Full")
+                .field("base", base)
+                .field("cap", cap)
+                .finish(),
+            #[cfg(feature = "jitter")]
+            Kind::Equal { base, cap } => f
+                .debug_struct("Equal")
+                .field("base", base)
+                .field("cap", cap)
+                .finish(),
+            #[cfg(feature = "jitter")]
+            Kind::Decorrelated { base, cap } => f
+                .debug_struct("Decorrelated")
+                .field("base", base)
+                .field("cap", cap)
+                .finish(),
+            #[cfg(feature = "jitter")]
+            Kind::Distribution(_) => f.write_str("Distribution(..)"),
+        }
+    }
+}
+
+/// Jitter strategies are equal if they have the same parameters. Jitter constructed from
+/// a random distribution is only equal to its clones.
+impl PartialEq for Kind {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (
+                Kind::Uniform { min, max },
+                Kind::Uniform {
+                    min: omin,
+                    max: omax,
+                },
+            ) => min == omin && max == omax,
+            #[cfg(feature = "jitter")]
+            (Kind::Exponential { base, cap }, Kind::Exponential { base: ob, cap: oc })
+            | (Kind::Full { base, cap }, Kind::Full { base: ob, cap: oc })
+            | (Kind::Equal { base, cap }, Kind::Equal { base: ob, cap: oc })
+            | (Kind::Decorrelated { base, cap }, Kind::Decorrelated { base: ob, cap: oc }) => {
+                base == ob && cap == oc
+            }
+            #[cfg(feature = "jitter")]
+            (Kind::Distribution(d), Kind::Distribution(od)) => Arc::ptr_eq(d, od),
+            #[cfg(feature = "jitter")]
+            _ => false,
+        }
+    }
+}
+
+/// The progress of a single wait that applies jitter: How often the wait had to be retried,
+/// and how long the previous wait took.
+#[derive(Debug, Default, Clone, Copy)]
+#[cfg_attr(not(feature = "jitter"), allow(dead_code))]
+pub(crate) struct JitterState {
+    attempt: u32,
+    previous: Option<Nanos>,
 }
 
 impl Jitter {
     #[cfg(feature = "std")]
     /// The "empty" jitter interval - no jitter at all.
     pub(crate) const NONE: Jitter = Jitter {
-        min: Nanos::new(0),
-        max: Nanos::new(0),
+        kind: Kind::Uniform {
+            min: Nanos::new(0),
+            max: Nanos::new(0),
+        },
     };
 
     /// Constructs a new Jitter interval, waiting at most a duration of `max`.
     #[cfg(feature = "jitter")]
     pub fn up_to(max: Duration) -> Jitter {
         Jitter {
-            min: Nanos::from(0),
-            max: max.into(),
+            kind: Kind::Uniform {
+                min: Nanos::from(0),
+                max: max.into(),
+            },
         }
     }
 
@@ -86,24 +246,162 @@ impl Jitter {
     pub fn new(min: Duration, interval: Duration) -> Jitter {
         let min: Nanos = min.into();
         let max: Nanos = min + Nanos::from(interval);
-        Jitter { min, max }
+        Jitter {
+            kind: Kind::Uniform { min, max },
+        }
     }
 
-    /// Returns a random amount of jitter within the configured interval.
+    /// Constructs an exponential backoff, waiting `base * 2^attempt`, but at most `cap`.
     #[cfg(feature = "jitter")]
-    pub(crate) fn get(&self) -> Nanos {
-        if self.min == self.max {
-            return self.min;
+    pub fn exponential(base: Duration, cap: Duration) -> Jitter {
+        Jitter {
+            kind: Kind::Exponential {
+                base: base.into(),
+                cap: cap.into(),
+            },
         }
-        let uniform = Uniform::new(self.min, self.max);
-        uniform.sample(&mut thread_rng())
     }
 
-    /// Returns a random amount of jitter within the configured interval.
-    #[cfg(not(feature = "jitter"))]
+    /// Constructs a "full" jitter strategy, waiting a uniformly random duration between zero and
+    /// `base * 2^attempt` (but at most `cap`).
+    #[cfg(feature = "jitter")]
+    pub fn full(base: Duration, cap: Duration) -> Jitter {
+        Jitter {
+            kind: Kind::Full {
+                base: base.into(),
+                cap: cap.into(),
+            },
+        }
+    }
+
+    /// Constructs an "equal" jitter strategy, waiting half of `base * 2^attempt` (but at most
+    /// `cap`), plus a uniformly random duration of up to the other half.
+    #[cfg(feature = "jitter")]
+    pub fn equal(base: Duration, cap: Duration) -> Jitter {
+        Jitter {
+            kind: Kind::Equal {
+                base: base.into(),
+                cap: cap.into(),
+            },
+        }
+    }
+
+    /// Constructs a "decorrelated" jitter strategy, waiting a uniformly random duration between
+    /// `base` and three times the previous wait (but at most `cap`).
+    #[cfg(feature = "jitter")]
+    pub fn decorrelated(base: Duration, cap: Duration) -> Jitter {
+        Jitter {
+            kind: Kind::Decorrelated {
+                base: base.into(),
+                cap: cap.into(),
+            },
+        }
+    }
+
+    /// Constructs jitter that samples its amount from an arbitrary random distribution of
+    /// durations.
+    ///
+    /// ```rust
+    /// # #[cfg(feature = "jitter")]
+    /// # fn main() {
+    /// # use governor::Jitter;
+    /// # use std::time::Duration;
+    /// use rand::distributions::{Distribution, Standard};
+    ///
+    /// // Up to 1.024s of jitter, with a bias towards shorter waits:
+    /// let skewed = Standard.map(|x: f64| Duration::from_micros((x * x * 1024.0 * 1000.0) as u64));
+    /// let jitter = Jitter::from_distribution(skewed);
+    /// let reference = Duration::from_secs(1);
+    /// assert!(jitter + reference <= reference + Duration::from_millis(1024));
+    /// # }
+    /// # #[cfg(not(feature = "jitter"))]
+    /// # fn main() {}
+    /// ```
+    #[cfg(feature = "jitter")]
+    pub fn from_distribution<D>(distribution: D) -> Jitter
+    where
+        D: Distribution<Duration> + Send + Sync + 'static,
+    {
+        Jitter {
+            kind: Kind::Distribution(Arc::new(distribution)),
+        }
+    }
+
+    /// Adds the jitter for the next attempt of a wait to the duration `wait`.
+    #[cfg(feature = "std")]
+    pub(crate) fn add_next(&self, state: &mut JitterState, wait: Duration) -> Duration {
+        let amount: Duration = self.next(state).into();
+        wait + amount
+    }
+
+    /// Returns a random amount of jitter for the first attempt of a wait.
     pub(crate) fn get(&self) -> Nanos {
-        self.min
+        self.next(&mut JitterState::default())
+    }
+
+    /// Returns a random amount of jitter for the next attempt of a wait, and records the attempt
+    /// in `state`.
+    #[cfg(feature = "jitter")]
+    pub(crate) fn next(&self, state: &mut JitterState) -> Nanos {
+        let amount = match &self.kind {
+            Kind::Uniform { min, max } => sample_uniform(*min, *max),
+            Kind::Exponential { base, cap } => backoff(*base, *cap, state.attempt),
+            Kind::Full { base, cap } => {
+                sample_uniform(Nanos::from(0), backoff(*base, *cap, state.attempt))
+            }
+            Kind::Equal { base, cap } => {
+                let half = Nanos::from(backoff(*base, *cap, state.attempt).as_u64() / 2);
+                half + sample_uniform(Nanos::from(0), half)
+            }
+            Kind::Decorrelated { base, cap } => {
+                let previous = state.previous.unwrap_or(*base);
+                let max = Nanos::from(previous.as_u64().saturating_mul(3));
+                sample_uniform(*base, max.max(*base)).min(*cap)
+            }
+            Kind::Distribution(distribution) => distribution.sample_dyn(&mut thread_rng()).into(),
+        };
+        state.attempt = state.attempt.saturating_add(1);
+        state.previous = Some(amount);
+        amount
+    }
+
+    /// Returns a random amount of jitter for the next attempt of a wait.
+    #[cfg(not(feature = "jitter"))]
+    pub(crate) fn next(&self, _state: &mut JitterState) -> Nanos {
+        match self.kind {
+            Kind::Uniform { min, .. } => min,
+        }
+    }
+}
+
+/// An object-safe version of [`Distribution`], so that jitter can hold any distribution.
+#[cfg(feature = "jitter")]
+trait DynDistribution: Send + Sync {
+    fn sample_dyn(&self, rng: &mut dyn RngCore) -> Duration;
+}
+
+#[cfg(feature = "jitter")]
+impl<D: Distribution<Duration> + Send + Sync> DynDistribution for D {
+    fn sample_dyn(&self, rng: &mut dyn RngCore) -> Duration {
+        self.sample(rng)
+    }
+}
+
+/// Returns a uniformly random amount of nanoseconds between `min` and `max` (inclusive).
+#[cfg(feature = "jitter")]
+fn sample_uniform(min: Nanos, max: Nanos) -> Nanos {
+    if min >= max {
+        return min;
     }
+    let uniform = Uniform::new_inclusive(min, max);
+    uniform.sample(&mut thread_rng())
+}
+
+/// Returns `base * 2^attempt`, but at most `cap`.
+#[cfg(feature = "jitter")]
+fn backoff(base: Nanos, cap: Nanos, attempt: u32) -> Nanos {
+    let factor = 1u64.checked_shl(attempt).unwrap_or(u64::MAX);
+    Nanos::from(base.as_u64().saturating_mul(factor)).min(cap)
 }
 
 /// A random distribution of nanoseconds
@@ -30,35 +30,74 @@
 #![cfg_attr(not(feature = "std"), no_std)]
 // Clippy config: Deny warnings but allow unknown lint configuration (so I can use nightly)
 #![deny(warnings)]
-#![allow(clippy::unknown_clippy_lints)]
+#![allow(unknown_lints)]
 // Unfortunately necessary, otherwise features aren't supported in doctests:
 #![allow(clippy::needless_doctest_main)]
 
 extern crate no_std_compat as std;
 
 pub mod r#_guide;
+mod adaptive;
+#[cfg(feature = "axum")]
+pub mod axum;
+mod batched;
 pub mod clock;
+#[cfg(feature = "std")]
+mod concurrency;
+mod conforming;
 mod errors;
 mod gcra;
+mod handle;
+pub mod headers;
+#[cfg(any(feature = "std", feature = "jitter"))]
 mod jitter;
-mod nanos;
+pub mod nanos;
 mod quota;
+#[cfg(feature = "std")]
+pub mod retry;
+pub mod schedule;
+mod share;
 pub mod state;
+#[cfg(feature = "std")]
+pub mod test_util;
+#[cfg(feature = "std")]
+mod timer;
+#[cfg(feature = "tonic")]
+pub mod tonic;
 
+pub use adaptive::{AdaptiveRateLimiter, Aimd};
+pub use batched::BatchedRateLimiter;
+#[cfg(feature = "std")]
+pub use concurrency::{ConcurrencyLimiter, ConcurrencyPermit, RateAndConcurrencyLimiter};
+#[cfg(feature = "std")]
+pub use conforming::ConformingTicks;
+pub use conforming::ConformingTimes;
 pub use errors::*;
-pub use gcra::NotUntil;
+pub use gcra::{NotUntil, StateSnapshot};
+pub use handle::RateLimiterHandle;
 #[cfg(feature = "jitter")]
 pub use jitter::Jitter;
 #[cfg(all(not(feature = "std"), feature = "jitter"))]
 pub(crate) use jitter::Jitter;
 pub use quota::Quota;
 #[doc(inline)]
-pub use state::RateLimiter;
+pub use schedule::ScheduledRateLimiter;
+pub use share::QuotaShare;
+#[doc(inline)]
+pub use state::{RateLimiter, RateLimiterBuilder};
 
+#[cfg(feature = "std")]
+pub use state::direct::RatelimitFilter;
 #[cfg(feature = "std")]
 pub use state::direct::RatelimitedSink;
 #[cfg(feature = "std")]
 pub use state::direct::RatelimitedStream;
+#[cfg(feature = "std")]
+pub use state::direct::WaitStats;
+#[cfg(feature = "std")]
+pub use state::direct::{
+    AsyncRateLimitedReader, AsyncRateLimitedWriter, RateLimitedReader, RateLimitedWriter,
+};
 
 /// The collection of asynchronous traits exported from this crate.
 pub mod prelude {
@@ -19,12 +19,9 @@ impl Nanos {
     pub(crate) fn as_u64(self) -> u64 {
         self.0
     }
-}
 
-/// Nanos as used by Jitter and other std-only features.
-#[cfg(feature = "std")]
-impl Nanos {
-    pub(crate) const fn new(u: u64) -> Self {
+    /// Constructs a number of nanoseconds, also in `const` contexts.
+    pub const fn new(u: u64) -> Self {
         Nanos(u)
     }
 }
@@ -77,15 +74,15 @@ impl From<u64> for Nanos {
     }
 }
 
-impl Into<u64> for Nanos {
-    fn into(self) -> u64 {
-        self.0
+impl From<Nanos> for u64 {
+    fn from(n: Nanos) -> Self {
+        n.0
     }
 }
 
-impl Into<Duration> for Nanos {
-    fn into(self) -> Duration {
-        Duration::from_nanos(self.0)
+impl From<Nanos> for Duration {
+    fn from(n: Nanos) -> Self {
+        Duration::from_nanos(n.0)
     }
 }
 
@@ -1,5 +1,6 @@
 use std::prelude::v1::*;
 
+use crate::QuotaError;
 use nonzero_ext::nonzero;
 use std::num::NonZeroU32;
 use std::time::Duration;
@@ -10,7 +11,10 @@ use std::time::Duration;
 /// allowed items until the rate limiter needs to replenish) and the amount of time for the rate
 /// limiter to replenish a single cell.
 ///
-/// Neither the number of cells nor the replenishment unit of time may be zero.
+/// Neither the number of cells nor the replenishment unit of time may be zero: Replenishment
+/// intervals that would be shorter than a nanosecond are rounded up to one nanosecond. The
+/// quotas that let no cells through at all and every cell through are constructed explicitly,
+/// with [`Quota::none`] and [`Quota::unlimited`]; no other quota behaves like them.
 ///
 /// # Burst sizes
 /// There are multiple ways of expressing the same quota: a quota given as `Quota::per_second(1)`
@@ -60,38 +64,74 @@ use std::time::Duration;
 pub struct Quota {
     pub(crate) max_burst: NonZeroU32,
     pub(crate) replenish_1_per: Duration,
+    pub(crate) start_empty: bool,
+    pub(crate) limit: Limit,
+}
+
+/// Which cells a [`Quota`] lets through.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub(crate) enum Limit {
+    /// The cells that conform to the quota's rate and burst size.
+    Rate,
+
+    /// No cells at all (see [`Quota::none`]).
+    None,
+
+    /// Every cell (see [`Quota::unlimited`]).
+    Unlimited,
+}
+
+/// Divides `period_ns` into `cells` replenishment intervals, rounding the interval down to whole
+/// nanoseconds, but never to less than one nanosecond.
+const fn replenish_interval(period_ns: u128, cells: NonZeroU32) -> Duration {
+    let ns = period_ns / (cells.get() as u128);
+    Duration::from_nanos(if ns == 0 { 1 } else { ns as u64 })
 }
 
 /// Constructors for Quotas
 impl Quota {
     /// Construct a quota for a number of cells per second. The given number of cells is also
     /// assumed to be the maximum burst size.
+    ///
+    /// Quotas for more than a billion cells per second replenish one cell per nanosecond.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use governor::Quota;
+    /// # use nonzero_ext::nonzero;
+    /// # use std::time::Duration;
+    /// let q = Quota::per_second(nonzero!(2_000_000_000u32));
+    /// assert_eq!(q.replenish_interval(), Duration::from_nanos(1));
+    /// assert!(!q.is_unlimited());
+    /// ```
     pub const fn per_second(max_burst: NonZeroU32) -> Quota {
-        let replenish_interval_ns = Duration::from_secs(1).as_nanos() / (max_burst.get() as u128);
         Quota {
             max_burst,
-            replenish_1_per: Duration::from_nanos(replenish_interval_ns as u64),
+            replenish_1_per: replenish_interval(Duration::from_secs(1).as_nanos(), max_burst),
+            start_empty: false,
+            limit: Limit::Rate,
         }
     }
 
     /// Construct a quota for a number of cells per 60-second period. The given number of cells is
     /// also assumed to be the maximum burst size.
     pub const fn per_minute(max_burst: NonZeroU32) -> Quota {
-        let replenish_interval_ns = Duration::from_secs(60).as_nanos() / (max_burst.get() as u128);
         Quota {
             max_burst,
-            replenish_1_per: Duration::from_nanos(replenish_interval_ns as u64),
+            replenish_1_per: replenish_interval(Duration::from_secs(60).as_nanos(), max_burst),
+            start_empty: false,
+            limit: Limit::Rate,
         }
     }
 
     /// Construct a quota for a number of cells per 60-minute (3600-second) period. The given number
     /// of cells is also assumed to be the maximum burst size.
     pub const fn per_hour(max_burst: NonZeroU32) -> Quota {
-        let replenish_interval_ns =
-            Duration::from_secs(60 * 60).as_nanos() / (max_burst.get() as u128);
         Quota {
             max_burst,
-            replenish_1_per: Duration::from_nanos(replenish_interval_ns as u64),
+            replenish_1_per: replenish_interval(Duration::from_secs(60 * 60).as_nanos(), max_burst),
+            start_empty: false,
+            limit: Limit::Rate,
         }
     }
 
@@ -114,23 +154,155 @@ impl Quota {
     ///     .unwrap()
     ///     .allow_burst(nonzero!(10u32));
     /// ```
-    pub fn with_period(replenish_1_per: Duration) -> Option<Quota> {
+    pub const fn with_period(replenish_1_per: Duration) -> Option<Quota> {
         if replenish_1_per.as_nanos() == 0 {
             None
         } else {
             Some(Quota {
                 max_burst: nonzero!(1u32),
                 replenish_1_per,
+                start_empty: false,
+                limit: Limit::Rate,
             })
         }
     }
 
+    /// Construct a quota for a number of cells per arbitrary period of time. The given number of
+    /// cells is also assumed to be the maximum burst size.
+    ///
+    /// The replenishment interval is `period` divided by `cells`, in whole nanoseconds. If the
+    /// division isn't exact, the interval is rounded up, so the quota never lets more than
+    /// `cells` through per `period` (it lets slightly fewer through, by at most one nanosecond's
+    /// worth per cell).
+    ///
+    /// Returns an error if `period` is zero, or if it is too long to represent in nanoseconds.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use governor::{Quota, QuotaError};
+    /// # use nonzero_ext::nonzero;
+    /// # use std::time::Duration;
+    /// let q = Quota::per(nonzero!(7u32), Duration::from_secs(90)).unwrap();
+    /// assert_eq!(q.burst_size().get(), 7);
+    /// assert_eq!(q.replenish_interval(), Duration::from_nanos(12_857_142_858));
+    ///
+    /// assert_eq!(
+    ///     Quota::per(nonzero!(7u32), Duration::from_secs(0)),
+    ///     Err(QuotaError::ZeroPeriod)
+    /// );
+    /// ```
+    pub const fn per(cells: NonZeroU32, period: Duration) -> Result<Quota, QuotaError> {
+        let period_ns = period.as_nanos();
+        if period_ns == 0 {
+            return Err(QuotaError::ZeroPeriod);
+        }
+        let n = cells.get() as u128;
+        let replenish_ns = period_ns.div_ceil(n);
+        if replenish_ns * n > u64::MAX as u128 {
+            return Err(QuotaError::PeriodTooLong);
+        }
+        Ok(Quota {
+            max_burst: cells,
+            replenish_1_per: Duration::from_nanos(replenish_ns as u64),
+            start_empty: false,
+            limit: Limit::Rate,
+        })
+    }
+
+    /// Construct a quota that lets no cells through at all, e.g. to shut off traffic with a kill
+    /// switch.
+    ///
+    /// Rate limiters with this quota reject every cell without consulting their state store. The
+    /// negative outcomes indicate a wait time so far in the future (over a hundred years) that it
+    /// will not practically conform; batches of cells are rejected as exceeding a capacity of
+    /// zero. Adjusting the burst size of this quota has no effect.
+    ///
+    /// # Example
+    /// ```rust
+    /// # #[cfg(feature = "std")] fn main() {
+    /// # use governor::{Quota, RateLimiter};
+    /// let lim = RateLimiter::direct(Quota::none());
+    /// assert!(lim.check().is_err());
+    /// # }
+    /// # #[cfg(not(feature = "std"))] fn main() {}
+    /// ```
+    pub const fn none() -> Quota {
+        Quota {
+            max_burst: nonzero!(1u32),
+            replenish_1_per: Duration::MAX,
+            start_empty: false,
+            limit: Limit::None,
+        }
+    }
+
+    /// Construct a quota that lets every cell through, e.g. to bypass rate limiting without
+    /// special-casing the code that checks the rate limiter.
+    ///
+    /// Rate limiters with this quota allow every cell (and every batch of cells, of any size)
+    /// through without consulting their state store, so they don't create states for new keys.
+    ///
+    /// # Example
+    /// ```rust
+    /// # #[cfg(feature = "std")] fn main() {
+    /// # use governor::{Quota, RateLimiter};
+    /// # use nonzero_ext::nonzero;
+    /// let lim = RateLimiter::direct(Quota::unlimited());
+    /// for _ in 0..1000 {
+    ///     assert_eq!(Ok(()), lim.check());
+    /// }
+    /// assert_eq!(Ok(()), lim.check_n(nonzero!(u32::MAX)));
+    /// # }
+    /// # #[cfg(not(feature = "std"))] fn main() {}
+    /// ```
+    pub const fn unlimited() -> Quota {
+        Quota {
+            max_burst: nonzero!(u32::MAX),
+            replenish_1_per: Duration::from_nanos(0),
+            start_empty: false,
+            limit: Limit::Unlimited,
+        }
+    }
+
     /// Adjusts the maximum burst size for a quota to construct a rate limiter with a capacity
     /// for at most the given number of cells.
     pub const fn allow_burst(self, max_burst: NonZeroU32) -> Quota {
         Quota { max_burst, ..self }
     }
 
+    /// Adjusts a quota so that rate limiters constructed with it start out with no capacity
+    /// available, which then fills up over time.
+    ///
+    /// By default, a rate limiting state (the state of a direct rate limiter, or of a key in
+    /// a keyed rate limiter) allows its full burst capacity through right away when it is first
+    /// used. This can cause a large burst of traffic when e.g. many processes start up at once.
+    /// With a quota that starts empty, the first cell is let through only after one
+    /// replenishment interval has passed since the state was first used, and the full burst
+    /// capacity is available after the entire burst size has been replenished.
+    ///
+    /// Note that keyed rate limiters treat keys that were removed from the state store (e.g. by
+    /// [`retain_recent`](struct.RateLimiter.html#method.retain_recent)) as new, so those keys
+    /// will start out empty again.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use governor::{clock::FakeRelativeClock, Quota, RateLimiter};
+    /// # use nonzero_ext::nonzero;
+    /// # use std::time::Duration;
+    /// let clock = FakeRelativeClock::default();
+    /// let quota = Quota::per_second(nonzero!(10u32)).starting_empty();
+    /// let lim = RateLimiter::direct_with_clock(quota, &clock);
+    /// assert!(lim.check().is_err());
+    ///
+    /// clock.advance(Duration::from_millis(100));
+    /// assert_eq!(Ok(()), lim.check());
+    /// ```
+    pub const fn starting_empty(self) -> Quota {
+        Quota {
+            start_empty: true,
+            ..self
+        }
+    }
+
     /// Construct a quota for a given burst size, replenishing the entire burst size in that
     /// given unit of time.
     ///
@@ -147,7 +319,7 @@ impl Quota {
     #[deprecated(
         since = "0.2.0",
         note = "This constructor is often confusing and non-intuitive. \
-    Use the `per_(interval)` / `with_period` and `max_burst` constructors instead."
+    Use the `per_(interval)` / `per` / `with_period` and `max_burst` constructors instead."
     )]
     pub fn new(max_burst: NonZeroU32, replenish_all_per: Duration) -> Option<Quota> {
         if replenish_all_per.as_nanos() == 0 {
@@ -155,7 +327,9 @@ impl Quota {
         } else {
             Some(Quota {
                 max_burst,
-                replenish_1_per: replenish_all_per / max_burst.get(),
+                replenish_1_per: replenish_interval(replenish_all_per.as_nanos(), max_burst),
+                start_empty: false,
+                limit: Limit::Rate,
             })
         }
     }
@@ -174,9 +348,137 @@ impl Quota {
         self.max_burst
     }
 
+    /// Whether new rate limiting states start out with no capacity available. See
+    /// [`starting_empty`](#method.starting_empty).
+    pub const fn starts_empty(&self) -> bool {
+        self.start_empty
+    }
+
+    /// Whether this is the quota that lets no cells through, constructed with
+    /// [`none`](#method.none) (or derived from it). Quotas with very long replenishment
+    /// intervals still let their burst capacity through, and are not such a quota.
+    pub const fn is_none(&self) -> bool {
+        matches!(self.limit, Limit::None)
+    }
+
+    /// Whether this is the quota that lets every cell through, constructed with
+    /// [`unlimited`](#method.unlimited) (or derived from it). Quotas with very many cells per
+    /// second still limit the rate to one cell per nanosecond, and are not such a quota.
+    pub const fn is_unlimited(&self) -> bool {
+        matches!(self.limit, Limit::Unlimited)
+    }
+
     /// The time it takes to replenish the entire maximum burst size.
+    ///
+    /// This is `Duration::MAX` for the quota that lets no cells through.
     pub const fn burst_size_replenished_in(&self) -> Duration {
+        if self.is_none() {
+            return Duration::MAX;
+        }
         let fill_in_ns = self.replenish_1_per.as_nanos() * self.max_burst.get() as u128;
         Duration::from_nanos(fill_in_ns as u64)
     }
+
+    /// The (average) number of cells that the quota allows through in the given period of time,
+    /// not counting the burst capacity.
+    ///
+    /// This is infinite for the quota that lets every cell through, and zero for the one that
+    /// lets none through.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use governor::Quota;
+    /// # use nonzero_ext::nonzero;
+    /// # use std::time::Duration;
+    /// let q = Quota::per_minute(nonzero!(30u32));
+    /// assert_eq!(q.cells_per(Duration::from_secs(60)), 30.0);
+    /// assert_eq!(q.cells_per_second(), 0.5);
+    /// ```
+    pub fn cells_per(&self, period: Duration) -> f64 {
+        if self.is_none() {
+            return 0.0;
+        }
+        period.as_nanos() as f64 / self.replenish_1_per.as_nanos() as f64
+    }
+
+    /// The (average) number of cells that the quota allows through per second, not counting the
+    /// burst capacity.
+    pub fn cells_per_second(&self) -> f64 {
+        self.cells_per(Duration::from_secs(1))
+    }
+}
+
+/// Deriving quotas from other quotas
+impl Quota {
+    /// Returns a quota that allows `factor` times as many cells through as this quota, e.g. to
+    /// derive the quota of one of several downstream clients from an upstream quota.
+    ///
+    /// Both the rate at which cells replenish and the burst size get scaled; the burst size is
+    /// rounded down, but is at least one cell. Returns `None` if `factor` isn't a positive
+    /// number, or if the resulting replenishment interval would be zero or too long to
+    /// represent. Scaling the quotas that let all or no cells through returns them unchanged.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use governor::Quota;
+    /// # use nonzero_ext::nonzero;
+    /// # use std::time::Duration;
+    /// let upstream = Quota::per_second(nonzero!(100u32));
+    /// // Split the upstream quota between four clients:
+    /// let client = upstream.scaled(0.25).unwrap();
+    /// assert_eq!(client, Quota::per_second(nonzero!(25u32)));
+    /// assert_eq!(upstream.scaled(0.0), None);
+    /// ```
+    pub fn scaled(self, factor: f64) -> Option<Quota> {
+        if !(factor.is_finite() && factor > 0.0) {
+            return None;
+        }
+        if self.is_none() || self.is_unlimited() {
+            return Some(self);
+        }
+        let replenish_ns = (self.replenish_1_per.as_nanos() as f64 / factor).round();
+        if !(replenish_ns >= 1.0 && replenish_ns < u64::MAX as f64) {
+            return None;
+        }
+        let max_burst = (f64::from(self.max_burst.get()) * factor)
+            .floor()
+            .min(f64::from(u32::MAX)) as u32;
+        Some(Quota {
+            max_burst: NonZeroU32::new(max_burst).unwrap_or(nonzero!(1u32)),
+            replenish_1_per: Duration::from_nanos(replenish_ns as u64),
+            ..self
+        })
+    }
+
+    /// Returns a quota that is at least as restrictive as both this quota and `other`: It has
+    /// the longer of the two replenishment intervals and the smaller of the two burst sizes, and
+    /// starts empty if either of them does.
+    ///
+    /// The quota that lets no cells through is more restrictive than any other quota, and the one
+    /// that lets every cell through is less restrictive than any other.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use governor::Quota;
+    /// # use nonzero_ext::nonzero;
+    /// let upstream = Quota::per_minute(nonzero!(60u32));
+    /// let requested = Quota::per_second(nonzero!(5u32));
+    /// assert_eq!(
+    ///     upstream.min(requested),
+    ///     Quota::per_second(nonzero!(1u32)).allow_burst(nonzero!(5u32))
+    /// );
+    /// ```
+    pub fn min(self, other: Quota) -> Quota {
+        let limit = match (self.limit, other.limit) {
+            (Limit::None, _) | (_, Limit::None) => Limit::None,
+            (Limit::Unlimited, Limit::Unlimited) => Limit::Unlimited,
+            _ => Limit::Rate,
+        };
+        Quota {
+            max_burst: std::cmp::min(self.max_burst, other.max_burst),
+            replenish_1_per: std::cmp::max(self.replenish_1_per, other.replenish_1_per),
+            start_empty: self.start_empty || other.start_empty,
+            limit,
+        }
+    }
 }
@@ -0,0 +1,161 @@
+//! Retrying operations at the rate that a rate limiter allows.
+//!
+//! Retry loops and rate limiters are often glued together by hand: Every attempt at an
+//! operation (the first one and each retry) should count against the rate limit, and a retry
+//! shouldn't go out before the rate limiter allows it. The
+//! [`retrying`](../struct.RateLimiter.html#method.retrying) and
+//! [`retrying_key`](../struct.RateLimiter.html#method.retrying_key) methods on rate limiters do
+//! this: They wait until the rate limiter allows a cell through before each attempt, and consult
+//! a [`RetryPolicy`] after each failed attempt to decide whether (and after what additional
+//! delay) to try again.
+//!
+//! # Example
+//! ```rust
+//! # use governor::{retry::MaxRetries, Quota, RateLimiter};
+//! # use nonzero_ext::nonzero;
+//! # futures::executor::block_on(async {
+//! let lim = RateLimiter::direct(Quota::per_second(nonzero!(10u32)));
+//! let mut attempts = 0;
+//! let result: Result<u32, &str> = lim
+//!     .retrying(MaxRetries::new(3), || {
+//!         attempts += 1;
+//!         let attempt = attempts;
+//!         async move {
+//!             if attempt < 3 {
+//!                 Err("flaky")
+//!             } else {
+//!                 Ok(attempt)
+//!             }
+//!         }
+//!     })
+//!     .await;
+//! assert_eq!(result, Ok(3));
+//! # });
+//! ```
+
+use std::prelude::v1::*;
+
+use crate::clock;
+use crate::state::{keyed::KeyedStateStore, DirectStateStore, NotKeyed};
+use crate::RateLimiter;
+use futures::Future;
+use std::hash::Hash;
+use std::time::Duration;
+
+/// Decides whether a failed operation should be attempted again.
+///
+/// This is implemented for closures taking the error and the number of attempts made so far,
+/// which makes it easy to adapt the policies of retry crates (like `tryhard` or `futures-retry`)
+/// or to write a custom backoff.
+pub trait RetryPolicy<E> {
+    /// Returns the delay to wait (in addition to waiting for the rate limiter) before attempting
+    /// the operation again, after the `attempt`th attempt failed with `error`. Returns `None` to
+    /// give up and return the error.
+    fn retry_after(&mut self, error: &E, attempt: u32) -> Option<Duration>;
+}
+
+impl<E, F> RetryPolicy<E> for F
+where
+    F: FnMut(&E, u32) -> Option<Duration>,
+{
+    fn retry_after(&mut self, error: &E, attempt: u32) -> Option<Duration> {
+        self(error, attempt)
+    }
+}
+
+/// A retry policy that retries every error up to a maximum number of times, as soon as the rate
+/// limiter allows it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MaxRetries(u32);
+
+impl MaxRetries {
+    /// Constructs a policy that gives up after `retries` retries (i.e., after `retries + 1`
+    /// attempts).
+    pub const fn new(retries: u32) -> MaxRetries {
+        MaxRetries(retries)
+    }
+}
+
+impl<E> RetryPolicy<E> for MaxRetries {
+    fn retry_after(&mut self, _error: &E, attempt: u32) -> Option<Duration> {
+        if attempt <= self.0 {
+            Some(Duration::new(0, 0))
+        } else {
+            None
+        }
+    }
+}
+
+/// Performs the retry loop, calling `until_ready` before each attempt.
+async fn retry_loop<T, E, P, F, Fut, R, RFut, C>(
+    clock: &C,
+    mut until_ready: R,
+    mut policy: P,
+    mut op: F,
+) -> Result<T, E>
+where
+    C: clock::ReasonablyRealtime,
+    P: RetryPolicy<E>,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+    R: FnMut() -> RFut,
+    RFut: Future<Output = ()>,
+{
+    let mut attempt = 0;
+    loop {
+        until_ready().await;
+        attempt += 1;
+        let error = match op().await {
+            Ok(value) => return Ok(value),
+            Err(error) => error,
+        };
+        match policy.retry_after(&error, attempt) {
+            None => return Err(error),
+            Some(delay) if delay > Duration::new(0, 0) => clock.delay(delay).await,
+            Some(_) => {}
+        }
+    }
+}
+
+/// # Direct rate limiters - Retrying
+impl<S, C> RateLimiter<NotKeyed, S, C>
+where
+    S: DirectStateStore,
+    C: clock::ReasonablyRealtime,
+{
+    /// Runs the operation `op` until it succeeds or `policy` gives up, waiting until the rate
+    /// limiter allows it before each attempt.
+    ///
+    /// Returns the result of the first successful attempt, or the error of the last attempt.
+    /// See the [`retry`][crate::retry] module for details.
+    pub async fn retrying<T, E, P, F, Fut>(&self, policy: P, op: F) -> Result<T, E>
+    where
+        P: RetryPolicy<E>,
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        retry_loop(self.clock(), || self.until_ready(), policy, op).await
+    }
+}
+
+/// # Keyed rate limiters - Retrying
+impl<K, S, C> RateLimiter<K, S, C>
+where
+    K: Hash + Eq + Clone,
+    S: KeyedStateStore<K>,
+    C: clock::ReasonablyRealtime,
+{
+    /// Runs the operation `op` until it succeeds or `policy` gives up, waiting until the rate
+    /// limiter allows it for `key` before each attempt.
+    ///
+    /// Returns the result of the first successful attempt, or the error of the last attempt.
+    /// See the [`retry`][crate::retry] module for details.
+    pub async fn retrying_key<T, E, P, F, Fut>(&self, key: &K, policy: P, op: F) -> Result<T, E>
+    where
+        P: RetryPolicy<E>,
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        retry_loop(self.clock(), || self.until_key_ready(key), policy, op).await
+    }
+}
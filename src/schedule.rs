@@ -0,0 +1,350 @@
+//! Quotas that change over time, according to a schedule.
+//!
+//! A [`ScheduledRateLimiter`] consults a [`QuotaSchedule`] on each rate-limiting decision, and
+//! enforces the quota that the schedule selects for the current time. Schedules can be given as
+//! closures over the rate limiter's clock readings, or (in `std` mode) as a [`DailySchedule`] of
+//! time-of-day windows.
+
+use std::prelude::v1::*;
+
+use std::hash::Hash;
+use std::num::NonZeroU32;
+
+use crate::state::keyed::KeyedStateStore;
+use crate::state::{DirectStateStore, NotKeyed, StateStore};
+use crate::{clock, NegativeMultiDecision, NotUntil, Quota, RateLimiter};
+
+#[cfg(feature = "std")]
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Selects the quota that a rate limiter should enforce at a given time.
+///
+/// This trait is implemented for all closures that take a clock reading and return a
+/// [`Quota`].
+pub trait QuotaSchedule<P: clock::Reference> {
+    /// Returns the quota in effect at the time `now`.
+    fn quota_at(&self, now: P) -> Quota;
+}
+
+impl<P, F> QuotaSchedule<P> for F
+where
+    P: clock::Reference,
+    F: Fn(P) -> Quota,
+{
+    fn quota_at(&self, now: P) -> Quota {
+        self(now)
+    }
+}
+
+/// A schedule of quotas that repeats every day.
+///
+/// The schedule consists of a default quota, and any number of time-of-day windows (measured
+/// from midnight UTC) with their own quotas. If windows overlap, the window that was added
+/// first takes precedence.
+///
+/// `DailySchedule` works with clocks that report [`SystemTime`]s, like the
+/// [`SystemClock`][crate::clock::SystemClock].
+///
+/// # Example
+/// ```rust
+/// # use governor::{clock::SystemClock, schedule::DailySchedule, Quota, ScheduledRateLimiter};
+/// # use nonzero_ext::nonzero;
+/// # use std::time::Duration;
+/// let hour = Duration::from_secs(60 * 60);
+/// // Allow 10 cells per second during business hours, and 100/s at all other times:
+/// let schedule = DailySchedule::new(Quota::per_second(nonzero!(100u32)))
+///     .with_window(hour * 9, hour * 17, Quota::per_second(nonzero!(10u32)));
+/// let lim = ScheduledRateLimiter::direct_with_clock(schedule, &SystemClock::default());
+/// assert_eq!(Ok(()), lim.check());
+/// ```
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, PartialEq)]
+pub struct DailySchedule {
+    default: Quota,
+    windows: Vec<(Duration, Duration, Quota)>,
+}
+
+#[cfg(feature = "std")]
+impl DailySchedule {
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    /// Constructs a schedule that enforces `default` outside of any time windows.
+    pub fn new(default: Quota) -> DailySchedule {
+        DailySchedule {
+            default,
+            windows: Vec::new(),
+        }
+    }
+
+    /// Adds a window during which `quota` is in effect.
+    ///
+    /// The window starts `start` after midnight UTC, and ends (exclusively) `end` after
+    /// midnight UTC. If `end` lies before `start`, the window spans midnight.
+    ///
+    /// # Panics
+    /// Panics if `start` or `end` are a full day or longer.
+    pub fn with_window(mut self, start: Duration, end: Duration, quota: Quota) -> DailySchedule {
+        assert!(
+            start < Self::DAY && end < Self::DAY,
+            "time-of-day windows must lie within a day"
+        );
+        self.windows.push((start, end, quota));
+        self
+    }
+
+    /// Returns the quota in effect at the given time of day (measured from midnight UTC).
+    pub fn quota_at_time_of_day(&self, time_of_day: Duration) -> Quota {
+        self.windows
+            .iter()
+            .find(|(start, end, _)| {
+                if start <= end {
+                    *start <= time_of_day && time_of_day < *end
+                } else {
+                    *start <= time_of_day || time_of_day < *end
+                }
+            })
+            .map(|(_, _, quota)| *quota)
+            .unwrap_or(self.default)
+    }
+}
+
+#[cfg(feature = "std")]
+impl QuotaSchedule<SystemTime> for DailySchedule {
+    fn quota_at(&self, now: SystemTime) -> Quota {
+        let since_epoch = now.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let time_of_day = Duration::new(
+            since_epoch.as_secs() % Self::DAY.as_secs(),
+            since_epoch.subsec_nanos(),
+        );
+        self.quota_at_time_of_day(time_of_day)
+    }
+}
+
+/// A rate limiter that enforces the quota selected by a [`QuotaSchedule`].
+///
+/// The schedule is consulted lazily: Each rate-limiting decision first retrieves the quota in
+/// effect at the current time, and adjusts the rate limiter's parameters if the quota differs
+/// from the one previously in effect. The rate limiting state itself is kept across quota
+/// changes.
+///
+/// # Example
+/// ```rust
+/// # use governor::{clock::FakeRelativeClock, nanos::Nanos, Quota, ScheduledRateLimiter};
+/// # use nonzero_ext::nonzero;
+/// # use std::time::Duration;
+/// let clock = FakeRelativeClock::default();
+/// let schedule = |now: Nanos| {
+///     if now < Nanos::from(Duration::from_secs(60)) {
+///         Quota::per_second(nonzero!(1u32))
+///     } else {
+///         Quota::per_second(nonzero!(10u32))
+///     }
+/// };
+/// let lim = ScheduledRateLimiter::direct_with_clock(schedule, &clock);
+/// assert_eq!(lim.current_quota(), Quota::per_second(nonzero!(1u32)));
+/// clock.advance(Duration::from_secs(60));
+/// assert_eq!(Ok(()), lim.check());
+/// assert_eq!(lim.current_quota(), Quota::per_second(nonzero!(10u32)));
+/// ```
+#[derive(Debug)]
+pub struct ScheduledRateLimiter<K, S, C, Q>
+where
+    S: StateStore<Key = K>,
+    C: clock::Clock,
+    Q: QuotaSchedule<C::Instant>,
+{
+    limiter: RateLimiter<K, S, C>,
+    schedule: Q,
+}
+
+/// # Scheduled rate limiters - Constructors
+impl<K, S, C, Q> ScheduledRateLimiter<K, S, C, Q>
+where
+    S: StateStore<Key = K>,
+    C: clock::Clock,
+    Q: QuotaSchedule<C::Instant>,
+{
+    /// Creates a new scheduled rate limiter from components.
+    pub fn new(schedule: Q, state: S, clock: &C) -> Self {
+        let quota = schedule.quota_at(clock.now());
+        ScheduledRateLimiter {
+            limiter: RateLimiter::new(quota, state, clock),
+            schedule,
+        }
+    }
+
+    /// Returns the quota that was most recently selected from the schedule.
+    pub fn current_quota(&self) -> Quota {
+        self.limiter.gcra().quota()
+    }
+
+    /// Returns the schedule that the rate limiter selects its quota from.
+    pub fn schedule(&self) -> &Q {
+        &self.schedule
+    }
+
+    /// Consumes the scheduled rate limiter and returns the underlying rate limiter, with the
+    /// most recently selected quota.
+    pub fn into_inner(self) -> RateLimiter<K, S, C> {
+        self.limiter
+    }
+
+    /// Reads the clock and applies the quota scheduled for that time.
+    fn scheduled_now(&self) -> C::Instant {
+        let now = self.limiter.clock().now();
+        self.limiter.gcra().set_quota(self.schedule.quota_at(now));
+        now
+    }
+}
+
+impl<C, Q> ScheduledRateLimiter<NotKeyed, crate::state::InMemoryState, C, Q>
+where
+    C: clock::Clock,
+    Q: QuotaSchedule<C::Instant>,
+{
+    /// Constructs a new direct scheduled rate limiter with a custom clock.
+    pub fn direct_with_clock(schedule: Q, clock: &C) -> Self {
+        Self::new(schedule, Default::default(), clock)
+    }
+}
+
+impl<K, C, Q> ScheduledRateLimiter<K, crate::state::keyed::DefaultKeyedStateStore<K>, C, Q>
+where
+    K: Clone + Hash + Eq,
+    C: clock::Clock,
+    Q: QuotaSchedule<C::Instant>,
+{
+    /// Constructs a new keyed scheduled rate limiter with a custom clock, backed by the
+    /// [`DefaultKeyedStateStore`][crate::state::keyed::DefaultKeyedStateStore].
+    pub fn keyed_with_clock(schedule: Q, clock: &C) -> Self {
+        Self::new(schedule, Default::default(), clock)
+    }
+}
+
+/// # Scheduled direct rate limiters - Manually checking cells
+impl<S, C, Q> ScheduledRateLimiter<NotKeyed, S, C, Q>
+where
+    S: DirectStateStore,
+    C: clock::Clock,
+    Q: QuotaSchedule<C::Instant>,
+{
+    /// Allow a single cell through the rate limiter, under the currently scheduled quota.
+    ///
+    /// See [`RateLimiter::check`].
+    pub fn check(&self) -> Result<(), NotUntil<C::Instant>> {
+        let now = self.scheduled_now();
+        self.limiter.test_key_at(&NotKeyed::NonKey, now)
+    }
+
+    /// Allow *only all* `n` cells through the rate limiter, under the currently scheduled quota.
+    ///
+    /// See [`RateLimiter::check_n`].
+    pub fn check_n(
+        &self,
+        n: NonZeroU32,
+    ) -> Result<(), NegativeMultiDecision<NotUntil<C::Instant>>> {
+        let now = self.scheduled_now();
+        self.limiter.test_key_n_at(&NotKeyed::NonKey, n, now)
+    }
+}
+
+/// # Scheduled keyed rate limiters - Manually checking cells
+impl<K, S, C, Q> ScheduledRateLimiter<K, S, C, Q>
+where
+    S: KeyedStateStore<K>,
+    K: Hash,
+    C: clock::Clock,
+    Q: QuotaSchedule<C::Instant>,
+{
+    /// Allow a single cell through the rate limiter for the given key, under the currently
+    /// scheduled quota.
+    ///
+    /// See [`RateLimiter::check_key`].
+    pub fn check_key(&self, key: &K) -> Result<(), NotUntil<C::Instant>> {
+        let now = self.scheduled_now();
+        self.limiter.test_key_at(key, now)
+    }
+
+    /// Allow *only all* `n` cells through the rate limiter for the given key, under the
+    /// currently scheduled quota.
+    ///
+    /// See [`RateLimiter::check_key_n`].
+    pub fn check_key_n(
+        &self,
+        key: &K,
+        n: NonZeroU32,
+    ) -> Result<(), NegativeMultiDecision<NotUntil<C::Instant>>> {
+        let now = self.scheduled_now();
+        self.limiter.test_key_n_at(key, n, now)
+    }
+}
+
+#[cfg(feature = "std")]
+mod future {
+    use super::*;
+    use crate::jitter::JitterState;
+    use crate::Jitter;
+
+    /// # Scheduled direct rate limiters - `async`/`await`
+    impl<S, C, Q> ScheduledRateLimiter<NotKeyed, S, C, Q>
+    where
+        S: DirectStateStore,
+        C: clock::ReasonablyRealtime,
+        Q: QuotaSchedule<C::Instant>,
+    {
+        /// Asynchronously resolves as soon as the rate limiter allows it, under the quota
+        /// scheduled at the time of each check.
+        ///
+        /// See [`RateLimiter::until_ready`].
+        pub async fn until_ready(&self) {
+            self.until_ready_with_jitter(Jitter::NONE).await;
+        }
+
+        /// Asynchronously resolves as soon as the rate limiter allows it, with a randomized
+        /// wait period.
+        ///
+        /// See [`RateLimiter::until_ready_with_jitter`].
+        pub async fn until_ready_with_jitter(&self, jitter: Jitter) {
+            let mut jitter_state = JitterState::default();
+            while let Err(negative) = self.check() {
+                let delay = self.limiter.clock().delay(jitter.add_next(
+                    &mut jitter_state,
+                    negative.wait_time_from(self.limiter.clock().now()),
+                ));
+                delay.await;
+            }
+        }
+    }
+
+    /// # Scheduled keyed rate limiters - `async`/`await`
+    impl<K, S, C, Q> ScheduledRateLimiter<K, S, C, Q>
+    where
+        K: Hash + Eq + Clone,
+        S: KeyedStateStore<K>,
+        C: clock::ReasonablyRealtime,
+        Q: QuotaSchedule<C::Instant>,
+    {
+        /// Asynchronously resolves as soon as the rate limiter allows it for the given key,
+        /// under the quota scheduled at the time of each check.
+        ///
+        /// See [`RateLimiter::until_key_ready`].
+        pub async fn until_key_ready(&self, key: &K) {
+            self.until_key_ready_with_jitter(key, Jitter::NONE).await;
+        }
+
+        /// Asynchronously resolves as soon as the rate limiter allows it for the given key,
+        /// with a randomized wait period.
+        ///
+        /// See [`RateLimiter::until_key_ready_with_jitter`].
+        pub async fn until_key_ready_with_jitter(&self, key: &K, jitter: Jitter) {
+            let mut jitter_state = JitterState::default();
+            while let Err(negative) = self.check_key(key) {
+                let delay = self.limiter.clock().delay(jitter.add_next(
+                    &mut jitter_state,
+                    negative.wait_time_from(self.limiter.clock().now()),
+                ));
+                delay.await;
+            }
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use std::prelude::v1::*;
+
+use crate::clock;
+use crate::nanos::Nanos;
+use crate::state::{InMemoryState, NotKeyed, StateStore};
+use crate::{Quota, RateLimiter};
+use std::convert::Infallible;
+use std::num::NonZeroU32;
+use std::time::Duration;
+
+/// One replica's share of a quota that several replicas split between them.
+///
+/// See [`Quota::divided_by`].
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct QuotaShare {
+    quota: Quota,
+    // The theoretical arrival time that a rate limiter for this share starts out with, in
+    // nanoseconds since its construction.
+    initial_tat: Nanos,
+    initial_cells: u32,
+}
+
+impl QuotaShare {
+    /// Returns the quota of the share.
+    pub fn quota(&self) -> Quota {
+        self.quota
+    }
+
+    /// Returns the number of cells that a rate limiter for the share lets through right after
+    /// it is constructed.
+    pub fn initial_cells(&self) -> u32 {
+        self.initial_cells
+    }
+
+    /// Returns how long after its construction a rate limiter for the share lets the first
+    /// cell through that it didn't start out with: This is what staggers the replenishment of
+    /// the shares.
+    pub fn phase(&self) -> Duration {
+        if self.quota.is_none() || self.quota.is_unlimited() {
+            return Duration::new(0, 0);
+        }
+        let t: Nanos = self.quota.replenish_1_per.into();
+        let tau = t * u64::from(self.quota.max_burst.get());
+        (self.initial_tat + t * u64::from(self.initial_cells))
+            .saturating_sub(tau)
+            .into()
+    }
+}
+
+/// # Splitting quotas between replicas
+impl Quota {
+    /// Splits this quota into `n` shares, one for each of `n` replicas that share an upstream
+    /// budget, so that the replicas together let no more cells through than this quota does.
+    ///
+    /// Each share replenishes cells `n` times slower than this quota. The burst size is divided
+    /// as evenly as possible, with the first shares getting one cell more than the last ones if
+    /// it doesn't divide evenly (but each share can hold at least one cell). On top of that, the
+    /// shares' replenishment is staggered by their [phase][QuotaShare::phase], so that the
+    /// replicas' rate limiters (constructed at the same time, with
+    /// [`RateLimiter::direct_share`](struct.RateLimiter.html#method.direct_share)) let cells
+    /// through at exactly the times this quota would: Cell `k` of this quota goes to share
+    /// `k % n`.
+    ///
+    /// If this quota's burst size is smaller than `n`, the replicas' rate limiters may together
+    /// let up to `n` cells through at once after a period of inactivity. Returns `None` if the
+    /// shares' replenishment interval is too long to represent. Dividing the quotas that let
+    /// all or no cells through returns `n` copies of them.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use governor::Quota;
+    /// # use nonzero_ext::nonzero;
+    /// # use std::time::Duration;
+    /// let upstream = Quota::per_second(nonzero!(10u32)).allow_burst(nonzero!(5u32));
+    /// let shares = upstream.divided_by(nonzero!(2u32)).unwrap();
+    /// assert_eq!(shares[0].quota(), Quota::per_second(nonzero!(5u32)).allow_burst(nonzero!(3u32)));
+    /// assert_eq!(shares[1].quota(), Quota::per_second(nonzero!(5u32)).allow_burst(nonzero!(2u32)));
+    /// // The upstream quota replenishes its first cell after 100ms, and then one cell every
+    /// // 100ms; these go to the second share first:
+    /// assert_eq!(shares[1].phase(), Duration::from_millis(100));
+    /// assert_eq!(shares[0].phase(), Duration::from_millis(200));
+    /// ```
+    pub fn divided_by(self, n: NonZeroU32) -> Option<Vec<QuotaShare>> {
+        let n = n.get();
+        if self.is_none() || self.is_unlimited() {
+            let share = QuotaShare {
+                quota: self,
+                initial_tat: Nanos::from(0),
+                initial_cells: 0,
+            };
+            return Some(vec![share; n as usize]);
+        }
+
+        let t = self.replenish_1_per.as_nanos();
+        let share_t = t.checked_mul(u128::from(n))?;
+        let b = self.max_burst.get();
+        // The number of cells that the quota starts out with:
+        let b0 = if self.start_empty { 0 } else { b };
+        // Ensure that the share's capacity (and the theoretical arrival times) fit:
+        if share_t.checked_mul(u128::from(b / n + 2))? >= u128::from(u64::MAX / 2) {
+            return None;
+        }
+        let share_t = share_t as u64;
+        let t = t as u64;
+
+        let shares = (0..n)
+            .map(|i| {
+                let cells = |total: u32| total / n + u32::from(i < total % n);
+                let max_burst = cells(b).max(1);
+                let initial_cells = cells(b0);
+                // The first cell that the share doesn't start out with is cell
+                // `i + initial_cells * n` of this quota, which conforms after
+                // `(i + initial_cells * n - b0 + 1) * t`. Counting back the share's capacity
+                // from there gives the share's initial theoretical arrival time:
+                let steps = u64::from(i) + 1 + u64::from(max_burst) * u64::from(n) - u64::from(b0);
+                QuotaShare {
+                    quota: Quota {
+                        max_burst: NonZeroU32::new(max_burst).unwrap(),
+                        replenish_1_per: Duration::from_nanos(share_t),
+                        ..self
+                    },
+                    initial_tat: Nanos::from(steps * t),
+                    initial_cells,
+                }
+            })
+            .collect();
+        Some(shares)
+    }
+}
+
+/// # Direct rate limiters for quota shares
+#[cfg(feature = "std")]
+impl RateLimiter<NotKeyed, InMemoryState, clock::DefaultClock> {
+    /// Constructs a new in-memory direct rate limiter for one replica's share of a quota (see
+    /// [`Quota::divided_by`]), with the default real-time clock.
+    pub fn direct_share(share: &QuotaShare) -> Self {
+        let clock = clock::DefaultClock::default();
+        Self::direct_share_with_clock(share, &clock)
+    }
+}
+
+impl<C> RateLimiter<NotKeyed, InMemoryState, C>
+where
+    C: clock::Clock,
+{
+    /// Constructs a new direct rate limiter for one replica's share of a quota (see
+    /// [`Quota::divided_by`]), with a custom clock.
+    ///
+    /// The rate limiter starts out with the share's [initial cells][QuotaShare::initial_cells],
+    /// and replenishes cells in the share's [phase][QuotaShare::phase].
+    pub fn direct_share_with_clock(share: &QuotaShare, clock: &C) -> Self {
+        let state = InMemoryState::default();
+        if !(share.quota.is_none() || share.quota.is_unlimited()) {
+            let tat = share.initial_tat;
+            let _ =
+                state.measure_and_replace(&NotKeyed::NonKey, |_| Ok::<_, Infallible>(((), tat)));
+        }
+        RateLimiter::new(share.quota, state, clock)
+    }
+}
@@ -2,15 +2,37 @@
 
 use std::prelude::v1::*;
 
+mod builder;
 pub mod direct;
+mod hooks;
 mod in_memory;
+mod instrumented;
 pub mod keyed;
+mod local;
+#[cfg(all(feature = "mmap", unix))]
+mod mmap;
+mod timed;
+mod usage;
 
+pub use self::builder::RateLimiterBuilder;
+pub use self::hooks::Decision;
 pub use self::in_memory::InMemoryState;
+#[cfg(feature = "std")]
+pub use self::instrumented::{ContentionStats, InstrumentedStateStore};
+pub use self::local::LocalState;
+#[cfg(all(feature = "mmap", unix))]
+pub use self::mmap::MmapStateStore;
+pub use self::usage::{UsageBucket, UsageReport, UsageTracker};
+
+use std::convert::Infallible;
+use std::num::NonZeroU32;
+use std::sync::atomic::AtomicU64;
 
+use crate::clock::Reference;
 use crate::gcra::Gcra;
 use crate::nanos::Nanos;
-use crate::{clock, Quota};
+use crate::{clock, NegativeMultiDecision, NotUntil, Quota, StateSnapshot};
+use nonzero_ext::nonzero;
 
 pub use direct::*;
 
@@ -43,6 +65,39 @@ pub trait StateStore {
     fn measure_and_replace<T, F, E>(&self, key: &Self::Key, f: F) -> Result<T, E>
     where
         F: Fn(Option<Nanos>) -> Result<(T, Nanos), E>;
+
+    /// Returns the state store's rate limiting state for a given key (`None` if no measurement
+    /// was made yet), without modifying it.
+    ///
+    /// The default implementation uses [`measure_and_replace`](#tymethod.measure_and_replace)
+    /// with a closure that never replaces the value. State stores that would create a new
+    /// entry for the key in that case should override this method.
+    fn peek(&self, key: &Self::Key) -> Option<Nanos> {
+        let res: Result<(), Option<Nanos>> = self.measure_and_replace(key, Err);
+        res.err().flatten()
+    }
+
+    /// Records the outcome of a rate limiting decision for a given key, made at `t0` (in
+    /// nanoseconds since the rate limiter was constructed).
+    ///
+    /// Rate limiters call this once for every decision they make, after the decision was made
+    /// (including decisions that didn't need to consult the state, like the rejection of a batch
+    /// that exceeds the burst capacity). The default implementation does nothing; state stores
+    /// that keep statistics, like [`StatsStateStore`][keyed::StatsStateStore], override it.
+    fn record_decision(&self, key: &Self::Key, conforming: bool, t0: Nanos) {
+        let _ = (key, conforming, t0);
+    }
+
+    /// Forgets the rate limiting state for a given key, so that the next measurement at the key
+    /// starts from `None`, as if no measurement was made on it yet.
+    ///
+    /// The default implementation replaces the state with a theoretical arrival time of zero,
+    /// which is how [`InMemoryState`] represents a state that no measurement was made on.
+    /// State stores that represent such states differently, or that can drop the key
+    /// altogether, should override this method.
+    fn reset(&self, key: &Self::Key) {
+        let _: Result<(), Infallible> = self.measure_and_replace(key, |_| Ok(((), Nanos::from(0))));
+    }
 }
 
 /// A rate limiter.
@@ -60,6 +115,9 @@ where
     gcra: Gcra,
     clock: C,
     start: C::Instant,
+    hooks: hooks::Hooks<K, C::Instant>,
+    // The latest time given to a decision made at a given time, in nanoseconds since `start`.
+    latest_at: AtomicU64,
 }
 
 impl<K, S, C> RateLimiter<K, S, C>
@@ -80,6 +138,26 @@ where
             clock,
             gcra,
             start,
+            hooks: hooks::Hooks::new(),
+            latest_at: AtomicU64::new(0),
+        }
+    }
+
+    /// Constructs a new rate limiter, like [`new`](#method.new), but in `const` contexts (e.g.
+    /// for `static` items).
+    ///
+    /// As the clock can't be read at compile time, `start` gives the time that the rate
+    /// limiter's states are relative to (which must not lie after any reading of the clock):
+    /// e.g. the UNIX epoch for the [`SystemClock`][clock::SystemClock], or tick zero for the
+    /// [`TickClock`][clock::TickClock].
+    pub const fn new_const(quota: Quota, state: S, clock: C, start: C::Instant) -> Self {
+        RateLimiter {
+            state,
+            clock,
+            gcra: Gcra::new(quota),
+            start,
+            hooks: hooks::Hooks::new(),
+            latest_at: AtomicU64::new(0),
         }
     }
 
@@ -89,6 +167,117 @@ where
     pub fn into_state_store(self) -> S {
         self.state
     }
+
+    /// Replaces the rate-limiting state at `key` with a fresh state.
+    pub(crate) fn reset_state(&self, key: &K) {
+        self.state.reset(key);
+    }
+
+    /// Takes a snapshot of the rate-limiting state at `key`.
+    pub(crate) fn snapshot_state(&self, key: &K) -> StateSnapshot {
+        let t0 = self.clock.now().duration_since(self.start);
+        self.gcra.snapshot(self.state.peek(key), t0)
+    }
+
+    pub(crate) fn gcra(&self) -> &Gcra {
+        &self.gcra
+    }
+
+    pub(crate) fn clock(&self) -> &C {
+        &self.clock
+    }
+
+    /// Tests a single cell for the given key against the rate limiter, as of `t0`.
+    pub(crate) fn test_key_at(&self, key: &K, t0: C::Instant) -> Result<(), NotUntil<C::Instant>> {
+        self.test_key_noting_fresh_at(key, t0).0
+    }
+
+    /// Like [`test_key_at`](#method.test_key_at), but also returns whether the state store
+    /// held no state for the key before the decision.
+    pub(crate) fn test_key_noting_fresh_at(
+        &self,
+        key: &K,
+        t0: C::Instant,
+    ) -> (Result<(), NotUntil<C::Instant>>, bool) {
+        let (decision, fresh) = self.gcra.test_and_update(self.start, key, &self.state, t0);
+        if !self.hooks.is_empty() {
+            self.report_decision(key, nonzero!(1u32), t0, decision.as_ref().err());
+        }
+        (decision, fresh)
+    }
+
+    /// Tests `n` cells for the given key against the rate limiter, as of `t0`.
+    pub(crate) fn test_key_n_at(
+        &self,
+        key: &K,
+        n: NonZeroU32,
+        t0: C::Instant,
+    ) -> Result<(), NegativeMultiDecision<NotUntil<C::Instant>>> {
+        let decision = self
+            .gcra
+            .test_n_all_and_update(self.start, key, n, &self.state, t0);
+        if !self.hooks.is_empty() {
+            match &decision {
+                Ok(()) => self.report_decision(key, n, t0, None),
+                Err(NegativeMultiDecision::BatchNonConforming(_, negative)) => {
+                    self.report_decision(key, n, t0, Some(negative))
+                }
+                Err(NegativeMultiDecision::InsufficientCapacity(_))
+                    if self.hooks.wants_negative() =>
+                {
+                    let negative = self.gcra.never_at(self.start, t0);
+                    self.report_decision(key, n, t0, Some(&negative))
+                }
+                Err(NegativeMultiDecision::InsufficientCapacity(_)) => {}
+            }
+        }
+        decision
+    }
+
+    /// Tests `n` cells for the given key against the rate limiter, as of `t0`, like
+    /// [`test_key_n_at`](#method.test_key_n_at), but only reports positive decisions to the
+    /// state store and the callbacks. This lets callers probe whether a batch of cells fits,
+    /// and fall back to fewer cells if it doesn't.
+    pub(crate) fn probe_key_n_at(
+        &self,
+        key: &K,
+        n: NonZeroU32,
+        t0: C::Instant,
+    ) -> Result<(), NegativeMultiDecision<NotUntil<C::Instant>>> {
+        let elapsed = t0.duration_since(self.start);
+        let decision = self.gcra.decide_n(self.start, key, n, &self.state, elapsed);
+        if decision.is_ok() {
+            self.state.record_decision(key, true, elapsed);
+            if !self.hooks.is_empty() {
+                self.report_decision(key, n, t0, None);
+            }
+        }
+        decision
+    }
+
+    /// Tests the next instalment of an item that costs `n` cells for the given key against the
+    /// rate limiter, as of `t0`: At most the rate limiter's burst capacity is tested at once.
+    /// Returns the number of cells that were let through.
+    #[cfg(feature = "std")]
+    pub(crate) fn test_key_instalment_at(
+        &self,
+        key: &K,
+        n: NonZeroU32,
+        t0: C::Instant,
+    ) -> Result<NonZeroU32, NotUntil<C::Instant>> {
+        let n = n.min(self.gcra.quota().burst_size());
+        if n.get() == 1 {
+            return self.test_key_at(key, t0).map(|()| n);
+        }
+        match self.test_key_n_at(key, n, t0) {
+            Ok(()) => Ok(n),
+            Err(NegativeMultiDecision::BatchNonConforming(_, negative)) => Err(negative),
+            // The quota changed concurrently:
+            Err(NegativeMultiDecision::InsufficientCapacity(_)) => {
+                self.test_key_at(key, t0).map(|()| nonzero!(1u32))
+            }
+        }
+    }
 }
 
 #[cfg(feature = "std")]
@@ -100,4 +289,16 @@ where
     pub(crate) fn reference_reading(&self) -> C::Instant {
         self.clock.reference_point()
     }
+
+    /// Returns the time that passed between the rate limiter's construction and `t0`.
+    pub(crate) fn elapsed_at(&self, t0: C::Instant) -> Nanos {
+        t0.duration_since(self.start)
+    }
+
+    /// Returns the time it takes to replenish the `n` cells that a paced combinator was just
+    /// let through (or the rate limiter's burst capacity, if that is smaller).
+    pub(crate) fn pacing_interval(&self, n: NonZeroU32) -> Nanos {
+        let n = n.min(self.gcra.quota().burst_size());
+        Nanos::from(self.gcra.t().as_u64().saturating_mul(u64::from(n.get())))
+    }
 }
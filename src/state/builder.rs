@@ -0,0 +1,116 @@
+use std::prelude::v1::*;
+
+use crate::clock;
+use crate::state::keyed::DefaultKeyedStateStore;
+use crate::state::{InMemoryState, NotKeyed, StateStore};
+use crate::{Quota, RateLimiter};
+use std::hash::Hash;
+
+/// A builder for rate limiters, for configurations that the shorthand constructors (like
+/// [`RateLimiter::direct`](struct.RateLimiter.html#method.direct) or
+/// [`RateLimiter::keyed`](struct.RateLimiter.html#method.keyed)) don't cover.
+///
+/// Builders start out with a quota, configured for a direct, in-memory rate limiter with the
+/// default clock; each method replaces one of these components. The default clock is only
+/// constructed by [`build`](#method.build), if no other clock was given. Decision callbacks
+/// (like [`on_allowed`](struct.RateLimiter.html#method.on_allowed)) are registered on the rate
+/// limiter that `build` returns.
+///
+/// See [`RateLimiter::builder`](struct.RateLimiter.html#method.builder).
+#[derive(Debug)]
+pub struct RateLimiterBuilder<S, C>
+where
+    S: StateStore,
+    C: clock::Clock,
+{
+    quota: Quota,
+    state: S,
+    clock: ClockChoice<C>,
+}
+
+/// The clock that a builder's rate limiter gets.
+#[derive(Debug)]
+enum ClockChoice<C> {
+    /// A clock constructed by the given function once the rate limiter is built.
+    Default(fn() -> C),
+
+    /// The given clock.
+    Given(C),
+}
+
+/// # Rate limiters - Builder
+impl RateLimiter<NotKeyed, InMemoryState, clock::DefaultClock> {
+    /// Returns a builder for a rate limiter that enforces `quota`.
+    ///
+    /// The builder starts out configured for a direct, in-memory rate limiter with the default
+    /// clock.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use governor::{clock::FakeRelativeClock, Quota, RateLimiter};
+    /// # use nonzero_ext::nonzero;
+    /// let clock = FakeRelativeClock::default();
+    /// let lim = RateLimiter::builder(Quota::per_second(nonzero!(1u32)))
+    ///     .clock(&clock)
+    ///     .keyed::<&str>()
+    ///     .build();
+    /// assert!(lim.check_key(&"a").is_ok());
+    /// assert!(lim.check_key(&"a").is_err());
+    /// assert!(lim.check_key(&"b").is_ok());
+    /// ```
+    pub fn builder(quota: Quota) -> RateLimiterBuilder<InMemoryState, clock::DefaultClock> {
+        RateLimiterBuilder {
+            quota,
+            state: InMemoryState::default(),
+            clock: ClockChoice::Default(clock::DefaultClock::default),
+        }
+    }
+}
+
+impl<S, C> RateLimiterBuilder<S, C>
+where
+    S: StateStore,
+    C: clock::Clock,
+{
+    /// Replaces the quota that the rate limiter enforces.
+    pub fn quota(self, quota: Quota) -> Self {
+        RateLimiterBuilder { quota, ..self }
+    }
+
+    /// Makes the rate limiter use (a clone of) `clock`.
+    pub fn clock<C2: clock::Clock>(self, clock: &C2) -> RateLimiterBuilder<S, C2> {
+        RateLimiterBuilder {
+            quota: self.quota,
+            state: self.state,
+            clock: ClockChoice::Given(clock.clone()),
+        }
+    }
+
+    /// Makes the rate limiter keep its state in `state`. The rate limiter is keyed if the state
+    /// store is.
+    pub fn state_store<S2: StateStore>(self, state: S2) -> RateLimiterBuilder<S2, C> {
+        RateLimiterBuilder {
+            quota: self.quota,
+            state,
+            clock: self.clock,
+        }
+    }
+
+    /// Makes the rate limiter keyed by `K`, keeping its state in the
+    /// [`DefaultKeyedStateStore`]. This replaces any state store given before.
+    pub fn keyed<K>(self) -> RateLimiterBuilder<DefaultKeyedStateStore<K>, C>
+    where
+        K: Hash + Eq + Clone,
+    {
+        self.state_store(DefaultKeyedStateStore::default())
+    }
+
+    /// Constructs the rate limiter.
+    pub fn build(self) -> RateLimiter<S::Key, S, C> {
+        let clock = match self.clock {
+            ClockChoice::Default(default) => default(),
+            ClockChoice::Given(clock) => clock,
+        };
+        RateLimiter::new(self.quota, self.state, &clock)
+    }
+}
@@ -7,8 +7,9 @@ use std::prelude::v1::*;
 
 use std::num::NonZeroU32;
 
+use crate::clock::Reference;
 use crate::gcra::NotUntil;
-use crate::{clock, state::InMemoryState, NegativeMultiDecision, Quota};
+use crate::{clock, state::InMemoryState, NegativeMultiDecision, Quota, StateSnapshot};
 
 /// The "this state store does not use keys" key type.
 ///
@@ -50,7 +51,41 @@ where
     /// Constructs a new direct rate limiter for a quota with a custom clock.
     pub fn direct_with_clock(quota: Quota, clock: &C) -> Self {
         let state: InMemoryState = Default::default();
-        RateLimiter::new(quota, state, &clock)
+        RateLimiter::new(quota, state, clock)
+    }
+
+    /// Constructs a new direct rate limiter for a quota with a custom clock, in `const`
+    /// contexts: This allows declaring rate limiters as `static` items.
+    ///
+    /// Since the clock can't be read at compile time, the rate limiter measures time relative
+    /// to `start` (see [`RateLimiter::new_const`]).
+    ///
+    /// # Example
+    /// ```rust
+    /// # use governor::{clock::TickClock, nanos::Nanos, state::{InMemoryState, NotKeyed}, Quota, RateLimiter};
+    /// # use nonzero_ext::nonzero;
+    /// # use std::sync::atomic::{AtomicU64, Ordering};
+    /// static TICKS: AtomicU64 = AtomicU64::new(0);
+    ///
+    /// fn read_ticks() -> u64 {
+    ///     TICKS.load(Ordering::Relaxed)
+    /// }
+    ///
+    /// static LIMITER: RateLimiter<NotKeyed, InMemoryState, TickClock<fn() -> u64, 1_000>> =
+    ///     RateLimiter::direct_const(
+    ///         Quota::per_second(nonzero!(2u32)),
+    ///         TickClock::new(read_ticks),
+    ///         Nanos::new(0),
+    ///     );
+    ///
+    /// assert_eq!(Ok(()), LIMITER.check());
+    /// assert_eq!(Ok(()), LIMITER.check());
+    /// assert!(LIMITER.check().is_err());
+    /// TICKS.store(500, Ordering::Relaxed);
+    /// assert_eq!(Ok(()), LIMITER.check());
+    /// ```
+    pub const fn direct_const(quota: Quota, clock: C, start: C::Instant) -> Self {
+        RateLimiter::new_const(quota, InMemoryState::new(), clock, start)
     }
 }
 
@@ -65,8 +100,7 @@ where
     /// If the rate limit is reached, `check` returns information about the earliest
     /// time that a cell might be allowed through again.
     pub fn check(&self) -> Result<(), NotUntil<C::Instant>> {
-        self.gcra
-            .test_and_update(self.start, &NotKeyed::NonKey, &self.state, self.clock.now())
+        self.test_key_at(&NotKeyed::NonKey, self.clock.now())
     }
 
     /// Allow *only all* `n` cells through the rate limiter.
@@ -87,13 +121,65 @@ where
         &self,
         n: NonZeroU32,
     ) -> Result<(), NegativeMultiDecision<NotUntil<C::Instant>>> {
-        self.gcra.test_n_all_and_update(
-            self.start,
-            &NotKeyed::NonKey,
-            n,
-            &self.state,
-            self.clock.now(),
-        )
+        self.test_key_n_at(&NotKeyed::NonKey, n, self.clock.now())
+    }
+
+    /// Returns a snapshot of the rate limiter's state, for observability and debugging.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use governor::{clock::FakeRelativeClock, Quota, RateLimiter};
+    /// # use nonzero_ext::nonzero;
+    /// # use std::time::Duration;
+    /// let clock = FakeRelativeClock::default();
+    /// let lim = RateLimiter::direct_with_clock(Quota::per_second(nonzero!(5u32)), &clock);
+    /// lim.check().unwrap();
+    ///
+    /// let snapshot = lim.state_snapshot();
+    /// assert_eq!(snapshot.emission_interval(), Duration::from_millis(200));
+    /// assert_eq!(snapshot.tolerance(), Duration::from_secs(1));
+    /// assert_eq!(snapshot.remaining_burst_capacity(), 4);
+    /// ```
+    pub fn state_snapshot(&self) -> StateSnapshot {
+        self.snapshot_state(&NotKeyed::NonKey)
+    }
+
+    /// Resets the rate limiter's state to that of a newly-constructed rate limiter, restoring
+    /// its full burst capacity (unless the quota [starts empty][Quota::starting_empty]).
+    ///
+    /// This is useful for e.g. tests that share a rate limiter between cases.
+    pub fn reset(&self) {
+        self.reset_state(&NotKeyed::NonKey);
+    }
+
+    /// Grants the rate limiter `cells` one-time cells on top of the ones that replenished, e.g.
+    /// for an approved backfill, without reconstructing the rate limiter.
+    ///
+    /// The bonus rolls the rate limiter's state back by the weight of the bonus cells, so they
+    /// can be used right away and are not paid back: Using them doesn't delay later cells. The
+    /// state is never rolled back past a full bucket, so a bonus only restores the burst
+    /// capacity that was used up; bonus cells that don't fit are lost, and bonus cells that
+    /// aren't used become indistinguishable from replenished ones as the bucket fills up.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use governor::{clock::FakeRelativeClock, Quota, RateLimiter};
+    /// # use nonzero_ext::nonzero;
+    /// let clock = FakeRelativeClock::default();
+    /// let lim = RateLimiter::direct_with_clock(Quota::per_minute(nonzero!(4u32)), &clock);
+    /// lim.check_n(nonzero!(4u32)).unwrap();
+    /// assert!(lim.check().is_err());
+    ///
+    /// lim.grant_bonus(nonzero!(2u32));
+    /// for _ in 0..2 {
+    ///     lim.check().unwrap();
+    /// }
+    /// assert!(lim.check().is_err());
+    /// ```
+    pub fn grant_bonus(&self, cells: NonZeroU32) {
+        let t0 = self.clock.now().duration_since(self.start);
+        self.gcra
+            .grant_bonus(&NotKeyed::NonKey, &self.state, t0, cells);
     }
 }
 
@@ -102,6 +188,11 @@ mod future;
 #[cfg(feature = "std")]
 pub use future::*;
 
+#[cfg(feature = "std")]
+mod io;
+#[cfg(feature = "std")]
+pub use io::*;
+
 #[cfg(feature = "std")]
 mod sinks;
 #[cfg(feature = "std")]
@@ -1,29 +1,55 @@
-use std::{error::Error, fmt, num::NonZeroU32};
+use std::num::NonZeroU32;
+use std::time::Duration;
 
 use super::RateLimiter;
+use crate::jitter::JitterState;
 use crate::{
-    clock,
+    clock::{self, Reference},
     state::{DirectStateStore, NotKeyed},
     Jitter, NegativeMultiDecision,
 };
-use futures_timer::Delay;
 
-/// An error that occurs when the number of cells required in `check_n`
-/// exceeds the maximum capacity of the limiter.
-#[derive(Debug, Clone)]
-pub struct InsufficientCapacity(pub u32);
+pub use crate::errors::InsufficientCapacity;
 
-impl fmt::Display for InsufficientCapacity {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(
-            f,
-            "required number of cell {} exceeds bucket's capacity",
-            self.0
-        )
-    }
+/// How an asynchronous wait on a rate limiter went, returned by the `_with_stats` variants of
+/// the `until_ready` family of methods (e.g.
+/// [`until_ready_with_stats`](../struct.RateLimiter.html#method.until_ready_with_stats)).
+///
+/// Recording these in a histogram tells how much latency the rate limiter adds to the
+/// operations it throttles.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct WaitStats {
+    waited: Duration,
+    retries: u32,
 }
 
-impl Error for InsufficientCapacity {}
+impl WaitStats {
+    /// Returns how long the wait took, as measured by the rate limiter's clock. This is zero
+    /// if the rate limiter allowed the cells right away.
+    pub fn waited(&self) -> Duration {
+        self.waited
+    }
+
+    /// Returns how often the wait slept and then checked the rate limiter again. This is zero
+    /// if the rate limiter allowed the cells right away, and more than one if other
+    /// measurements (or jitter) made a wait run past the time that the rate limiter first
+    /// indicated.
+    pub fn retries(&self) -> u32 {
+        self.retries
+    }
+
+    /// Returns whether the wait had to sleep at all.
+    pub fn was_delayed(&self) -> bool {
+        self.retries > 0
+    }
+
+    pub(crate) fn since<P: Reference>(start: P, now: P, retries: u32) -> Self {
+        WaitStats {
+            waited: now.duration_since(start).into(),
+            retries,
+        }
+    }
+}
 
 #[cfg(feature = "std")]
 /// # Direct rate limiters - `async`/`await`
@@ -57,10 +83,43 @@ where
     /// which can help reduce the likelihood of thundering herd effects if multiple tasks try to
     /// wait on the same rate limiter.
     pub async fn until_ready_with_jitter(&self, jitter: Jitter) {
+        self.until_ready_with_stats(jitter).await;
+    }
+
+    /// Asynchronously resolves as soon as the rate limiter allows it, like
+    /// [`until_ready_with_jitter`](#method.until_ready_with_jitter), and reports how long it
+    /// waited and how often it checked the rate limiter again.
+    ///
+    /// Pass `Jitter::default()` to wait without jitter.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use governor::{clock::FakeRelativeClock, Jitter, Quota, RateLimiter};
+    /// # use nonzero_ext::nonzero;
+    /// # use std::time::Duration;
+    /// let clock = FakeRelativeClock::default();
+    /// let lim = RateLimiter::direct_with_clock(Quota::per_second(nonzero!(2u32)), &clock);
+    /// clock.block_on_auto_advance(async {
+    ///     let stats = lim.until_n_ready_with_stats(nonzero!(2u32), Jitter::default()).await.unwrap();
+    ///     assert!(!stats.was_delayed());
+    ///
+    ///     let stats = lim.until_ready_with_stats(Jitter::default()).await;
+    ///     assert_eq!(stats.waited(), Duration::from_millis(500));
+    ///     assert_eq!(stats.retries(), 1);
+    /// });
+    /// ```
+    pub async fn until_ready_with_stats(&self, jitter: Jitter) -> WaitStats {
+        let start = self.clock.now();
+        let mut jitter_state = JitterState::default();
+        let mut retries = 0;
         while let Err(negative) = self.check() {
-            let delay = Delay::new(jitter + negative.wait_time_from(self.clock.now()));
+            let delay = self.clock.delay(
+                jitter.add_next(&mut jitter_state, negative.wait_time_from(self.clock.now())),
+            );
             delay.await;
+            retries += 1;
         }
+        WaitStats::since(start, self.clock.now(), retries)
     }
 
     /// Asynchronously resolves as soon as the rate limiter allows it.
@@ -87,18 +146,39 @@ where
         n: NonZeroU32,
         jitter: Jitter,
     ) -> Result<(), InsufficientCapacity> {
+        self.until_n_ready_with_stats(n, jitter).await.map(|_| ())
+    }
+
+    /// Asynchronously resolves as soon as the rate limiter allows `n` cells through, like
+    /// [`until_n_ready_with_jitter`](#method.until_n_ready_with_jitter), and reports how long
+    /// it waited and how often it checked the rate limiter again.
+    ///
+    /// Returns `InsufficientCapacity` if the `n` provided exceeds the maximum
+    /// capacity of the rate limiter.
+    pub async fn until_n_ready_with_stats(
+        &self,
+        n: NonZeroU32,
+        jitter: Jitter,
+    ) -> Result<WaitStats, InsufficientCapacity> {
+        let start = self.clock.now();
+        let mut jitter_state = JitterState::default();
+        let mut retries = 0;
         while let Err(err) = self.check_n(n) {
             match err {
                 NegativeMultiDecision::BatchNonConforming(_, negative) => {
-                    let delay = Delay::new(jitter + negative.wait_time_from(self.clock.now()));
+                    let delay = self.clock.delay(
+                        jitter
+                            .add_next(&mut jitter_state, negative.wait_time_from(self.clock.now())),
+                    );
                     delay.await;
+                    retries += 1;
                 }
-                NegativeMultiDecision::InsufficientCapacity(cap) => {
-                    return Err(InsufficientCapacity(cap))
+                NegativeMultiDecision::InsufficientCapacity(insufficient) => {
+                    return Err(insufficient)
                 }
             }
         }
 
-        Ok(())
+        Ok(WaitStats::since(start, self.clock.now(), retries))
     }
 }
@@ -0,0 +1,448 @@
+use std::prelude::v1::*;
+
+use crate::clock::{self, Delay};
+use crate::handle::LimiterRef;
+use crate::{
+    state::{DirectStateStore, NotKeyed},
+    InsufficientCapacity, NegativeMultiDecision, RateLimiter, RateLimiterHandle,
+};
+use futures::io::{AsyncRead, AsyncWrite};
+use futures::ready;
+use futures::task::{Context, Poll};
+use futures::Future;
+use nonzero_ext::nonzero;
+use std::io::{self, Read, Write};
+use std::num::NonZeroU32;
+use std::pin::Pin;
+use std::thread;
+
+/// Returns the number of bytes out of `len` that can be charged against the rate limiter at
+/// once, i.e., at most its burst size.
+fn chunk_size<D: DirectStateStore, C: clock::Clock>(
+    limiter: &RateLimiter<NotKeyed, D, C>,
+    len: usize,
+) -> Option<NonZeroU32> {
+    let burst = limiter.gcra().quota().burst_size();
+    NonZeroU32::new(len.min(burst.get() as usize) as u32)
+}
+
+/// Returns an error if the rate limiter's quota lets no cells through at all, so that waiting
+/// for it would never end.
+fn ensure_capacity<D: DirectStateStore, C: clock::Clock>(
+    limiter: &RateLimiter<NotKeyed, D, C>,
+) -> io::Result<()> {
+    let quota = limiter.gcra().quota();
+    if quota.is_none() {
+        let insufficient = InsufficientCapacity::new(nonzero!(1u32), 0, quota);
+        return Err(io::Error::other(insufficient));
+    }
+    Ok(())
+}
+
+/// Blocks the current thread until the rate limiter lets `n` cells through.
+///
+/// The cells are charged in instalments of at most the rate limiter's current burst size, in
+/// case it has shrunk (on an adaptive rate limiter) since the chunk size was determined.
+fn wait_blocking<D: DirectStateStore, C: clock::Clock>(
+    limiter: &RateLimiter<NotKeyed, D, C>,
+    n: NonZeroU32,
+) -> io::Result<()> {
+    let mut owed = n;
+    loop {
+        let batch = owed.min(limiter.gcra().quota().burst_size());
+        match limiter.check_n(batch) {
+            Ok(()) => match NonZeroU32::new(owed.get() - batch.get()) {
+                Some(rest) => owed = rest,
+                None => return Ok(()),
+            },
+            Err(NegativeMultiDecision::BatchNonConforming(_, negative)) => {
+                thread::sleep(negative.wait_time_from(limiter.clock().now()));
+            }
+            Err(NegativeMultiDecision::InsufficientCapacity(insufficient))
+                if insufficient.max_burst() == 0 =>
+            {
+                return Err(io::Error::other(insufficient));
+            }
+            // The burst size changed concurrently; try again with the new one:
+            Err(NegativeMultiDecision::InsufficientCapacity(_)) => {}
+        }
+    }
+}
+
+/// The state of an asynchronous wait for the rate limiter to let a number of cells through.
+#[derive(Debug, Default)]
+struct Admission {
+    delay: Option<Delay>,
+    // How many of the cells the rate limiter has yet to let through, once it has let some of
+    // them through.
+    owed: Option<NonZeroU32>,
+}
+
+impl Admission {
+    /// Waits until the rate limiter lets `n` cells through, in instalments of at most its
+    /// current burst size (see [`wait_blocking`]).
+    fn poll<D: DirectStateStore, C: clock::ReasonablyRealtime>(
+        &mut self,
+        cx: &mut Context<'_>,
+        limiter: &RateLimiter<NotKeyed, D, C>,
+        n: NonZeroU32,
+    ) -> Poll<io::Result<()>> {
+        loop {
+            if let Some(delay) = &mut self.delay {
+                ready!(Pin::new(delay).poll(cx));
+                self.delay = None;
+            }
+            let owed = self.owed.unwrap_or(n);
+            let batch = owed.min(limiter.gcra().quota().burst_size());
+            match limiter.check_n(batch) {
+                Ok(()) => {
+                    self.owed = NonZeroU32::new(owed.get() - batch.get());
+                    if self.owed.is_none() {
+                        return Poll::Ready(Ok(()));
+                    }
+                }
+                Err(NegativeMultiDecision::BatchNonConforming(_, negative)) => {
+                    let clock = limiter.clock();
+                    self.delay = Some(clock.delay(negative.wait_time_from(clock.now())));
+                }
+                Err(NegativeMultiDecision::InsufficientCapacity(insufficient))
+                    if insufficient.max_burst() == 0 =>
+                {
+                    return Poll::Ready(Err(io::Error::other(insufficient)));
+                }
+                Err(NegativeMultiDecision::InsufficientCapacity(_)) => {}
+            }
+        }
+    }
+}
+
+/// A [`Read`] adapter that limits the rate at which bytes can be read, charging the rate limiter
+/// one cell per byte.
+///
+/// Each read is capped at the rate limiter's burst size, and blocks the current thread after
+/// reading until the rate limiter lets the bytes that were read through. This adapter should be
+/// used with a real-time clock. If the rate limiter's quota lets no bytes through (see
+/// [`Quota::none`][crate::Quota::none]), reads fail with an error instead of blocking forever.
+///
+/// # Example
+/// ```rust
+/// # use governor::{state::direct::RateLimitedReader, Quota, RateLimiter};
+/// # use nonzero_ext::nonzero;
+/// # use std::io::Read;
+/// // Read at most 1MiB per second:
+/// let lim = RateLimiter::direct(Quota::per_second(nonzero!(1024u32 * 1024)));
+/// let mut reader = RateLimitedReader::new(&b"hello world"[..], &lim);
+/// let mut buf = String::new();
+/// reader.read_to_string(&mut buf).unwrap();
+/// assert_eq!(buf, "hello world");
+/// ```
+pub struct RateLimitedReader<'a, R, D: DirectStateStore, C: clock::Clock> {
+    inner: R,
+    limiter: LimiterRef<'a, NotKeyed, D, C>,
+}
+
+impl<'a, R, D: DirectStateStore, C: clock::Clock> RateLimitedReader<'a, R, D, C> {
+    /// Wraps `inner`, limiting the rate at which bytes can be read from it to the rate limit of
+    /// `limiter`.
+    pub fn new(inner: R, limiter: &'a RateLimiter<NotKeyed, D, C>) -> Self {
+        RateLimitedReader {
+            inner,
+            limiter: LimiterRef::Borrowed(limiter),
+        }
+    }
+
+    /// Acquires a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Acquires a mutable reference to the underlying reader.
+    ///
+    /// Bytes read directly from the underlying reader don't count against the rate limit.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Consumes this adapter, returning the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R, D: DirectStateStore + 'static, C: clock::Clock + 'static>
+    RateLimitedReader<'static, R, D, C>
+{
+    /// Wraps `inner`, limiting the rate at which bytes can be read from it to the rate limit of
+    /// a rate limiter that the adapter holds on to.
+    pub fn new_owned(inner: R, limiter: impl Into<RateLimiterHandle<NotKeyed, D, C>>) -> Self {
+        RateLimitedReader {
+            inner,
+            limiter: LimiterRef::Owned(limiter.into()),
+        }
+    }
+}
+
+impl<'a, R: Read, D: DirectStateStore, C: clock::Clock> Read for RateLimitedReader<'a, R, D, C> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let chunk = match chunk_size(&self.limiter, buf.len()) {
+            Some(chunk) => chunk.get() as usize,
+            None => return self.inner.read(buf),
+        };
+        ensure_capacity(&self.limiter)?;
+        let read = self.inner.read(&mut buf[..chunk])?;
+        if let Some(n) = NonZeroU32::new(read as u32) {
+            wait_blocking(&self.limiter, n)?;
+        }
+        Ok(read)
+    }
+}
+
+/// A [`Write`] adapter that limits the rate at which bytes can be written, charging the rate
+/// limiter one cell per byte.
+///
+/// Each write is capped at the rate limiter's burst size, and blocks the current thread until
+/// the rate limiter lets the bytes through before writing them. If the underlying writer
+/// accepts fewer bytes than were let through, the difference still counts against the rate
+/// limit. This adapter should be used with a real-time clock. If the rate limiter's quota lets
+/// no bytes through (see [`Quota::none`][crate::Quota::none]), writes fail with an error
+/// instead of blocking forever.
+pub struct RateLimitedWriter<'a, W, D: DirectStateStore, C: clock::Clock> {
+    inner: W,
+    limiter: LimiterRef<'a, NotKeyed, D, C>,
+}
+
+impl<'a, W, D: DirectStateStore, C: clock::Clock> RateLimitedWriter<'a, W, D, C> {
+    /// Wraps `inner`, limiting the rate at which bytes can be written to it to the rate limit of
+    /// `limiter`.
+    pub fn new(inner: W, limiter: &'a RateLimiter<NotKeyed, D, C>) -> Self {
+        RateLimitedWriter {
+            inner,
+            limiter: LimiterRef::Borrowed(limiter),
+        }
+    }
+
+    /// Acquires a reference to the underlying writer.
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Acquires a mutable reference to the underlying writer.
+    ///
+    /// Bytes written directly to the underlying writer don't count against the rate limit.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    /// Consumes this adapter, returning the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W, D: DirectStateStore + 'static, C: clock::Clock + 'static>
+    RateLimitedWriter<'static, W, D, C>
+{
+    /// Wraps `inner`, limiting the rate at which bytes can be written to it to the rate limit of
+    /// a rate limiter that the adapter holds on to.
+    pub fn new_owned(inner: W, limiter: impl Into<RateLimiterHandle<NotKeyed, D, C>>) -> Self {
+        RateLimitedWriter {
+            inner,
+            limiter: LimiterRef::Owned(limiter.into()),
+        }
+    }
+}
+
+impl<'a, W: Write, D: DirectStateStore, C: clock::Clock> Write for RateLimitedWriter<'a, W, D, C> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let chunk = match chunk_size(&self.limiter, buf.len()) {
+            Some(chunk) => chunk,
+            None => return self.inner.write(buf),
+        };
+        wait_blocking(&self.limiter, chunk)?;
+        self.inner.write(&buf[..chunk.get() as usize])
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// An [`AsyncRead`] adapter that limits the rate at which bytes can be read, charging the rate
+/// limiter one cell per byte.
+///
+/// Each read is capped at the rate limiter's burst size. The bytes that were read are charged
+/// against the rate limiter before the next read proceeds, so the reader waits for (at most) one
+/// read's worth of bytes to be let through. If the rate limiter's quota lets no bytes through
+/// (see [`Quota::none`][crate::Quota::none]), reads fail with an error instead of waiting
+/// forever.
+pub struct AsyncRateLimitedReader<'a, R, D: DirectStateStore, C: clock::ReasonablyRealtime> {
+    inner: R,
+    limiter: LimiterRef<'a, NotKeyed, D, C>,
+    admission: Admission,
+    owed: Option<NonZeroU32>,
+}
+
+impl<'a, R, D: DirectStateStore, C: clock::ReasonablyRealtime> AsyncRateLimitedReader<'a, R, D, C> {
+    /// Wraps `inner`, limiting the rate at which bytes can be read from it to the rate limit of
+    /// `limiter`.
+    pub fn new(inner: R, limiter: &'a RateLimiter<NotKeyed, D, C>) -> Self {
+        Self::from_ref(inner, LimiterRef::Borrowed(limiter))
+    }
+
+    fn from_ref(inner: R, limiter: LimiterRef<'a, NotKeyed, D, C>) -> Self {
+        AsyncRateLimitedReader {
+            inner,
+            limiter,
+            admission: Admission::default(),
+            owed: None,
+        }
+    }
+
+    /// Acquires a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Acquires a mutable reference to the underlying reader.
+    ///
+    /// Bytes read directly from the underlying reader don't count against the rate limit.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Consumes this adapter, returning the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R, D: DirectStateStore + 'static, C: clock::ReasonablyRealtime + 'static>
+    AsyncRateLimitedReader<'static, R, D, C>
+{
+    /// Wraps `inner`, limiting the rate at which bytes can be read from it to the rate limit of
+    /// a rate limiter that the adapter holds on to.
+    pub fn new_owned(inner: R, limiter: impl Into<RateLimiterHandle<NotKeyed, D, C>>) -> Self {
+        Self::from_ref(inner, LimiterRef::Owned(limiter.into()))
+    }
+}
+
+impl<'a, R: AsyncRead + Unpin, D: DirectStateStore, C: clock::ReasonablyRealtime> AsyncRead
+    for AsyncRateLimitedReader<'a, R, D, C>
+{
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        if let Some(owed) = this.owed {
+            ready!(this.admission.poll(cx, &this.limiter, owed))?;
+            this.owed = None;
+        }
+        let chunk = match chunk_size(&this.limiter, buf.len()) {
+            Some(chunk) => chunk.get() as usize,
+            None => return Pin::new(&mut this.inner).poll_read(cx, buf),
+        };
+        ensure_capacity(&this.limiter)?;
+        let read = ready!(Pin::new(&mut this.inner).poll_read(cx, &mut buf[..chunk]))?;
+        this.owed = NonZeroU32::new(read as u32);
+        Poll::Ready(Ok(read))
+    }
+}
+
+/// An [`AsyncWrite`] adapter that limits the rate at which bytes can be written, charging the
+/// rate limiter one cell per byte.
+///
+/// Each write is capped at the rate limiter's burst size, and waits until the rate limiter lets
+/// the bytes through before writing them. If the underlying writer accepts fewer bytes than
+/// were let through, the difference still counts against the rate limit. If the rate limiter's
+/// quota lets no bytes through (see [`Quota::none`][crate::Quota::none]), writes fail with an
+/// error instead of waiting forever.
+pub struct AsyncRateLimitedWriter<'a, W, D: DirectStateStore, C: clock::ReasonablyRealtime> {
+    inner: W,
+    limiter: LimiterRef<'a, NotKeyed, D, C>,
+    admission: Admission,
+    admitted: Option<NonZeroU32>,
+}
+
+impl<'a, W, D: DirectStateStore, C: clock::ReasonablyRealtime> AsyncRateLimitedWriter<'a, W, D, C> {
+    /// Wraps `inner`, limiting the rate at which bytes can be written to it to the rate limit of
+    /// `limiter`.
+    pub fn new(inner: W, limiter: &'a RateLimiter<NotKeyed, D, C>) -> Self {
+        Self::from_ref(inner, LimiterRef::Borrowed(limiter))
+    }
+
+    fn from_ref(inner: W, limiter: LimiterRef<'a, NotKeyed, D, C>) -> Self {
+        AsyncRateLimitedWriter {
+            inner,
+            limiter,
+            admission: Admission::default(),
+            admitted: None,
+        }
+    }
+
+    /// Acquires a reference to the underlying writer.
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Acquires a mutable reference to the underlying writer.
+    ///
+    /// Bytes written directly to the underlying writer don't count against the rate limit.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    /// Consumes this adapter, returning the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W, D: DirectStateStore + 'static, C: clock::ReasonablyRealtime + 'static>
+    AsyncRateLimitedWriter<'static, W, D, C>
+{
+    /// Wraps `inner`, limiting the rate at which bytes can be written to it to the rate limit of
+    /// a rate limiter that the adapter holds on to.
+    pub fn new_owned(inner: W, limiter: impl Into<RateLimiterHandle<NotKeyed, D, C>>) -> Self {
+        Self::from_ref(inner, LimiterRef::Owned(limiter.into()))
+    }
+}
+
+impl<'a, W: AsyncWrite + Unpin, D: DirectStateStore, C: clock::ReasonablyRealtime> AsyncWrite
+    for AsyncRateLimitedWriter<'a, W, D, C>
+{
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        let admitted = match this.admitted {
+            Some(admitted) => admitted,
+            None => {
+                let chunk = match chunk_size(&this.limiter, buf.len()) {
+                    Some(chunk) => chunk,
+                    None => return Pin::new(&mut this.inner).poll_write(cx, buf),
+                };
+                ready!(this.admission.poll(cx, &this.limiter, chunk))?;
+                this.admitted = Some(chunk);
+                chunk
+            }
+        };
+        // The bytes that were let through stay available until the underlying writer accepts
+        // some, even if the caller retries with a different buffer:
+        let len = buf.len().min(admitted.get() as usize);
+        let written = ready!(Pin::new(&mut this.inner).poll_write(cx, &buf[..len]));
+        this.admitted = None;
+        Poll::Ready(written)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_close(cx)
+    }
+}
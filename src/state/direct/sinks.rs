@@ -1,14 +1,20 @@
 use std::prelude::v1::*;
 
+use crate::clock::Delay;
+use crate::handle::LimiterRef;
+use crate::jitter::JitterState;
+use crate::nanos::Nanos;
 use crate::{
     clock,
-    state::{DirectStateStore, NotKeyed},
-    Jitter, RateLimiter,
+    state::{keyed::KeyedStateStore, DirectStateStore, NotKeyed, StateStore},
+    Jitter, RateLimiter, RateLimiterHandle,
 };
+use futures::ready;
 use futures::task::{Context, Poll};
 use futures::{Future, Sink, Stream};
-use futures_timer::Delay;
-use std::marker::PhantomData;
+use nonzero_ext::nonzero;
+use std::hash::Hash;
+use std::num::NonZeroU32;
 use std::pin::Pin;
 
 /// Allows converting a [`futures::Sink`] combinator into a rate-limited sink.
@@ -34,17 +40,91 @@ where
     ) -> RatelimitedSink<'a, Item, S, D, C>
     where
         Self: Sized;
+
+    /// Limits the rate at which items can be put into the current sink to the rate limit of
+    /// `key` on a keyed rate limiter.
+    fn ratelimit_sink_for_key<'a, K, D: KeyedStateStore<K>, C: clock::ReasonablyRealtime>(
+        self,
+        limiter: &'a RateLimiter<K, D, C>,
+        key: K,
+    ) -> RatelimitedSink<'a, Item, S, D, C, K>
+    where
+        Self: Sized,
+        K: Hash + Eq + Clone;
+
+    /// Limits the rate at which items can be put into the current sink to the rate limit of
+    /// `key` on a keyed rate limiter, with a randomized wait period.
+    #[cfg(feature = "jitter")]
+    fn ratelimit_sink_for_key_with_jitter<
+        'a,
+        K,
+        D: KeyedStateStore<K>,
+        C: clock::ReasonablyRealtime,
+    >(
+        self,
+        limiter: &'a RateLimiter<K, D, C>,
+        key: K,
+        jitter: Jitter,
+    ) -> RatelimitedSink<'a, Item, S, D, C, K>
+    where
+        Self: Sized,
+        K: Hash + Eq + Clone;
+
+    /// Limits the rate at which items can be put into the current sink, charging each item the
+    /// number of cells that `cost` returns for it.
+    ///
+    /// This is useful for e.g. limiting the number of bytes sent, rather than the number of
+    /// frames. See [`RatelimitedSink::with_cost`] for details.
+    fn ratelimit_sink_weighted<'a, D: DirectStateStore, C: clock::ReasonablyRealtime, F>(
+        self,
+        limiter: &'a RateLimiter<NotKeyed, D, C>,
+        cost: F,
+    ) -> RatelimitedSink<'a, Item, S, D, C>
+    where
+        Self: Sized,
+        F: Fn(&Item) -> NonZeroU32 + Send + Sync + 'static;
+
+    /// Limits the rate at which items can be put into the current sink, using a rate limiter
+    /// that the combinator holds on to (e.g. a [`RateLimiterHandle`], or an
+    /// [`Arc`][std::sync::Arc] of a rate limiter).
+    ///
+    /// Unlike [`ratelimit_sink`](#tymethod.ratelimit_sink), the resulting combinator doesn't
+    /// borrow the rate limiter, so it can be returned from functions or moved into spawned
+    /// tasks.
+    fn ratelimit_sink_owned<D, C>(
+        self,
+        limiter: impl Into<RateLimiterHandle<NotKeyed, D, C>>,
+    ) -> RatelimitedSink<'static, Item, S, D, C>
+    where
+        Self: Sized,
+        D: DirectStateStore + 'static,
+        C: clock::ReasonablyRealtime + 'static;
+
+    /// Limits the rate at which items can be put into the current sink to the rate limit of
+    /// `key` on a keyed rate limiter that the combinator holds on to.
+    ///
+    /// See [`ratelimit_sink_owned`](#tymethod.ratelimit_sink_owned).
+    fn ratelimit_sink_for_key_owned<K, D, C>(
+        self,
+        limiter: impl Into<RateLimiterHandle<K, D, C>>,
+        key: K,
+    ) -> RatelimitedSink<'static, Item, S, D, C, K>
+    where
+        Self: Sized,
+        K: Hash + Eq + Clone + 'static,
+        D: KeyedStateStore<K> + 'static,
+        C: clock::ReasonablyRealtime + 'static;
 }
 
 impl<Item, S: Sink<Item>> SinkRateLimitExt<Item, S> for S {
     fn ratelimit_sink<D: DirectStateStore, C: clock::ReasonablyRealtime>(
         self,
         limiter: &RateLimiter<NotKeyed, D, C>,
-    ) -> RatelimitedSink<Item, S, D, C>
+    ) -> RatelimitedSink<'_, Item, S, D, C>
     where
         Self: Sized,
     {
-        RatelimitedSink::new(self, limiter, Jitter::NONE)
+        RatelimitedSink::new(self, LimiterRef::Borrowed(limiter), NotKeyed::NonKey)
     }
 
     #[cfg(feature = "jitter")]
@@ -52,11 +132,76 @@ impl<Item, S: Sink<Item>> SinkRateLimitExt<Item, S> for S {
         self,
         limiter: &RateLimiter<NotKeyed, D, C>,
         jitter: Jitter,
-    ) -> RatelimitedSink<Item, S, D, C>
+    ) -> RatelimitedSink<'_, Item, S, D, C>
+    where
+        Self: Sized,
+    {
+        RatelimitedSink::new(self, LimiterRef::Borrowed(limiter), NotKeyed::NonKey)
+            .with_jitter(jitter)
+    }
+
+    fn ratelimit_sink_for_key<K, D: KeyedStateStore<K>, C: clock::ReasonablyRealtime>(
+        self,
+        limiter: &RateLimiter<K, D, C>,
+        key: K,
+    ) -> RatelimitedSink<'_, Item, S, D, C, K>
+    where
+        Self: Sized,
+        K: Hash + Eq + Clone,
+    {
+        RatelimitedSink::new(self, LimiterRef::Borrowed(limiter), key)
+    }
+
+    #[cfg(feature = "jitter")]
+    fn ratelimit_sink_for_key_with_jitter<K, D: KeyedStateStore<K>, C: clock::ReasonablyRealtime>(
+        self,
+        limiter: &RateLimiter<K, D, C>,
+        key: K,
+        jitter: Jitter,
+    ) -> RatelimitedSink<'_, Item, S, D, C, K>
+    where
+        Self: Sized,
+        K: Hash + Eq + Clone,
+    {
+        RatelimitedSink::new(self, LimiterRef::Borrowed(limiter), key).with_jitter(jitter)
+    }
+
+    fn ratelimit_sink_weighted<D: DirectStateStore, C: clock::ReasonablyRealtime, F>(
+        self,
+        limiter: &RateLimiter<NotKeyed, D, C>,
+        cost: F,
+    ) -> RatelimitedSink<'_, Item, S, D, C>
+    where
+        Self: Sized,
+        F: Fn(&Item) -> NonZeroU32 + Send + Sync + 'static,
+    {
+        self.ratelimit_sink(limiter).with_cost(cost)
+    }
+
+    fn ratelimit_sink_owned<D, C>(
+        self,
+        limiter: impl Into<RateLimiterHandle<NotKeyed, D, C>>,
+    ) -> RatelimitedSink<'static, Item, S, D, C>
+    where
+        Self: Sized,
+        D: DirectStateStore + 'static,
+        C: clock::ReasonablyRealtime + 'static,
+    {
+        RatelimitedSink::new(self, LimiterRef::Owned(limiter.into()), NotKeyed::NonKey)
+    }
+
+    fn ratelimit_sink_for_key_owned<K, D, C>(
+        self,
+        limiter: impl Into<RateLimiterHandle<K, D, C>>,
+        key: K,
+    ) -> RatelimitedSink<'static, Item, S, D, C, K>
     where
         Self: Sized,
+        K: Hash + Eq + Clone + 'static,
+        D: KeyedStateStore<K> + 'static,
+        C: clock::ReasonablyRealtime + 'static,
     {
-        RatelimitedSink::new(self, limiter, jitter)
+        RatelimitedSink::new(self, LimiterRef::Owned(limiter.into()), key)
     }
 }
 
@@ -69,33 +214,102 @@ enum State {
 
 /// A [`Sink`][futures::Sink] combinator that only allows sending elements when the rate-limiter
 /// allows it.
+///
+/// On keyed rate limiters, the combinator uses the rate limit of a single key, `K`. Combinators
+/// that hold on to their rate limiter (rather than borrowing it) have a lifetime `'a` of
+/// `'static`.
 pub struct RatelimitedSink<
     'a,
     Item,
     S: Sink<Item>,
-    D: DirectStateStore,
+    D: StateStore<Key = K>,
     C: clock::ReasonablyRealtime,
+    K = NotKeyed,
 > {
     inner: S,
     state: State,
-    limiter: &'a RateLimiter<NotKeyed, D, C>,
+    limiter: LimiterRef<'a, K, D, C>,
+    key: K,
     delay: Delay,
     jitter: Jitter,
-    phantom: PhantomData<Item>,
+    jitter_state: JitterState,
+    cost: Option<Cost<Item>>,
+    buf: Option<(Item, NonZeroU32)>,
+    // How many of the cells that the current item costs the rate limiter has yet to let
+    // through, once it has let some of them through.
+    owed: Option<NonZeroU32>,
+    pacing: bool,
+    // When a paced combinator may let the next item through, relative to the rate limiter's
+    // construction.
+    paced_until: Option<Nanos>,
 }
 
+/// The function that determines the number of cells an item costs.
+type Cost<Item> = Box<dyn Fn(&Item) -> NonZeroU32 + Send + Sync>;
+
 /// Conversion methods for the sink combinator.
-impl<'a, Item, S: Sink<Item>, D: DirectStateStore, C: clock::ReasonablyRealtime>
-    RatelimitedSink<'a, Item, S, D, C>
+impl<'a, Item, S: Sink<Item>, D: StateStore<Key = K>, C: clock::ReasonablyRealtime, K>
+    RatelimitedSink<'a, Item, S, D, C, K>
 {
-    fn new(inner: S, limiter: &'a RateLimiter<NotKeyed, D, C>, jitter: Jitter) -> Self {
+    fn new(inner: S, limiter: LimiterRef<'a, K, D, C>, key: K) -> Self {
         RatelimitedSink {
             inner,
+            delay: limiter.clock().delay(Default::default()),
             limiter,
-            delay: Delay::new(Default::default()),
+            key,
             state: State::NotReady,
-            jitter,
-            phantom: PhantomData,
+            jitter: Jitter::NONE,
+            jitter_state: JitterState::default(),
+            cost: None,
+            buf: None,
+            owed: None,
+            pacing: false,
+            paced_until: None,
+        }
+    }
+
+    /// Makes the combinator wait a randomized period (see [`Jitter`]) on top of the time that
+    /// the rate limiter asks for, whenever it has to wait.
+    ///
+    /// This must be called before any items are sent.
+    pub fn with_jitter(self, jitter: Jitter) -> Self {
+        RatelimitedSink { jitter, ..self }
+    }
+
+    /// Makes the combinator charge each item the number of cells that `cost` returns for it,
+    /// instead of one cell per item.
+    ///
+    /// As the cost of an item is only known once it is sent, a weighted sink accepts each item
+    /// right away, and holds it back until the rate limiter lets its cost through (at most one
+    /// item at a time). Items that cost more than the rate limiter's burst capacity are charged
+    /// in instalments of at most the burst capacity, waiting for each instalment to be let
+    /// through before the item is sent.
+    ///
+    /// This must be called before any items are sent.
+    pub fn with_cost<F>(self, cost: F) -> Self
+    where
+        F: Fn(&Item) -> NonZeroU32 + Send + Sync + 'static,
+    {
+        RatelimitedSink {
+            cost: Some(Box::new(cost)),
+            ..self
+        }
+    }
+
+    /// Makes the combinator space items out evenly, by the rate limiter's emission interval
+    /// (the time it takes to replenish one cell), instead of letting a burst of items through
+    /// at once and then throttling.
+    ///
+    /// Each item is sent no earlier than one emission interval (times the number of cells it
+    /// costs) after the previous one, and only once the rate limiter lets it through. This is
+    /// useful for sending to peers that enforce their rate limit strictly, without tolerating
+    /// bursts.
+    ///
+    /// This must be called before any items are sent.
+    pub fn with_pacing(self) -> Self {
+        RatelimitedSink {
+            pacing: true,
+            ..self
         }
     }
 
@@ -110,37 +324,51 @@ impl<'a, Item, S: Sink<Item>, D: DirectStateStore, C: clock::ReasonablyRealtime>
     }
 
     /// Consumes this combinator, returning the underlying sink.
+    ///
+    /// Any item that a [weighted](#method.with_cost) sink is holding back is dropped.
     pub fn into_inner(self) -> S {
         self.inner
     }
 }
 
-impl<'a, Item, S: Sink<Item>, D: DirectStateStore, C: clock::ReasonablyRealtime> Sink<Item>
-    for RatelimitedSink<'a, Item, S, D, C>
+impl<'a, Item, S: Sink<Item>, D: StateStore<Key = K>, C: clock::ReasonablyRealtime, K>
+    RatelimitedSink<'a, Item, S, D, C, K>
 where
     S: Unpin,
-    Item: Unpin,
 {
-    type Error = S::Error;
-
-    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+    /// Waits until the rate limiter lets `n` cells through, in instalments of at most its burst
+    /// capacity.
+    fn poll_admitted(&mut self, cx: &mut Context<'_>, n: NonZeroU32) -> Poll<()> {
         loop {
             match self.state {
                 State::NotReady => {
                     let reference = self.limiter.reference_reading();
-                    if let Err(negative) = self.limiter.check() {
-                        let earliest = negative.wait_time_with_offset(reference, self.jitter);
-                        self.delay.reset(earliest);
-                        let future = Pin::new(&mut self.delay);
-                        match future.poll(cx) {
-                            Poll::Pending => {
+                    let now = self.limiter.clock().now();
+                    let elapsed = self.limiter.elapsed_at(now);
+                    if let Some(until) = self.paced_until.filter(|until| *until > elapsed) {
+                        self.delay.reset(until.saturating_sub(elapsed).into());
+                        self.state = State::Wait;
+                    } else {
+                        let owed = self.owed.unwrap_or(n);
+                        match self.limiter.test_key_instalment_at(&self.key, owed, now) {
+                            Err(negative) => {
+                                let offset = self.jitter.next(&mut self.jitter_state);
+                                let earliest = negative.wait_time_with_offset(reference, offset);
+                                self.delay.reset(earliest);
                                 self.state = State::Wait;
-                                return Poll::Pending;
                             }
-                            Poll::Ready(_) => {}
+                            Ok(charged) => {
+                                self.jitter_state = JitterState::default();
+                                self.owed = NonZeroU32::new(owed.get() - charged.get());
+                                if self.owed.is_none() {
+                                    if self.pacing {
+                                        self.paced_until =
+                                            Some(elapsed + self.limiter.pacing_interval(n));
+                                    }
+                                    self.state = State::Ready;
+                                }
+                            }
                         }
-                    } else {
-                        self.state = State::Ready;
                     }
                 }
                 State::Wait => {
@@ -154,15 +382,58 @@ where
                         }
                     }
                 }
-                State::Ready => {
-                    let inner = Pin::new(&mut self.inner);
-                    return inner.poll_ready(cx);
-                }
+                State::Ready => return Poll::Ready(()),
             }
         }
     }
 
+    /// Sends the item that a weighted sink holds back into the underlying sink, once the rate
+    /// limiter lets its cost through.
+    fn poll_send_buffered(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), S::Error>> {
+        let n = match &self.buf {
+            Some((_, n)) => *n,
+            None => return Poll::Ready(Ok(())),
+        };
+        ready!(self.poll_admitted(cx, n));
+        ready!(Pin::new(&mut self.inner).poll_ready(cx))?;
+        let (item, _) = self.buf.take().expect("buffered item should be present");
+        self.state = State::NotReady;
+        Poll::Ready(Pin::new(&mut self.inner).start_send(item))
+    }
+}
+
+impl<'a, Item, S: Sink<Item>, D: StateStore<Key = K>, C: clock::ReasonablyRealtime, K> Sink<Item>
+    for RatelimitedSink<'a, Item, S, D, C, K>
+where
+    S: Unpin,
+    Item: Unpin,
+    K: Unpin,
+{
+    type Error = S::Error;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = &mut *self;
+        if this.cost.is_some() {
+            // Weighted sinks accept the item first, and hold it back until its cost is known and
+            // let through:
+            ready!(this.poll_send_buffered(cx))?;
+        } else {
+            ready!(this.poll_admitted(cx, nonzero!(1u32)));
+        }
+        let inner = Pin::new(&mut this.inner);
+        inner.poll_ready(cx)
+    }
+
     fn start_send(mut self: Pin<&mut Self>, item: Item) -> Result<(), Self::Error> {
+        if let Some(cost) = &self.cost {
+            assert!(
+                self.buf.is_none(),
+                "Protocol violation: should not start_send before we say we can"
+            );
+            let n = cost(&item);
+            self.buf = Some((item, n));
+            return Ok(());
+        }
         match self.state {
             State::Wait | State::NotReady => {
                 unreachable!("Protocol violation: should not start_send before we say we can");
@@ -176,23 +447,26 @@ where
     }
 
     fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        ready!(self.poll_send_buffered(cx))?;
         let inner = Pin::new(&mut self.inner);
         inner.poll_flush(cx)
     }
 
     fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        ready!(self.poll_send_buffered(cx))?;
         let inner = Pin::new(&mut self.inner);
         inner.poll_close(cx)
     }
 }
 
 /// Pass-through implementation for [`futures::Stream`] if the Sink also implements it.
-impl<'a, Item, S: Stream + Sink<Item>, D: DirectStateStore, C: clock::ReasonablyRealtime> Stream
-    for RatelimitedSink<'a, Item, S, D, C>
+impl<'a, Item, S: Stream + Sink<Item>, D: StateStore<Key = K>, C: clock::ReasonablyRealtime, K>
+    Stream for RatelimitedSink<'a, Item, S, D, C, K>
 where
     S::Item: Unpin,
     S: Unpin,
     Item: Unpin,
+    K: Unpin,
 {
     type Item = <S as Stream>::Item;
 
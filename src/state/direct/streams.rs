@@ -2,11 +2,17 @@
 
 use std::prelude::v1::*;
 
-use crate::state::{DirectStateStore, NotKeyed};
-use crate::{clock, Jitter, RateLimiter};
+use crate::clock::Delay;
+use crate::handle::LimiterRef;
+use crate::jitter::JitterState;
+use crate::nanos::Nanos;
+use crate::state::{keyed::KeyedStateStore, DirectStateStore, NotKeyed, StateStore};
+use crate::{clock, Jitter, RateLimiter, RateLimiterHandle};
 use futures::task::{Context, Poll};
 use futures::{Future, Sink, Stream};
-use futures_timer::Delay;
+use nonzero_ext::nonzero;
+use std::hash::Hash;
+use std::num::NonZeroU32;
 use std::pin::Pin;
 use std::time::Duration;
 
@@ -19,7 +25,7 @@ pub trait StreamRateLimitExt<'a>: Stream {
     /// The combinator will buffer at most one item in order to adhere to the
     /// given limiter. I.e. if it already has an item buffered and needs to wait
     /// it will not `poll` the underlying stream.
-    fn ratelimit_stream<D: DirectStateStore, C: clock::Clock>(
+    fn ratelimit_stream<D: DirectStateStore, C>(
         self,
         limiter: &'a RateLimiter<NotKeyed, D, C>,
     ) -> RatelimitedStream<'a, Self, D, C>
@@ -34,7 +40,7 @@ pub trait StreamRateLimitExt<'a>: Stream {
     /// The combinator will buffer at most one item in order to adhere to the
     /// given limiter. I.e. if it already has an item buffered and needs to wait
     /// it will not `poll` the underlying stream.
-    fn ratelimit_stream_with_jitter<D: DirectStateStore, C: clock::Clock>(
+    fn ratelimit_stream_with_jitter<D: DirectStateStore, C>(
         self,
         limiter: &'a RateLimiter<NotKeyed, D, C>,
         jitter: Jitter,
@@ -42,10 +48,114 @@ pub trait StreamRateLimitExt<'a>: Stream {
     where
         Self: Sized,
         C: clock::ReasonablyRealtime;
+
+    /// Limits the rate at which the stream produces items to the rate limit of `key` on a keyed
+    /// rate limiter.
+    ///
+    /// Like [`ratelimit_stream`](#tymethod.ratelimit_stream), this combinator buffers at most
+    /// one item.
+    fn ratelimit_stream_for_key<K, D: KeyedStateStore<K>, C>(
+        self,
+        limiter: &'a RateLimiter<K, D, C>,
+        key: K,
+    ) -> RatelimitedStream<'a, Self, D, C, K>
+    where
+        Self: Sized,
+        K: Hash + Eq + Clone,
+        C: clock::ReasonablyRealtime;
+
+    /// Limits the rate at which the stream produces items to the rate limit of `key` on a keyed
+    /// rate limiter, with a randomized wait period.
+    ///
+    /// Like [`ratelimit_stream`](#tymethod.ratelimit_stream), this combinator buffers at most
+    /// one item.
+    fn ratelimit_stream_for_key_with_jitter<K, D: KeyedStateStore<K>, C>(
+        self,
+        limiter: &'a RateLimiter<K, D, C>,
+        key: K,
+        jitter: Jitter,
+    ) -> RatelimitedStream<'a, Self, D, C, K>
+    where
+        Self: Sized,
+        K: Hash + Eq + Clone,
+        C: clock::ReasonablyRealtime;
+
+    /// Limits the rate at which the stream produces items, charging each item the number of
+    /// cells that `cost` returns for it.
+    ///
+    /// Like [`ratelimit_stream`](#tymethod.ratelimit_stream), this combinator buffers at most
+    /// one item. See [`RatelimitedStream::with_cost`] for details.
+    fn ratelimit_stream_weighted<D: DirectStateStore, C, F>(
+        self,
+        limiter: &'a RateLimiter<NotKeyed, D, C>,
+        cost: F,
+    ) -> RatelimitedStream<'a, Self, D, C>
+    where
+        Self: Sized,
+        C: clock::ReasonablyRealtime,
+        F: Fn(&Self::Item) -> NonZeroU32 + Send + Sync + 'static;
+
+    /// Limits the rate at which the stream produces items by dropping the items that exceed the
+    /// rate limit, instead of waiting until they can be let through.
+    ///
+    /// This is useful where delaying items is worse than discarding them, e.g. when sampling
+    /// telemetry events. The combinator never buffers items, and polls the underlying stream as
+    /// fast as it is polled itself.
+    fn ratelimit_filter<D: DirectStateStore, C: clock::Clock>(
+        self,
+        limiter: &'a RateLimiter<NotKeyed, D, C>,
+    ) -> RatelimitFilter<'a, Self, D, C>
+    where
+        Self: Sized;
+
+    /// Limits the rate at which the stream produces items by passing the items that exceed the
+    /// rate limit to `overflow` (e.g., to send them to a side channel), instead of waiting until
+    /// they can be let through.
+    ///
+    /// See [`ratelimit_filter`](#tymethod.ratelimit_filter).
+    fn ratelimit_filter_with_overflow<D: DirectStateStore, C: clock::Clock, F>(
+        self,
+        limiter: &'a RateLimiter<NotKeyed, D, C>,
+        overflow: F,
+    ) -> RatelimitFilter<'a, Self, D, C>
+    where
+        Self: Sized,
+        F: FnMut(Self::Item) + Send + 'static;
+
+    /// Limits the rate at which the stream produces items, using a rate limiter that the
+    /// combinator holds on to (e.g. a [`RateLimiterHandle`], or an [`Arc`][std::sync::Arc] of a
+    /// rate limiter).
+    ///
+    /// Unlike [`ratelimit_stream`](#tymethod.ratelimit_stream), the resulting combinator
+    /// doesn't borrow the rate limiter, so it can be returned from functions or moved into
+    /// spawned tasks.
+    fn ratelimit_stream_owned<D, C>(
+        self,
+        limiter: impl Into<RateLimiterHandle<NotKeyed, D, C>>,
+    ) -> RatelimitedStream<'static, Self, D, C>
+    where
+        Self: Sized,
+        D: DirectStateStore + 'static,
+        C: clock::ReasonablyRealtime + 'static;
+
+    /// Limits the rate at which the stream produces items to the rate limit of `key` on a keyed
+    /// rate limiter that the combinator holds on to.
+    ///
+    /// See [`ratelimit_stream_owned`](#tymethod.ratelimit_stream_owned).
+    fn ratelimit_stream_for_key_owned<K, D, C>(
+        self,
+        limiter: impl Into<RateLimiterHandle<K, D, C>>,
+        key: K,
+    ) -> RatelimitedStream<'static, Self, D, C, K>
+    where
+        Self: Sized,
+        K: Hash + Eq + Clone + 'static,
+        D: KeyedStateStore<K> + 'static,
+        C: clock::ReasonablyRealtime + 'static;
 }
 
 impl<'a, S: Stream> StreamRateLimitExt<'a> for S {
-    fn ratelimit_stream<D: DirectStateStore, C: clock::Clock>(
+    fn ratelimit_stream<D: DirectStateStore, C>(
         self,
         limiter: &'a RateLimiter<NotKeyed, D, C>,
     ) -> RatelimitedStream<'a, Self, D, C>
@@ -56,7 +166,7 @@ impl<'a, S: Stream> StreamRateLimitExt<'a> for S {
         self.ratelimit_stream_with_jitter(limiter, Jitter::NONE)
     }
 
-    fn ratelimit_stream_with_jitter<D: DirectStateStore, C: clock::Clock>(
+    fn ratelimit_stream_with_jitter<D: DirectStateStore, C>(
         self,
         limiter: &'a RateLimiter<NotKeyed, D, C>,
         jitter: Jitter,
@@ -65,15 +175,107 @@ impl<'a, S: Stream> StreamRateLimitExt<'a> for S {
         Self: Sized,
         C: clock::ReasonablyRealtime,
     {
-        RatelimitedStream {
+        RatelimitedStream::new(self, LimiterRef::Borrowed(limiter), NotKeyed::NonKey)
+            .with_jitter(jitter)
+    }
+
+    fn ratelimit_stream_for_key<K, D: KeyedStateStore<K>, C>(
+        self,
+        limiter: &'a RateLimiter<K, D, C>,
+        key: K,
+    ) -> RatelimitedStream<'a, Self, D, C, K>
+    where
+        Self: Sized,
+        K: Hash + Eq + Clone,
+        C: clock::ReasonablyRealtime,
+    {
+        self.ratelimit_stream_for_key_with_jitter(limiter, key, Jitter::NONE)
+    }
+
+    fn ratelimit_stream_for_key_with_jitter<K, D: KeyedStateStore<K>, C>(
+        self,
+        limiter: &'a RateLimiter<K, D, C>,
+        key: K,
+        jitter: Jitter,
+    ) -> RatelimitedStream<'a, Self, D, C, K>
+    where
+        Self: Sized,
+        K: Hash + Eq + Clone,
+        C: clock::ReasonablyRealtime,
+    {
+        RatelimitedStream::new(self, LimiterRef::Borrowed(limiter), key).with_jitter(jitter)
+    }
+
+    fn ratelimit_stream_weighted<D: DirectStateStore, C, F>(
+        self,
+        limiter: &'a RateLimiter<NotKeyed, D, C>,
+        cost: F,
+    ) -> RatelimitedStream<'a, Self, D, C>
+    where
+        Self: Sized,
+        C: clock::ReasonablyRealtime,
+        F: Fn(&Self::Item) -> NonZeroU32 + Send + Sync + 'static,
+    {
+        self.ratelimit_stream(limiter).with_cost(cost)
+    }
+
+    fn ratelimit_filter<D: DirectStateStore, C: clock::Clock>(
+        self,
+        limiter: &'a RateLimiter<NotKeyed, D, C>,
+    ) -> RatelimitFilter<'a, Self, D, C>
+    where
+        Self: Sized,
+    {
+        RatelimitFilter {
             inner: self,
             limiter,
-            buf: None,
-            delay: Delay::new(Duration::new(0, 0)),
-            jitter,
-            state: State::ReadInner,
+            overflow: None,
+            dropped: 0,
         }
     }
+
+    fn ratelimit_filter_with_overflow<D: DirectStateStore, C: clock::Clock, F>(
+        self,
+        limiter: &'a RateLimiter<NotKeyed, D, C>,
+        overflow: F,
+    ) -> RatelimitFilter<'a, Self, D, C>
+    where
+        Self: Sized,
+        F: FnMut(Self::Item) + Send + 'static,
+    {
+        RatelimitFilter {
+            inner: self,
+            limiter,
+            overflow: Some(Box::new(overflow)),
+            dropped: 0,
+        }
+    }
+
+    fn ratelimit_stream_owned<D, C>(
+        self,
+        limiter: impl Into<RateLimiterHandle<NotKeyed, D, C>>,
+    ) -> RatelimitedStream<'static, Self, D, C>
+    where
+        Self: Sized,
+        D: DirectStateStore + 'static,
+        C: clock::ReasonablyRealtime + 'static,
+    {
+        RatelimitedStream::new(self, LimiterRef::Owned(limiter.into()), NotKeyed::NonKey)
+    }
+
+    fn ratelimit_stream_for_key_owned<K, D, C>(
+        self,
+        limiter: impl Into<RateLimiterHandle<K, D, C>>,
+        key: K,
+    ) -> RatelimitedStream<'static, Self, D, C, K>
+    where
+        Self: Sized,
+        K: Hash + Eq + Clone + 'static,
+        D: KeyedStateStore<K> + 'static,
+        C: clock::ReasonablyRealtime + 'static,
+    {
+        RatelimitedStream::new(self, LimiterRef::Owned(limiter.into()), key)
+    }
 }
 
 #[derive(PartialEq, Debug)]
@@ -86,18 +288,90 @@ enum State {
 /// A [`Stream`][futures::Stream] combinator which will limit the rate of items being received.
 ///
 /// This is produced by the [`StreamRateLimitExt::ratelimit_stream`] and
-/// [`StreamRateLimitExt::ratelimit_stream_with_jitter`] methods.
-pub struct RatelimitedStream<'a, S: Stream, D: DirectStateStore, C: clock::Clock> {
+/// [`StreamRateLimitExt::ratelimit_stream_with_jitter`] methods, (using the rate limit of a
+/// single key `K` on a keyed rate limiter) their `_for_key` variants, and (holding on to the
+/// rate limiter instead of borrowing it, in which case `'a` is `'static`) the `_owned` variants
+/// of the former. [`with_jitter`](#method.with_jitter) adds jitter to any of them.
+pub struct RatelimitedStream<'a, S: Stream, D: StateStore<Key = K>, C: clock::Clock, K = NotKeyed> {
     inner: S,
-    limiter: &'a RateLimiter<NotKeyed, D, C>,
+    limiter: LimiterRef<'a, K, D, C>,
+    key: K,
     delay: Delay,
     buf: Option<S::Item>,
     jitter: Jitter,
+    jitter_state: JitterState,
+    cost: Option<Cost<S::Item>>,
+    // How many of the cells that the current item costs the rate limiter has yet to let
+    // through, once it has let some of them through.
+    owed: Option<NonZeroU32>,
     state: State,
+    pacing: bool,
+    // When a paced combinator may let the next item through, relative to the rate limiter's
+    // construction.
+    paced_until: Option<Nanos>,
+}
+
+/// The function that determines the number of cells an item costs.
+type Cost<Item> = Box<dyn Fn(&Item) -> NonZeroU32 + Send + Sync>;
+
+impl<'a, S: Stream, D: StateStore<Key = K>, C: clock::ReasonablyRealtime, K>
+    RatelimitedStream<'a, S, D, C, K>
+{
+    fn new(inner: S, limiter: LimiterRef<'a, K, D, C>, key: K) -> Self {
+        RatelimitedStream {
+            inner,
+            delay: limiter.clock().delay(Duration::new(0, 0)),
+            limiter,
+            key,
+            buf: None,
+            jitter: Jitter::NONE,
+            jitter_state: JitterState::default(),
+            cost: None,
+            owed: None,
+            state: State::ReadInner,
+            pacing: false,
+            paced_until: None,
+        }
+    }
+
+    /// Makes the combinator wait a randomized period (see [`Jitter`]) on top of the time that
+    /// the rate limiter asks for, whenever it has to wait.
+    pub fn with_jitter(self, jitter: Jitter) -> Self {
+        RatelimitedStream { jitter, ..self }
+    }
+
+    /// Makes the combinator charge each item the number of cells that `cost` returns for it,
+    /// instead of one cell per item.
+    ///
+    /// Items that cost more than the rate limiter's burst capacity are charged in instalments of
+    /// at most the burst capacity, waiting for each instalment to be let through before the
+    /// item is produced.
+    pub fn with_cost<F>(self, cost: F) -> Self
+    where
+        F: Fn(&S::Item) -> NonZeroU32 + Send + Sync + 'static,
+    {
+        RatelimitedStream {
+            cost: Some(Box::new(cost)),
+            ..self
+        }
+    }
+
+    /// Makes the combinator space items out evenly, by the rate limiter's emission interval
+    /// (the time it takes to replenish one cell), instead of letting a burst of items through
+    /// at once and then throttling.
+    ///
+    /// Each item is produced no earlier than one emission interval (times the number of cells
+    /// it costs) after the previous one, and only once the rate limiter lets it through.
+    pub fn with_pacing(self) -> Self {
+        RatelimitedStream {
+            pacing: true,
+            ..self
+        }
+    }
 }
 
 /// Conversion methods for the stream combinator.
-impl<'a, S: Stream, D: DirectStateStore, C: clock::Clock> RatelimitedStream<'a, S, D, C> {
+impl<'a, S: Stream, D: StateStore<Key = K>, C: clock::Clock, K> RatelimitedStream<'a, S, D, C, K> {
     /// Acquires a reference to the underlying stream that this combinator is pulling from.
     pub fn get_ref(&self) -> &S {
         &self.inner
@@ -117,7 +391,8 @@ impl<'a, S: Stream, D: DirectStateStore, C: clock::Clock> RatelimitedStream<'a,
 }
 
 /// Implements the [`futures::Stream`] combinator.
-impl<'a, S: Stream, D: DirectStateStore, C: clock::Clock> Stream for RatelimitedStream<'a, S, D, C>
+impl<'a, S: Stream, D: StateStore<Key = K>, C: clock::Clock, K> Stream
+    for RatelimitedStream<'a, S, D, C, K>
 where
     S: Unpin,
     S::Item: Unpin,
@@ -145,20 +420,45 @@ where
                 }
                 State::NotReady => {
                     let reference = self.limiter.reference_reading();
-                    if let Err(negative) = self.limiter.check() {
-                        let earliest = negative.wait_time_with_offset(reference, self.jitter);
-                        self.delay.reset(earliest);
-                        let future = Pin::new(&mut self.delay);
-                        match future.poll(cx) {
-                            Poll::Pending => {
-                                self.state = State::Wait;
-                                return Poll::Pending;
+                    let now = self.limiter.clock().now();
+                    let n = match (&self.cost, &self.buf) {
+                        (Some(cost), Some(item)) => cost(item),
+                        _ => nonzero!(1u32),
+                    };
+                    let elapsed = self.limiter.elapsed_at(now);
+                    if let Some(until) = self.paced_until.filter(|until| *until > elapsed) {
+                        self.delay.reset(until.saturating_sub(elapsed).into());
+                        self.state = State::Wait;
+                    } else {
+                        let owed = self.owed.unwrap_or(n);
+                        match self.limiter.test_key_instalment_at(&self.key, owed, now) {
+                            Err(negative) => {
+                                let this = &mut *self;
+                                let offset = this.jitter.next(&mut this.jitter_state);
+                                let earliest = negative.wait_time_with_offset(reference, offset);
+                                self.delay.reset(earliest);
+                                let future = Pin::new(&mut self.delay);
+                                match future.poll(cx) {
+                                    Poll::Pending => {
+                                        self.state = State::Wait;
+                                        return Poll::Pending;
+                                    }
+                                    Poll::Ready(_) => {}
+                                }
+                            }
+                            Ok(charged) => {
+                                self.jitter_state = JitterState::default();
+                                self.owed = NonZeroU32::new(owed.get() - charged.get());
+                                if self.owed.is_none() {
+                                    if self.pacing {
+                                        self.paced_until =
+                                            Some(elapsed + self.limiter.pacing_interval(n));
+                                    }
+                                    self.state = State::ReadInner;
+                                    return Poll::Ready(self.buf.take());
+                                }
                             }
-                            Poll::Ready(_) => {}
                         }
-                    } else {
-                        self.state = State::ReadInner;
-                        return Poll::Ready(self.buf.take());
                     }
                 }
                 State::Wait => {
@@ -182,11 +482,12 @@ where
 }
 
 /// Pass-through implementation for [`futures::Sink`] if the Stream also implements it.
-impl<'a, Item, S: Stream + Sink<Item>, D: DirectStateStore, C: clock::Clock> Sink<Item>
-    for RatelimitedStream<'a, S, D, C>
+impl<'a, Item, S: Stream + Sink<Item>, D: StateStore<Key = K>, C: clock::Clock, K> Sink<Item>
+    for RatelimitedStream<'a, S, D, C, K>
 where
     S: Unpin,
     S::Item: Unpin,
+    K: Unpin,
 {
     type Error = <S as Sink<Item>>::Error;
 
@@ -210,3 +511,67 @@ where
         inner.poll_close(cx)
     }
 }
+
+/// A [`Stream`][futures::Stream] combinator which drops the items that exceed the rate limit.
+///
+/// This is produced by the [`StreamRateLimitExt::ratelimit_filter`] and
+/// [`StreamRateLimitExt::ratelimit_filter_with_overflow`] methods.
+pub struct RatelimitFilter<'a, S: Stream, D: DirectStateStore, C: clock::Clock> {
+    inner: S,
+    limiter: &'a RateLimiter<NotKeyed, D, C>,
+    overflow: Option<Box<dyn FnMut(S::Item) + Send>>,
+    dropped: u64,
+}
+
+impl<'a, S: Stream, D: DirectStateStore, C: clock::Clock> RatelimitFilter<'a, S, D, C> {
+    /// Returns the number of items that exceeded the rate limit so far.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Acquires a reference to the underlying stream that this combinator is pulling from.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Acquires a mutable reference to the underlying stream that this combinator is pulling from.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes this combinator, returning the underlying stream.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+/// Implements the [`futures::Stream`] combinator.
+impl<'a, S: Stream, D: DirectStateStore, C: clock::Clock> Stream for RatelimitFilter<'a, S, D, C>
+where
+    S: Unpin,
+{
+    type Item = S::Item;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            let inner = Pin::new(&mut self.inner);
+            match inner.poll_next(cx) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Ready(Some(item)) => {
+                    if self.limiter.check().is_ok() {
+                        return Poll::Ready(Some(item));
+                    }
+                    self.dropped += 1;
+                    if let Some(overflow) = &mut self.overflow {
+                        overflow(item);
+                    }
+                }
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.inner.size_hint().1)
+    }
+}
@@ -0,0 +1,264 @@
+use std::prelude::v1::*;
+
+use crate::clock::Reference;
+use crate::state::StateStore;
+use crate::{clock, NotUntil, Quota, RateLimiter, StateSnapshot};
+use std::fmt;
+use std::num::NonZeroU32;
+
+type AllowedHook<K> = Box<dyn Fn(&K, NonZeroU32) + Send + Sync>;
+type DeniedHook<K, P> = Box<dyn Fn(&K, NonZeroU32, &NotUntil<P>) + Send + Sync>;
+type DecidedHook<K, P> = Box<dyn Fn(&K, &Decision<P>) + Send + Sync>;
+
+/// The callbacks that a rate limiter calls with the outcome of each decision.
+pub(crate) struct Hooks<K, P: clock::Reference> {
+    allowed: Vec<AllowedHook<K>>,
+    denied: Vec<DeniedHook<K, P>>,
+    decided: Vec<DecidedHook<K, P>>,
+}
+
+impl<K, P: clock::Reference> Hooks<K, P> {
+    pub(crate) const fn new() -> Self {
+        Hooks {
+            allowed: Vec::new(),
+            denied: Vec::new(),
+            decided: Vec::new(),
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.allowed.is_empty() && self.denied.is_empty() && self.decided.is_empty()
+    }
+
+    /// Returns whether any callbacks want to know about negative outcomes.
+    pub(crate) fn wants_negative(&self) -> bool {
+        !self.denied.is_empty() || !self.decided.is_empty()
+    }
+}
+
+impl<K, P: clock::Reference> Default for Hooks<K, P> {
+    fn default() -> Self {
+        Hooks::new()
+    }
+}
+
+impl<K, P: clock::Reference> fmt::Debug for Hooks<K, P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        f.debug_struct("Hooks")
+            .field("allowed", &self.allowed.len())
+            .field("denied", &self.denied.len())
+            .field("decided", &self.decided.len())
+            .finish()
+    }
+}
+
+/// A rate limiting decision, along with the rate limiter's parameters at the time it was made.
+///
+/// This is what callbacks registered with [`on_decision`](struct.RateLimiter.html#method.on_decision)
+/// get called with. It lets them compute e.g. the absolute time at which a key's bucket is
+/// replenished, or the ratio of the remaining burst capacity, without needing access to the
+/// rate limiter.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Decision<P: clock::Reference> {
+    cells: NonZeroU32,
+    negative: Option<NotUntil<P>>,
+    quota: Quota,
+    start: P,
+    state: StateSnapshot,
+}
+
+impl<P: clock::Reference> Decision<P> {
+    /// Returns the number of cells that were tested.
+    pub fn cells(&self) -> NonZeroU32 {
+        self.cells
+    }
+
+    /// Returns whether the cells were let through.
+    pub fn is_allowed(&self) -> bool {
+        self.negative.is_none()
+    }
+
+    /// Returns the negative outcome of the decision, if the cells were rejected.
+    ///
+    /// Batches of cells that exceed the burst capacity have a negative outcome indicating a
+    /// wait time so far in the future that it will not practically conform (see
+    /// [`on_denied`](struct.RateLimiter.html#method.on_denied)).
+    pub fn negative(&self) -> Option<&NotUntil<P>> {
+        self.negative.as_ref()
+    }
+
+    /// Returns the rate limiter's quota at the time of the decision.
+    pub fn quota(&self) -> Quota {
+        self.quota
+    }
+
+    /// Returns the time at which the rate limiter was constructed, which the times in the
+    /// [state snapshot](#method.state_snapshot) are relative to.
+    pub fn start(&self) -> P {
+        self.start
+    }
+
+    /// Returns the time at which the decision was made.
+    pub fn time_of_decision(&self) -> P {
+        self.start + self.state.time_of_measurement()
+    }
+
+    /// Returns a snapshot of the rate limiting state right after the decision.
+    ///
+    /// With concurrent decisions on the same state, the snapshot of a positive decision may
+    /// already reflect some of them.
+    pub fn state_snapshot(&self) -> StateSnapshot {
+        self.state
+    }
+}
+
+impl<K, S, C> RateLimiter<K, S, C>
+where
+    S: StateStore<Key = K>,
+    C: clock::Clock,
+{
+    /// Calls the callbacks with the outcome of a decision on `n` cells made at `t0`.
+    pub(crate) fn report_decision(
+        &self,
+        key: &K,
+        n: NonZeroU32,
+        t0: C::Instant,
+        negative: Option<&NotUntil<C::Instant>>,
+    ) {
+        let hooks = &self.hooks;
+        match negative {
+            None => hooks.allowed.iter().for_each(|hook| hook(key, n)),
+            Some(negative) => hooks.denied.iter().for_each(|hook| hook(key, n, negative)),
+        }
+        if hooks.decided.is_empty() {
+            return;
+        }
+        let state = match negative {
+            Some(negative) => negative.state_snapshot(),
+            None => self
+                .gcra
+                .snapshot(self.state.peek(key), t0.duration_since(self.start)),
+        };
+        let decision = Decision {
+            cells: n,
+            negative: negative.copied(),
+            quota: self.gcra.quota(),
+            start: self.start,
+            state,
+        };
+        for hook in &hooks.decided {
+            hook(key, &decision);
+        }
+    }
+}
+
+/// # Rate limiters - Decision hooks
+impl<K, S, C> RateLimiter<K, S, C>
+where
+    S: StateStore<Key = K>,
+    C: clock::Clock,
+{
+    /// Registers a callback that gets called whenever the rate limiter lets cells through,
+    /// with the key (the [`NotKeyed`][crate::state::NotKeyed] key, for direct rate limiters)
+    /// and the number of cells.
+    ///
+    /// Callbacks are called synchronously from the checking method, after the decision was
+    /// made, so they should be quick: e.g. emitting a log line or incrementing a counter.
+    /// Methods that wait for the rate limiter (like
+    /// [`until_ready`](#method.until_ready)) make a decision, and call the callbacks, on every
+    /// attempt. Any number of callbacks can be registered.
+    ///
+    /// # Example
+    /// ```rust
+    /// # #[cfg(feature = "std")] fn main() {
+    /// # use governor::{Quota, RateLimiter};
+    /// # use nonzero_ext::nonzero;
+    /// # use std::sync::atomic::{AtomicU32, Ordering};
+    /// # use std::sync::Arc;
+    /// let allowed = Arc::new(AtomicU32::new(0));
+    /// let denied = Arc::new(AtomicU32::new(0));
+    /// let lim = RateLimiter::direct(Quota::per_hour(nonzero!(2u32)))
+    ///     .on_allowed({
+    ///         let allowed = allowed.clone();
+    ///         move |_, n| {
+    ///             allowed.fetch_add(n.get(), Ordering::Relaxed);
+    ///         }
+    ///     })
+    ///     .on_denied({
+    ///         let denied = denied.clone();
+    ///         move |_, _, _| {
+    ///             denied.fetch_add(1, Ordering::Relaxed);
+    ///         }
+    ///     });
+    /// lim.check_n(nonzero!(2u32)).unwrap();
+    /// assert!(lim.check().is_err());
+    /// assert_eq!(allowed.load(Ordering::Relaxed), 2);
+    /// assert_eq!(denied.load(Ordering::Relaxed), 1);
+    /// # }
+    /// # #[cfg(not(feature = "std"))] fn main() {}
+    /// ```
+    pub fn on_allowed<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&K, NonZeroU32) + Send + Sync + 'static,
+    {
+        self.hooks.allowed.push(Box::new(hook));
+        self
+    }
+
+    /// Registers a callback that gets called whenever the rate limiter rejects cells, with the
+    /// key, the number of cells and the negative outcome.
+    ///
+    /// Batches of cells that exceed the burst capacity (which
+    /// [`check_n`](#method.check_n) rejects with
+    /// [`InsufficientCapacity`][crate::NegativeMultiDecision::InsufficientCapacity]) are
+    /// reported with the same kind of negative outcome as cells under
+    /// [`Quota::none()`][crate::Quota::none].
+    ///
+    /// The same considerations as for [`on_allowed`](#method.on_allowed) apply.
+    pub fn on_denied<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&K, NonZeroU32, &NotUntil<C::Instant>) + Send + Sync + 'static,
+    {
+        self.hooks.denied.push(Box::new(hook));
+        self
+    }
+
+    /// Registers a callback that gets called with the outcome of every decision, along with
+    /// the rate limiter's quota, the time that it was constructed and a snapshot of the
+    /// rate limiting state.
+    ///
+    /// This is useful for callbacks that need more context than
+    /// [`on_allowed`](#method.on_allowed) and [`on_denied`](#method.on_denied) provide, e.g.
+    /// to compute the time at which a key's bucket is replenished. Taking the snapshot for
+    /// positive decisions reads the state once more. The same considerations as for
+    /// [`on_allowed`](#method.on_allowed) apply.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use governor::{clock::FakeRelativeClock, Quota, RateLimiter};
+    /// # use nonzero_ext::nonzero;
+    /// # use std::sync::{Arc, Mutex};
+    /// # use std::time::Duration;
+    /// let clock = FakeRelativeClock::default();
+    /// let usage = Arc::new(Mutex::new(Vec::new()));
+    /// let lim = RateLimiter::direct_with_clock(Quota::per_second(nonzero!(4u32)), &clock)
+    ///     .on_decision({
+    ///         let usage = usage.clone();
+    ///         move |_, decision| {
+    ///             let burst = decision.quota().burst_size().get();
+    ///             let remaining = decision.state_snapshot().remaining_burst_capacity();
+    ///             usage.lock().unwrap().push(f64::from(remaining) / f64::from(burst));
+    ///         }
+    ///     });
+    /// lim.check().unwrap();
+    /// lim.check_n(nonzero!(2u32)).unwrap();
+    /// assert_eq!(*usage.lock().unwrap(), vec![0.75, 0.25]);
+    /// ```
+    pub fn on_decision<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&K, &Decision<C::Instant>) + Send + Sync + 'static,
+    {
+        self.hooks.decided.push(Box::new(hook));
+        self
+    }
+}
@@ -21,7 +21,19 @@ use std::time::Duration;
 pub struct InMemoryState(AtomicU64);
 
 impl InMemoryState {
-    pub(crate) fn measure_and_replace_one<T, F, E>(&self, f: F) -> Result<T, E>
+    /// Constructs a state that no measurement was made on yet, like
+    /// [`InMemoryState::default`], but in `const` contexts (e.g. for `static` items).
+    pub const fn new() -> Self {
+        InMemoryState(AtomicU64::new(0))
+    }
+
+    /// Updates the rate limiting state using the given closure, with the same contract as
+    /// [`StateStore::measure_and_replace`].
+    ///
+    /// Keyed state stores that keep one `InMemoryState` per key can delegate their
+    /// `measure_and_replace` implementation to this method once they found (or created) the
+    /// key's state.
+    pub fn measure_and_replace_one<T, F, E>(&self, f: F) -> Result<T, E>
     where
         F: Fn(Option<Nanos>) -> Result<(T, Nanos), E>,
     {
@@ -44,7 +56,20 @@ impl InMemoryState {
         decision.map(|(result, _)| result)
     }
 
-    pub(crate) fn is_older_than(&self, nanos: Nanos) -> bool {
+    /// Returns the theoretical arrival time, if a measurement was made yet.
+    ///
+    /// This is what keyed state stores return from [`StateStore::peek`] and
+    /// [`ShrinkableKeyedStateStore::snapshot`][crate::state::keyed::ShrinkableKeyedStateStore::snapshot].
+    pub fn tat(&self) -> Option<Nanos> {
+        NonZeroU64::new(self.0.load(Ordering::Relaxed)).map(|n| n.get().into())
+    }
+
+    /// Returns `true` if the state is indistinguishable from a fresh state at `nanos` (i.e., its
+    /// theoretical arrival time is at or before `nanos`).
+    ///
+    /// Keyed state stores use this to decide which keys to drop in
+    /// [`ShrinkableKeyedStateStore::retain_recent`][crate::state::keyed::ShrinkableKeyedStateStore::retain_recent].
+    pub fn is_older_than(&self, nanos: Nanos) -> bool {
         self.0.load(Ordering::Relaxed) <= nanos.into()
     }
 }
@@ -59,6 +84,10 @@ impl StateStore for InMemoryState {
     {
         self.measure_and_replace_one(f)
     }
+
+    fn peek(&self, _key: &Self::Key) -> Option<Nanos> {
+        self.tat()
+    }
 }
 
 impl Debug for InMemoryState {
@@ -0,0 +1,257 @@
+#![cfg(feature = "std")]
+
+use std::prelude::v1::*;
+
+use crate::clock;
+use crate::nanos::Nanos;
+use crate::state::keyed::ShrinkableKeyedStateStore;
+use crate::state::StateStore;
+use crate::RateLimiter;
+use std::cell::Cell;
+use std::fmt;
+use std::hash::Hash;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// Measurements of how a state store's rate limiting states are updated, recorded by an
+/// [`InstrumentedStateStore`].
+///
+/// Each rate limiting decision is a call to
+/// [`measure_and_replace`][StateStore::measure_and_replace], which runs the decision closure
+/// once, and again each time the state changed concurrently before the new state could be
+/// stored (a failed compare-and-swap). The measurements tell these retries apart from the time
+/// spent in the closure itself.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct ContentionStats {
+    decisions: u64,
+    attempts: u64,
+    contended: u64,
+    max_attempts: u64,
+    closure_time: Duration,
+}
+
+impl ContentionStats {
+    /// Returns the number of calls to `measure_and_replace`.
+    pub fn decisions(&self) -> u64 {
+        self.decisions
+    }
+
+    /// Returns the number of times the decision closure ran, over all decisions.
+    pub fn attempts(&self) -> u64 {
+        self.attempts
+    }
+
+    /// Returns the number of times the decision closure ran again because the state changed
+    /// concurrently.
+    pub fn retries(&self) -> u64 {
+        self.attempts.saturating_sub(self.decisions)
+    }
+
+    /// Returns the number of decisions that needed at least one retry.
+    pub fn contended(&self) -> u64 {
+        self.contended
+    }
+
+    /// Returns the share of decisions that needed at least one retry, between `0.0` and `1.0`
+    /// (`0.0` if no decisions were made).
+    pub fn contention_rate(&self) -> f64 {
+        if self.decisions == 0 {
+            0.0
+        } else {
+            self.contended as f64 / self.decisions as f64
+        }
+    }
+
+    /// Returns the largest number of times the decision closure ran for a single decision.
+    pub fn max_attempts(&self) -> u64 {
+        self.max_attempts
+    }
+
+    /// Returns the total time spent in the decision closure, over all attempts.
+    pub fn closure_time(&self) -> Duration {
+        self.closure_time
+    }
+}
+
+/// A state store that measures the rate limiting decisions made with another state store, to
+/// find out how much time is lost to contention between threads.
+///
+/// The store passes all operations on to the wrapped store, and records how often each
+/// [`measure_and_replace`][StateStore::measure_and_replace] call had to retry, and how long its
+/// decision closure ran (see [`ContentionStats`]). This works for direct and keyed state stores
+/// alike. Measuring the closure's execution time reads the system clock on every attempt, so
+/// this store is meant for investigating performance problems, rather than for everyday use.
+///
+/// # Example
+/// ```rust
+/// # use governor::{
+/// #     clock::FakeRelativeClock,
+/// #     state::{InMemoryState, InstrumentedStateStore},
+/// #     Quota, RateLimiter,
+/// # };
+/// # use nonzero_ext::nonzero;
+/// let clock = FakeRelativeClock::default();
+/// let store = InstrumentedStateStore::new(InMemoryState::default());
+/// let lim = RateLimiter::new(Quota::per_second(nonzero!(10u32)), store, &clock);
+/// for _ in 0..20 {
+///     let _ = lim.check();
+/// }
+/// let stats = lim.contention_stats();
+/// assert_eq!(stats.decisions(), 20);
+/// // A single thread never contends with itself:
+/// assert_eq!(stats.retries(), 0);
+/// ```
+pub struct InstrumentedStateStore<S> {
+    inner: S,
+    decisions: AtomicU64,
+    attempts: AtomicU64,
+    contended: AtomicU64,
+    max_attempts: AtomicU64,
+    closure_nanos: AtomicU64,
+}
+
+impl<S: StateStore> InstrumentedStateStore<S> {
+    /// Constructs a state store that measures the decisions made with `inner`.
+    pub fn new(inner: S) -> Self {
+        InstrumentedStateStore {
+            inner,
+            decisions: AtomicU64::new(0),
+            attempts: AtomicU64::new(0),
+            contended: AtomicU64::new(0),
+            max_attempts: AtomicU64::new(0),
+            closure_nanos: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns a reference to the wrapped state store.
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Consumes the instrumented store and returns the wrapped state store.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    /// Returns the measurements recorded so far.
+    ///
+    /// The measurements are updated independently of each other, so a snapshot taken while
+    /// decisions are being made may be slightly inconsistent (e.g. count an attempt for a
+    /// decision that it doesn't count yet).
+    pub fn contention_stats(&self) -> ContentionStats {
+        ContentionStats {
+            decisions: self.decisions.load(Ordering::Relaxed),
+            attempts: self.attempts.load(Ordering::Relaxed),
+            contended: self.contended.load(Ordering::Relaxed),
+            max_attempts: self.max_attempts.load(Ordering::Relaxed),
+            closure_time: Duration::from_nanos(self.closure_nanos.load(Ordering::Relaxed)),
+        }
+    }
+
+    /// Resets the measurements to zero.
+    pub fn reset_contention_stats(&self) {
+        self.decisions.store(0, Ordering::Relaxed);
+        self.attempts.store(0, Ordering::Relaxed);
+        self.contended.store(0, Ordering::Relaxed);
+        self.max_attempts.store(0, Ordering::Relaxed);
+        self.closure_nanos.store(0, Ordering::Relaxed);
+    }
+}
+
+impl<S: StateStore + Default> Default for InstrumentedStateStore<S> {
+    fn default() -> Self {
+        Self::new(S::default())
+    }
+}
+
+impl<S: fmt::Debug> fmt::Debug for InstrumentedStateStore<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        f.debug_struct("InstrumentedStateStore")
+            .field("inner", &self.inner)
+            .field("decisions", &self.decisions.load(Ordering::Relaxed))
+            .field("attempts", &self.attempts.load(Ordering::Relaxed))
+            .finish()
+    }
+}
+
+impl<S: StateStore> StateStore for InstrumentedStateStore<S> {
+    type Key = S::Key;
+
+    fn measure_and_replace<T, F, E>(&self, key: &Self::Key, f: F) -> Result<T, E>
+    where
+        F: Fn(Option<Nanos>) -> Result<(T, Nanos), E>,
+    {
+        let attempts = Cell::new(0u64);
+        let closure_time = Cell::new(Duration::new(0, 0));
+        let result = self.inner.measure_and_replace(key, |prev| {
+            let started = Instant::now();
+            let decision = f(prev);
+            closure_time.set(closure_time.get() + started.elapsed());
+            attempts.set(attempts.get() + 1);
+            decision
+        });
+
+        let attempts = attempts.get();
+        self.decisions.fetch_add(1, Ordering::Relaxed);
+        self.attempts.fetch_add(attempts, Ordering::Relaxed);
+        if attempts > 1 {
+            self.contended.fetch_add(1, Ordering::Relaxed);
+        }
+        self.max_attempts.fetch_max(attempts, Ordering::Relaxed);
+        let closure_nanos = closure_time.get().as_nanos() as u64;
+        self.closure_nanos
+            .fetch_add(closure_nanos, Ordering::Relaxed);
+        result
+    }
+
+    fn peek(&self, key: &Self::Key) -> Option<Nanos> {
+        self.inner.peek(key)
+    }
+
+    fn reset(&self, key: &Self::Key) {
+        self.inner.reset(key)
+    }
+
+    fn record_decision(&self, key: &Self::Key, conforming: bool, t0: Nanos) {
+        self.inner.record_decision(key, conforming, t0);
+    }
+}
+
+impl<K, S> ShrinkableKeyedStateStore<K> for InstrumentedStateStore<S>
+where
+    K: Hash + Eq + Clone,
+    S: ShrinkableKeyedStateStore<K>,
+{
+    fn retain_recent(&self, drop_below: Nanos) {
+        self.inner.retain_recent(drop_below);
+    }
+
+    fn shrink_to_fit(&self) {
+        self.inner.shrink_to_fit();
+    }
+
+    fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    fn snapshot(&self) -> Vec<(K, Option<Nanos>)> {
+        self.inner.snapshot()
+    }
+}
+
+/// # Rate limiters - Contention measurements
+impl<K, S, C> RateLimiter<K, InstrumentedStateStore<S>, C>
+where
+    S: StateStore<Key = K>,
+    C: clock::Clock,
+{
+    /// Returns the measurements that the rate limiter's [`InstrumentedStateStore`] recorded so
+    /// far.
+    pub fn contention_stats(&self) -> ContentionStats {
+        self.state.contention_stats()
+    }
+}
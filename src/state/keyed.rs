@@ -6,6 +6,102 @@
 //!
 //! Rate limiters based on these types are constructed with
 //! [the `RateLimiter` constructors](../struct.RateLimiter.html#keyed-rate-limiters---default-constructors)
+//!
+//! # Implementing a custom keyed state store
+//!
+//! Keyed rate limiters can keep their states in any data structure that implements
+//! [`StateStore`] with a hashable key type (these get [`KeyedStateStore`] implemented
+//! automatically). To support the housekeeping methods (like
+//! [`retain_recent`](../struct.RateLimiter.html#method.retain_recent) and
+//! [`len`](../struct.RateLimiter.html#method.len)), the store also needs to implement
+//! [`ShrinkableKeyedStateStore`].
+//!
+//! The easiest way to write such a store is to keep one [`InMemoryState`][crate::state::InMemoryState]
+//! per key, and to delegate to its methods:
+//! * [`measure_and_replace_one`][crate::state::InMemoryState::measure_and_replace_one] makes the
+//!   rate limiting decision for a key, atomically updating its state,
+//! * [`tat`][crate::state::InMemoryState::tat] returns the state for [`StateStore::peek`] and
+//!   [`ShrinkableKeyedStateStore::snapshot`], and
+//! * [`is_older_than`][crate::state::InMemoryState::is_older_than] tells
+//!   [`ShrinkableKeyedStateStore::retain_recent`] which keys to drop.
+//!
+//! This example keeps the states in a sharded map:
+//!
+//! ```rust
+//! # use governor::{clock::FakeRelativeClock, nanos::Nanos, Quota, RateLimiter};
+//! # use governor::state::{keyed::ShrinkableKeyedStateStore, InMemoryState, StateStore};
+//! # use nonzero_ext::nonzero;
+//! # use std::collections::{hash_map::DefaultHasher, HashMap};
+//! # use std::hash::{Hash, Hasher};
+//! # use std::sync::Mutex;
+//! # use std::time::Duration;
+//! struct ShardedStore<K> {
+//!     shards: Vec<Mutex<HashMap<K, InMemoryState>>>,
+//! }
+//!
+//! impl<K: Hash> ShardedStore<K> {
+//!     fn shard(&self, key: &K) -> &Mutex<HashMap<K, InMemoryState>> {
+//!         let mut hasher = DefaultHasher::new();
+//!         key.hash(&mut hasher);
+//!         &self.shards[hasher.finish() as usize % self.shards.len()]
+//!     }
+//! }
+//!
+//! impl<K: Hash + Eq + Clone> StateStore for ShardedStore<K> {
+//!     type Key = K;
+//!
+//!     fn measure_and_replace<T, F, E>(&self, key: &K, f: F) -> Result<T, E>
+//!     where
+//!         F: Fn(Option<Nanos>) -> Result<(T, Nanos), E>,
+//!     {
+//!         let mut shard = self.shard(key).lock().unwrap();
+//!         shard.entry(key.clone()).or_default().measure_and_replace_one(f)
+//!     }
+//!
+//!     fn peek(&self, key: &K) -> Option<Nanos> {
+//!         let shard = self.shard(key).lock().unwrap();
+//!         shard.get(key).and_then(InMemoryState::tat)
+//!     }
+//! }
+//!
+//! impl<K: Hash + Eq + Clone> ShrinkableKeyedStateStore<K> for ShardedStore<K> {
+//!     fn retain_recent(&self, drop_below: Nanos) {
+//!         for shard in &self.shards {
+//!             shard.lock().unwrap().retain(|_, state| !state.is_older_than(drop_below));
+//!         }
+//!     }
+//!
+//!     fn len(&self) -> usize {
+//!         self.shards.iter().map(|shard| shard.lock().unwrap().len()).sum()
+//!     }
+//!
+//!     fn is_empty(&self) -> bool {
+//!         self.len() == 0
+//!     }
+//!
+//!     fn snapshot(&self) -> Vec<(K, Option<Nanos>)> {
+//!         let mut snapshot = vec![];
+//!         for shard in &self.shards {
+//!             let shard = shard.lock().unwrap();
+//!             snapshot.extend(shard.iter().map(|(key, state)| (key.clone(), state.tat())));
+//!         }
+//!         snapshot
+//!     }
+//! }
+//!
+//! let clock = FakeRelativeClock::default();
+//! let store = ShardedStore {
+//!     shards: (0..4).map(|_| Mutex::new(HashMap::new())).collect(),
+//! };
+//! let lim = RateLimiter::new(Quota::per_second(nonzero!(1u32)), store, &clock);
+//! assert!(lim.check_key(&"tenant-1").is_ok());
+//! assert!(lim.check_key(&"tenant-1").is_err());
+//! assert_eq!(lim.len(), 1);
+//!
+//! clock.advance(Duration::from_secs(2));
+//! lim.retain_recent();
+//! assert!(lim.is_empty());
+//! ```
 
 use std::hash::Hash;
 use std::num::NonZeroU32;
@@ -15,7 +111,7 @@ use crate::state::StateStore;
 use crate::{
     clock::{self, Reference},
     nanos::Nanos,
-    NegativeMultiDecision, NotUntil, Quota, RateLimiter,
+    NegativeMultiDecision, NotUntil, Quota, RateLimiter, StateSnapshot,
 };
 
 /// A trait for state stores with one rate limiting state per key.
@@ -88,8 +184,38 @@ where
     /// If the rate limit is reached, `check_key` returns information about the earliest
     /// time that a cell might be allowed through again under that key.
     pub fn check_key(&self, key: &K) -> Result<(), NotUntil<C::Instant>> {
-        self.gcra
-            .test_and_update(self.start, key, &self.state, self.clock.now())
+        self.test_key_at(key, self.clock.now())
+    }
+
+    /// Allow a single cell through the rate limiter for the given key, like
+    /// [`check_key`](#method.check_key), and report whether the key was newly added to the
+    /// state store by this decision.
+    ///
+    /// This is useful for e.g. logging the first request of each client, or provisioning
+    /// resources for new keys. Keys that were removed from the state store (by
+    /// [`retain_recent`](#method.retain_recent)) or [reset](#method.reset_key) count as new
+    /// again. Rate limiters for quotas that let all or no cells through don't keep any state,
+    /// and never report keys as new.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use governor::{Quota, RateLimiter};
+    /// # use nonzero_ext::nonzero;
+    /// let lim = RateLimiter::keyed(Quota::per_second(nonzero!(1u32)));
+    /// let first = lim.check_key_reporting_new(&"alice");
+    /// assert!(first.newly_created);
+    /// assert_eq!(Ok(()), first.decision);
+    ///
+    /// let second = lim.check_key_reporting_new(&"alice");
+    /// assert!(!second.newly_created);
+    /// assert!(second.decision.is_err());
+    /// ```
+    pub fn check_key_reporting_new(&self, key: &K) -> KeyCheck<C::Instant> {
+        let (decision, newly_created) = self.test_key_noting_fresh_at(key, self.clock.now());
+        KeyCheck {
+            decision,
+            newly_created,
+        }
     }
 
     /// Allow *only all* `n` cells through the rate limiter for the given key.
@@ -111,11 +237,42 @@ where
         key: &K,
         n: NonZeroU32,
     ) -> Result<(), NegativeMultiDecision<NotUntil<C::Instant>>> {
-        self.gcra
-            .test_n_all_and_update(self.start, key, n, &self.state, self.clock.now())
+        self.test_key_n_at(key, n, self.clock.now())
+    }
+
+    /// Returns a snapshot of the rate-limiting state for the given key, for observability and
+    /// debugging.
+    ///
+    /// Taking a snapshot does not add the key to the rate limiter's state store.
+    pub fn key_state_snapshot(&self, key: &K) -> StateSnapshot {
+        self.snapshot_state(key)
+    }
+
+    /// Resets the rate-limiting state for the given key to that of a key that was never seen
+    /// before, restoring its full burst capacity (unless the quota
+    /// [starts empty][Quota::starting_empty]).
+    ///
+    /// This can be used to lift the rate limit on e.g. a customer after resolving an incident,
+    /// without having to re-create the rate limiter. The state store forgets the key with
+    /// [`StateStore::reset`], so the key counts as [new](#method.check_key_reporting_new)
+    /// again.
+    pub fn reset_key(&self, key: &K) {
+        self.reset_state(key);
     }
 }
 
+/// The outcome of [`check_key_reporting_new`](../struct.RateLimiter.html#method.check_key_reporting_new).
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct KeyCheck<P: clock::Reference> {
+    /// The rate limiting decision, as returned by
+    /// [`check_key`](../struct.RateLimiter.html#method.check_key).
+    pub decision: Result<(), NotUntil<P>>,
+
+    /// Whether the state store held no state for the key before the decision, i.e. whether the
+    /// key was seen for the first time.
+    pub newly_created: bool,
+}
+
 /// Keyed rate limiters that can be "cleaned up".
 ///
 /// Any keyed state store implementing this trait allows users to evict elements that are
@@ -125,8 +282,17 @@ where
 /// As this does not make sense for not all keyed state stores (e.g. stores that auto-expire like
 /// memcache), this is an optional trait. All the keyed state stores in this crate implement
 /// shrinking.
+///
+/// See [the module documentation](index.html#implementing-a-custom-keyed-state-store) for how to
+/// implement it.
 pub trait ShrinkableKeyedStateStore<K: Hash>: KeyedStateStore<K> {
     /// Remove those keys with state older than `drop_below`.
+    ///
+    /// `drop_below` is a point in time, in nanoseconds since the rate limiter was created. Keys
+    /// whose theoretical arrival time is at or before it are indistinguishable from keys that
+    /// were never seen, so dropping them doesn't change any rate limiting decisions. Passing
+    /// the largest possible value (as [`reset_all`](../struct.RateLimiter.html#method.reset_all)
+    /// does) drops all keys.
     fn retain_recent(&self, drop_below: Nanos);
 
     /// Shrinks the capacity of the state store, if possible.
@@ -146,6 +312,18 @@ pub trait ShrinkableKeyedStateStore<K: Hash>: KeyedStateStore<K> {
     /// imprecise results (indicating that the state store is empty
     /// while a concurrent rate-limiting operation is taking place).
     fn is_empty(&self) -> bool;
+
+    /// Returns a snapshot of the keys stored in the state store, along with the theoretical
+    /// arrival time of their rate-limiting states (`None` if no measurement was made yet).
+    ///
+    /// As with [`len`](#tymethod.len), concurrent rate-limiting operations may change the
+    /// state store while the snapshot is taken, and are not guaranteed to be reflected in it.
+    ///
+    /// The default implementation returns an empty snapshot, for state stores that can't list
+    /// their keys.
+    fn snapshot(&self) -> Vec<(K, Option<Nanos>)> {
+        Vec::new()
+    }
 }
 
 /// # Keyed rate limiters - Housekeeping
@@ -174,6 +352,11 @@ where
         self.state.retain_recent(drop_below);
     }
 
+    /// Resets the rate-limiting states for all keys, by removing all keys from the state store.
+    pub fn reset_all(&self) {
+        self.state.retain_recent(Nanos::from(u64::MAX));
+    }
+
     /// Shrinks the capacity of the rate limiter's state store, if possible.
     pub fn shrink_to_fit(&self) {
         self.state.shrink_to_fit();
@@ -195,12 +378,74 @@ where
     pub fn is_empty(&self) -> bool {
         self.state.is_empty()
     }
+
+    /// Returns a snapshot of the keys in the rate limiter's state store, along with the number
+    /// of cells that each key could let through right now.
+    ///
+    /// This is useful for e.g. showing which keys are currently being throttled: Those keys with
+    /// a remaining capacity of `0`. As with [`len`](#method.len), this method may return
+    /// imprecise results if rate-limiting decisions are made concurrently. State stores that
+    /// don't implement [`snapshot`](trait.ShrinkableKeyedStateStore.html#method.snapshot)
+    /// report no keys.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use governor::{clock::FakeRelativeClock, Quota, RateLimiter};
+    /// # use nonzero_ext::nonzero;
+    /// let clock = FakeRelativeClock::default();
+    /// let lim = RateLimiter::hashmap_with_clock(Quota::per_second(nonzero!(2u32)), &clock);
+    /// lim.check_key(&"cus_1").unwrap();
+    /// lim.check_key(&"cus_1").unwrap();
+    /// lim.check_key(&"cus_2").unwrap();
+    ///
+    /// let mut capacities = lim.remaining_capacities();
+    /// capacities.sort();
+    /// assert_eq!(capacities, vec![("cus_1", 0), ("cus_2", 1)]);
+    /// ```
+    pub fn remaining_capacities(&self) -> Vec<(K, u32)> {
+        let t0 = self.clock.now().duration_since(self.start);
+        self.state
+            .snapshot()
+            .into_iter()
+            .map(|(key, tat)| (key, self.gcra.remaining_cells(tat, t0)))
+            .collect()
+    }
 }
 
 mod hashmap;
 
 pub use hashmap::HashMapStateStore;
 
+mod sharded_hashmap;
+
+pub use sharded_hashmap::ShardedHashMapStateStore;
+
+mod local_hashmap;
+
+pub use local_hashmap::LocalHashMapStateStore;
+
+mod fixed_capacity;
+
+mod cas;
+
+pub use cas::{CasBackend, CasStateStore, FailurePolicy, VersionedBlob};
+
+mod gossip;
+
+pub use gossip::{GossipCount, GossipDigest, GossipStateStore, GossipTransport};
+
+mod stats;
+
+pub use stats::{KeyStats, StatsStateStore};
+
+#[cfg(feature = "std")]
+mod synced;
+
+#[cfg(feature = "std")]
+pub use synced::SyncedStateStore;
+
+pub use fixed_capacity::{FixedCapacityStateStore, OverflowPolicy};
+
 #[cfg(all(feature = "std", feature = "dashmap"))]
 mod dashmap;
 
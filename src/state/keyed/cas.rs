@@ -0,0 +1,369 @@
+use std::prelude::v1::*;
+
+use crate::clock::{self, Reference};
+use crate::gcra;
+use crate::nanos::Nanos;
+use crate::state::StateStore;
+use crate::{Quota, RateLimiter};
+use std::convert::TryInto;
+use std::fmt;
+
+/// A blob stored in a [`CasBackend`], along with its version.
+pub type VersionedBlob<V> = (Vec<u8>, V);
+
+/// A storage backend that offers versioned reads and conditional writes (compare-and-swap) of
+/// blobs, like an embedded database or a map in shared memory.
+///
+/// Implementing this trait is all that's needed to use such a backend as the state store of a
+/// keyed rate limiter: The [`CasStateStore`] adapter implements the compare-and-swap loop that
+/// makes rate limiting decisions safe against concurrent updates from other processes.
+///
+/// # Blocking
+///
+/// The methods of this trait block until the backend answers, and a [`CasStateStore`] calls
+/// them on the thread that makes the rate limiting decision. A backend that has to wait for I/O
+/// (like a key-value store that is reached over the network) makes every decision wait for it
+/// too, so a `CasStateStore` on top of such a backend must not be used from asynchronous tasks,
+/// where it would stall the executor. A
+/// [`SyncedStateStore`][crate::state::keyed::SyncedStateStore] makes its decisions without
+/// waiting for the backend, and only syncs with it on a background thread.
+///
+/// There is no asynchronous variant of this trait: Rate limiting decisions are made
+/// synchronously (see [`StateStore::measure_and_replace`]), so a state store can't await a
+/// backend while making one. Network stores (like etcd, DynamoDB or memcached) that only offer
+/// asynchronous clients can be used by blocking on their futures in a `SyncedStateStore`'s
+/// background thread.
+pub trait CasBackend {
+    /// The keys that the backend stores blobs under.
+    type Key;
+
+    /// The version token of a blob, which changes whenever the blob is written.
+    type Version;
+
+    /// The errors that talking to the backend can result in.
+    type Error;
+
+    /// Returns the blob stored under `key` along with its version, or `None` if there is none.
+    fn get(&self, key: &Self::Key) -> Result<Option<VersionedBlob<Self::Version>>, Self::Error>;
+
+    /// Stores `blob` under `key`, but only if the stored blob's version is still `expected`
+    /// (with `None` meaning that no blob may be stored under `key` yet).
+    ///
+    /// Returns `Ok(true)` if the blob was stored, and `Ok(false)` if the stored blob was changed
+    /// in the meantime.
+    fn compare_and_swap(
+        &self,
+        key: &Self::Key,
+        expected: Option<&Self::Version>,
+        blob: Vec<u8>,
+    ) -> Result<bool, Self::Error>;
+}
+
+#[cfg(feature = "std")]
+impl<B: CasBackend> CasBackend for std::sync::Arc<B> {
+    type Key = B::Key;
+    type Version = B::Version;
+    type Error = B::Error;
+
+    fn get(&self, key: &Self::Key) -> Result<Option<VersionedBlob<Self::Version>>, Self::Error> {
+        (**self).get(key)
+    }
+
+    fn compare_and_swap(
+        &self,
+        key: &Self::Key,
+        expected: Option<&Self::Version>,
+        blob: Vec<u8>,
+    ) -> Result<bool, Self::Error> {
+        (**self).compare_and_swap(key, expected, blob)
+    }
+}
+
+/// What a [`CasStateStore`] decides when its backend returns an error.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum FailurePolicy {
+    /// Cells are allowed through, but not counted against the rate limit ("fail open").
+    Allow,
+
+    /// Cells are rejected ("fail closed"), as if the quota were
+    /// [`Quota::none()`][crate::Quota::none].
+    Deny,
+}
+
+/// A keyed rate limiter state store that keeps its states in a [`CasBackend`].
+///
+/// Each state is stored as an 8-byte big-endian blob, holding the theoretical arrival time in
+/// nanoseconds since a shared epoch (the UNIX epoch, for rate limiters constructed with
+/// [`cas`](../struct.RateLimiter.html#method.cas)). Rate limiters in different processes that
+/// use the same backend and epoch share their rate limits. Blobs that can't be decoded are
+/// treated as fresh states, and overwritten by the next positive decision; resetting a key
+/// (with [`reset_key`](../struct.RateLimiter.html#method.reset_key)) stores an empty blob.
+///
+/// Every rate limiting decision costs at least two calls to the backend (one read, one
+/// conditional write), and more if other processes update the same key concurrently. These calls
+/// block the thread that makes the decision (see [`CasBackend`](trait.CasBackend.html#blocking)),
+/// so this store must not be used from asynchronous tasks unless the backend answers right away.
+///
+/// This store doesn't implement
+/// [`ShrinkableKeyedStateStore`][crate::state::keyed::ShrinkableKeyedStateStore]:
+/// Expire stale keys in the backend instead (e.g., with a TTL of the quota's
+/// [`burst_size_replenished_in`][Quota::burst_size_replenished_in]).
+///
+/// # Example
+/// ```rust
+/// # use governor::{clock::FakeRelativeClock, nanos::Nanos, Quota, RateLimiter};
+/// # use governor::state::keyed::CasBackend;
+/// # use nonzero_ext::nonzero;
+/// # use std::collections::HashMap;
+/// # use std::convert::Infallible;
+/// # use std::sync::{Arc, Mutex};
+/// # #[cfg(feature = "std")] fn main() {
+/// /// An in-memory backend, using counters as versions.
+/// #[derive(Default)]
+/// struct Backend(Mutex<HashMap<String, (Vec<u8>, u64)>>);
+///
+/// impl CasBackend for Backend {
+///     type Key = String;
+///     type Version = u64;
+///     type Error = Infallible;
+///
+///     fn get(&self, key: &String) -> Result<Option<(Vec<u8>, u64)>, Infallible> {
+///         Ok(self.0.lock().unwrap().get(key).cloned())
+///     }
+///
+///     fn compare_and_swap(
+///         &self,
+///         key: &String,
+///         expected: Option<&u64>,
+///         blob: Vec<u8>,
+///     ) -> Result<bool, Infallible> {
+///         let mut map = self.0.lock().unwrap();
+///         let version = map.get(key).map(|(_, version)| *version);
+///         if version.as_ref() != expected {
+///             return Ok(false);
+///         }
+///         map.insert(key.clone(), (blob, version.unwrap_or(0) + 1));
+///         Ok(true)
+///     }
+/// }
+///
+/// let backend = Arc::new(Backend::default());
+/// let clock = FakeRelativeClock::default();
+/// let quota = Quota::per_second(nonzero!(1u32));
+/// // Two rate limiters (e.g. in two processes) sharing one backend:
+/// let lim1 = RateLimiter::cas_with_clock(quota, backend.clone(), &clock, Nanos::from(0));
+/// let lim2 = RateLimiter::cas_with_clock(quota, backend.clone(), &clock, Nanos::from(0));
+/// assert!(lim1.check_key(&"user".to_string()).is_ok());
+/// assert!(lim2.check_key(&"user".to_string()).is_err());
+/// # }
+/// # #[cfg(not(feature = "std"))] fn main() {}
+/// ```
+pub struct CasStateStore<B: CasBackend> {
+    backend: B,
+    offset: Nanos,
+    failure_policy: FailurePolicy,
+    on_error: Option<ErrorHook<B::Error>>,
+}
+
+/// The function that gets called with each backend error.
+pub(super) type ErrorHook<E> = Box<dyn Fn(&E) + Send + Sync>;
+
+impl<B: CasBackend> CasStateStore<B> {
+    /// Constructs a state store on top of `backend`, which rejects cells if the backend returns
+    /// an error.
+    ///
+    /// The states that this store keeps are relative to the rate limiter's creation time, which
+    /// is only useful if a single rate limiter uses the backend. To share rate limits with
+    /// other rate limiters, pass the state store to
+    /// [`cas`](../struct.RateLimiter.html#method.cas) or
+    /// [`cas_with_clock`](../struct.RateLimiter.html#method.cas_with_clock) instead.
+    pub fn new(backend: B) -> Self {
+        CasStateStore {
+            backend,
+            offset: Nanos::from(0),
+            failure_policy: FailurePolicy::Deny,
+            on_error: None,
+        }
+    }
+
+    /// Sets what the state store decides when the backend returns an error.
+    pub fn with_failure_policy(self, failure_policy: FailurePolicy) -> Self {
+        CasStateStore {
+            failure_policy,
+            ..self
+        }
+    }
+
+    /// Sets a function that gets called with each error that the backend returns, e.g. to log
+    /// or count them.
+    pub fn on_error<F>(self, on_error: F) -> Self
+    where
+        F: Fn(&B::Error) + Send + Sync + 'static,
+    {
+        CasStateStore {
+            on_error: Some(Box::new(on_error)),
+            ..self
+        }
+    }
+
+    /// Returns a reference to the backend.
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    /// Returns the state store's failure policy.
+    pub fn failure_policy(&self) -> FailurePolicy {
+        self.failure_policy
+    }
+
+    fn decode(&self, blob: &[u8]) -> Option<Nanos> {
+        decode_tat(blob).map(|tat| tat.saturating_sub(self.offset))
+    }
+
+    fn encode(&self, tat: Nanos) -> Vec<u8> {
+        encode_tat(tat + self.offset)
+    }
+
+    /// Makes the decision that the failure policy prescribes for a backend error.
+    fn fail<T, F, E>(&self, error: B::Error, f: &F, positive: Option<T>) -> Result<T, E>
+    where
+        F: Fn(Option<Nanos>) -> Result<(T, Nanos), E>,
+    {
+        if let Some(on_error) = &self.on_error {
+            on_error(&error);
+        }
+        match (self.failure_policy, positive) {
+            (FailurePolicy::Allow, Some(result)) => Ok(result),
+            (FailurePolicy::Allow, None) => f(None).map(|(result, _)| result),
+            (FailurePolicy::Deny, _) => gcra::reject(f),
+        }
+    }
+}
+
+impl<B: CasBackend> From<B> for CasStateStore<B> {
+    fn from(backend: B) -> Self {
+        CasStateStore::new(backend)
+    }
+}
+
+impl<B: CasBackend> fmt::Debug for CasStateStore<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        f.debug_struct("CasStateStore")
+            .field("offset", &self.offset)
+            .field("failure_policy", &self.failure_policy)
+            .finish()
+    }
+}
+
+/// Decodes a theoretical arrival time (relative to the shared epoch) from a blob.
+pub(super) fn decode_tat(blob: &[u8]) -> Option<Nanos> {
+    let tat: [u8; 8] = blob.try_into().ok()?;
+    Some(Nanos::from(u64::from_be_bytes(tat)))
+}
+
+/// Encodes a theoretical arrival time (relative to the shared epoch) into a blob.
+pub(super) fn encode_tat(tat: Nanos) -> Vec<u8> {
+    tat.as_u64().to_be_bytes().to_vec()
+}
+
+/// Replaces the blob stored under `key` (if any) with an empty blob, which decodes as a state
+/// that no measurement was made on yet.
+pub(super) fn clear_blob<B: CasBackend>(backend: &B, key: &B::Key) -> Result<(), B::Error> {
+    loop {
+        let version = match backend.get(key)? {
+            Some((blob, _)) if blob.is_empty() => return Ok(()),
+            Some((_, version)) => version,
+            None => return Ok(()),
+        };
+        if backend.compare_and_swap(key, Some(&version), Vec::new())? {
+            return Ok(());
+        }
+    }
+}
+
+impl<B: CasBackend> StateStore for CasStateStore<B> {
+    type Key = B::Key;
+
+    fn measure_and_replace<T, F, E>(&self, key: &Self::Key, f: F) -> Result<T, E>
+    where
+        F: Fn(Option<Nanos>) -> Result<(T, Nanos), E>,
+    {
+        loop {
+            let (tat, version) = match self.backend.get(key) {
+                Ok(Some((blob, version))) => (self.decode(&blob), Some(version)),
+                Ok(None) => (None, None),
+                Err(error) => return self.fail(error, &f, None),
+            };
+            let (result, new_tat) = f(tat)?;
+            match self
+                .backend
+                .compare_and_swap(key, version.as_ref(), self.encode(new_tat))
+            {
+                Ok(true) => return Ok(result),
+                // Another rate limiter updated the state; re-measure against the new state:
+                Ok(false) => continue,
+                Err(error) => return self.fail(error, &f, Some(result)),
+            }
+        }
+    }
+
+    fn peek(&self, key: &Self::Key) -> Option<Nanos> {
+        match self.backend.get(key) {
+            Ok(Some((blob, _))) => self.decode(&blob),
+            Ok(None) => None,
+            Err(error) => {
+                if let Some(on_error) = &self.on_error {
+                    on_error(&error);
+                }
+                None
+            }
+        }
+    }
+
+    fn reset(&self, key: &Self::Key) {
+        if let Err(error) = clear_blob(&self.backend, key) {
+            if let Some(on_error) = &self.on_error {
+                on_error(&error);
+            }
+        }
+    }
+}
+
+/// # Keyed rate limiters - compare-and-swap backends
+impl<K, B, C> RateLimiter<K, CasStateStore<B>, C>
+where
+    B: CasBackend<Key = K>,
+    C: clock::Clock,
+{
+    /// Constructs a new rate limiter with a custom clock, keeping its states in a backend
+    /// relative to `epoch`.
+    ///
+    /// `store` can be either the backend itself or a [`CasStateStore`] (e.g., one with a
+    /// different failure policy). All rate limiters that share state through the backend must
+    /// use the same epoch, and clocks that agree (well enough) on the time elapsed since it.
+    pub fn cas_with_clock(
+        quota: Quota,
+        store: impl Into<CasStateStore<B>>,
+        clock: &C,
+        epoch: C::Instant,
+    ) -> Self {
+        let mut limiter = RateLimiter::new(quota, store.into(), clock);
+        limiter.state.offset = limiter.start.duration_since(epoch);
+        limiter
+    }
+}
+
+#[cfg(feature = "std")]
+impl<K, B> RateLimiter<K, CasStateStore<B>, clock::SystemClock>
+where
+    B: CasBackend<Key = K>,
+{
+    /// Constructs a new rate limiter that keeps its states in a backend, using the system clock
+    /// and the UNIX epoch.
+    ///
+    /// This allows rate limiters in different processes (and on different machines, as long as
+    /// their system clocks are synchronized) to share rate limits through the backend.
+    pub fn cas(quota: Quota, store: impl Into<CasStateStore<B>>) -> Self {
+        RateLimiter::cas_with_clock(quota, store, &clock::SystemClock, std::time::UNIX_EPOCH)
+    }
+}
@@ -27,6 +27,14 @@ impl<K: Hash + Eq + Clone> StateStore for DashMapStateStore<K> {
         let entry = self.entry(key.clone()).or_default();
         (*entry).measure_and_replace_one(f)
     }
+
+    fn peek(&self, key: &Self::Key) -> Option<Nanos> {
+        self.get(key).and_then(|v| v.tat())
+    }
+
+    fn reset(&self, key: &Self::Key) {
+        self.remove(key);
+    }
 }
 
 /// # Keyed rate limiters - [`DashMap`]-backed
@@ -59,4 +67,10 @@ impl<K: Hash + Eq + Clone> ShrinkableKeyedStateStore<K> for DashMapStateStore<K>
     fn is_empty(&self) -> bool {
         self.is_empty()
     }
+
+    fn snapshot(&self) -> Vec<(K, Option<Nanos>)> {
+        self.iter()
+            .map(|e| (e.key().clone(), e.value().tat()))
+            .collect()
+    }
 }
@@ -0,0 +1,196 @@
+use std::prelude::v1::*;
+
+use crate::gcra;
+use crate::nanos::Nanos;
+use crate::state::keyed::ShrinkableKeyedStateStore;
+use crate::state::{InMemoryState, StateStore};
+use crate::{clock, Quota, RateLimiter};
+use parking_lot::Mutex;
+use std::fmt;
+use std::hash::Hash;
+
+/// What a [`FixedCapacityStateStore`] does when a new key doesn't fit into it anymore.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum OverflowPolicy {
+    /// Cells for keys that don't fit into the state store are rejected, as if the quota were
+    /// [`Quota::none()`].
+    ///
+    /// To make room for new keys, remove stale keys with
+    /// [`retain_recent`](../struct.RateLimiter.html#method.retain_recent).
+    Deny,
+
+    /// The key whose rate-limiting state is the most replenished (and so, the closest to being
+    /// indistinguishable from a fresh state) gets evicted, making room for the new key.
+    ///
+    /// This means that, with more active keys than the store has capacity for, keys may get to
+    /// exceed their quota.
+    EvictMostReplenished,
+}
+
+/// A keyed rate limiter state store that holds at most `N` keys, without allocating.
+///
+/// This state store is intended for environments without an allocator (or where allocating
+/// while making rate-limiting decisions is undesirable), like firmware that limits the rate
+/// of requests per peripheral or connection. Keys are looked up by linear search, so `N` should
+/// be fairly small.
+///
+/// # Example
+/// ```rust
+/// # use governor::{clock::FakeRelativeClock, state::keyed::{FixedCapacityStateStore, OverflowPolicy}, Quota, RateLimiter};
+/// # use nonzero_ext::nonzero;
+/// let clock = FakeRelativeClock::default();
+/// let lim = RateLimiter::<u8, FixedCapacityStateStore<u8, 2>, _>::fixed_capacity_with_clock(
+///     Quota::per_second(nonzero!(1u32)),
+///     OverflowPolicy::Deny,
+///     &clock,
+/// );
+/// assert!(lim.check_key(&1).is_ok());
+/// assert!(lim.check_key(&2).is_ok());
+/// // No room for a third key:
+/// assert!(lim.check_key(&3).is_err());
+/// ```
+pub struct FixedCapacityStateStore<K, const N: usize> {
+    slots: Mutex<[Option<(K, InMemoryState)>; N]>,
+    overflow: OverflowPolicy,
+}
+
+impl<K, const N: usize> FixedCapacityStateStore<K, N> {
+    /// Constructs a new, empty state store that handles overflows according to `overflow`.
+    pub fn new(overflow: OverflowPolicy) -> Self {
+        FixedCapacityStateStore {
+            slots: Mutex::new([(); N].map(|_| None)),
+            overflow,
+        }
+    }
+
+    /// Returns the maximum number of keys that the state store can hold.
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Returns the state store's overflow policy.
+    pub fn overflow_policy(&self) -> OverflowPolicy {
+        self.overflow
+    }
+}
+
+/// The default fixed-capacity state store denies cells for keys that don't fit.
+impl<K, const N: usize> Default for FixedCapacityStateStore<K, N> {
+    fn default() -> Self {
+        Self::new(OverflowPolicy::Deny)
+    }
+}
+
+impl<K: fmt::Debug, const N: usize> fmt::Debug for FixedCapacityStateStore<K, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        let slots = self.slots.lock();
+        f.debug_struct("FixedCapacityStateStore")
+            .field("capacity", &N)
+            .field(
+                "keys",
+                &slots.iter().flatten().map(|(k, _)| k).collect::<Vec<_>>(),
+            )
+            .field("overflow", &self.overflow)
+            .finish()
+    }
+}
+
+impl<K: Hash + Eq + Clone, const N: usize> StateStore for FixedCapacityStateStore<K, N> {
+    type Key = K;
+
+    fn measure_and_replace<T, F, E>(&self, key: &Self::Key, f: F) -> Result<T, E>
+    where
+        F: Fn(Option<Nanos>) -> Result<(T, Nanos), E>,
+    {
+        let mut slots = self.slots.lock();
+        if let Some((_, state)) = slots.iter().flatten().find(|(k, _)| k == key) {
+            return state.measure_and_replace_one(f);
+        }
+
+        let slot = slots
+            .iter()
+            .position(Option::is_none)
+            .or_else(|| match self.overflow {
+                OverflowPolicy::Deny => None,
+                OverflowPolicy::EvictMostReplenished => slots
+                    .iter()
+                    .enumerate()
+                    .min_by_key(|(_, slot)| slot.as_ref().and_then(|(_, state)| state.tat()))
+                    .map(|(i, _)| i),
+            });
+        let slot = match slot {
+            Some(slot) => slot,
+            None => return gcra::reject(f),
+        };
+        let (_, state) = slots[slot].insert((key.clone(), InMemoryState::default()));
+        state.measure_and_replace_one(f)
+    }
+
+    fn peek(&self, key: &Self::Key) -> Option<Nanos> {
+        let slots = self.slots.lock();
+        slots
+            .iter()
+            .flatten()
+            .find(|(k, _)| k == key)
+            .and_then(|(_, state)| state.tat())
+    }
+
+    fn reset(&self, key: &Self::Key) {
+        let mut slots = self.slots.lock();
+        for slot in slots.iter_mut() {
+            if matches!(slot, Some((k, _)) if k == key) {
+                *slot = None;
+            }
+        }
+    }
+}
+
+impl<K: Hash + Eq + Clone, const N: usize> ShrinkableKeyedStateStore<K>
+    for FixedCapacityStateStore<K, N>
+{
+    fn retain_recent(&self, drop_below: Nanos) {
+        let mut slots = self.slots.lock();
+        for slot in slots.iter_mut() {
+            if let Some((_, state)) = slot {
+                if state.is_older_than(drop_below) {
+                    *slot = None;
+                }
+            }
+        }
+    }
+
+    /// The fixed-capacity state store can not shrink.
+    fn shrink_to_fit(&self) {}
+
+    fn len(&self) -> usize {
+        let slots = self.slots.lock();
+        slots.iter().flatten().count()
+    }
+
+    fn is_empty(&self) -> bool {
+        let slots = self.slots.lock();
+        slots.iter().all(Option::is_none)
+    }
+
+    fn snapshot(&self) -> Vec<(K, Option<Nanos>)> {
+        let slots = self.slots.lock();
+        slots
+            .iter()
+            .flatten()
+            .map(|(k, state)| (k.clone(), state.tat()))
+            .collect()
+    }
+}
+
+/// # Keyed rate limiters - fixed-capacity
+impl<K, C, const N: usize> RateLimiter<K, FixedCapacityStateStore<K, N>, C>
+where
+    K: Hash + Eq + Clone,
+    C: clock::Clock,
+{
+    /// Constructs a new rate limiter with a custom clock, backed by a state store that holds at
+    /// most `N` keys.
+    pub fn fixed_capacity_with_clock(quota: Quota, overflow: OverflowPolicy, clock: &C) -> Self {
+        RateLimiter::new(quota, FixedCapacityStateStore::new(overflow), clock)
+    }
+}
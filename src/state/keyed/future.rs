@@ -1,12 +1,16 @@
 use std::prelude::v1::*;
 
+use crate::jitter::JitterState;
 use crate::{
     clock::{self},
-    state::keyed::KeyedStateStore,
-    Jitter, RateLimiter,
+    state::{
+        direct::{InsufficientCapacity, WaitStats},
+        keyed::KeyedStateStore,
+    },
+    Jitter, NegativeMultiDecision, RateLimiter,
 };
-use futures_timer::Delay;
 use std::hash::Hash;
+use std::num::NonZeroU32;
 
 #[cfg(feature = "std")]
 /// # Keyed rate limiters - `async`/`await`
@@ -41,9 +45,94 @@ where
     /// which can help reduce the likelihood of thundering herd effects if multiple tasks try to
     /// wait on the same rate limiter.
     pub async fn until_key_ready_with_jitter(&self, key: &K, jitter: Jitter) {
+        self.until_key_ready_with_stats(key, jitter).await;
+    }
+
+    /// Asynchronously resolves as soon as the rate limiter allows it, like
+    /// [`until_key_ready_with_jitter`](#method.until_key_ready_with_jitter), and reports how
+    /// long it waited and how often it checked the rate limiter again.
+    ///
+    /// Pass `Jitter::default()` to wait without jitter.
+    pub async fn until_key_ready_with_stats(&self, key: &K, jitter: Jitter) -> WaitStats {
+        let start = self.clock.now();
+        let mut jitter_state = JitterState::default();
+        let mut retries = 0;
         while let Err(negative) = self.check_key(key) {
-            let delay = Delay::new(jitter + negative.wait_time_from(self.clock.now()));
+            let delay = self.clock.delay(
+                jitter.add_next(&mut jitter_state, negative.wait_time_from(self.clock.now())),
+            );
             delay.await;
+            retries += 1;
+        }
+        WaitStats::since(start, self.clock.now(), retries)
+    }
+
+    /// Asynchronously resolves as soon as the rate limiter allows `n` cells through for `key`.
+    ///
+    /// This is similar to `until_key_ready` except it waits for an abitrary number
+    /// of `n` cells to be available.
+    ///
+    /// Returns `InsufficientCapacity` if the `n` provided exceeds the maximum
+    /// capacity of the rate limiter.
+    pub async fn until_key_n_ready(
+        &self,
+        key: &K,
+        n: NonZeroU32,
+    ) -> Result<(), InsufficientCapacity> {
+        self.until_key_n_ready_with_jitter(key, n, Jitter::NONE)
+            .await
+    }
+
+    /// Asynchronously resolves as soon as the rate limiter allows `n` cells through for `key`,
+    /// with a randomized wait period.
+    ///
+    /// This is similar to `until_key_ready_with_jitter` except it waits for an
+    /// abitrary number of `n` cells to be available.
+    ///
+    /// Returns `InsufficientCapacity` if the `n` provided exceeds the maximum
+    /// capacity of the rate limiter.
+    pub async fn until_key_n_ready_with_jitter(
+        &self,
+        key: &K,
+        n: NonZeroU32,
+        jitter: Jitter,
+    ) -> Result<(), InsufficientCapacity> {
+        self.until_key_n_ready_with_stats(key, n, jitter)
+            .await
+            .map(|_| ())
+    }
+
+    /// Asynchronously resolves as soon as the rate limiter allows `n` cells through for `key`,
+    /// like [`until_key_n_ready_with_jitter`](#method.until_key_n_ready_with_jitter), and
+    /// reports how long it waited and how often it checked the rate limiter again.
+    ///
+    /// Returns `InsufficientCapacity` if the `n` provided exceeds the maximum
+    /// capacity of the rate limiter.
+    pub async fn until_key_n_ready_with_stats(
+        &self,
+        key: &K,
+        n: NonZeroU32,
+        jitter: Jitter,
+    ) -> Result<WaitStats, InsufficientCapacity> {
+        let start = self.clock.now();
+        let mut jitter_state = JitterState::default();
+        let mut retries = 0;
+        while let Err(err) = self.check_key_n(key, n) {
+            match err {
+                NegativeMultiDecision::BatchNonConforming(_, negative) => {
+                    let delay = self.clock.delay(
+                        jitter
+                            .add_next(&mut jitter_state, negative.wait_time_from(self.clock.now())),
+                    );
+                    delay.await;
+                    retries += 1;
+                }
+                NegativeMultiDecision::InsufficientCapacity(insufficient) => {
+                    return Err(insufficient)
+                }
+            }
         }
+
+        Ok(WaitStats::since(start, self.clock.now(), retries))
     }
 }
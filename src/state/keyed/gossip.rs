@@ -0,0 +1,397 @@
+use std::prelude::v1::*;
+
+use crate::clock::{self, Reference};
+use crate::nanos::Nanos;
+use crate::state::keyed::ShrinkableKeyedStateStore;
+use crate::state::{InMemoryState, StateStore};
+use crate::{Quota, RateLimiter};
+use parking_lot::Mutex;
+use std::cell::Cell;
+use std::cmp;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// The weight of the cells that one node let through for a key, as gossiped between the nodes
+/// of a [`GossipStateStore`].
+///
+/// A node starts counting anew (with a higher `incarnation`) whenever it starts tracking a key
+/// again, e.g. after it dropped the key's state in
+/// [`retain_recent`](../struct.RateLimiter.html#method.retain_recent). The fields are public so
+/// that transports can encode and decode digests in whatever format they use.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct GossipCount<K> {
+    /// The key that the cells were let through for.
+    pub key: K,
+
+    /// The ID of the node that let the cells through.
+    pub node: u64,
+
+    /// The generation of the node's count for the key. Counts of higher incarnations replace
+    /// those of lower ones.
+    pub incarnation: u64,
+
+    /// The total weight of the cells that the node let through in this incarnation.
+    pub weight: Nanos,
+}
+
+/// A digest of the cells that a [`GossipStateStore`]'s node knows were let through, by itself
+/// and by the nodes it heard from.
+///
+/// Digests are state-based: Each contains the node's complete knowledge, so merging digests is
+/// commutative and idempotent, and digests that get lost, duplicated or reordered in transit
+/// only delay the nodes' convergence.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct GossipDigest<K> {
+    counts: Vec<GossipCount<K>>,
+}
+
+impl<K> GossipDigest<K> {
+    /// Constructs a digest from its counts, e.g. after decoding one received from a peer.
+    pub fn new(counts: Vec<GossipCount<K>>) -> Self {
+        GossipDigest { counts }
+    }
+
+    /// Returns the counts in the digest.
+    pub fn counts(&self) -> &[GossipCount<K>] {
+        &self.counts
+    }
+
+    /// Consumes the digest and returns its counts, e.g. to encode them.
+    pub fn into_counts(self) -> Vec<GossipCount<K>> {
+        self.counts
+    }
+}
+
+/// A way to send digests of a [`GossipStateStore`] to its peers.
+///
+/// This is implemented for closures that take a digest, so that sending it over e.g. a channel
+/// or a UDP socket doesn't need a named type.
+pub trait GossipTransport<K> {
+    /// Sends `digest` to some or all of the node's peers.
+    fn send(&self, digest: GossipDigest<K>);
+}
+
+impl<K, F> GossipTransport<K> for F
+where
+    F: Fn(GossipDigest<K>),
+{
+    fn send(&self, digest: GossipDigest<K>) {
+        self(digest)
+    }
+}
+
+/// An experimental keyed state store that makes rate limiting decisions locally, and shares them
+/// with the state stores of other nodes (its peers) by exchanging digests over a transport that
+/// the user supplies.
+///
+/// This is meant for enforcing a rate limit across a number of nodes where no shared backend
+/// (like one for a [`SyncedStateStore`][crate::state::keyed::SyncedStateStore]) is available.
+/// Each node counts the weight of the cells that it lets through for each key. Periodically,
+/// each node sends a [digest](../struct.RateLimiter.html#method.send_digest) of all the counts
+/// it knows to its peers, which [merge](../struct.RateLimiter.html#method.merge_digest) it into
+/// their own: The cells that a peer let through since its previous digest count against the
+/// key's local state. Digests can be relayed, so the nodes don't need to be fully connected,
+/// and each node needs a unique ID.
+///
+/// # Over-admission
+///
+/// Between two digests, each node only knows about the cells that it let through itself: With
+/// `N` nodes, up to `N` times a key's burst capacity, plus `N - 1` times the cells that
+/// replenish between digests, can be let through in the worst case. Once the digests are
+/// merged, the over-admitted cells count against the key, so it is throttled for
+/// correspondingly longer afterwards. Merging never leaves a key's state worse than empty,
+/// though, so a node that learns of more cells than its peers could have let through (e.g.
+/// because it relayed the counts of a key that it dropped in the meantime) throttles the key at
+/// most until its burst capacity replenished.
+///
+/// All nodes must use the same quota. A node that restarts (and so loses its counts) must use a
+/// new ID, or its peers would ignore its counts until they exceed the ones from before the
+/// restart.
+///
+/// A node remembers the latest count that it merged from each peer for a key even after it
+/// dropped the key's state (by [`reset_key`](../struct.RateLimiter.html#method.reset_key) or
+/// [`retain_recent`](../struct.RateLimiter.html#method.retain_recent)), so that these counts
+/// don't count against the key again when peers keep sending them. This takes a little memory
+/// for every key and peer that the node ever heard of, for as long as the state store exists.
+///
+/// # Example
+/// ```rust
+/// # use governor::{clock::FakeRelativeClock, Quota, RateLimiter};
+/// # use nonzero_ext::nonzero;
+/// let clock = FakeRelativeClock::default();
+/// let quota = Quota::per_second(nonzero!(2u32));
+/// let a = RateLimiter::gossip_with_clock(quota, 1, &clock);
+/// let b = RateLimiter::gossip_with_clock(quota, 2, &clock);
+///
+/// a.check_key(&"tenant").unwrap();
+/// a.check_key(&"tenant").unwrap();
+/// // B doesn't know of A's cells yet:
+/// assert!(b.check_key(&"tenant").is_ok());
+///
+/// // Once it merges A's digest, it does:
+/// a.send_digest(&|digest| b.merge_digest(&digest));
+/// assert!(b.check_key(&"tenant").is_err());
+/// ```
+pub struct GossipStateStore<K, C: clock::Clock> {
+    node: u64,
+    states: Mutex<HashMap<K, Entry>>,
+    peers: Mutex<HashMap<K, Seen>>,
+    next_incarnation: AtomicU64,
+    clock: C,
+    start: C::Instant,
+    // The state of a fresh key, and the one of an empty bucket, relative to the present:
+    fresh: Nanos,
+    empty: Nanos,
+}
+
+/// The latest incarnation and weight that were merged for a key from each peer, by the peers'
+/// IDs.
+type Seen = HashMap<u64, (u64, Nanos)>;
+
+/// A key's local state, along with the count of the cells that this node let through.
+struct Entry {
+    state: InMemoryState,
+    incarnation: u64,
+    weight: Nanos,
+}
+
+impl<K: Hash + Eq + Clone, C: clock::Clock> GossipStateStore<K, C> {
+    fn now(&self) -> Nanos {
+        self.clock.now().duration_since(self.start)
+    }
+
+    fn new_entry(&self) -> Entry {
+        let incarnation = self.next_incarnation.fetch_add(1, Ordering::Relaxed);
+        Entry {
+            state: InMemoryState::default(),
+            incarnation,
+            weight: Nanos::from(0),
+        }
+    }
+
+    /// Returns the ID of the node that this state store belongs to.
+    pub fn node(&self) -> u64 {
+        self.node
+    }
+
+    /// Returns a digest of all the counts that the state store knows.
+    pub fn digest(&self) -> GossipDigest<K> {
+        let states = self.states.lock();
+        let peers = self.peers.lock();
+        let mut counts = Vec::new();
+        for (key, entry) in states.iter() {
+            counts.push(GossipCount {
+                key: key.clone(),
+                node: self.node,
+                incarnation: entry.incarnation,
+                weight: entry.weight,
+            });
+        }
+        for (key, seen) in peers.iter() {
+            for (&node, &(incarnation, weight)) in seen {
+                counts.push(GossipCount {
+                    key: key.clone(),
+                    node,
+                    incarnation,
+                    weight,
+                });
+            }
+        }
+        GossipDigest { counts }
+    }
+
+    /// Merges a digest received from a peer, counting the cells that the peers let through
+    /// since their previous digests against the local states.
+    pub fn merge(&self, digest: &GossipDigest<K>) {
+        let now = self.now();
+        let empty = Nanos::from(now.as_u64().saturating_add(self.empty.as_u64()));
+        let mut states = self.states.lock();
+        let mut peers = self.peers.lock();
+        for count in &digest.counts {
+            if count.node == self.node {
+                continue;
+            }
+            let seen = match peers.get_mut(&count.key) {
+                Some(seen) => seen,
+                None => peers.entry(count.key.clone()).or_default(),
+            }
+            .entry(count.node)
+            .or_insert((0, Nanos::from(0)));
+            let added = match count.incarnation.cmp(&seen.0) {
+                cmp::Ordering::Greater => count.weight,
+                cmp::Ordering::Equal if count.weight > seen.1 => {
+                    count.weight.saturating_sub(seen.1)
+                }
+                _ => continue,
+            };
+            *seen = (count.incarnation, count.weight);
+            let entry = match states.get_mut(&count.key) {
+                Some(entry) => entry,
+                None => states
+                    .entry(count.key.clone())
+                    .or_insert_with(|| self.new_entry()),
+            };
+            let fresh = now + self.fresh;
+            let _: Result<(), ()> = entry.state.measure_and_replace_one(|tat| {
+                let tat = tat.unwrap_or(fresh);
+                let debited = cmp::max(tat, now).as_u64().saturating_add(added.as_u64());
+                Ok(((), cmp::max(tat, cmp::min(Nanos::from(debited), empty))))
+            });
+        }
+    }
+}
+
+impl<K, C: clock::Clock> fmt::Debug for GossipStateStore<K, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        f.debug_struct("GossipStateStore")
+            .field("node", &self.node)
+            .field("keys", &self.states.lock().len())
+            .finish()
+    }
+}
+
+impl<K: Hash + Eq + Clone, C: clock::Clock> StateStore for GossipStateStore<K, C> {
+    type Key = K;
+
+    fn measure_and_replace<T, F, E>(&self, key: &Self::Key, f: F) -> Result<T, E>
+    where
+        F: Fn(Option<Nanos>) -> Result<(T, Nanos), E>,
+    {
+        let mut states = self.states.lock();
+        let entry = match states.get_mut(key) {
+            Some(entry) => entry,
+            None => states
+                .entry(key.clone())
+                .or_insert_with(|| self.new_entry()),
+        };
+        let now = self.now();
+        let update = Cell::new(None);
+        let result = entry.state.measure_and_replace_one(|tat| {
+            let (result, new_tat) = f(tat)?;
+            update.set(Some((tat, new_tat)));
+            Ok((result, new_tat))
+        })?;
+        if let Some((tat, new_tat)) = update.get() {
+            // The cells' weight is how far they moved the state past the present:
+            let base = cmp::max(tat.unwrap_or(now + self.fresh), now);
+            entry.weight = entry.weight + new_tat.saturating_sub(base);
+        }
+        Ok(result)
+    }
+
+    fn peek(&self, key: &Self::Key) -> Option<Nanos> {
+        let states = self.states.lock();
+        states.get(key).and_then(|entry| entry.state.tat())
+    }
+
+    /// Drops the key's state and this node's count for it, like `retain_recent` does for stale
+    /// keys. The counts merged from peers are kept, so peers' cells that were counted before
+    /// aren't counted against the key again.
+    fn reset(&self, key: &Self::Key) {
+        self.states.lock().remove(key);
+    }
+}
+
+impl<K: Hash + Eq + Clone, C: clock::Clock> ShrinkableKeyedStateStore<K>
+    for GossipStateStore<K, C>
+{
+    /// Removes stale keys' states, along with this node's counts for them. The counts merged
+    /// from peers are kept (see [`GossipStateStore`]).
+    fn retain_recent(&self, drop_below: Nanos) {
+        let mut states = self.states.lock();
+        states.retain(|_, entry| !entry.state.is_older_than(drop_below));
+    }
+
+    fn shrink_to_fit(&self) {
+        self.states.lock().shrink_to_fit();
+        self.peers.lock().shrink_to_fit();
+    }
+
+    fn len(&self) -> usize {
+        self.states.lock().len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.states.lock().is_empty()
+    }
+
+    fn snapshot(&self) -> Vec<(K, Option<Nanos>)> {
+        let states = self.states.lock();
+        states
+            .iter()
+            .map(|(key, entry)| (key.clone(), entry.state.tat()))
+            .collect()
+    }
+}
+
+/// # Keyed rate limiters - shared between peers via gossip
+impl<K, C> RateLimiter<K, GossipStateStore<K, C>, C>
+where
+    K: Hash + Eq + Clone,
+    C: clock::Clock,
+{
+    /// Constructs a new rate limiter with a custom clock, which shares its decisions with other
+    /// nodes' rate limiters by exchanging digests (see [`GossipStateStore`]). `node` is the ID
+    /// of this node, which must be unique among the peers.
+    pub fn gossip_with_clock(quota: Quota, node: u64, clock: &C) -> Self {
+        let store = GossipStateStore {
+            node,
+            states: Mutex::new(HashMap::new()),
+            peers: Mutex::new(HashMap::new()),
+            next_incarnation: AtomicU64::new(1),
+            clock: clock.clone(),
+            start: clock.now(),
+            fresh: Nanos::from(0),
+            empty: Nanos::from(0),
+        };
+        let mut limiter = RateLimiter::new(quota, store, clock);
+        let (t, tau) = (limiter.gcra.t().as_u64(), limiter.gcra.tau().as_u64());
+        let empty = Nanos::from(t.saturating_add(tau));
+        limiter.state.start = limiter.start;
+        limiter.state.fresh = if quota.starts_empty() {
+            empty
+        } else {
+            limiter.gcra.t()
+        };
+        limiter.state.empty = empty;
+        limiter
+    }
+
+    /// Returns the ID of this node.
+    pub fn node(&self) -> u64 {
+        self.state.node()
+    }
+
+    /// Returns a digest of all the cells that this node knows were let through, by itself and
+    /// its peers.
+    pub fn digest(&self) -> GossipDigest<K> {
+        self.state.digest()
+    }
+
+    /// Sends a digest of all the cells that this node knows were let through over `transport`.
+    /// Call this periodically, e.g. once a second.
+    pub fn send_digest(&self, transport: &impl GossipTransport<K>) {
+        transport.send(self.digest());
+    }
+
+    /// Merges a digest received from a peer, so that the cells the peers let through (since
+    /// their previous digests) count against this node's rate limiting states.
+    pub fn merge_digest(&self, digest: &GossipDigest<K>) {
+        self.state.merge(digest);
+    }
+}
+
+#[cfg(feature = "std")]
+impl<K> RateLimiter<K, GossipStateStore<K, clock::DefaultClock>, clock::DefaultClock>
+where
+    K: Hash + Eq + Clone,
+{
+    /// Constructs a new rate limiter with the default clock, which shares its decisions with
+    /// other nodes' rate limiters by exchanging digests (see [`GossipStateStore`]).
+    pub fn gossip(quota: Quota, node: u64) -> Self {
+        let clock = clock::DefaultClock::default();
+        RateLimiter::gossip_with_clock(quota, node, &clock)
+    }
+}
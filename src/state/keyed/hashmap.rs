@@ -29,11 +29,18 @@ impl<K: Hash + Eq + Clone> StateStore for HashMapStateStore<K> {
             return v.measure_and_replace_one(f);
         }
         // not-so-fast path: make a new entry and measure it.
-        let entry = (*map)
-            .entry(key.clone())
-            .or_insert_with(InMemoryState::default);
+        let entry = (*map).entry(key.clone()).or_default();
         entry.measure_and_replace_one(f)
     }
+
+    fn peek(&self, key: &Self::Key) -> Option<Nanos> {
+        let map = self.lock();
+        map.get(key).and_then(InMemoryState::tat)
+    }
+
+    fn reset(&self, key: &Self::Key) {
+        self.lock().remove(key);
+    }
 }
 
 impl<K: Hash + Eq + Clone> ShrinkableKeyedStateStore<K> for HashMapStateStore<K> {
@@ -55,6 +62,11 @@ impl<K: Hash + Eq + Clone> ShrinkableKeyedStateStore<K> for HashMapStateStore<K>
         let map = self.lock();
         (*map).is_empty()
     }
+
+    fn snapshot(&self) -> Vec<(K, Option<Nanos>)> {
+        let map = self.lock();
+        map.iter().map(|(k, v)| (k.clone(), v.tat())).collect()
+    }
 }
 
 /// # Keyed rate limiters - [`HashMap`]-backed
@@ -0,0 +1,93 @@
+use std::prelude::v1::*;
+
+use crate::nanos::Nanos;
+use crate::state::keyed::ShrinkableKeyedStateStore;
+use crate::state::{LocalState, StateStore};
+use crate::{clock, Quota, RateLimiter};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// A single-threaded implementation of a keyed rate limiter state store using [`HashMap`].
+///
+/// Like the [`LocalState`] it holds per key, this state store can not be shared between threads,
+/// and avoids the cost of locking and atomic operations. It also supports keys that are not
+/// [`Send`], like [`Rc`][std::rc::Rc]s.
+pub type LocalHashMapStateStore<K> = RefCell<HashMap<K, LocalState>>;
+
+impl<K: Hash + Eq + Clone> StateStore for LocalHashMapStateStore<K> {
+    type Key = K;
+
+    fn measure_and_replace<T, F, E>(&self, key: &Self::Key, f: F) -> Result<T, E>
+    where
+        F: Fn(Option<Nanos>) -> Result<(T, Nanos), E>,
+    {
+        let mut map = self.borrow_mut();
+        if let Some(v) = map.get(key) {
+            return v.measure_and_replace_one(f);
+        }
+        let entry = map.entry(key.clone()).or_default();
+        entry.measure_and_replace_one(f)
+    }
+
+    fn peek(&self, key: &Self::Key) -> Option<Nanos> {
+        self.borrow().get(key).and_then(LocalState::tat)
+    }
+
+    fn reset(&self, key: &Self::Key) {
+        self.borrow_mut().remove(key);
+    }
+}
+
+impl<K: Hash + Eq + Clone> ShrinkableKeyedStateStore<K> for LocalHashMapStateStore<K> {
+    fn retain_recent(&self, drop_below: Nanos) {
+        self.borrow_mut()
+            .retain(|_, v| !v.is_older_than(drop_below));
+    }
+
+    fn shrink_to_fit(&self) {
+        self.borrow_mut().shrink_to_fit();
+    }
+
+    fn len(&self) -> usize {
+        self.borrow().len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.borrow().is_empty()
+    }
+
+    fn snapshot(&self) -> Vec<(K, Option<Nanos>)> {
+        self.borrow()
+            .iter()
+            .map(|(k, v)| (k.clone(), v.tat()))
+            .collect()
+    }
+}
+
+/// # Keyed single-threaded rate limiters - [`HashMap`]-backed
+#[cfg(feature = "std")]
+impl<K> RateLimiter<K, LocalHashMapStateStore<K>, clock::DefaultClock>
+where
+    K: Hash + Eq + Clone,
+{
+    /// Constructs a new single-threaded keyed rate limiter backed by a [`HashMap`], with the
+    /// default real-time clock.
+    pub fn local_hashmap(quota: Quota) -> Self {
+        let clock = clock::DefaultClock::default();
+        Self::local_hashmap_with_clock(quota, &clock)
+    }
+}
+
+impl<K, C> RateLimiter<K, LocalHashMapStateStore<K>, C>
+where
+    K: Hash + Eq + Clone,
+    C: clock::Clock,
+{
+    /// Constructs a new single-threaded keyed rate limiter with a custom clock, backed by a
+    /// [`HashMap`].
+    pub fn local_hashmap_with_clock(quota: Quota, clock: &C) -> Self {
+        let state: LocalHashMapStateStore<K> = RefCell::new(HashMap::new());
+        RateLimiter::new(quota, state, clock)
+    }
+}
@@ -0,0 +1,205 @@
+use std::prelude::v1::*;
+
+use crate::nanos::Nanos;
+use crate::state::keyed::ShrinkableKeyedStateStore;
+use crate::state::{InMemoryState, StateStore};
+use crate::{clock, Quota, RateLimiter};
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+/// A keyed rate limiter state store that spreads its keys over `SHARDS` independently locked
+/// [`HashMap`]s.
+///
+/// Each key is routed to a shard by its hash, so rate-limiting decisions on keys in different
+/// shards don't contend for the same lock. This makes it a middle ground between the
+/// [`HashMapStateStore`][super::HashMapStateStore] (one lock for all keys) and the
+/// `DashMapStateStore` (which needs the `dashmap` feature).
+///
+/// The routing is stable: It doesn't depend on the process or on the order in which keys are
+/// inserted, only on the key's [`Hash`] implementation and on `SHARDS`, so a key always ends
+/// up in the same shard (see [`shard_index`][ShardedHashMapStateStore::shard_index]).
+/// Housekeeping (like [`retain_recent`](../struct.RateLimiter.html#method.retain_recent)) locks
+/// one shard at a time.
+///
+/// # Example
+/// ```rust
+/// # use governor::{clock::FakeRelativeClock, state::keyed::ShardedHashMapStateStore, Quota, RateLimiter};
+/// # use nonzero_ext::nonzero;
+/// let clock = FakeRelativeClock::default();
+/// let lim = RateLimiter::<u32, ShardedHashMapStateStore<u32, 8>, _>::sharded_hashmap_with_clock(
+///     Quota::per_second(nonzero!(1u32)),
+///     &clock,
+/// );
+/// assert!(lim.check_key(&1).is_ok());
+/// assert!(lim.check_key(&1).is_err());
+/// assert!(lim.check_key(&2).is_ok());
+/// ```
+pub struct ShardedHashMapStateStore<K, const SHARDS: usize> {
+    shards: [Mutex<HashMap<K, InMemoryState>>; SHARDS],
+}
+
+impl<K, const SHARDS: usize> ShardedHashMapStateStore<K, SHARDS> {
+    /// Constructs a new, empty state store.
+    ///
+    /// # Panics
+    /// Panics if `SHARDS` is zero.
+    pub fn new() -> Self {
+        assert!(SHARDS > 0, "a sharded state store needs at least one shard");
+        ShardedHashMapStateStore {
+            shards: [(); SHARDS].map(|_| Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Returns the number of shards that the state store spreads its keys over.
+    pub const fn shard_count(&self) -> usize {
+        SHARDS
+    }
+
+    /// Returns the number of keys in each shard, e.g. to check how evenly the keys are spread.
+    pub fn shard_lens(&self) -> [usize; SHARDS] {
+        let mut lens = [0; SHARDS];
+        for (len, shard) in lens.iter_mut().zip(self.shards.iter()) {
+            *len = shard.lock().len();
+        }
+        lens
+    }
+}
+
+impl<K: Hash, const SHARDS: usize> ShardedHashMapStateStore<K, SHARDS> {
+    /// Returns the index of the shard that `key` is routed to.
+    pub fn shard_index(&self, key: &K) -> usize {
+        let mut hasher = Fnv1a::default();
+        key.hash(&mut hasher);
+        (hasher.finish() % SHARDS as u64) as usize
+    }
+
+    fn shard(&self, key: &K) -> &Mutex<HashMap<K, InMemoryState>> {
+        &self.shards[self.shard_index(key)]
+    }
+}
+
+impl<K, const SHARDS: usize> Default for ShardedHashMapStateStore<K, SHARDS> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, const SHARDS: usize> fmt::Debug for ShardedHashMapStateStore<K, SHARDS> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ShardedHashMapStateStore")
+            .field("shards", &SHARDS)
+            .finish()
+    }
+}
+
+impl<K: Hash + Eq + Clone, const SHARDS: usize> StateStore for ShardedHashMapStateStore<K, SHARDS> {
+    type Key = K;
+
+    fn measure_and_replace<T, F, E>(&self, key: &Self::Key, f: F) -> Result<T, E>
+    where
+        F: Fn(Option<Nanos>) -> Result<(T, Nanos), E>,
+    {
+        let mut map = self.shard(key).lock();
+        if let Some(v) = map.get(key) {
+            // fast path: a rate limiter is already present for the key.
+            return v.measure_and_replace_one(f);
+        }
+        // not-so-fast path: make a new entry and measure it.
+        let entry = map.entry(key.clone()).or_default();
+        entry.measure_and_replace_one(f)
+    }
+
+    fn peek(&self, key: &Self::Key) -> Option<Nanos> {
+        let map = self.shard(key).lock();
+        map.get(key).and_then(InMemoryState::tat)
+    }
+
+    fn reset(&self, key: &Self::Key) {
+        self.shard(key).lock().remove(key);
+    }
+}
+
+impl<K: Hash + Eq + Clone, const SHARDS: usize> ShrinkableKeyedStateStore<K>
+    for ShardedHashMapStateStore<K, SHARDS>
+{
+    fn retain_recent(&self, drop_below: Nanos) {
+        for shard in self.shards.iter() {
+            shard.lock().retain(|_, v| !v.is_older_than(drop_below));
+        }
+    }
+
+    fn shrink_to_fit(&self) {
+        for shard in self.shards.iter() {
+            shard.lock().shrink_to_fit();
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.shards.iter().map(|shard| shard.lock().len()).sum()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.shards.iter().all(|shard| shard.lock().is_empty())
+    }
+
+    fn snapshot(&self) -> Vec<(K, Option<Nanos>)> {
+        let mut snapshot = Vec::new();
+        for shard in self.shards.iter() {
+            let map = shard.lock();
+            snapshot.extend(map.iter().map(|(k, v)| (k.clone(), v.tat())));
+        }
+        snapshot
+    }
+}
+
+/// The 64-bit FNV-1a hash, which (unlike the maps' hashers) is the same in every process.
+struct Fnv1a(u64);
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Fnv1a(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for Fnv1a {
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        // FNV's low bits are mixed poorly; fold the high bits in before taking the remainder.
+        self.0 ^ (self.0 >> 32)
+    }
+}
+
+/// # Keyed rate limiters - sharded [`HashMap`]-backed
+impl<K, C, const SHARDS: usize> RateLimiter<K, ShardedHashMapStateStore<K, SHARDS>, C>
+where
+    K: Hash + Eq + Clone,
+    C: clock::Clock,
+{
+    /// Constructs a new rate limiter with a custom clock, backed by `SHARDS` independently
+    /// locked [`HashMap`]s.
+    pub fn sharded_hashmap_with_clock(quota: Quota, clock: &C) -> Self {
+        RateLimiter::new(quota, ShardedHashMapStateStore::new(), clock)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<K, const SHARDS: usize>
+    RateLimiter<K, ShardedHashMapStateStore<K, SHARDS>, clock::DefaultClock>
+where
+    K: Hash + Eq + Clone,
+{
+    /// Constructs a new keyed rate limiter backed by `SHARDS` independently locked
+    /// [`HashMap`]s, with the default real-time clock.
+    pub fn sharded_hashmap(quota: Quota) -> Self {
+        let clock = clock::DefaultClock::default();
+        Self::sharded_hashmap_with_clock(quota, &clock)
+    }
+}
@@ -0,0 +1,238 @@
+use std::prelude::v1::*;
+
+use crate::clock::{self, Reference};
+use crate::nanos::Nanos;
+use crate::state::keyed::{DefaultKeyedStateStore, KeyedStateStore, ShrinkableKeyedStateStore};
+use crate::state::StateStore;
+use crate::RateLimiter;
+use parking_lot::Mutex;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+use std::time::Duration;
+
+/// The rate limiting statistics that a [`StatsStateStore`] keeps for a key.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct KeyStats {
+    allowed: u64,
+    denied: u64,
+    last_seen: Nanos,
+}
+
+impl KeyStats {
+    /// Returns the number of positive decisions that were made for the key.
+    pub fn allowed(&self) -> u64 {
+        self.allowed
+    }
+
+    /// Returns the number of negative decisions that were made for the key.
+    pub fn denied(&self) -> u64 {
+        self.denied
+    }
+
+    /// Returns the time of the latest decision made for the key, in nanoseconds since the rate
+    /// limiter was constructed.
+    pub fn last_seen(&self) -> Nanos {
+        self.last_seen
+    }
+}
+
+/// A keyed state store that keeps statistics about the decisions made for each key, on top of
+/// another keyed state store.
+///
+/// For each key, the statistics count the positive and negative rate limiting decisions, and
+/// record when the latest decision was made (see [`KeyStats`]). Decisions on batches of cells
+/// (e.g. with [`check_key_n`](../struct.RateLimiter.html#method.check_key_n)) count as one
+/// decision.
+///
+/// The statistics are kept separately from the rate limiting states, and outlive them: Removing
+/// stale keys with [`retain_recent`](../struct.RateLimiter.html#method.retain_recent) doesn't
+/// remove their statistics, so that keys that were throttled a while ago can still be
+/// investigated. To keep the statistics from growing without bounds, remove old ones with
+/// [`forget_stats_older_than`](../struct.RateLimiter.html#method.forget_stats_older_than).
+///
+/// # Example
+/// ```rust
+/// # use governor::{clock::FakeRelativeClock, state::keyed::StatsStateStore, Quota, RateLimiter};
+/// # use nonzero_ext::nonzero;
+/// let clock = FakeRelativeClock::default();
+/// let quota = Quota::per_second(nonzero!(1u32));
+/// let lim = RateLimiter::new(quota, StatsStateStore::<&str>::default(), &clock);
+/// for _ in 0..5 {
+///     let _ = lim.check_key(&"scraper");
+/// }
+/// lim.check_key(&"customer").unwrap();
+///
+/// let throttled = lim.most_throttled(10);
+/// assert_eq!(throttled.len(), 1);
+/// let (key, stats) = throttled[0];
+/// assert_eq!(key, "scraper");
+/// assert_eq!((stats.allowed(), stats.denied()), (1, 4));
+/// ```
+pub struct StatsStateStore<K, S = DefaultKeyedStateStore<K>> {
+    inner: S,
+    stats: Mutex<HashMap<K, KeyStats>>,
+}
+
+impl<K: Hash + Eq + Clone, S: KeyedStateStore<K>> StatsStateStore<K, S> {
+    /// Constructs a state store that keeps statistics on top of `inner`, which keeps the rate
+    /// limiting states.
+    pub fn new(inner: S) -> Self {
+        StatsStateStore {
+            inner,
+            stats: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns a reference to the state store that keeps the rate limiting states.
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns the statistics for `key`, or `None` if no decision was made for it yet (or its
+    /// statistics were removed).
+    pub fn key_stats(&self, key: &K) -> Option<KeyStats> {
+        self.stats.lock().get(key).copied()
+    }
+
+    /// Returns the statistics of all keys, in no particular order.
+    pub fn stats(&self) -> Vec<(K, KeyStats)> {
+        let stats = self.stats.lock();
+        stats.iter().map(|(k, s)| (k.clone(), *s)).collect()
+    }
+
+    /// Returns the (at most) `n` keys with the most negative decisions, along with their
+    /// statistics, starting with the most throttled key. Keys that were never throttled are
+    /// not included. Among keys that were throttled equally often, the most recently seen come
+    /// first.
+    pub fn most_throttled(&self, n: usize) -> Vec<(K, KeyStats)> {
+        let mut throttled: Vec<(K, KeyStats)> = {
+            let stats = self.stats.lock();
+            stats
+                .iter()
+                .filter(|(_, s)| s.denied > 0)
+                .map(|(k, s)| (k.clone(), *s))
+                .collect()
+        };
+        throttled.sort_unstable_by_key(|(_, s)| Reverse((s.denied, s.last_seen)));
+        throttled.truncate(n);
+        throttled
+    }
+
+    /// Removes the statistics of keys whose latest decision was made before `drop_below` (in
+    /// nanoseconds since the rate limiter was constructed).
+    pub fn retain_stats(&self, drop_below: Nanos) {
+        self.stats.lock().retain(|_, s| s.last_seen >= drop_below);
+    }
+
+    /// Removes the statistics of all keys.
+    pub fn clear_stats(&self) {
+        self.stats.lock().clear();
+    }
+}
+
+impl<K: Hash + Eq + Clone, S: KeyedStateStore<K> + Default> Default for StatsStateStore<K, S> {
+    fn default() -> Self {
+        Self::new(S::default())
+    }
+}
+
+impl<K, S: fmt::Debug> fmt::Debug for StatsStateStore<K, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        f.debug_struct("StatsStateStore")
+            .field("inner", &self.inner)
+            .field("keys_with_stats", &self.stats.lock().len())
+            .finish()
+    }
+}
+
+impl<K: Hash + Eq + Clone, S: KeyedStateStore<K>> StateStore for StatsStateStore<K, S> {
+    type Key = K;
+
+    fn measure_and_replace<T, F, E>(&self, key: &Self::Key, f: F) -> Result<T, E>
+    where
+        F: Fn(Option<Nanos>) -> Result<(T, Nanos), E>,
+    {
+        self.inner.measure_and_replace(key, f)
+    }
+
+    fn peek(&self, key: &Self::Key) -> Option<Nanos> {
+        self.inner.peek(key)
+    }
+
+    fn reset(&self, key: &Self::Key) {
+        self.inner.reset(key)
+    }
+
+    fn record_decision(&self, key: &Self::Key, conforming: bool, t0: Nanos) {
+        {
+            let mut stats = self.stats.lock();
+            let entry = match stats.get_mut(key) {
+                Some(entry) => entry,
+                None => stats.entry(key.clone()).or_default(),
+            };
+            if conforming {
+                entry.allowed += 1;
+            } else {
+                entry.denied += 1;
+            }
+            entry.last_seen = entry.last_seen.max(t0);
+        }
+        self.inner.record_decision(key, conforming, t0);
+    }
+}
+
+impl<K, S> ShrinkableKeyedStateStore<K> for StatsStateStore<K, S>
+where
+    K: Hash + Eq + Clone,
+    S: ShrinkableKeyedStateStore<K>,
+{
+    fn retain_recent(&self, drop_below: Nanos) {
+        self.inner.retain_recent(drop_below);
+    }
+
+    fn shrink_to_fit(&self) {
+        self.inner.shrink_to_fit();
+        self.stats.lock().shrink_to_fit();
+    }
+
+    fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    fn snapshot(&self) -> Vec<(K, Option<Nanos>)> {
+        self.inner.snapshot()
+    }
+}
+
+/// # Keyed rate limiters - statistics
+impl<K, S, C> RateLimiter<K, StatsStateStore<K, S>, C>
+where
+    K: Hash + Eq + Clone,
+    S: KeyedStateStore<K>,
+    C: clock::Clock,
+{
+    /// Returns the statistics for `key`, or `None` if no decision was made for it yet (or its
+    /// statistics were removed).
+    pub fn key_stats(&self, key: &K) -> Option<KeyStats> {
+        self.state.key_stats(key)
+    }
+
+    /// Returns the (at most) `n` keys with the most negative decisions, along with their
+    /// statistics, starting with the most throttled key. See
+    /// [`StatsStateStore::most_throttled`].
+    pub fn most_throttled(&self, n: usize) -> Vec<(K, KeyStats)> {
+        self.state.most_throttled(n)
+    }
+
+    /// Removes the statistics of keys for which no decision was made in the last `age`.
+    pub fn forget_stats_older_than(&self, age: Duration) {
+        let now = self.clock.now().duration_since(self.start);
+        self.state.retain_stats(now.saturating_sub(age.into()));
+    }
+}
@@ -0,0 +1,376 @@
+use std::prelude::v1::*;
+
+use crate::clock::{self, Reference};
+use crate::nanos::Nanos;
+use crate::state::keyed::cas::{clear_blob, decode_tat, encode_tat, ErrorHook};
+use crate::state::keyed::{CasBackend, ShrinkableKeyedStateStore};
+use crate::state::{InMemoryState, StateStore};
+use crate::{Quota, RateLimiter};
+use parking_lot::Mutex;
+use std::cell::Cell;
+use std::cmp;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+use std::sync::{Arc, Weak};
+use std::thread;
+use std::time::Duration;
+
+/// A keyed rate limiter state store that makes rate limiting decisions locally, and
+/// periodically reconciles them with the states that other rate limiters keep in a shared
+/// [`CasBackend`].
+///
+/// Rate limiters using a [`CasStateStore`][crate::state::keyed::CasStateStore] make at least
+/// one round trip to the backend for every decision. This state store instead keeps a local copy
+/// of each key's state, and only talks to the backend when it syncs: It then adds the cells that
+/// it let through since the last sync to the shared state, and adopts the shared state (which
+/// includes the cells that other rate limiters let through) as its local copy. Syncing happens
+/// on a background thread in a fixed interval, and whenever
+/// [`sync`](../struct.RateLimiter.html#method.sync) is called. The background thread is stopped
+/// when the rate limiter is dropped.
+///
+/// The shared states use the same representation as those of a `CasStateStore` (relative to the
+/// UNIX epoch, for rate limiters constructed with
+/// [`synced`](../struct.RateLimiter.html#method.synced)), so both kinds of state store can share
+/// a backend.
+///
+/// # Over-admission
+///
+/// Between two syncs, each rate limiter only knows about the cells that it let through itself.
+/// With `N` rate limiters sharing a key, up to `N` times the quota can be let through in the
+/// worst case: Each rate limiter may use up the key's full burst capacity, plus the cells that
+/// replenish during one sync interval, before it learns about the others. Once the rate
+/// limiters synced, the over-admitted cells count against the key, so it is throttled for
+/// correspondingly longer afterwards; over longer periods, the rate averages out to the quota.
+/// Shorter sync intervals reduce the over-admission, at the cost of more backend round trips
+/// (one or two per locally-known key and sync).
+///
+/// If the backend returns an error while syncing, the local decisions are kept and pushed with
+/// the next sync.
+pub struct SyncedStateStore<K, B, C>
+where
+    B: CasBackend<Key = K>,
+    C: clock::Clock,
+{
+    shared: Arc<Shared<K, B, C>>,
+}
+
+struct Shared<K, B: CasBackend<Key = K>, C: clock::Clock> {
+    backend: B,
+    states: Mutex<HashMap<K, Entry>>,
+    clock: C,
+    start: C::Instant,
+    offset: Nanos,
+    on_error: Option<ErrorHook<B::Error>>,
+}
+
+/// The local copy of a key's state.
+#[derive(Default)]
+struct Entry {
+    state: InMemoryState,
+
+    /// The weight of the cells that were let through since the last sync.
+    unsynced: Nanos,
+}
+
+impl<K, B, C> Shared<K, B, C>
+where
+    K: Hash + Eq + Clone,
+    B: CasBackend<Key = K>,
+    C: clock::Clock,
+{
+    fn now(&self) -> Nanos {
+        self.clock.now().duration_since(self.start)
+    }
+
+    fn sync(&self) {
+        let pending: Vec<(K, Nanos, Nanos)> = {
+            let mut states = self.states.lock();
+            states
+                .iter_mut()
+                .map(|(key, entry)| {
+                    let unsynced = std::mem::replace(&mut entry.unsynced, Nanos::from(0));
+                    let tat = entry.state.tat().unwrap_or_else(|| Nanos::from(0));
+                    (key.clone(), unsynced, tat)
+                })
+                .collect()
+        };
+        for (key, unsynced, local_tat) in pending {
+            match self.push(&key, unsynced, local_tat) {
+                Ok(shared_tat) => {
+                    let states = self.states.lock();
+                    if let Some(entry) = states.get(&key) {
+                        // Cells let through during the round trip aren't included in the
+                        // shared state yet:
+                        let tat = shared_tat + entry.unsynced;
+                        let _: Result<(), ()> = entry.state.measure_and_replace_one(|local| {
+                            Ok(((), cmp::max(local.unwrap_or_else(|| Nanos::from(0)), tat)))
+                        });
+                    }
+                }
+                Err(error) => {
+                    if let Some(on_error) = &self.on_error {
+                        on_error(&error);
+                    }
+                    let mut states = self.states.lock();
+                    if let Some(entry) = states.get_mut(&key) {
+                        entry.unsynced = entry.unsynced + unsynced;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Adds `unsynced` to the shared state of `key`, returning the new shared state (relative to
+    /// the rate limiter's start).
+    ///
+    /// The new shared state is at least the local state `local_tat`: If the shared state is
+    /// older than the cells that were let through locally, they count from the local state
+    /// instead.
+    fn push(&self, key: &K, unsynced: Nanos, local_tat: Nanos) -> Result<Nanos, B::Error> {
+        loop {
+            let (tat, version) = match self.backend.get(key)? {
+                Some((blob, version)) => (decode_tat(&blob), Some(version)),
+                None => (None, None),
+            };
+            let tat = tat.unwrap_or_else(|| Nanos::from(0));
+            if unsynced == Nanos::from(0) {
+                return Ok(tat.saturating_sub(self.offset));
+            }
+            let new_tat = cmp::max(tat + unsynced, local_tat + self.offset);
+            if self
+                .backend
+                .compare_and_swap(key, version.as_ref(), encode_tat(new_tat))?
+            {
+                return Ok(new_tat.saturating_sub(self.offset));
+            }
+        }
+    }
+}
+
+impl<K, B, C> SyncedStateStore<K, B, C>
+where
+    K: Hash + Eq + Clone,
+    B: CasBackend<Key = K>,
+    C: clock::Clock,
+{
+    /// Returns the number of keys whose local state is kept.
+    fn local_len(&self) -> usize {
+        self.shared.states.lock().len()
+    }
+}
+
+impl<K, B, C> fmt::Debug for SyncedStateStore<K, B, C>
+where
+    B: CasBackend<Key = K>,
+    C: clock::Clock,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        f.debug_struct("SyncedStateStore")
+            .field("offset", &self.shared.offset)
+            .finish()
+    }
+}
+
+impl<K, B, C> StateStore for SyncedStateStore<K, B, C>
+where
+    K: Hash + Eq + Clone,
+    B: CasBackend<Key = K>,
+    C: clock::Clock,
+{
+    type Key = K;
+
+    fn measure_and_replace<T, F, E>(&self, key: &Self::Key, f: F) -> Result<T, E>
+    where
+        F: Fn(Option<Nanos>) -> Result<(T, Nanos), E>,
+    {
+        let mut states = self.shared.states.lock();
+        let entry = states.entry(key.clone()).or_default();
+        let now = self.shared.now();
+        let update = Cell::new(None);
+        let result = entry.state.measure_and_replace_one(|tat| {
+            let (result, new_tat) = f(tat)?;
+            update.set(Some((tat, new_tat)));
+            Ok((result, new_tat))
+        })?;
+        if let Some((tat, new_tat)) = update.get() {
+            // The cells' weight is how far they moved the state past the present:
+            let base = cmp::max(tat.unwrap_or(now), now);
+            entry.unsynced = entry.unsynced + new_tat.saturating_sub(base);
+        }
+        Ok(result)
+    }
+
+    fn peek(&self, key: &Self::Key) -> Option<Nanos> {
+        let states = self.shared.states.lock();
+        states.get(key).and_then(|entry| entry.state.tat())
+    }
+
+    /// Drops the local copy of the key's state, including the cells that weren't synced yet,
+    /// and resets the shared state in the backend, so the key is fresh for all rate limiters
+    /// that share it. This blocks the calling thread until the backend answers.
+    fn reset(&self, key: &Self::Key) {
+        self.shared.states.lock().remove(key);
+        if let Err(error) = clear_blob(&self.shared.backend, key) {
+            if let Some(on_error) = &self.shared.on_error {
+                on_error(&error);
+            }
+        }
+    }
+}
+
+impl<K, B, C> ShrinkableKeyedStateStore<K> for SyncedStateStore<K, B, C>
+where
+    K: Hash + Eq + Clone,
+    B: CasBackend<Key = K>,
+    C: clock::Clock,
+{
+    /// Removes the local copies of stale keys' states, except for those with cells that weren't
+    /// synced yet.
+    fn retain_recent(&self, drop_below: Nanos) {
+        let mut states = self.shared.states.lock();
+        states.retain(|_, entry| {
+            entry.unsynced > Nanos::from(0) || !entry.state.is_older_than(drop_below)
+        });
+    }
+
+    fn shrink_to_fit(&self) {
+        self.shared.states.lock().shrink_to_fit();
+    }
+
+    fn len(&self) -> usize {
+        self.local_len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.local_len() == 0
+    }
+
+    fn snapshot(&self) -> Vec<(K, Option<Nanos>)> {
+        let states = self.shared.states.lock();
+        states
+            .iter()
+            .map(|(key, entry)| (key.clone(), entry.state.tat()))
+            .collect()
+    }
+}
+
+/// # Keyed rate limiters - locally cached, synced with a backend
+impl<K, B, C> RateLimiter<K, SyncedStateStore<K, B, C>, C>
+where
+    K: Hash + Eq + Clone + Send + 'static,
+    B: CasBackend<Key = K> + Send + Sync + 'static,
+    C: clock::Clock + Send + Sync + 'static,
+    C::Instant: Send + Sync,
+{
+    /// Constructs a new rate limiter with a custom clock, which makes decisions locally and syncs
+    /// them with the shared states in `backend` (relative to `epoch`) once in `interval`.
+    ///
+    /// If `interval` is `None`, no background thread is started, and the rate limiter only
+    /// syncs when [`sync`](#method.sync) is called.
+    pub fn synced_with_clock(
+        quota: Quota,
+        backend: B,
+        clock: &C,
+        epoch: C::Instant,
+        interval: Option<Duration>,
+    ) -> Result<Self, std::io::Error> {
+        Self::synced_with_clock_and_error_hook(quota, backend, clock, epoch, interval, None)
+    }
+
+    fn synced_with_clock_and_error_hook(
+        quota: Quota,
+        backend: B,
+        clock: &C,
+        epoch: C::Instant,
+        interval: Option<Duration>,
+        on_error: Option<ErrorHook<B::Error>>,
+    ) -> Result<Self, std::io::Error> {
+        let shared = Shared {
+            backend,
+            states: Mutex::new(HashMap::new()),
+            clock: clock.clone(),
+            start: clock.now(),
+            offset: Nanos::from(0),
+            on_error,
+        };
+        let mut limiter = RateLimiter::new(
+            quota,
+            SyncedStateStore {
+                shared: Arc::new(shared),
+            },
+            clock,
+        );
+        let shared = Arc::get_mut(&mut limiter.state.shared).expect("state store is not shared");
+        shared.start = limiter.start;
+        shared.offset = limiter.start.duration_since(epoch);
+
+        if let Some(interval) = interval {
+            let weak: Weak<Shared<K, B, C>> = Arc::downgrade(&limiter.state.shared);
+            thread::Builder::new()
+                .name("governor-sync".to_string())
+                .spawn(move || loop {
+                    thread::sleep(interval);
+                    match weak.upgrade() {
+                        Some(shared) => shared.sync(),
+                        None => return,
+                    }
+                })?;
+        }
+        Ok(limiter)
+    }
+
+    /// Like [`synced_with_clock`](#method.synced_with_clock), but calls `on_error` with each
+    /// error that the backend returns while syncing, e.g. to log or count them.
+    pub fn synced_with_clock_and_on_error<F>(
+        quota: Quota,
+        backend: B,
+        clock: &C,
+        epoch: C::Instant,
+        interval: Option<Duration>,
+        on_error: F,
+    ) -> Result<Self, std::io::Error>
+    where
+        F: Fn(&B::Error) + Send + Sync + 'static,
+    {
+        Self::synced_with_clock_and_error_hook(
+            quota,
+            backend,
+            clock,
+            epoch,
+            interval,
+            Some(Box::new(on_error)),
+        )
+    }
+}
+
+impl<K, B> RateLimiter<K, SyncedStateStore<K, B, clock::SystemClock>, clock::SystemClock>
+where
+    K: Hash + Eq + Clone + Send + 'static,
+    B: CasBackend<Key = K> + Send + Sync + 'static,
+{
+    /// Constructs a new rate limiter which makes decisions locally and syncs them with the
+    /// shared states in `backend` once in `interval`, using the system clock and the UNIX epoch.
+    pub fn synced(quota: Quota, backend: B, interval: Duration) -> Result<Self, std::io::Error> {
+        RateLimiter::synced_with_clock(
+            quota,
+            backend,
+            &clock::SystemClock,
+            std::time::UNIX_EPOCH,
+            Some(interval),
+        )
+    }
+}
+
+impl<K, B, C> RateLimiter<K, SyncedStateStore<K, B, C>, C>
+where
+    K: Hash + Eq + Clone,
+    B: CasBackend<Key = K>,
+    C: clock::Clock,
+{
+    /// Syncs the local rate limiting states with the shared states in the backend right away,
+    /// blocking until all round trips to the backend are done.
+    pub fn sync(&self) {
+        self.state.shared.sync();
+    }
+}
@@ -0,0 +1,93 @@
+use std::prelude::v1::*;
+
+use crate::nanos::Nanos;
+use crate::state::{NotKeyed, StateStore};
+use crate::{clock, Quota, RateLimiter};
+use std::cell::Cell;
+use std::fmt;
+use std::num::NonZeroU64;
+use std::time::Duration;
+
+/// A single-threaded, in-memory representation of a GCRA's rate-limiting state.
+///
+/// Unlike [`InMemoryState`][crate::state::InMemoryState], this state neither uses atomic
+/// operations nor is [`Sync`]: Rate limiters using it can only be used from one thread at a
+/// time. In exchange, updating the state is a plain memory write, which makes it a good fit for
+/// single-threaded executors (like tokio's `LocalSet`) and WebAssembly.
+///
+/// Since rate limiters using this state are not `Sync`, the futures returned by their
+/// asynchronous methods (like [`until_ready`][crate::RateLimiter::until_ready]) are not `Send`.
+#[derive(Default)]
+pub struct LocalState(Cell<u64>);
+
+impl LocalState {
+    /// Updates the rate limiting state using the given closure, with the same contract as
+    /// [`StateStore::measure_and_replace`].
+    ///
+    /// See [`InMemoryState::measure_and_replace_one`][crate::state::InMemoryState::measure_and_replace_one].
+    pub fn measure_and_replace_one<T, F, E>(&self, f: F) -> Result<T, E>
+    where
+        F: Fn(Option<Nanos>) -> Result<(T, Nanos), E>,
+    {
+        // No other thread can observe the state, so there is no need to re-check it:
+        let (result, new_data) = f(self.tat())?;
+        self.0.set(new_data.into());
+        Ok(result)
+    }
+
+    /// Returns the theoretical arrival time, if a measurement was made yet.
+    pub fn tat(&self) -> Option<Nanos> {
+        NonZeroU64::new(self.0.get()).map(|n| n.get().into())
+    }
+
+    /// Returns `true` if the state is indistinguishable from a fresh state at `nanos`.
+    pub fn is_older_than(&self, nanos: Nanos) -> bool {
+        self.0.get() <= nanos.into()
+    }
+}
+
+impl StateStore for LocalState {
+    type Key = NotKeyed;
+
+    fn measure_and_replace<T, F, E>(&self, _key: &Self::Key, f: F) -> Result<T, E>
+    where
+        F: Fn(Option<Nanos>) -> Result<(T, Nanos), E>,
+    {
+        self.measure_and_replace_one(f)
+    }
+
+    fn peek(&self, _key: &Self::Key) -> Option<Nanos> {
+        self.tat()
+    }
+}
+
+impl fmt::Debug for LocalState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        let d = Duration::from_nanos(self.0.get());
+        write!(f, "LocalState({:?})", d)
+    }
+}
+
+/// # Direct single-threaded rate limiters - Constructors
+///
+/// These rate limiters keep their state in a [`LocalState`], and so can not be shared between
+/// threads.
+#[cfg(feature = "std")]
+impl RateLimiter<NotKeyed, LocalState, clock::DefaultClock> {
+    /// Constructs a new single-threaded direct rate limiter for a quota with the default
+    /// real-time clock.
+    pub fn direct_local(quota: Quota) -> RateLimiter<NotKeyed, LocalState, clock::DefaultClock> {
+        let clock = clock::DefaultClock::default();
+        Self::direct_local_with_clock(quota, &clock)
+    }
+}
+
+impl<C> RateLimiter<NotKeyed, LocalState, C>
+where
+    C: clock::Clock,
+{
+    /// Constructs a new single-threaded direct rate limiter for a quota with a custom clock.
+    pub fn direct_local_with_clock(quota: Quota, clock: &C) -> Self {
+        RateLimiter::new(quota, LocalState::default(), clock)
+    }
+}
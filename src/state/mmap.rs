@@ -0,0 +1,372 @@
+use std::prelude::v1::*;
+
+use crate::clock::Reference;
+use crate::gcra;
+use crate::nanos::Nanos;
+use crate::state::{NotKeyed, StateStore};
+use crate::{clock, Quota, RateLimiter};
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::marker::PhantomData;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::ptr;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Identifies files holding rate limiting states ("govrnmap").
+const MAGIC: u64 = u64::from_le_bytes(*b"govrnmap");
+
+/// The version of the file layout; bump this when changing it.
+const VERSION: u64 = 1;
+
+/// The number of 64-bit words in the file header. The header consists of the magic number, the
+/// layout version and the number of slots (in the upper and lower 32 bits), the file's epoch in
+/// nanoseconds since the clock's epoch (usually the UNIX epoch), and reserved words.
+const HEADER_WORDS: usize = 8;
+
+/// A state store that keeps its rate limiting states in a memory-mapped file, so that they
+/// survive restarts of the process.
+///
+/// The file holds a small versioned header, followed by one 64-bit slot for each state. Rate
+/// limiting decisions update the slots with atomic compare-and-swap operations right in the
+/// mapped memory, exactly like [`InMemoryState`][crate::state::InMemoryState] does, so there
+/// is no serialization involved; it is up to the operating system to write the changes back to
+/// disk (see [`flush`](#method.flush) to force that).
+///
+/// The states are stored relative to the epoch that the file was created at, and rebased onto
+/// the rate limiter's clock when the file is opened: A rate limiter that is restarted remembers
+/// the cells that were let through before, and replenishes them for the time that it was
+/// stopped. This makes the state store a good fit for enforcing long-running quotas on a single
+/// node, e.g. a daily cap on the number of messages sent.
+///
+/// Direct rate limiters use one slot (see
+/// [`RateLimiter::direct_mmap`](../struct.RateLimiter.html#method.direct_mmap)); keyed ones
+/// have a fixed number of slots, and use keys of type `usize` that index into them (see
+/// [`RateLimiter::keyed_mmap`](../struct.RateLimiter.html#method.keyed_mmap)).
+///
+/// # Caveats
+/// Several processes may open the same file at once and share its states. However, the file
+/// must not be truncated or modified by other means while it is mapped (which would crash the
+/// processes that map it), and it must be on a local file system. Opening a file with a
+/// different layout version or number of slots fails.
+pub struct MmapStateStore<K = NotKeyed> {
+    map: Mapping,
+    slots: usize,
+    // The distance between the rate limiter's start and the file's epoch, in nanoseconds. This
+    // is added to the theoretical arrival times to get the values stored in the file.
+    offset: i128,
+    key: PhantomData<fn(&K)>,
+}
+
+impl<K> MmapStateStore<K> {
+    /// Opens (or creates) the file at `path` with `slots` slots, `now` being the current time
+    /// relative to the clock's epoch.
+    fn open(path: &Path, slots: usize, now: Nanos) -> io::Result<Self> {
+        let slots_word = u32::try_from(slots)
+            .ok()
+            .filter(|&slots| slots > 0)
+            .ok_or_else(|| invalid_data("state files hold between 1 and 2^32-1 slots"))?;
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        let len = (HEADER_WORDS + slots) * 8;
+        let actual_len = file.metadata()?.len();
+        if actual_len == 0 {
+            file.set_len(len as u64)?;
+        } else if actual_len != len as u64 {
+            return Err(invalid_data("state file has an unexpected size"));
+        }
+
+        let store = MmapStateStore {
+            map: Mapping::new(&file, len)?,
+            slots,
+            offset: 0,
+            key: PhantomData,
+        };
+
+        // Fill in the header of a new file, and validate the header of an existing one. The
+        // magic number is set last, so that processes opening a new file at the same time all
+        // see the same header (or an error).
+        let magic = store.word(0).load(Ordering::Acquire);
+        if magic != 0 && magic != MAGIC {
+            return Err(invalid_data("file does not hold rate limiting states"));
+        }
+        let layout = VERSION << 32 | u64::from(slots_word);
+        match store
+            .word(1)
+            .compare_exchange(0, layout, Ordering::AcqRel, Ordering::Acquire)
+        {
+            Ok(_) => {}
+            Err(existing) if existing == layout => {}
+            Err(existing) if existing >> 32 != VERSION => {
+                return Err(invalid_data("state file has an unsupported layout version"));
+            }
+            Err(_) => {
+                return Err(invalid_data("state file has a different number of slots"));
+            }
+        }
+        let epoch = u64::from(now).max(1);
+        let _ = store
+            .word(2)
+            .compare_exchange(0, epoch, Ordering::AcqRel, Ordering::Acquire);
+        store.word(0).store(MAGIC, Ordering::Release);
+        Ok(store)
+    }
+
+    /// Rebases the stored states onto a rate limiter that started `start` after the clock's
+    /// epoch.
+    fn rebase(&mut self, start: Nanos) {
+        let epoch = self.word(2).load(Ordering::Acquire);
+        self.offset = i128::from(u64::from(start)) - i128::from(epoch);
+    }
+
+    fn word(&self, index: usize) -> &AtomicU64 {
+        debug_assert!(index < HEADER_WORDS + self.slots);
+        // Safety: The mapping is page-aligned and large enough to hold the header and all
+        // slots, and lives as long as `self`.
+        unsafe { &*(self.map.ptr as *const AtomicU64).add(index) }
+    }
+
+    /// Returns the slot at `index`, or `None` if it is out of range.
+    fn slot(&self, index: usize) -> Option<&AtomicU64> {
+        if index < self.slots {
+            Some(self.word(HEADER_WORDS + index))
+        } else {
+            None
+        }
+    }
+
+    fn to_tat(&self, stored: u64) -> Option<Nanos> {
+        if stored == 0 {
+            return None;
+        }
+        let tat = i128::from(stored) - self.offset;
+        Some(Nanos::from(tat.max(0).min(i128::from(u64::MAX)) as u64))
+    }
+
+    fn to_stored(&self, tat: Nanos) -> u64 {
+        let stored = i128::from(u64::from(tat)) + self.offset;
+        stored.max(1).min(i128::from(u64::MAX)) as u64
+    }
+
+    fn measure_and_replace_slot<T, F, E>(&self, index: usize, f: F) -> Result<T, E>
+    where
+        F: Fn(Option<Nanos>) -> Result<(T, Nanos), E>,
+    {
+        let slot = match self.slot(index) {
+            Some(slot) => slot,
+            None => return gcra::reject(f),
+        };
+        let mut prev = slot.load(Ordering::Acquire);
+        loop {
+            let (result, new_data) = f(self.to_tat(prev))?;
+            match slot.compare_exchange_weak(
+                prev,
+                self.to_stored(new_data),
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => return Ok(result),
+                Err(next_prev) => prev = next_prev,
+            }
+        }
+    }
+
+    fn peek_slot(&self, index: usize) -> Option<Nanos> {
+        self.slot(index)
+            .and_then(|slot| self.to_tat(slot.load(Ordering::Acquire)))
+    }
+
+    fn reset_slot(&self, index: usize) {
+        if let Some(slot) = self.slot(index) {
+            slot.store(0, Ordering::Release);
+        }
+    }
+
+    /// Returns the number of slots in the file.
+    pub fn slots(&self) -> usize {
+        self.slots
+    }
+
+    /// Writes the states back to the file on disk, blocking until that is done.
+    pub fn flush(&self) -> io::Result<()> {
+        // Safety: The mapping is valid for the lifetime of `self`.
+        let ret = unsafe { libc::msync(self.map.ptr, self.map.len, libc::MS_SYNC) };
+        if ret == 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+}
+
+impl StateStore for MmapStateStore<NotKeyed> {
+    type Key = NotKeyed;
+
+    fn measure_and_replace<T, F, E>(&self, _key: &Self::Key, f: F) -> Result<T, E>
+    where
+        F: Fn(Option<Nanos>) -> Result<(T, Nanos), E>,
+    {
+        self.measure_and_replace_slot(0, f)
+    }
+
+    fn peek(&self, _key: &Self::Key) -> Option<Nanos> {
+        self.peek_slot(0)
+    }
+
+    fn reset(&self, _key: &Self::Key) {
+        self.reset_slot(0);
+    }
+}
+
+/// Keys index into the slots of the file.
+///
+/// Keys that are out of range for the number of slots have no state: Cells for them are
+/// rejected, as if the quota were [`Quota::none()`].
+impl StateStore for MmapStateStore<usize> {
+    type Key = usize;
+
+    fn measure_and_replace<T, F, E>(&self, key: &Self::Key, f: F) -> Result<T, E>
+    where
+        F: Fn(Option<Nanos>) -> Result<(T, Nanos), E>,
+    {
+        self.measure_and_replace_slot(*key, f)
+    }
+
+    fn peek(&self, key: &Self::Key) -> Option<Nanos> {
+        self.peek_slot(*key)
+    }
+
+    fn reset(&self, key: &Self::Key) {
+        self.reset_slot(*key);
+    }
+}
+
+impl<K> fmt::Debug for MmapStateStore<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        f.debug_struct("MmapStateStore")
+            .field("slots", &self.slots)
+            .finish()
+    }
+}
+
+/// A shared, writable mapping of a file into memory.
+struct Mapping {
+    ptr: *mut libc::c_void,
+    len: usize,
+}
+
+// Safety: The mapped memory is only accessed through atomic operations.
+unsafe impl Send for Mapping {}
+unsafe impl Sync for Mapping {}
+
+impl Mapping {
+    fn new(file: &File, len: usize) -> io::Result<Self> {
+        // Safety: We map a fresh region of memory (the mapping outlives the file descriptor,
+        // which is fine), and check for errors.
+        let ptr = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Mapping { ptr, len })
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        // Safety: The region was mapped in `Mapping::new`, and nothing refers to it anymore.
+        unsafe {
+            libc::munmap(self.ptr, self.len);
+        }
+    }
+}
+
+fn invalid_data(message: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// # Persistent rate limiters - Constructors
+///
+/// These rate limiters keep their states in a memory-mapped file (see [`MmapStateStore`]).
+impl<C> RateLimiter<NotKeyed, MmapStateStore<NotKeyed>, C>
+where
+    C: clock::Clock,
+{
+    /// Constructs a new direct rate limiter with a custom clock, which keeps its state in the
+    /// file at `path` (creating it if it doesn't exist yet).
+    ///
+    /// The state is stored relative to `epoch`, which must be the same each time the file is
+    /// opened. Fails if the file can't be opened or mapped, or if it has a different layout.
+    pub fn direct_mmap_with_clock(
+        quota: Quota,
+        path: impl AsRef<Path>,
+        clock: &C,
+        epoch: C::Instant,
+    ) -> io::Result<Self> {
+        let store = MmapStateStore::open(path.as_ref(), 1, clock.now().duration_since(epoch))?;
+        let mut limiter = RateLimiter::new(quota, store, clock);
+        limiter.state.rebase(limiter.start.duration_since(epoch));
+        Ok(limiter)
+    }
+}
+
+impl RateLimiter<NotKeyed, MmapStateStore<NotKeyed>, clock::SystemClock> {
+    /// Constructs a new direct rate limiter which keeps its state in the file at `path`
+    /// (creating it if it doesn't exist yet), using the system clock and the UNIX epoch.
+    pub fn direct_mmap(quota: Quota, path: impl AsRef<Path>) -> io::Result<Self> {
+        RateLimiter::direct_mmap_with_clock(quota, path, &clock::SystemClock, std::time::UNIX_EPOCH)
+    }
+}
+
+impl<C> RateLimiter<usize, MmapStateStore<usize>, C>
+where
+    C: clock::Clock,
+{
+    /// Constructs a new keyed rate limiter with a custom clock, which keeps the states for the
+    /// keys `0..slots` in the file at `path` (creating it if it doesn't exist yet).
+    ///
+    /// The states are stored relative to `epoch`, which must be the same each time the file is
+    /// opened. Fails if the file can't be opened or mapped, or if it has a different layout or
+    /// number of slots.
+    pub fn keyed_mmap_with_clock(
+        quota: Quota,
+        path: impl AsRef<Path>,
+        slots: usize,
+        clock: &C,
+        epoch: C::Instant,
+    ) -> io::Result<Self> {
+        let store = MmapStateStore::open(path.as_ref(), slots, clock.now().duration_since(epoch))?;
+        let mut limiter = RateLimiter::new(quota, store, clock);
+        limiter.state.rebase(limiter.start.duration_since(epoch));
+        Ok(limiter)
+    }
+}
+
+impl RateLimiter<usize, MmapStateStore<usize>, clock::SystemClock> {
+    /// Constructs a new keyed rate limiter which keeps the states for the keys `0..slots` in
+    /// the file at `path` (creating it if it doesn't exist yet), using the system clock and the
+    /// UNIX epoch.
+    pub fn keyed_mmap(quota: Quota, path: impl AsRef<Path>, slots: usize) -> io::Result<Self> {
+        RateLimiter::keyed_mmap_with_clock(
+            quota,
+            path,
+            slots,
+            &clock::SystemClock,
+            std::time::UNIX_EPOCH,
+        )
+    }
+}
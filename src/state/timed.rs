@@ -0,0 +1,175 @@
+use std::prelude::v1::*;
+
+use crate::clock::{self, Reference};
+use crate::nanos::Nanos;
+use crate::state::{keyed::KeyedStateStore, DirectStateStore, NotKeyed, StateStore};
+use crate::{CheckAtError, NegativeMultiDecision, NotUntil, RateLimiter};
+use std::hash::Hash;
+use std::num::NonZeroU32;
+use std::sync::atomic::Ordering;
+
+/// The outcome of a single-cell decision made at a given time.
+type CheckAtResult<T, P> = Result<T, CheckAtError<NotUntil<P>, P>>;
+
+/// The outcome of a batch decision made at a given time.
+type CheckNAtResult<P> = Result<(), CheckAtError<NegativeMultiDecision<NotUntil<P>>, P>>;
+
+impl<K, S, C> RateLimiter<K, S, C>
+where
+    S: StateStore<Key = K>,
+    C: clock::Clock,
+{
+    /// Records `t` as the time of a decision made at a given time, unless it lies before the
+    /// latest such time (which is returned in that case) or before the rate limiter's start.
+    fn record_time(&self, t: C::Instant) -> Result<(), C::Instant> {
+        let latest = |ns: u64| self.start + Nanos::from(ns);
+        if t < self.start {
+            return Err(latest(self.latest_at.load(Ordering::Acquire)));
+        }
+        let ns = t.duration_since(self.start).as_u64();
+        let prev = self.latest_at.fetch_max(ns, Ordering::AcqRel);
+        if ns < prev {
+            Err(latest(prev))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn key_at(&self, key: &K, t: C::Instant) -> CheckAtResult<(), C::Instant> {
+        self.record_time(t).map_err(CheckAtError::OutOfOrder)?;
+        self.test_key_at(key, t)
+            .map_err(CheckAtError::NonConforming)
+    }
+
+    fn key_n_at(&self, key: &K, n: NonZeroU32, t: C::Instant) -> CheckNAtResult<C::Instant> {
+        self.record_time(t).map_err(CheckAtError::OutOfOrder)?;
+        self.test_key_n_at(key, n, t)
+            .map_err(CheckAtError::NonConforming)
+    }
+
+    fn key_ready_at(&self, key: &K, t: C::Instant) -> CheckAtResult<C::Instant, C::Instant> {
+        self.record_time(t).map_err(CheckAtError::OutOfOrder)?;
+        let mut t = t;
+        loop {
+            match self.test_key_at(key, t) {
+                Ok(()) => return Ok(t),
+                Err(negative) if self.gcra.quota().is_none() => {
+                    return Err(CheckAtError::NonConforming(negative))
+                }
+                Err(negative) => t = negative.earliest_possible(),
+            }
+        }
+    }
+}
+
+/// # Direct rate limiters - Decisions at given times
+///
+/// These methods make rate limiting decisions at a time given by the caller, instead of the
+/// time that the rate limiter's clock reads. This is useful for replaying logs of events, and
+/// for deterministic simulations.
+///
+/// The given times must not go backwards: Each of them must lie at or after the rate limiter's
+/// construction, and at or after the time given to any earlier call of these methods
+/// (including calls for other keys, on keyed rate limiters). Otherwise, no decision is made,
+/// and the methods return [`CheckAtError::OutOfOrder`] with the latest time given so far.
+/// Decisions made at the clock's current time (e.g. with [`check`](#method.check)) aren't
+/// taken into account.
+impl<S, C> RateLimiter<NotKeyed, S, C>
+where
+    S: DirectStateStore,
+    C: clock::Clock,
+{
+    /// Allow a single cell through the rate limiter, as if at time `t`.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use governor::{clock::{Clock, FakeRelativeClock}, CheckAtError, Quota, RateLimiter};
+    /// # use nonzero_ext::nonzero;
+    /// # use std::time::Duration;
+    /// let clock = FakeRelativeClock::default();
+    /// let lim = RateLimiter::direct_with_clock(Quota::per_second(nonzero!(1u32)), &clock);
+    /// let start = clock.now();
+    /// assert_eq!(Ok(()), lim.check_at(start + Duration::from_secs(10)));
+    /// assert!(lim.check_at(start + Duration::from_millis(10_500)).is_err());
+    /// assert_eq!(Ok(()), lim.check_at(start + Duration::from_secs(11)));
+    ///
+    /// // Times must not go backwards:
+    /// assert_eq!(
+    ///     lim.check_at(start + Duration::from_secs(5)),
+    ///     Err(CheckAtError::OutOfOrder(start + Duration::from_secs(11)))
+    /// );
+    /// ```
+    pub fn check_at(&self, t: C::Instant) -> CheckAtResult<(), C::Instant> {
+        self.key_at(&NotKeyed::NonKey, t)
+    }
+
+    /// Allow *only all* `n` cells through the rate limiter, as if at time `t`. See
+    /// [`check_n`](#method.check_n).
+    pub fn check_n_at(&self, n: NonZeroU32, t: C::Instant) -> CheckNAtResult<C::Instant> {
+        self.key_n_at(&NotKeyed::NonKey, n, t)
+    }
+
+    /// Allows a single cell through the rate limiter at the earliest time at or after `t` that
+    /// the rate limiter allows, and returns that time.
+    ///
+    /// Unlike [`until_ready`](#method.until_ready), this doesn't wait: It computes the time at
+    /// which a caller who started waiting at `t` would have been let through. If the rate
+    /// limiter lets [no cells through][crate::Quota::none], it returns the negative outcome.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use governor::{clock::{Clock, FakeRelativeClock}, Quota, RateLimiter};
+    /// # use nonzero_ext::nonzero;
+    /// # use std::time::Duration;
+    /// let clock = FakeRelativeClock::default();
+    /// let lim = RateLimiter::direct_with_clock(Quota::per_second(nonzero!(2u32)), &clock);
+    /// let start = clock.now();
+    /// for _ in 0..2 {
+    ///     assert_eq!(Ok(start), lim.until_ready_at(start));
+    /// }
+    /// assert_eq!(Ok(start + Duration::from_millis(500)), lim.until_ready_at(start));
+    /// ```
+    pub fn until_ready_at(&self, t: C::Instant) -> CheckAtResult<C::Instant, C::Instant> {
+        self.key_ready_at(&NotKeyed::NonKey, t)
+    }
+}
+
+/// # Keyed rate limiters - Decisions at given times
+///
+/// These methods make rate limiting decisions at a time given by the caller, instead of the
+/// time that the rate limiter's clock reads. See the
+/// [direct rate limiter's methods](#direct-rate-limiters---decisions-at-given-times) for
+/// details.
+impl<K, S, C> RateLimiter<K, S, C>
+where
+    S: KeyedStateStore<K>,
+    K: Hash,
+    C: clock::Clock,
+{
+    /// Allow a single cell through the rate limiter for the given key, as if at time `t`.
+    pub fn check_key_at(&self, key: &K, t: C::Instant) -> CheckAtResult<(), C::Instant> {
+        self.key_at(key, t)
+    }
+
+    /// Allow *only all* `n` cells through the rate limiter for the given key, as if at time
+    /// `t`. See [`check_key_n`](#method.check_key_n).
+    pub fn check_key_n_at(
+        &self,
+        key: &K,
+        n: NonZeroU32,
+        t: C::Instant,
+    ) -> CheckNAtResult<C::Instant> {
+        self.key_n_at(key, n, t)
+    }
+
+    /// Allows a single cell through the rate limiter for the given key at the earliest time at
+    /// or after `t` that the rate limiter allows, and returns that time. See
+    /// [`until_ready_at`](#method.until_ready_at).
+    pub fn until_key_ready_at(
+        &self,
+        key: &K,
+        t: C::Instant,
+    ) -> CheckAtResult<C::Instant, C::Instant> {
+        self.key_ready_at(key, t)
+    }
+}
@@ -0,0 +1,300 @@
+use std::prelude::v1::*;
+
+use crate::clock;
+use crate::nanos::Nanos;
+use crate::state::{Decision, StateStore};
+use crate::{Quota, RateLimiter};
+use parking_lot::Mutex;
+use std::fmt;
+use std::num::NonZeroUsize;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Counts the cells that a rate limiter let through (and rejected) in fixed-width time
+/// buckets, keeping the most recent ones, for capacity planning.
+///
+/// Register the tracker with a rate limiter using
+/// [`track_usage`](struct.RateLimiter.html#method.track_usage), and take a [`UsageReport`]
+/// whenever needed. A tracker keeps `buckets` buckets of `bucket_width` each, e.g. one bucket
+/// per minute for the last hour; the counts in older buckets are dropped. Clones of a tracker
+/// share their counts.
+///
+/// The counts include every decision the rate limiter makes, so methods that wait for the rate
+/// limiter (like [`until_ready`](struct.RateLimiter.html#method.until_ready)) may count as
+/// rejected more than once before they count as let through. Keyed rate limiters count the
+/// cells of all keys together.
+///
+/// # Example
+/// ```rust
+/// # use governor::{clock::FakeRelativeClock, state::UsageTracker, Quota, RateLimiter};
+/// # use nonzero_ext::nonzero;
+/// # use std::time::Duration;
+/// let clock = FakeRelativeClock::default();
+/// // One bucket per minute, for the last hour:
+/// let usage = UsageTracker::new(Duration::from_secs(60), nonzero!(60usize));
+/// let lim = RateLimiter::direct_with_clock(Quota::per_minute(nonzero!(10u32)), &clock)
+///     .track_usage(&usage);
+/// for _ in 0..5 {
+///     lim.check().unwrap();
+/// }
+/// clock.advance(Duration::from_secs(60));
+/// for _ in 0..8 {
+///     lim.check().unwrap();
+/// }
+///
+/// let report = usage.report();
+/// assert_eq!(report.buckets().len(), 2);
+/// assert_eq!(report.allowed_cells(), 13);
+/// // The busiest minute used 80% of the quota's rate:
+/// assert_eq!(report.peak_utilization(), 0.8);
+/// ```
+#[derive(Clone)]
+pub struct UsageTracker {
+    inner: Arc<Mutex<Usage>>,
+}
+
+struct Usage {
+    bucket_width: Nanos,
+    slots: Vec<Option<Slot>>,
+    latest: Option<u64>,
+    quota: Option<Quota>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Slot {
+    index: u64,
+    allowed: u64,
+    denied: u64,
+}
+
+impl UsageTracker {
+    /// Constructs a tracker that keeps the counts of the latest `buckets` buckets, each
+    /// `bucket_width` long.
+    ///
+    /// # Panics
+    /// Panics if `bucket_width` is zero.
+    pub fn new(bucket_width: Duration, buckets: NonZeroUsize) -> Self {
+        assert!(
+            bucket_width > Duration::new(0, 0),
+            "usage buckets must not be empty"
+        );
+        UsageTracker {
+            inner: Arc::new(Mutex::new(Usage {
+                bucket_width: bucket_width.into(),
+                slots: vec![None; buckets.get()],
+                latest: None,
+                quota: None,
+            })),
+        }
+    }
+
+    /// Counts the cells of a decision that was made `t0` after the rate limiter's construction.
+    fn record(&self, t0: Nanos, cells: u64, allowed: bool, quota: Quota) {
+        let mut usage = self.inner.lock();
+        let index = t0.as_u64() / usage.bucket_width.as_u64();
+        let buckets = usage.slots.len() as u64;
+        if let Some(latest) = usage.latest {
+            if index + buckets <= latest {
+                // Concurrent decisions may get reported out of order; this one's bucket was
+                // dropped already.
+                return;
+            }
+        }
+        if usage.latest.unwrap_or(0) <= index {
+            usage.latest = Some(index);
+            usage.quota = Some(quota);
+        }
+        let slot = &mut usage.slots[(index % buckets) as usize];
+        let slot = match slot {
+            Some(slot) if slot.index == index => slot,
+            _ => slot.insert(Slot {
+                index,
+                allowed: 0,
+                denied: 0,
+            }),
+        };
+        if allowed {
+            slot.allowed += cells;
+        } else {
+            slot.denied += cells;
+        }
+    }
+
+    /// Returns a report of the counts in the kept buckets.
+    ///
+    /// The report ends with the bucket of the latest decision: Buckets that passed since then
+    /// without any decisions aren't included. Buckets in between without decisions are
+    /// included, with counts of zero. Before the first decision, the report has no buckets.
+    pub fn report(&self) -> UsageReport {
+        let usage = self.inner.lock();
+        let width = usage.bucket_width.as_u64();
+        let buckets = match usage.latest {
+            None => vec![],
+            Some(latest) => {
+                let first = (latest + 1).saturating_sub(usage.slots.len() as u64);
+                (first..=latest)
+                    .map(|index| {
+                        let slot = usage.slots[(index % usage.slots.len() as u64) as usize]
+                            .filter(|slot| slot.index == index);
+                        UsageBucket {
+                            start: Nanos::new(index.saturating_mul(width)).into(),
+                            allowed_cells: slot.map_or(0, |slot| slot.allowed),
+                            denied_cells: slot.map_or(0, |slot| slot.denied),
+                        }
+                    })
+                    .collect()
+            }
+        };
+        UsageReport {
+            bucket_width: usage.bucket_width.into(),
+            buckets,
+            quota: usage.quota,
+        }
+    }
+
+    /// Drops all counts.
+    pub fn clear(&self) {
+        let mut usage = self.inner.lock();
+        usage.slots.iter_mut().for_each(|slot| *slot = None);
+        usage.latest = None;
+        usage.quota = None;
+    }
+}
+
+impl fmt::Debug for UsageTracker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let usage = self.inner.lock();
+        f.debug_struct("UsageTracker")
+            .field("bucket_width", &usage.bucket_width)
+            .field("buckets", &usage.slots.len())
+            .finish()
+    }
+}
+
+/// The counts of one time bucket in a [`UsageReport`].
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct UsageBucket {
+    start: Duration,
+    allowed_cells: u64,
+    denied_cells: u64,
+}
+
+impl UsageBucket {
+    /// Returns the time at which the bucket starts, relative to the rate limiter's construction.
+    pub fn start(&self) -> Duration {
+        self.start
+    }
+
+    /// Returns the number of cells that the rate limiter let through during the bucket.
+    pub fn allowed_cells(&self) -> u64 {
+        self.allowed_cells
+    }
+
+    /// Returns the number of cells that the rate limiter rejected during the bucket.
+    pub fn denied_cells(&self) -> u64 {
+        self.denied_cells
+    }
+}
+
+/// A snapshot of the counts that a [`UsageTracker`] keeps, oldest bucket first.
+#[derive(Debug, PartialEq, Clone)]
+pub struct UsageReport {
+    bucket_width: Duration,
+    buckets: Vec<UsageBucket>,
+    quota: Option<Quota>,
+}
+
+impl UsageReport {
+    /// Returns how long each bucket is.
+    pub fn bucket_width(&self) -> Duration {
+        self.bucket_width
+    }
+
+    /// Returns the buckets, oldest first.
+    pub fn buckets(&self) -> &[UsageBucket] {
+        &self.buckets
+    }
+
+    /// Returns the total number of cells let through, over all buckets.
+    pub fn allowed_cells(&self) -> u64 {
+        self.buckets.iter().map(|b| b.allowed_cells).sum()
+    }
+
+    /// Returns the total number of cells rejected, over all buckets.
+    pub fn denied_cells(&self) -> u64 {
+        self.buckets.iter().map(|b| b.denied_cells).sum()
+    }
+
+    /// Returns the rate limiter's quota as of the latest decision, or `None` if no decision
+    /// was made yet.
+    pub fn quota(&self) -> Option<Quota> {
+        self.quota
+    }
+
+    /// Returns the number of cells that the quota replenishes during one bucket (see
+    /// [`Quota::cells_per`]): the most cells that a direct rate limiter can let through per
+    /// bucket in the long run, not counting its burst capacity.
+    ///
+    /// This is zero before the first decision.
+    pub fn capacity_per_bucket(&self) -> f64 {
+        self.quota
+            .map_or(0.0, |quota| quota.cells_per(self.bucket_width))
+    }
+
+    /// Returns the share of the [capacity](#method.capacity_per_bucket) that the given
+    /// bucket's allowed cells used: Values around `1.0` mean that the rate limiter ran at its
+    /// limit. Bursts can make this exceed `1.0`.
+    pub fn utilization(&self, bucket: &UsageBucket) -> f64 {
+        let capacity = self.capacity_per_bucket();
+        if bucket.allowed_cells == 0 {
+            0.0
+        } else if capacity == 0.0 {
+            f64::INFINITY
+        } else {
+            bucket.allowed_cells as f64 / capacity
+        }
+    }
+
+    /// Returns the highest [utilization](#method.utilization) of any bucket (`0.0` if there
+    /// are no buckets).
+    pub fn peak_utilization(&self) -> f64 {
+        self.buckets
+            .iter()
+            .map(|b| self.utilization(b))
+            .fold(0.0, f64::max)
+    }
+
+    /// Returns the average [utilization](#method.utilization) over all buckets (`0.0` if there
+    /// are no buckets).
+    pub fn mean_utilization(&self) -> f64 {
+        if self.buckets.is_empty() {
+            return 0.0;
+        }
+        let total: f64 = self.buckets.iter().map(|b| self.utilization(b)).sum();
+        total / self.buckets.len() as f64
+    }
+}
+
+/// # Rate limiters - Usage reports
+impl<K, S, C> RateLimiter<K, S, C>
+where
+    S: StateStore<Key = K>,
+    C: clock::Clock,
+{
+    /// Registers a [`UsageTracker`] that counts the cells that the rate limiter lets through
+    /// and rejects.
+    ///
+    /// The tracker is called like a callback registered with
+    /// [`on_decision`](#method.on_decision).
+    pub fn track_usage(self, tracker: &UsageTracker) -> Self {
+        let tracker = tracker.clone();
+        self.on_decision(move |_, decision: &Decision<C::Instant>| {
+            tracker.record(
+                decision.state_snapshot().time_of_measurement(),
+                u64::from(decision.cells().get()),
+                decision.is_allowed(),
+                decision.quota(),
+            );
+        })
+    }
+}
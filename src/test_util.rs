@@ -0,0 +1,543 @@
+//! Tools for testing state stores against the guarantees of the GCRA.
+//!
+//! Custom state stores (e.g. ones backed by a database, or ones that cache states locally) have
+//! to uphold the same guarantees as the state stores in this crate, which is hard to test with
+//! hand-written sequences of decisions. A [`Simulation`] instead replays a long, randomly
+//! generated (but deterministic, given a seed) sequence of rate limiting decisions against a
+//! state store, from several threads taking turns, under a [`FakeRelativeClock`]. It checks
+//! every decision against an oracle, and records every violation of these invariants:
+//!
+//! * The rate limiter never lets more cells through for a key than the quota allows: No more
+//!   than the burst size (plus one more cell after a period of inactivity) at once, and no more
+//!   than one cell per replenishment interval on top of that.
+//! * The theoretical arrival time that the state store holds for a key never decreases, and
+//!   isn't lost once it has been stored.
+//!
+//! Threads that take turns can't catch races between concurrent decisions. A
+//! [`concurrent`](Simulation::concurrent) simulation instead lets all its threads race to make
+//! a decision on every step, and checks the invariants on the total number of cells that were
+//! let through for each key in each step; such simulations aren't deterministic.
+//!
+//! # Example
+//! ```rust
+//! # use governor::{state::InMemoryState, test_util::Simulation, Quota};
+//! # use nonzero_ext::nonzero;
+//! let report = Simulation::direct()
+//!     .threads(4)
+//!     .steps(1000)
+//!     .seed(7)
+//!     .run(Quota::per_second(nonzero!(10u32)), InMemoryState::default());
+//! report.assert_ok();
+//! assert!(report.allowed_cells() > 0);
+//! ```
+
+use std::prelude::v1::*;
+
+use crate::clock::{Clock, FakeRelativeClock};
+use crate::nanos::Nanos;
+use crate::state::{NotKeyed, StateStore};
+use crate::{Quota, RateLimiter};
+use std::fmt;
+use std::num::NonZeroU32;
+use std::sync::{Barrier, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// A deterministic simulation of rate limiting decisions on a state store.
+///
+/// See [the module documentation](index.html).
+#[derive(Debug, Clone)]
+pub struct Simulation<K> {
+    keys: Vec<K>,
+    threads: usize,
+    steps: usize,
+    seed: u64,
+    max_cells: NonZeroU32,
+    max_advance: Option<Duration>,
+    concurrent: bool,
+}
+
+impl Simulation<NotKeyed> {
+    /// Constructs a simulation of decisions on a direct rate limiter.
+    pub fn direct() -> Self {
+        Simulation::keyed(vec![NotKeyed::NonKey])
+    }
+}
+
+impl<K> Simulation<K> {
+    /// Constructs a simulation of decisions on a keyed rate limiter, picking the key of each
+    /// decision from `keys`.
+    ///
+    /// # Panics
+    /// Panics if `keys` is empty.
+    pub fn keyed(keys: Vec<K>) -> Self {
+        assert!(!keys.is_empty(), "a simulation needs at least one key");
+        Simulation {
+            keys,
+            threads: 4,
+            steps: 1000,
+            seed: 0,
+            max_cells: NonZeroU32::new(1).unwrap(),
+            max_advance: None,
+            concurrent: false,
+        }
+    }
+
+    /// Sets the number of threads that make decisions (4 by default).
+    pub fn threads(self, threads: usize) -> Self {
+        Simulation {
+            threads: threads.max(1),
+            ..self
+        }
+    }
+
+    /// Sets the number of steps to simulate (1000 by default). Each step is one decision,
+    /// or one decision per thread in [`concurrent`](#method.concurrent) simulations.
+    pub fn steps(self, steps: usize) -> Self {
+        Simulation { steps, ..self }
+    }
+
+    /// Sets the seed that determines the sequence of decisions (0 by default). Simulations with
+    /// the same parameters and seed make the same decisions, in the same order, at the same
+    /// times.
+    pub fn seed(self, seed: u64) -> Self {
+        Simulation { seed, ..self }
+    }
+
+    /// Sets the maximum number of cells that each decision tests (1 by default); decisions on
+    /// more than one cell use [`check_key_n`](../struct.RateLimiter.html#method.check_key_n).
+    pub fn max_cells(self, max_cells: NonZeroU32) -> Self {
+        Simulation { max_cells, ..self }
+    }
+
+    /// Sets the maximum time that the clock advances between two decisions. By default, this is
+    /// twice the quota's replenishment interval, so that the rate limiter lets about half the
+    /// cells through (or a millisecond, for quotas that let all or no cells through).
+    pub fn max_advance(self, max_advance: Duration) -> Self {
+        Simulation {
+            max_advance: Some(max_advance),
+            ..self
+        }
+    }
+
+    /// Makes the threads race instead of taking turns: On each step, the clock advances, and
+    /// then every thread makes a decision at the same time.
+    ///
+    /// This exercises the state store's handling of concurrent updates, which a simulation
+    /// whose threads take turns can't. As the order of the decisions within a step is up to
+    /// the threads, the invariants are checked on the total number of cells let through for
+    /// each key in each step, and the outcome of the simulation may differ between runs with
+    /// the same seed.
+    pub fn concurrent(self) -> Self {
+        Simulation {
+            concurrent: true,
+            ..self
+        }
+    }
+
+    /// Runs the simulation on a new rate limiter for `quota` using `store`.
+    pub fn run<S>(&self, quota: Quota, store: S) -> SimulationReport
+    where
+        K: Sync,
+        S: StateStore<Key = K> + Sync,
+    {
+        let clock = FakeRelativeClock::default();
+        let limiter = RateLimiter::new(quota, store, &clock);
+        self.run_limiter(&limiter, &clock)
+    }
+
+    /// Runs the simulation on an existing rate limiter, e.g. one constructed with a state store
+    /// that needs a clock.
+    ///
+    /// The simulation advances `clock`, which must be the rate limiter's clock. Decisions start
+    /// at the current time of the clock, and the states for the keys should be fresh.
+    pub fn run_limiter<S>(
+        &self,
+        limiter: &RateLimiter<K, S, FakeRelativeClock>,
+        clock: &FakeRelativeClock,
+    ) -> SimulationReport
+    where
+        K: Sync,
+        S: StateStore<Key = K> + Sync,
+    {
+        let t = limiter.gcra().t();
+        let tau = limiter.gcra().tau();
+        let quota = limiter.gcra().quota();
+        let max_advance = match self.max_advance {
+            Some(max_advance) => max_advance.into(),
+            None if quota.is_none() || quota.is_unlimited() => Duration::from_millis(1).into(),
+            None => t * 2,
+        };
+        let schedule = self.schedule(max_advance);
+
+        let oracle = Mutex::new(Oracle {
+            step: 0,
+            t,
+            tau,
+            keys: vec![KeyOracle::default(); self.keys.len()],
+            report: SimulationReport::default(),
+        });
+        if self.concurrent {
+            return self.run_concurrently(limiter, clock, &schedule, oracle);
+        }
+        let turn = Condvar::new();
+        thread::scope(|scope| {
+            for thread in 0..self.threads {
+                let (schedule, oracle, turn) = (&schedule, &oracle, &turn);
+                scope.spawn(move || loop {
+                    let mut guard = oracle.lock().unwrap();
+                    while guard.step < schedule.len() && schedule[guard.step].thread != thread {
+                        guard = turn.wait(guard).unwrap();
+                    }
+                    let step = guard.step;
+                    let decision = match schedule.get(step) {
+                        Some(decision) => decision,
+                        None => return,
+                    };
+                    clock.advance(decision.advance.into());
+                    let now = clock.now();
+                    let key = &self.keys[decision.key];
+                    let elapsed = limiter.elapsed_at(now);
+                    let allowed = limiter.test_key_n_at(key, decision.cells, now).is_ok();
+                    let tat = limiter.snapshot_state(key).theoretical_arrival_time();
+                    guard.record(step, decision.key, elapsed, decision.cells, allowed);
+                    guard.observe(step, decision.key, elapsed, tat);
+                    guard.step += 1;
+                    turn.notify_all();
+                });
+            }
+        });
+        let mut oracle = oracle.into_inner().unwrap();
+        oracle.report.steps = schedule.len();
+        oracle.report
+    }
+
+    /// Lets the threads race to make the decisions of each step (see
+    /// [`concurrent`](#method.concurrent)).
+    fn run_concurrently<S>(
+        &self,
+        limiter: &RateLimiter<K, S, FakeRelativeClock>,
+        clock: &FakeRelativeClock,
+        schedule: &[ScheduledDecision],
+        oracle: Mutex<Oracle>,
+    ) -> SimulationReport
+    where
+        K: Sync,
+        S: StateStore<Key = K> + Sync,
+    {
+        let steps: Vec<&[ScheduledDecision]> = schedule.chunks(self.threads).collect();
+        if let Some(first) = steps.first() {
+            clock.advance(first[0].advance.into());
+        }
+        // The number of cells let through for each key in the current step:
+        let admitted = Mutex::new(vec![0u64; self.keys.len()]);
+        let barrier = Barrier::new(self.threads);
+        thread::scope(|scope| {
+            for thread in 0..self.threads {
+                let (steps, oracle, admitted, barrier) = (&steps, &oracle, &admitted, &barrier);
+                scope.spawn(move || {
+                    for (step, decisions) in steps.iter().enumerate() {
+                        barrier.wait();
+                        if let Some(decision) = decisions.get(thread) {
+                            let now = clock.now();
+                            let key = &self.keys[decision.key];
+                            let allowed = limiter.test_key_n_at(key, decision.cells, now).is_ok();
+                            if allowed {
+                                admitted.lock().unwrap()[decision.key] +=
+                                    u64::from(decision.cells.get());
+                            } else {
+                                oracle.lock().unwrap().report.denied_decisions += 1;
+                            }
+                        }
+                        if !barrier.wait().is_leader() {
+                            continue;
+                        }
+                        // All decisions of the step were made; check them before the next step:
+                        let elapsed = limiter.elapsed_at(clock.now());
+                        let mut oracle = oracle.lock().unwrap();
+                        let mut admitted = admitted.lock().unwrap();
+                        for (index, cells) in admitted.iter_mut().enumerate() {
+                            if let Some(n) = NonZeroU32::new(*cells as u32) {
+                                oracle.record(step, index, elapsed, n, true);
+                            }
+                            let tat = limiter
+                                .snapshot_state(&self.keys[index])
+                                .theoretical_arrival_time();
+                            oracle.observe(step, index, elapsed, tat);
+                            *cells = 0;
+                        }
+                        if let Some(next) = steps.get(step + 1) {
+                            clock.advance(next[0].advance.into());
+                        }
+                    }
+                });
+            }
+        });
+        let mut oracle = oracle.into_inner().unwrap();
+        oracle.report.steps = steps.len();
+        oracle.report
+    }
+
+    /// Generates the sequence of decisions: One per step, or one per thread and step in
+    /// concurrent simulations (where only the first decision of each step advances the clock).
+    fn schedule(&self, max_advance: Nanos) -> Vec<ScheduledDecision> {
+        let mut rng = SplitMix64(self.seed);
+        let decisions = if self.concurrent {
+            self.steps * self.threads
+        } else {
+            self.steps
+        };
+        (0..decisions)
+            .map(|_| ScheduledDecision {
+                thread: rng.below(self.threads as u64) as usize,
+                key: rng.below(self.keys.len() as u64) as usize,
+                cells: NonZeroU32::new(1 + rng.below(u64::from(self.max_cells.get())) as u32)
+                    .unwrap(),
+                advance: Nanos::from(rng.below(max_advance.as_u64().saturating_add(1))),
+            })
+            .collect()
+    }
+}
+
+/// One decision in the schedule of a simulation.
+#[derive(Debug, Clone, Copy)]
+struct ScheduledDecision {
+    thread: usize,
+    key: usize,
+    cells: NonZeroU32,
+    advance: Nanos,
+}
+
+/// A small, deterministic pseudo-random number generator.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a number in `0..n` (or 0, if `n` is 0).
+    fn below(&mut self, n: u64) -> u64 {
+        if n == 0 {
+            0
+        } else {
+            self.next() % n
+        }
+    }
+}
+
+struct Oracle {
+    step: usize,
+    t: Nanos,
+    tau: Nanos,
+    keys: Vec<KeyOracle>,
+    report: SimulationReport,
+}
+
+/// What the oracle knows about a key.
+#[derive(Default, Clone)]
+struct KeyOracle {
+    // The theoretical arrival time of a GCRA that lets the cells through that the rate limiter
+    // let through, as permissively as the quota allows.
+    reference_tat: Option<Nanos>,
+    // The last theoretical arrival time that the state store held.
+    stored_tat: Option<Nanos>,
+}
+
+impl Oracle {
+    /// Checks the outcome of a decision on `cells` cells for the key at index `key` (or of all
+    /// the decisions on that key in a step of a concurrent simulation, all of which were made at
+    /// the same time).
+    fn record(
+        &mut self,
+        step: usize,
+        key: usize,
+        elapsed: Nanos,
+        cells: NonZeroU32,
+        allowed: bool,
+    ) {
+        if !allowed {
+            self.report.denied_decisions += 1;
+            return;
+        }
+        let t = self.t;
+        let n = u64::from(cells.get());
+        self.report.allowed_cells += n;
+        // Rate limiters for quotas that let all cells through can't over-admit:
+        if t.as_u64() == 0 {
+            return;
+        }
+        let oracle = &mut self.keys[key];
+        let reference_tat = oracle.reference_tat.unwrap_or(elapsed).max(elapsed);
+        let earliest = (reference_tat + t * (n - 1)).saturating_sub(self.tau);
+        oracle.reference_tat = Some(reference_tat + t * n);
+        if elapsed < earliest {
+            self.violation(step, key, elapsed, ViolationKind::OverAdmitted(cells));
+        }
+    }
+
+    /// Checks the state that the state store holds for the key at index `key` after a decision.
+    fn observe(&mut self, step: usize, key: usize, elapsed: Nanos, tat: Option<Nanos>) {
+        let oracle = &mut self.keys[key];
+        let violation = match (oracle.stored_tat, tat) {
+            (Some(_), None) => Some(ViolationKind::StateLost),
+            (Some(before), Some(after)) if after < before => {
+                Some(ViolationKind::TatDecreased { before, after })
+            }
+            _ => None,
+        };
+        oracle.stored_tat = tat.or(oracle.stored_tat);
+        if let Some(kind) = violation {
+            self.violation(step, key, elapsed, kind);
+        }
+    }
+
+    fn violation(&mut self, step: usize, key: usize, time: Nanos, kind: ViolationKind) {
+        self.report.violations.push(Violation {
+            step,
+            key,
+            time,
+            kind,
+        });
+    }
+}
+
+/// The outcome of a [`Simulation`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SimulationReport {
+    steps: usize,
+    allowed_cells: u64,
+    denied_decisions: u64,
+    violations: Vec<Violation>,
+}
+
+impl SimulationReport {
+    /// Returns the number of steps simulated (see [`Simulation::steps`]).
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+
+    /// Returns the number of cells that the rate limiter let through.
+    pub fn allowed_cells(&self) -> u64 {
+        self.allowed_cells
+    }
+
+    /// Returns the number of decisions with a negative outcome.
+    pub fn denied_decisions(&self) -> u64 {
+        self.denied_decisions
+    }
+
+    /// Returns the violations of the GCRA's invariants, in the order they occurred.
+    pub fn violations(&self) -> &[Violation] {
+        &self.violations
+    }
+
+    /// Returns whether the state store upheld all invariants.
+    pub fn is_ok(&self) -> bool {
+        self.violations.is_empty()
+    }
+
+    /// Panics, listing the first few violations, unless the state store upheld all invariants.
+    pub fn assert_ok(&self) {
+        if self.is_ok() {
+            return;
+        }
+        let first: Vec<String> = self
+            .violations
+            .iter()
+            .take(5)
+            .map(|v| v.to_string())
+            .collect();
+        panic!(
+            "{} of {} decisions violated the GCRA's invariants, first: {}",
+            self.violations.len(),
+            self.steps,
+            first.join("; ")
+        );
+    }
+}
+
+/// A violation of the GCRA's invariants, found by a [`Simulation`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Violation {
+    step: usize,
+    key: usize,
+    time: Nanos,
+    kind: ViolationKind,
+}
+
+impl Violation {
+    /// Returns the index of the step, in the simulation, whose decision (or decisions, in
+    /// concurrent simulations) violated the invariant.
+    pub fn step(&self) -> usize {
+        self.step
+    }
+
+    /// Returns the index of the key (in the keys passed to [`Simulation::keyed`]) of the
+    /// decision.
+    pub fn key(&self) -> usize {
+        self.key
+    }
+
+    /// Returns the time of the decision, in nanoseconds since the rate limiter was constructed.
+    pub fn time(&self) -> Nanos {
+        self.time
+    }
+
+    /// Returns the kind of violation.
+    pub fn kind(&self) -> ViolationKind {
+        self.kind
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        write!(
+            f,
+            "step {} (key #{}, at {:?}): {}",
+            self.step, self.key, self.time, self.kind
+        )
+    }
+}
+
+/// The kinds of [`Violation`]s.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ViolationKind {
+    /// The rate limiter let the given number of cells through, which the quota didn't allow.
+    OverAdmitted(NonZeroU32),
+
+    /// The theoretical arrival time that the state store held decreased.
+    TatDecreased {
+        /// The theoretical arrival time before the decision.
+        before: Nanos,
+        /// The theoretical arrival time after the decision.
+        after: Nanos,
+    },
+
+    /// The state store held a state before the decision, and none after it.
+    StateLost,
+}
+
+impl fmt::Display for ViolationKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            ViolationKind::OverAdmitted(n) => {
+                write!(
+                    f,
+                    "{} cells were let through that the quota didn't allow",
+                    n
+                )
+            }
+            ViolationKind::TatDecreased { before, after } => write!(
+                f,
+                "theoretical arrival time decreased from {:?} to {:?}",
+                before, after
+            ),
+            ViolationKind::StateLost => write!(f, "the state was lost"),
+        }
+    }
+}
@@ -0,0 +1,176 @@
+//! The timers that asynchronous waits for rate limiters use.
+//!
+//! By default, this is [`futures_timer::Delay`], which runs its own timer thread. With the `tokio`
+//! feature enabled, delays that are created from within a tokio runtime are instead driven by
+//! tokio's timer. That way, waits also respect
+//! [`tokio::time::pause`](https://docs.rs/tokio/1/tokio/time/fn.pause.html). Outside of a tokio
+//! runtime, delays fall back to `futures_timer`.
+//!
+//! Clocks can also supply their own delays, via
+//! [`ReasonablyRealtime::delay`][crate::clock::ReasonablyRealtime::delay]:
+//! The [`FakeRelativeClock`] resolves delays when it gets advanced past their deadline.
+
+use std::prelude::v1::*;
+
+use crate::clock::{Clock, FakeRelativeClock};
+use crate::nanos::Nanos;
+use futures::task::{Context, Poll, Waker};
+use futures::Future;
+use parking_lot::Mutex;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// A future that resolves after a given duration has passed on a clock.
+///
+/// Delays are created by the [`ReasonablyRealtime::delay`][crate::clock::ReasonablyRealtime::delay]
+/// method of the rate limiter's clock.
+#[derive(Debug)]
+pub struct Delay(Inner);
+
+#[derive(Debug)]
+enum Inner {
+    FuturesTimer(futures_timer::Delay),
+
+    #[cfg(feature = "tokio")]
+    Tokio(Pin<Box<tokio::time::Sleep>>),
+
+    Fake(FakeDelay),
+}
+
+impl Delay {
+    /// Creates a new delay that resolves after `dur` has passed in real time.
+    pub(crate) fn new(dur: Duration) -> Delay {
+        #[cfg(feature = "tokio")]
+        {
+            if tokio::runtime::Handle::try_current().is_ok() {
+                return Delay(Inner::Tokio(Box::pin(tokio::time::sleep(dur))));
+            }
+        }
+        Delay(Inner::FuturesTimer(futures_timer::Delay::new(dur)))
+    }
+
+    /// Creates a new delay that resolves once `clock` was advanced by `dur`.
+    pub(crate) fn fake(clock: &FakeRelativeClock, dur: Duration) -> Delay {
+        Delay(Inner::Fake(FakeDelay {
+            clock: clock.clone(),
+            deadline: clock.now() + Nanos::from(dur),
+            id: clock.timers().next_id(),
+        }))
+    }
+
+    /// Resets the delay to resolve `dur` from now.
+    ///
+    /// Delays are often created ahead of time (e.g. when constructing a stream combinator),
+    /// possibly outside a tokio runtime; a delay that gets reset from within a runtime switches
+    /// over to the tokio timer.
+    pub(crate) fn reset(&mut self, dur: Duration) {
+        match &mut self.0 {
+            #[cfg(feature = "tokio")]
+            Inner::FuturesTimer(_) if tokio::runtime::Handle::try_current().is_ok() => {
+                *self = Delay::new(dur);
+            }
+            Inner::FuturesTimer(delay) => delay.reset(dur),
+
+            #[cfg(feature = "tokio")]
+            Inner::Tokio(sleep) => sleep.as_mut().reset(tokio::time::Instant::now() + dur),
+
+            Inner::Fake(delay) => delay.deadline = delay.clock.now() + Nanos::from(dur),
+        }
+    }
+}
+
+impl Future for Delay {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        match &mut self.0 {
+            Inner::FuturesTimer(delay) => Pin::new(delay).poll(cx),
+
+            #[cfg(feature = "tokio")]
+            Inner::Tokio(sleep) => sleep.as_mut().poll(cx),
+
+            Inner::Fake(delay) => delay.poll(cx),
+        }
+    }
+}
+
+/// A delay on a [`FakeRelativeClock`].
+#[derive(Debug)]
+struct FakeDelay {
+    clock: FakeRelativeClock,
+    deadline: Nanos,
+    id: u64,
+}
+
+impl FakeDelay {
+    fn poll(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        let timers = self.clock.timers();
+        let mut sleepers = timers.sleepers.lock();
+        // Checking the time while holding the lock ensures that advancing the clock will see
+        // the registration (and wake us up) if we don't see the new time:
+        if self.clock.now() >= self.deadline {
+            sleepers.retain(|s| s.id != self.id);
+            return Poll::Ready(());
+        }
+        match sleepers.iter_mut().find(|s| s.id == self.id) {
+            Some(sleeper) => {
+                sleeper.deadline = self.deadline;
+                sleeper.waker.clone_from(cx.waker());
+            }
+            None => sleepers.push(Sleeper {
+                id: self.id,
+                deadline: self.deadline,
+                waker: cx.waker().clone(),
+            }),
+        }
+        Poll::Pending
+    }
+}
+
+impl Drop for FakeDelay {
+    fn drop(&mut self) {
+        self.clock
+            .timers()
+            .sleepers
+            .lock()
+            .retain(|s| s.id != self.id);
+    }
+}
+
+#[derive(Debug)]
+struct Sleeper {
+    id: u64,
+    deadline: Nanos,
+    waker: Waker,
+}
+
+/// The delays waiting on a [`FakeRelativeClock`] (and all its clones).
+#[derive(Debug, Default)]
+pub(crate) struct FakeTimers {
+    ids: AtomicU64,
+    sleepers: Mutex<Vec<Sleeper>>,
+}
+
+impl FakeTimers {
+    fn next_id(&self) -> u64 {
+        self.ids.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Wakes up all delays whose deadline is at or before `now`.
+    pub(crate) fn wake_due(&self, now: Nanos) {
+        let due: Vec<Waker> = {
+            let mut sleepers = self.sleepers.lock();
+            let (due, pending): (Vec<Sleeper>, Vec<Sleeper>) =
+                sleepers.drain(..).partition(|s| s.deadline <= now);
+            *sleepers = pending;
+            due.into_iter().map(|s| s.waker).collect()
+        };
+        due.into_iter().for_each(Waker::wake);
+    }
+
+    /// Returns the earliest deadline of all pending delays.
+    pub(crate) fn next_deadline(&self) -> Option<Nanos> {
+        self.sleepers.lock().iter().map(|s| s.deadline).min()
+    }
+}
@@ -0,0 +1,168 @@
+//! Rate limiting for gRPC services built with [`tonic`].
+//!
+//! The [`RateLimitInterceptor`] makes keyed rate limiting decisions for each request it sees,
+//! keyed by the value of a metadata entry (e.g., an API token). Requests that exceed the rate
+//! limit are rejected with the `RESOURCE_EXHAUSTED` status code; the status carries a
+//! `google.rpc.RetryInfo` error detail that tells the client when it may try again, as well as
+//! the rate limiting headers (see [`headers`][crate::headers]) as lowercase metadata entries,
+//! including a `retry-after` entry.
+//!
+//! The interceptor can be used wherever tonic accepts an
+//! [`Interceptor`][tonic::service::Interceptor] (like the `with_interceptor` constructors of
+//! generated servers and clients), or as a tower layer, via
+//! [`layer`](RateLimitInterceptor::layer).
+//!
+//! # Example
+//! ```rust
+//! # use governor::{tonic::RateLimitInterceptor, Quota, RateLimiter};
+//! # use nonzero_ext::nonzero;
+//! # use tonic::{service::Interceptor, Code, Request};
+//! # use tonic_types::StatusExt;
+//! # use std::time::Duration;
+//! let lim = RateLimiter::keyed(Quota::per_minute(nonzero!(1u32)));
+//! let mut interceptor = RateLimitInterceptor::new(lim, "authorization");
+//!
+//! let mut request = Request::new(());
+//! request.metadata_mut().insert("authorization", "token-a".parse().unwrap());
+//! let request = interceptor.call(request).unwrap();
+//!
+//! let status = interceptor.call(request).unwrap_err();
+//! assert_eq!(status.code(), Code::ResourceExhausted);
+//! assert_eq!(status.metadata().get("retry-after").unwrap(), "60");
+//! let retry_info = status.get_details_retry_info().unwrap();
+//! assert!(retry_info.retry_delay.unwrap() <= Duration::from_secs(60));
+//! ```
+
+use std::prelude::v1::*;
+
+use crate::headers::RateLimitHeaders;
+use crate::state::keyed::KeyedStateStore;
+use crate::{clock, NotUntil, RateLimiterHandle};
+use tonic::metadata::{MetadataKey, MetadataMap, MetadataValue};
+use tonic::service::interceptor::InterceptorLayer;
+use tonic::service::{interceptor, Interceptor};
+use tonic::{Code, Request, Status};
+use tonic_types::{ErrorDetails, StatusExt};
+
+/// A tonic [`Interceptor`] that rate limits requests by the value of a metadata entry.
+///
+/// By default, all requests that lack the metadata entry (or whose entry isn't valid ASCII)
+/// are limited under one shared key, the empty string; use
+/// [`with_fallback_key`](#method.with_fallback_key) to pick a different key, or to reject these
+/// requests instead.
+pub struct RateLimitInterceptor<S, C>
+where
+    S: KeyedStateStore<String>,
+    C: clock::Clock,
+{
+    limiter: RateLimiterHandle<String, S, C>,
+    metadata_key: &'static str,
+    fallback_key: Option<String>,
+}
+
+impl<S, C> RateLimitInterceptor<S, C>
+where
+    S: KeyedStateStore<String>,
+    C: clock::Clock,
+{
+    /// Constructs an interceptor that rate limits requests using `limiter`, keyed by the value
+    /// of the `metadata_key` entry in each request's metadata.
+    pub fn new(
+        limiter: impl Into<RateLimiterHandle<String, S, C>>,
+        metadata_key: &'static str,
+    ) -> Self {
+        RateLimitInterceptor {
+            limiter: limiter.into(),
+            metadata_key,
+            fallback_key: Some(String::new()),
+        }
+    }
+
+    /// Sets the key under which requests that lack the metadata entry (or whose entry isn't
+    /// valid ASCII) are limited.
+    ///
+    /// If `fallback_key` is `None`, these requests are rejected with the `UNAUTHENTICATED`
+    /// status code instead, without consulting the rate limiter.
+    pub fn with_fallback_key(self, fallback_key: Option<String>) -> Self {
+        RateLimitInterceptor {
+            fallback_key,
+            ..self
+        }
+    }
+
+    /// Returns the rate limiter that the interceptor uses.
+    pub fn limiter(&self) -> &RateLimiterHandle<String, S, C> {
+        &self.limiter
+    }
+
+    /// Returns a tower layer that applies this interceptor to the services it wraps.
+    pub fn layer(self) -> InterceptorLayer<Self> {
+        interceptor(self)
+    }
+}
+
+impl<S, C> Clone for RateLimitInterceptor<S, C>
+where
+    S: KeyedStateStore<String>,
+    C: clock::Clock,
+{
+    fn clone(&self) -> Self {
+        RateLimitInterceptor {
+            limiter: self.limiter.clone(),
+            metadata_key: self.metadata_key,
+            fallback_key: self.fallback_key.clone(),
+        }
+    }
+}
+
+impl<S, C> Interceptor for RateLimitInterceptor<S, C>
+where
+    S: KeyedStateStore<String>,
+    C: clock::Clock,
+{
+    fn call(&mut self, request: Request<()>) -> Result<Request<()>, Status> {
+        let key = match request
+            .metadata()
+            .get(self.metadata_key)
+            .and_then(|value| value.to_str().ok())
+        {
+            Some(key) => key.to_string(),
+            None => match &self.fallback_key {
+                Some(key) => key.clone(),
+                None => {
+                    return Err(Status::unauthenticated(format!(
+                        "missing `{}` metadata entry",
+                        self.metadata_key
+                    )))
+                }
+            },
+        };
+        match self.limiter.check_key(&key) {
+            Ok(()) => Ok(request),
+            Err(negative) => Err(resource_exhausted(&negative)),
+        }
+    }
+}
+
+/// Returns a `RESOURCE_EXHAUSTED` status for a negative rate limiting decision, carrying a
+/// `google.rpc.RetryInfo` error detail and the rate limiting headers as metadata entries.
+pub fn resource_exhausted<P: clock::Reference>(not_until: &NotUntil<P>) -> Status {
+    let headers = RateLimitHeaders::from_not_until(not_until);
+    let mut metadata = MetadataMap::new();
+    for (name, value) in headers.header_values() {
+        let name = MetadataKey::from_bytes(name.to_ascii_lowercase().as_bytes());
+        let value = value.parse::<MetadataValue<_>>();
+        if let (Ok(name), Ok(value)) = (name, value) {
+            metadata.insert(name, value);
+        }
+    }
+    Status::with_error_details_and_metadata(
+        Code::ResourceExhausted,
+        format!(
+            "rate limit exceeded, retry after {} seconds",
+            headers.retry_after_value().unwrap_or_default()
+        ),
+        ErrorDetails::with_retry_info(headers.retry_after()),
+        metadata,
+    )
+}
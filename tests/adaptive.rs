@@ -0,0 +1,91 @@
+use governor::{
+    clock::{Clock, FakeRelativeClock},
+    AdaptiveRateLimiter, Aimd, Quota,
+};
+use nonzero_ext::nonzero;
+use std::time::Duration;
+
+#[test]
+fn starts_at_maximum() {
+    let clock = FakeRelativeClock::default();
+    let policy = Aimd::new(Quota::per_second(nonzero!(5u32)));
+    let lim = AdaptiveRateLimiter::direct_with_clock(policy, &clock);
+    assert_eq!(lim.current_quota(), Quota::per_second(nonzero!(5u32)));
+    for _ in 0..5 {
+        assert_eq!(Ok(()), lim.check());
+    }
+    assert_ne!(Ok(()), lim.check());
+}
+
+#[test]
+fn throttling_decreases_multiplicatively() {
+    let clock = FakeRelativeClock::default();
+    let policy = Aimd::new(Quota::per_second(nonzero!(8u32))).multiplicative_decrease(0.5);
+    let lim = AdaptiveRateLimiter::direct_with_clock(policy, &clock);
+
+    lim.report_throttled();
+    assert_eq!(lim.current_quota(), Quota::per_second(nonzero!(4u32)));
+    lim.report_throttled();
+    assert_eq!(lim.current_quota(), Quota::per_second(nonzero!(2u32)));
+
+    // The burst capacity shrinks along with the rate:
+    assert_eq!(Ok(()), lim.check());
+    assert_eq!(Ok(()), lim.check());
+    assert_ne!(Ok(()), lim.check(), "Now: {:?}", clock.now());
+
+    // ...and so does the replenishment rate:
+    clock.advance(Duration::from_millis(250));
+    assert_ne!(Ok(()), lim.check(), "Now: {:?}", clock.now());
+    clock.advance(Duration::from_millis(250));
+    assert_eq!(Ok(()), lim.check(), "Now: {:?}", clock.now());
+}
+
+#[test]
+fn throttling_stops_at_minimum() {
+    let clock = FakeRelativeClock::default();
+    let policy = Aimd::new(Quota::per_second(nonzero!(100u32)))
+        .with_minimum(Quota::per_second(nonzero!(10u32)))
+        .multiplicative_decrease(0.1);
+    let lim = AdaptiveRateLimiter::direct_with_clock(policy, &clock);
+
+    for _ in 0..10 {
+        lim.report_throttled();
+    }
+    assert_eq!(lim.current_quota(), Quota::per_second(nonzero!(10u32)));
+}
+
+#[test]
+fn success_increases_additively() {
+    let clock = FakeRelativeClock::default();
+    let policy = Aimd::new(Quota::per_second(nonzero!(10u32)))
+        .multiplicative_decrease(0.5)
+        .additive_increase(1.0);
+    let lim = AdaptiveRateLimiter::direct_with_clock(policy, &clock);
+
+    lim.report_throttled();
+    assert_eq!(lim.current_quota(), Quota::per_second(nonzero!(5u32)));
+    lim.report_success();
+    assert_eq!(lim.current_quota().burst_size(), nonzero!(6u32));
+    lim.report_success();
+    lim.report_success();
+    assert_eq!(lim.current_quota().burst_size(), nonzero!(8u32));
+
+    // Never exceed the maximum:
+    for _ in 0..10 {
+        lim.report_success();
+    }
+    assert_eq!(lim.current_quota(), Quota::per_second(nonzero!(10u32)));
+}
+
+#[test]
+fn keyed_keys_share_quota() {
+    let policy = Aimd::new(Quota::per_second(nonzero!(4u32)));
+    let lim = AdaptiveRateLimiter::keyed(policy);
+    lim.report_throttled();
+
+    for key in &[1u32, 2u32] {
+        assert_eq!(Ok(()), lim.check_key(key));
+        assert_eq!(Ok(()), lim.check_key(key));
+        assert_ne!(Ok(()), lim.check_key(key));
+    }
+}
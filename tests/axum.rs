@@ -0,0 +1,262 @@
+#![cfg(feature = "axum")]
+
+use axum::body::Body;
+use axum::extract::ConnectInfo;
+use axum::http::{Request, StatusCode};
+use axum::response::Response;
+use axum::{routing::get, Router};
+use futures::future::{self, Ready};
+use governor::axum::{AuthenticatedUser, HeaderKey, KeyExtractor, PeerIp, RateLimitLayer};
+use governor::{clock::FakeRelativeClock, Quota, RateLimiter};
+use nonzero_ext::nonzero;
+use std::convert::Infallible;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tower::{Layer, Service, ServiceExt};
+
+fn app<X>(extractor: X, clock: &FakeRelativeClock) -> Router
+where
+    X: KeyExtractor + Clone + Send + Sync + 'static,
+    X::Key: Send + Sync + 'static,
+{
+    let lim = RateLimiter::dashmap_with_clock(Quota::per_second(nonzero!(1u32)), clock);
+    Router::new()
+        .route("/", get(|| async { "Hello!" }))
+        .layer(RateLimitLayer::new(lim, extractor))
+}
+
+fn from_peer(peer: &str) -> Request<Body> {
+    let mut request = Request::new(Body::empty());
+    request
+        .extensions_mut()
+        .insert(ConnectInfo(peer.parse::<SocketAddr>().unwrap()));
+    request
+}
+
+fn forwarded(peer: &str, forwarded_for: &str) -> Request<Body> {
+    let mut request = from_peer(peer);
+    request
+        .headers_mut()
+        .insert("x-forwarded-for", forwarded_for.parse().unwrap());
+    request
+}
+
+async fn status(app: &Router, request: Request<Body>) -> StatusCode {
+    app.clone().oneshot(request).await.unwrap().status()
+}
+
+fn ip(ip: &str) -> IpAddr {
+    ip.parse().unwrap()
+}
+
+#[tokio::test]
+async fn rejects_with_429_and_headers() {
+    let clock = FakeRelativeClock::default();
+    let app = app(PeerIp::new(), &clock);
+
+    assert_eq!(
+        status(&app, from_peer("192.0.2.1:1000")).await,
+        StatusCode::OK
+    );
+    assert_eq!(
+        status(&app, from_peer("192.0.2.2:1000")).await,
+        StatusCode::OK
+    );
+    let response: Response = app
+        .clone()
+        .oneshot(from_peer("192.0.2.1:2000"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(response.headers()["retry-after"], "1");
+    assert_eq!(response.headers()["x-ratelimit-limit"], "1");
+    assert_eq!(response.headers()["x-ratelimit-remaining"], "0");
+
+    clock.advance(Duration::from_secs(1));
+    assert_eq!(
+        status(&app, from_peer("192.0.2.1:1000")).await,
+        StatusCode::OK
+    );
+}
+
+#[test]
+fn peer_ip_ignores_forwarded_for_by_default() {
+    let extractor = PeerIp::new();
+    assert_eq!(
+        extractor.extract(&forwarded("192.0.2.1:1000", "203.0.113.7")),
+        ip("192.0.2.1")
+    );
+    // Without connection info, all requests share a key:
+    assert_eq!(extractor.extract(&Request::new(())), ip("0.0.0.0"));
+}
+
+#[test]
+fn peer_ip_looks_through_trusted_proxies() {
+    let extractor = PeerIp::behind_proxies(|ip| ip.is_loopback());
+    // The client can't choose its key by adding entries of its own:
+    assert_eq!(
+        extractor.extract(&forwarded(
+            "127.0.0.1:1000",
+            "10.0.0.1, 203.0.113.7, 127.0.0.2"
+        )),
+        ip("203.0.113.7")
+    );
+    // Proxies are only trusted if they are the peer, or reported by a trusted proxy:
+    assert_eq!(
+        extractor.extract(&forwarded("192.0.2.1:1000", "203.0.113.7")),
+        ip("192.0.2.1")
+    );
+    // Invalid entries stop the walk at the proxy that added them:
+    assert_eq!(
+        extractor.extract(&forwarded("127.0.0.1:1000", "203.0.113.7, unknown")),
+        ip("127.0.0.1")
+    );
+    // All entries come from trusted proxies:
+    assert_eq!(
+        extractor.extract(&forwarded("127.0.0.1:1000", "127.0.0.3, 127.0.0.2")),
+        ip("127.0.0.3")
+    );
+    assert_eq!(
+        extractor.extract(&from_peer("127.0.0.1:1000")),
+        ip("127.0.0.1")
+    );
+}
+
+#[tokio::test]
+async fn limits_by_header() {
+    let clock = FakeRelativeClock::default();
+    let app = app(HeaderKey::new("X-Api-Key"), &clock);
+    let request = |key: Option<&str>| {
+        let mut request = Request::new(Body::empty());
+        if let Some(key) = key {
+            request
+                .headers_mut()
+                .insert("x-api-key", key.parse().unwrap());
+        }
+        request
+    };
+
+    assert_eq!(status(&app, request(Some("a"))).await, StatusCode::OK);
+    assert_eq!(status(&app, request(Some("b"))).await, StatusCode::OK);
+    assert_eq!(
+        status(&app, request(Some("a"))).await,
+        StatusCode::TOO_MANY_REQUESTS
+    );
+    assert_eq!(status(&app, request(None)).await, StatusCode::OK);
+    assert_eq!(
+        status(&app, request(None)).await,
+        StatusCode::TOO_MANY_REQUESTS
+    );
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct UserId(u32);
+
+#[tokio::test]
+async fn limits_by_authenticated_user() {
+    let clock = FakeRelativeClock::default();
+    let app = app(AuthenticatedUser::<UserId>::new(), &clock);
+    let request = |user: Option<u32>| {
+        let mut request = Request::new(Body::empty());
+        if let Some(user) = user {
+            request.extensions_mut().insert(UserId(user));
+        }
+        request
+    };
+
+    assert_eq!(status(&app, request(Some(1))).await, StatusCode::OK);
+    assert_eq!(status(&app, request(Some(2))).await, StatusCode::OK);
+    assert_eq!(status(&app, request(None)).await, StatusCode::OK);
+    assert_eq!(
+        status(&app, request(Some(1))).await,
+        StatusCode::TOO_MANY_REQUESTS
+    );
+    assert_eq!(
+        status(&app, request(None)).await,
+        StatusCode::TOO_MANY_REQUESTS
+    );
+}
+
+/// An inner service that holds one unit of shared capacity from `poll_ready` until it is called
+/// or dropped.
+struct Reserving {
+    reserved: Arc<AtomicUsize>,
+    ready: bool,
+}
+
+impl Clone for Reserving {
+    fn clone(&self) -> Self {
+        Reserving {
+            reserved: self.reserved.clone(),
+            ready: false,
+        }
+    }
+}
+
+impl Drop for Reserving {
+    fn drop(&mut self) {
+        if self.ready {
+            self.reserved.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+}
+
+impl Service<Request<Body>> for Reserving {
+    type Response = Response;
+    type Error = Infallible;
+    type Future = Ready<Result<Response, Infallible>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+        if !self.ready {
+            self.ready = true;
+            self.reserved.fetch_add(1, Ordering::SeqCst);
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, _request: Request<Body>) -> Self::Future {
+        assert!(self.ready, "called without poll_ready");
+        self.ready = false;
+        self.reserved.fetch_sub(1, Ordering::SeqCst);
+        future::ready(Ok(Response::new(Body::empty())))
+    }
+}
+
+#[tokio::test]
+async fn rejections_keep_the_readied_inner_service() {
+    let clock = FakeRelativeClock::default();
+    let lim = RateLimiter::dashmap_with_clock(Quota::per_second(nonzero!(1u32)), &clock);
+    let reserved = Arc::new(AtomicUsize::new(0));
+    let mut svc = RateLimitLayer::new(lim, HeaderKey::new("X-Api-Key")).layer(Reserving {
+        reserved: reserved.clone(),
+        ready: false,
+    });
+    let request = |key: &str| {
+        let mut request = Request::new(Body::empty());
+        request
+            .headers_mut()
+            .insert("x-api-key", key.parse().unwrap());
+        request
+    };
+
+    let response = svc.ready().await.unwrap().call(request("a")).await;
+    assert_eq!(response.unwrap().status(), StatusCode::OK);
+    assert_eq!(reserved.load(Ordering::SeqCst), 0);
+
+    for _ in 0..3 {
+        let response = svc.ready().await.unwrap().call(request("a")).await;
+        assert_eq!(response.unwrap().status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(reserved.load(Ordering::SeqCst), 1);
+    }
+
+    let response = svc.ready().await.unwrap().call(request("b")).await;
+    assert_eq!(response.unwrap().status(), StatusCode::OK);
+    assert_eq!(reserved.load(Ordering::SeqCst), 0);
+
+    svc.ready().await.unwrap();
+    drop(svc);
+    assert_eq!(reserved.load(Ordering::SeqCst), 0);
+}
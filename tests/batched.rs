@@ -0,0 +1,134 @@
+use governor::{
+    clock::{Clock, FakeRelativeClock},
+    BatchedRateLimiter, Quota, RateLimiter,
+};
+use nonzero_ext::nonzero;
+use std::time::Duration;
+
+#[test]
+fn buys_cells_in_batches() {
+    let clock = FakeRelativeClock::default();
+    let lim = RateLimiter::direct_with_clock(Quota::per_second(nonzero!(10u32)), &clock);
+    let batched = BatchedRateLimiter::new(lim, nonzero!(4u32));
+
+    assert_eq!(Ok(()), batched.check());
+    assert_eq!(batched.stock(), 3);
+    assert_eq!(
+        batched
+            .rate_limiter()
+            .state_snapshot()
+            .remaining_burst_capacity(),
+        6
+    );
+    for _ in 0..3 {
+        assert_eq!(Ok(()), batched.check());
+    }
+    assert_eq!(batched.stock(), 0);
+    assert_eq!(
+        batched
+            .rate_limiter()
+            .state_snapshot()
+            .remaining_burst_capacity(),
+        6
+    );
+}
+
+#[test]
+fn falls_back_to_single_cells() {
+    let clock = FakeRelativeClock::default();
+    let lim = RateLimiter::direct_with_clock(Quota::per_second(nonzero!(5u32)), &clock);
+    let batched = BatchedRateLimiter::new(lim, nonzero!(3u32));
+
+    // One batch of 3, then single cells for the remaining 2:
+    for _ in 0..5 {
+        assert_eq!(Ok(()), batched.check());
+    }
+    assert_eq!(batched.stock(), 0);
+    let negative = batched.check().unwrap_err();
+    assert_eq!(
+        negative.wait_time_from(clock.now()),
+        Duration::from_millis(200)
+    );
+}
+
+#[test]
+fn batch_size_is_limited_to_burst_size() {
+    let clock = FakeRelativeClock::default();
+    let lim = RateLimiter::direct_with_clock(Quota::per_second(nonzero!(2u32)), &clock);
+    let batched = BatchedRateLimiter::new(lim, nonzero!(100u32));
+
+    assert_eq!(Ok(()), batched.check());
+    assert_eq!(batched.stock(), 1);
+    assert_eq!(Ok(()), batched.check());
+    assert!(batched.check().is_err());
+}
+
+#[test]
+fn clones_keep_separate_stock() {
+    let clock = FakeRelativeClock::default();
+    let lim = RateLimiter::direct_with_clock(Quota::per_second(nonzero!(4u32)), &clock);
+    let first = BatchedRateLimiter::new(lim, nonzero!(2u32));
+    let second = first.clone();
+
+    assert_eq!(Ok(()), first.check());
+    assert_eq!(Ok(()), second.check());
+    assert_eq!((first.stock(), second.stock()), (1, 1));
+    assert_eq!(
+        first
+            .rate_limiter()
+            .state_snapshot()
+            .remaining_burst_capacity(),
+        0
+    );
+
+    // Each clone can still use its stock, but neither can buy more:
+    assert_eq!(Ok(()), first.check());
+    assert_eq!(Ok(()), second.check());
+    assert!(first.check().is_err());
+    assert!(second.check().is_err());
+}
+
+#[test]
+fn reports_no_denial_for_batches_that_dont_fit() {
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+
+    let allowed = Arc::new(AtomicU32::new(0));
+    let denied = Arc::new(AtomicU32::new(0));
+    let decided = Arc::new(AtomicU32::new(0));
+    let clock = FakeRelativeClock::default();
+    let lim = RateLimiter::direct_with_clock(Quota::per_second(nonzero!(5u32)), &clock)
+        .on_allowed({
+            let allowed = allowed.clone();
+            move |_, n| {
+                allowed.fetch_add(n.get(), Ordering::Relaxed);
+            }
+        })
+        .on_denied({
+            let denied = denied.clone();
+            move |_, _, _| {
+                denied.fetch_add(1, Ordering::Relaxed);
+            }
+        })
+        .on_decision({
+            let decided = decided.clone();
+            move |_, _| {
+                decided.fetch_add(1, Ordering::Relaxed);
+            }
+        });
+    let batched = BatchedRateLimiter::new(lim, nonzero!(3u32));
+
+    for _ in 0..4 {
+        assert_eq!(Ok(()), batched.check());
+    }
+    // A batch of 3, then a batch of 3 that doesn't fit and a single cell instead:
+    assert_eq!(allowed.load(Ordering::Relaxed), 4);
+    assert_eq!(denied.load(Ordering::Relaxed), 0);
+    assert_eq!(decided.load(Ordering::Relaxed), 2);
+
+    assert_eq!(Ok(()), batched.check());
+    assert!(batched.check().is_err());
+    assert_eq!(allowed.load(Ordering::Relaxed), 5);
+    assert_eq!(denied.load(Ordering::Relaxed), 1);
+    assert_eq!(decided.load(Ordering::Relaxed), 4);
+}
@@ -0,0 +1,44 @@
+use governor::{
+    clock::FakeRelativeClock,
+    state::{keyed::HashMapStateStore, InMemoryState, NotKeyed},
+    Quota, RateLimiter,
+};
+use nonzero_ext::nonzero;
+use std::time::Duration;
+
+#[test]
+fn builds_direct_limiter() {
+    let clock = FakeRelativeClock::default();
+    let lim: RateLimiter<NotKeyed, InMemoryState, FakeRelativeClock> =
+        RateLimiter::builder(Quota::per_second(nonzero!(2u32)))
+            .clock(&clock)
+            .build();
+    assert_eq!(Ok(()), lim.check());
+    assert_eq!(Ok(()), lim.check());
+    assert!(lim.check().is_err());
+
+    clock.advance(Duration::from_millis(500));
+    assert_eq!(Ok(()), lim.check());
+}
+
+#[test]
+fn builds_keyed_limiter_with_state_store() {
+    let clock = FakeRelativeClock::default();
+    let lim = RateLimiter::builder(Quota::per_second(nonzero!(1u32)))
+        .state_store(HashMapStateStore::<u32>::default())
+        .clock(&clock)
+        .build();
+    assert_eq!(Ok(()), lim.check_key(&1));
+    assert!(lim.check_key(&1).is_err());
+    assert_eq!(Ok(()), lim.check_key(&2));
+    assert_eq!(lim.len(), 2);
+}
+
+#[test]
+fn later_quota_wins() {
+    let lim = RateLimiter::builder(Quota::per_second(nonzero!(1u32)))
+        .keyed::<&str>()
+        .quota(Quota::per_second(nonzero!(3u32)))
+        .build();
+    assert_eq!(lim.key_state_snapshot(&"a").remaining_burst_capacity(), 3);
+}
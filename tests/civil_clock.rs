@@ -0,0 +1,158 @@
+#![cfg(any(feature = "chrono", feature = "time"))]
+
+use governor::clock::{Clock, Reference};
+use governor::nanos::Nanos;
+use governor::state::InMemoryState;
+use governor::{Quota, RateLimiter};
+use nonzero_ext::nonzero;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// A fake system clock, in seconds since the UNIX epoch.
+fn system_clock(start: i64) -> Arc<AtomicI64> {
+    Arc::new(AtomicI64::new(start))
+}
+
+#[cfg(feature = "chrono")]
+mod chrono_clock {
+    use super::*;
+    use chrono::{DateTime, Utc};
+    use governor::clock::ChronoClock;
+
+    fn clock(secs: &Arc<AtomicI64>) -> ChronoClock {
+        let secs = Arc::clone(secs);
+        ChronoClock::from_fn(move || {
+            DateTime::from_timestamp(secs.load(Ordering::SeqCst), 0).unwrap()
+        })
+    }
+
+    #[test]
+    fn reference_arithmetic() {
+        let t = DateTime::from_timestamp(1_000, 0).unwrap();
+        let later = t + Nanos::from(Duration::from_millis(1_500));
+        assert_eq!(later, DateTime::from_timestamp(1_001, 500_000_000).unwrap());
+        assert_eq!(
+            later.duration_since(t),
+            Nanos::from(Duration::from_millis(1_500))
+        );
+        assert_eq!(t.duration_since(later), Nanos::from(0));
+        assert_eq!(
+            later.saturating_sub(Nanos::from(Duration::from_millis(1_500))),
+            t
+        );
+        assert_eq!(
+            DateTime::<Utc>::MIN_UTC.saturating_sub(Nanos::from(1)),
+            DateTime::<Utc>::MIN_UTC
+        );
+        assert!(t + Nanos::from(u64::MAX) > t);
+    }
+
+    #[test]
+    fn holds_still_on_backwards_jumps() {
+        let secs = system_clock(1_000);
+        let clock = clock(&secs);
+        let copy = clock.clone();
+        assert_eq!(clock.now(), DateTime::from_timestamp(1_000, 0).unwrap());
+
+        secs.store(400, Ordering::SeqCst);
+        assert_eq!(clock.now(), DateTime::from_timestamp(1_000, 0).unwrap());
+        assert_eq!(copy.now(), DateTime::from_timestamp(1_000, 0).unwrap());
+
+        secs.store(1_001, Ordering::SeqCst);
+        assert_eq!(copy.now(), DateTime::from_timestamp(1_001, 0).unwrap());
+    }
+
+    #[test]
+    fn calendar_aligned_limiter() {
+        let midnight = DateTime::from_timestamp(86_400 * 20_000, 0).unwrap();
+        let secs = system_clock(midnight.timestamp() + 3_600);
+        let lim = RateLimiter::new_const(
+            Quota::per_hour(nonzero!(1u32)),
+            InMemoryState::default(),
+            clock(&secs),
+            midnight,
+        );
+        lim.check().unwrap();
+        assert!(lim.check().is_err());
+
+        // The system's clock gets set back by a day; the rate limiter keeps counting from the
+        // latest reading rather than denying cells for a day:
+        secs.fetch_sub(86_400, Ordering::SeqCst);
+        assert!(lim.check().is_err());
+        secs.fetch_add(86_400 + 3_600, Ordering::SeqCst);
+        lim.check().unwrap();
+    }
+}
+
+#[cfg(feature = "time")]
+mod time_clock {
+    use super::*;
+    use governor::clock::TimeClock;
+    use time::{OffsetDateTime, UtcOffset};
+
+    fn clock(secs: &Arc<AtomicI64>) -> TimeClock {
+        let secs = Arc::clone(secs);
+        TimeClock::from_fn(move || {
+            OffsetDateTime::from_unix_timestamp(secs.load(Ordering::SeqCst)).unwrap()
+        })
+    }
+
+    fn at(secs: i64) -> OffsetDateTime {
+        OffsetDateTime::from_unix_timestamp(secs).unwrap()
+    }
+
+    #[test]
+    fn reference_arithmetic() {
+        let t = at(1_000);
+        let later = t + Nanos::from(Duration::from_millis(1_500));
+        assert_eq!(later, at(1_001) + time::Duration::milliseconds(500));
+        assert_eq!(
+            later.duration_since(t),
+            Nanos::from(Duration::from_millis(1_500))
+        );
+        assert_eq!(t.duration_since(later), Nanos::from(0));
+        assert_eq!(
+            Reference::saturating_sub(&later, Nanos::from(Duration::from_millis(1_500))),
+            t
+        );
+        // Readings in other offsets compare by their instant:
+        let offset = t.to_offset(UtcOffset::from_hms(2, 0, 0).unwrap());
+        assert_eq!(offset.duration_since(t), Nanos::from(0));
+        assert!(t + Nanos::from(u64::MAX) > t);
+    }
+
+    #[test]
+    fn holds_still_on_backwards_jumps() {
+        let secs = system_clock(1_000);
+        let clock = clock(&secs);
+        let copy = clock.clone();
+        assert_eq!(clock.now(), at(1_000));
+
+        secs.store(400, Ordering::SeqCst);
+        assert_eq!(clock.now(), at(1_000));
+        assert_eq!(copy.now(), at(1_000));
+
+        secs.store(1_001, Ordering::SeqCst);
+        assert_eq!(copy.now(), at(1_001));
+    }
+
+    #[test]
+    fn calendar_aligned_limiter() {
+        let midnight = at(86_400 * 20_000);
+        let secs = system_clock(midnight.unix_timestamp() + 3_600);
+        let lim = RateLimiter::new_const(
+            Quota::per_hour(nonzero!(1u32)),
+            InMemoryState::default(),
+            clock(&secs),
+            midnight,
+        );
+        lim.check().unwrap();
+        assert!(lim.check().is_err());
+
+        secs.fetch_sub(86_400, Ordering::SeqCst);
+        assert!(lim.check().is_err());
+        secs.fetch_add(86_400 + 3_600, Ordering::SeqCst);
+        lim.check().unwrap();
+    }
+}
@@ -0,0 +1,35 @@
+#![cfg(feature = "std")]
+
+use governor::clock::{Clock, CoarseClock};
+use governor::{Quota, RateLimiter};
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[test]
+fn advances_in_steps() {
+    let clock = CoarseClock::from_interval(Duration::from_millis(10)).unwrap();
+    assert_eq!(clock.resolution(), Duration::from_millis(10));
+
+    let start = clock.now();
+    thread::sleep(Duration::from_millis(50));
+    assert!(clock.now() > start);
+
+    // The clock never runs ahead of the real time:
+    assert!(clock.now() <= Instant::now());
+}
+
+#[test]
+fn until_ready_observes_waited_time() {
+    let clock = CoarseClock::from_interval(Duration::from_millis(5)).unwrap();
+    let lim = RateLimiter::direct_with_clock(
+        Quota::with_period(Duration::from_millis(20)).unwrap(),
+        &clock,
+    );
+    let start = Instant::now();
+    futures::executor::block_on(async {
+        for _ in 0..3 {
+            lim.until_ready().await;
+        }
+    });
+    assert!(start.elapsed() >= Duration::from_millis(40));
+}
@@ -0,0 +1,84 @@
+#![cfg(feature = "std")]
+
+use futures::executor::block_on;
+use futures::task::noop_waker_ref;
+use futures::FutureExt;
+use governor::{ConcurrencyLimiter, Quota, RateAndConcurrencyLimiter, RateLimiter};
+use more_asserts::*;
+use nonzero_ext::*;
+use std::future::Future;
+use std::pin::pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+#[test]
+fn limits_permits() {
+    let lim = ConcurrencyLimiter::new(nonzero!(2u32));
+    let first = lim.try_acquire().unwrap();
+    let second = block_on(lim.acquire());
+    assert_eq!(lim.in_flight(), 2);
+    assert!(lim.try_acquire().is_none());
+    assert!(lim.acquire().now_or_never().is_none());
+
+    drop(first);
+    assert_eq!(lim.in_flight(), 1);
+    let _third = lim.try_acquire().unwrap();
+    drop(second);
+    assert_eq!(lim.in_flight(), 1);
+    assert_eq!(lim.max_in_flight().get(), 2);
+}
+
+#[test]
+fn waiters_get_permits_in_order() {
+    let lim = ConcurrencyLimiter::new(nonzero!(1u32));
+    let mut cx = Context::from_waker(noop_waker_ref());
+    let held = lim.try_acquire().unwrap();
+
+    let mut first = pin!(lim.acquire());
+    let mut second = pin!(lim.acquire());
+    assert!(first.as_mut().poll(&mut cx).is_pending());
+    assert!(second.as_mut().poll(&mut cx).is_pending());
+    // Waiting tasks keep new ones from jumping the line:
+    assert!(lim.try_acquire().is_none());
+
+    drop(held);
+    assert!(second.as_mut().poll(&mut cx).is_pending());
+    let permit = match first.as_mut().poll(&mut cx) {
+        Poll::Ready(permit) => permit,
+        Poll::Pending => panic!("the first waiter should get the permit"),
+    };
+    drop(permit);
+    assert!(second.as_mut().poll(&mut cx).is_ready());
+}
+
+#[test]
+fn dropped_waiters_give_up_their_place() {
+    let lim = ConcurrencyLimiter::new(nonzero!(1u32));
+    let mut cx = Context::from_waker(noop_waker_ref());
+    let held = lim.try_acquire().unwrap();
+    {
+        let mut waiting = pin!(lim.acquire());
+        assert!(waiting.as_mut().poll(&mut cx).is_pending());
+    }
+    drop(held);
+    assert!(lim.try_acquire().is_some());
+}
+
+#[test]
+fn enforces_rate_and_concurrency() {
+    let i = Instant::now();
+    let lim = RateAndConcurrencyLimiter::new(
+        RateLimiter::direct(Quota::per_second(nonzero!(10u32))),
+        ConcurrencyLimiter::new(nonzero!(2u32)),
+    );
+    // exhaust the rate limiter:
+    while lim.rate_limiter().check().is_ok() {}
+
+    let permit = block_on(lim.acquire());
+    assert_ge!(i.elapsed(), Duration::from_millis(100));
+    let _other = block_on(lim.acquire());
+    assert_eq!(lim.concurrency_limiter().in_flight(), 2);
+    assert!(lim.acquire().now_or_never().is_none());
+    drop(permit);
+    assert_eq!(lim.concurrency_limiter().in_flight(), 1);
+}
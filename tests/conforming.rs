@@ -0,0 +1,88 @@
+use governor::{
+    clock::{Clock, FakeRelativeClock, Reference},
+    nanos::Nanos,
+    Quota, RateLimiter,
+};
+use nonzero_ext::nonzero;
+use std::time::Duration;
+
+fn check_conforming_times(quota: Quota) {
+    let clock = FakeRelativeClock::default();
+    clock.advance(Duration::from_secs(3));
+    let lim = RateLimiter::direct_with_clock(quota, &clock);
+    if quota.starts_empty() {
+        // The state starts filling up when it's first used:
+        assert_ne!(Ok(()), lim.check());
+    }
+    for time in quota.conforming_times(clock.now()).take(50) {
+        let now = clock.now();
+        assert!(time >= now);
+        clock.advance(time.duration_since(now).into());
+        assert_eq!(Ok(()), lim.check(), "{:?} at {:?}", quota, clock.now());
+    }
+}
+
+#[test]
+fn times_conform() {
+    check_conforming_times(Quota::per_second(nonzero!(5u32)));
+    check_conforming_times(Quota::per_minute(nonzero!(3u32)).allow_burst(nonzero!(7u32)));
+    check_conforming_times(Quota::per(nonzero!(7u32), Duration::from_secs(90)).unwrap());
+    check_conforming_times(Quota::per_second(nonzero!(5u32)).starting_empty());
+}
+
+#[test]
+fn times_are_earliest() {
+    let quota = Quota::per_second(nonzero!(4u32)).allow_burst(nonzero!(2u32));
+    let clock = FakeRelativeClock::default();
+    let lim = RateLimiter::direct_with_clock(quota, &clock);
+    let times: Vec<Nanos> = quota.conforming_times(Nanos::from(0)).take(6).collect();
+    assert_eq!(
+        times,
+        [0, 0, 250, 500, 750, 1000]
+            .iter()
+            .map(|ms| Nanos::from(Duration::from_millis(*ms)))
+            .collect::<Vec<_>>()
+    );
+    for time in times {
+        let target: Duration = time.into();
+        let now: Duration = clock.now().into();
+        if target > now {
+            clock.advance(target - now - Duration::from_nanos(1));
+            assert_ne!(Ok(()), lim.check());
+            clock.advance(Duration::from_nanos(1));
+        }
+        assert_eq!(Ok(()), lim.check());
+    }
+}
+
+#[test]
+fn sentinel_quotas() {
+    assert_eq!(Quota::none().conforming_times(Nanos::from(0)).next(), None);
+    assert!(Quota::unlimited()
+        .conforming_times(Nanos::from(5))
+        .take(100)
+        .all(|time| time == Nanos::from(5)));
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn ticks() {
+    use futures::StreamExt;
+
+    let clock = FakeRelativeClock::default();
+    let quota = Quota::per_second(nonzero!(2u32));
+    let ticks: Vec<_> = clock.block_on_auto_advance(
+        quota
+            .conforming_ticks(&clock)
+            .take(5)
+            .map(|tick| (tick, clock.now()))
+            .collect(),
+    );
+    let expected: Vec<_> = quota.conforming_times(Nanos::from(0)).take(5).collect();
+    assert_eq!(
+        ticks.iter().map(|(tick, _)| *tick).collect::<Vec<_>>(),
+        expected
+    );
+    // Each tick is yielded once it is reached:
+    assert!(ticks.iter().all(|(tick, now)| tick == now));
+}
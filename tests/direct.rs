@@ -1,5 +1,6 @@
 use governor::{
     clock::{Clock, FakeRelativeClock},
+    nanos::Nanos,
     NegativeMultiDecision, Quota, RateLimiter,
 };
 use nonzero_ext::nonzero;
@@ -103,18 +104,21 @@ fn all_capacity_check_rejects_excess() {
     let clock = FakeRelativeClock::default();
     let lb = RateLimiter::direct_with_clock(Quota::per_second(nonzero!(5u32)), &clock);
 
-    assert_eq!(
-        Err(NegativeMultiDecision::InsufficientCapacity(5)),
-        lb.check_n(nonzero!(15u32))
-    );
-    assert_eq!(
-        Err(NegativeMultiDecision::InsufficientCapacity(5)),
-        lb.check_n(nonzero!(6u32))
-    );
-    assert_eq!(
-        Err(NegativeMultiDecision::InsufficientCapacity(5)),
-        lb.check_n(nonzero!(7u32))
-    );
+    assert!(matches!(
+        lb.check_n(nonzero!(15u32)),
+        Err(NegativeMultiDecision::InsufficientCapacity(insufficient))
+            if insufficient.requested().get() == 15 && insufficient.max_burst() == 5
+    ));
+    assert!(matches!(
+        lb.check_n(nonzero!(6u32)),
+        Err(NegativeMultiDecision::InsufficientCapacity(insufficient))
+            if insufficient.requested().get() == 6 && insufficient.max_burst() == 5
+    ));
+    assert!(matches!(
+        lb.check_n(nonzero!(7u32)),
+        Err(NegativeMultiDecision::InsufficientCapacity(insufficient))
+            if insufficient.requested().get() == 7 && insufficient.max_burst() == 5
+    ));
 }
 
 #[test]
@@ -163,3 +167,268 @@ fn actual_threadsafety() {
     clock.advance(ms * 998);
     assert_eq!(Ok(()), lim.check());
 }
+
+#[test]
+fn reset_restores_capacity() {
+    let clock = FakeRelativeClock::default();
+    let lb = RateLimiter::direct_with_clock(Quota::per_second(nonzero!(2u32)), &clock);
+    assert_eq!(Ok(()), lb.check_n(nonzero!(2u32)));
+    assert_ne!(Ok(()), lb.check());
+
+    lb.reset();
+    assert_eq!(Ok(()), lb.check_n(nonzero!(2u32)));
+    assert_ne!(Ok(()), lb.check());
+}
+
+#[test]
+fn starting_empty_fills_up() {
+    let clock = FakeRelativeClock::default();
+    let quota = Quota::per_second(nonzero!(5u32)).starting_empty();
+    let lb = RateLimiter::direct_with_clock(quota, &clock);
+    let ms = Duration::from_millis(1);
+
+    let negative = lb.check().unwrap_err();
+    assert_eq!(negative.wait_time_from(clock.now()), ms * 200);
+
+    clock.advance(ms * 200);
+    assert_eq!(Ok(()), lb.check());
+    assert_ne!(Ok(()), lb.check());
+
+    // After a full replenishment period, the full burst capacity is available:
+    clock.advance(ms * 1000);
+    assert_eq!(Ok(()), lb.check_n(nonzero!(5u32)));
+    assert_ne!(Ok(()), lb.check());
+}
+
+#[test]
+fn state_snapshot() {
+    let clock = FakeRelativeClock::default();
+    let lb = RateLimiter::direct_with_clock(Quota::per_second(nonzero!(4u32)), &clock);
+    let ms = Duration::from_millis(1);
+
+    let snapshot = lb.state_snapshot();
+    assert_eq!(snapshot.theoretical_arrival_time(), None);
+    assert_eq!(snapshot.remaining_burst_capacity(), 4);
+
+    lb.check_n(nonzero!(4u32)).unwrap();
+    clock.advance(ms * 100);
+    let snapshot = lb.state_snapshot();
+    assert_eq!(snapshot.emission_interval(), ms * 250);
+    assert_eq!(snapshot.tolerance(), ms * 1000);
+    assert_eq!(
+        snapshot.theoretical_arrival_time(),
+        Some(Nanos::from(ms * 1250))
+    );
+    assert_eq!(snapshot.time_of_measurement(), Nanos::from(ms * 100));
+    assert_eq!(snapshot.remaining_burst_capacity(), 0);
+
+    let negative = lb.check().unwrap_err();
+    assert_eq!(negative.emission_interval(), ms * 250);
+    assert_eq!(negative.tolerance(), ms * 1000);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn negative_outcomes_are_errors() {
+    use std::error::Error;
+
+    let clock = FakeRelativeClock::default();
+    let lb = RateLimiter::direct_with_clock(Quota::per_second(nonzero!(2u32)), &clock);
+    let ms = Duration::from_millis(1);
+
+    let check = || -> Result<(), Box<dyn Error>> {
+        lb.check()?;
+        Ok(())
+    };
+    assert!(check().is_ok());
+    assert!(check().is_ok());
+    let err = check().unwrap_err();
+    assert!(err.to_string().starts_with("rate-limited until"));
+
+    clock.advance(ms * 100);
+    let negative = lb.check().unwrap_err();
+    assert_eq!(negative.retry_after(), ms * 400);
+    assert_eq!(negative.retry_after(), negative.wait_time_from(clock.now()));
+    assert_eq!(negative.time_of_decision(), clock.now());
+    assert_eq!(negative.state_snapshot().remaining_burst_capacity(), 0);
+
+    let multi = lb.check_n(nonzero!(2u32)).unwrap_err();
+    assert!(multi
+        .to_string()
+        .starts_with("batch of 2 cells is non-conforming"));
+    assert!(multi.source().is_some());
+    let insufficient = lb.check_n(nonzero!(3u32)).unwrap_err();
+    assert_eq!(
+        insufficient.to_string(),
+        "required number of 3 cells exceeds the capacity of 2 cells"
+    );
+    assert!(insufficient.source().is_none());
+}
+
+#[test]
+fn arbitrary_periods() {
+    let clock = FakeRelativeClock::default();
+    let quota = Quota::per(nonzero!(7u32), Duration::from_secs(90)).unwrap();
+    let lb = RateLimiter::direct_with_clock(quota, &clock);
+    for _ in 0..7 {
+        assert_eq!(Ok(()), lb.check());
+    }
+    assert_ne!(Ok(()), lb.check());
+
+    // The full burst capacity is back no earlier than 90s later:
+    clock.advance(Duration::from_secs(90) - Duration::from_nanos(1));
+    assert_eq!(lb.check_n(nonzero!(7u32)).map_err(|_| ()), Err(()));
+    clock.advance(Duration::from_nanos(7));
+    assert_eq!(Ok(()), lb.check_n(nonzero!(7u32)));
+}
+
+#[test]
+fn quota_none_rejects_everything() {
+    let clock = FakeRelativeClock::default();
+    let lb = RateLimiter::direct_with_clock(Quota::none(), &clock);
+    let negative = lb.check().unwrap_err();
+    assert!(negative.retry_after() > Duration::from_secs(100 * 365 * 24 * 60 * 60));
+    assert_eq!(negative.state_snapshot().remaining_burst_capacity(), 0);
+    assert!(matches!(
+        lb.check_n(nonzero!(1u32)),
+        Err(NegativeMultiDecision::InsufficientCapacity(insufficient))
+            if insufficient.max_burst() == 0 && insufficient.quota() == Quota::none()
+    ));
+
+    clock.advance(Duration::from_secs(60 * 60 * 24 * 365));
+    assert_ne!(Ok(()), lb.check());
+    assert!(Quota::none().is_none());
+    assert!(Quota::none().allow_burst(nonzero!(10u32)).is_none());
+    assert_eq!(Quota::none().cells_per_second(), 0.0);
+}
+
+#[test]
+fn quota_unlimited_allows_everything() {
+    let clock = FakeRelativeClock::default();
+    let lb = RateLimiter::direct_with_clock(Quota::unlimited(), &clock);
+    for _ in 0..10_000 {
+        assert_eq!(Ok(()), lb.check());
+    }
+    assert_eq!(Ok(()), lb.check_n(nonzero!(u32::MAX)));
+    assert_eq!(lb.state_snapshot().remaining_burst_capacity(), u32::MAX);
+    assert!(Quota::unlimited().is_unlimited());
+    assert!(!Quota::unlimited().is_none());
+    assert_eq!(Quota::unlimited().cells_per_second(), f64::INFINITY);
+}
+
+#[test]
+fn sentinel_quotas_combine() {
+    let q = Quota::per_second(nonzero!(5u32));
+    assert_eq!(q.min(Quota::unlimited()), q);
+    assert_eq!(Quota::unlimited().min(q), q);
+    assert_eq!(q.min(Quota::none()), Quota::none());
+    assert_eq!(Quota::unlimited().min(Quota::none()), Quota::none());
+    assert_eq!(Quota::none().scaled(2.0), Some(Quota::none()));
+    assert_eq!(Quota::unlimited().scaled(0.5), Some(Quota::unlimited()));
+}
+
+#[test]
+fn only_sentinel_quotas_are_sentinels() {
+    let clock = FakeRelativeClock::default();
+    let fast = Quota::per_second(nonzero!(2_000_000_000u32));
+    assert!(!fast.is_unlimited());
+    assert_eq!(fast.replenish_interval(), Duration::from_nanos(1));
+    let lb = RateLimiter::direct_with_clock(fast.allow_burst(nonzero!(2u32)), &clock);
+    assert!(matches!(
+        lb.check_n(nonzero!(3u32)),
+        Err(NegativeMultiDecision::InsufficientCapacity(insufficient))
+            if insufficient.max_burst() == 2
+    ));
+
+    let slow = Quota::with_period(Duration::MAX).unwrap();
+    assert!(!slow.is_none());
+    assert!(!slow.is_unlimited());
+}
+
+#[test]
+fn const_quotas() {
+    const QUOTA: Quota = Quota::per_second(nonzero!(10u32)).allow_burst(nonzero!(2u32));
+    const PERIODIC: Option<Quota> = Quota::with_period(Duration::from_millis(100));
+    assert_eq!(PERIODIC.map(|q| q.allow_burst(nonzero!(2u32))), Some(QUOTA));
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn static_limiter() {
+    use governor::clock::SystemClock;
+    use governor::state::{InMemoryState, NotKeyed};
+    use std::time::UNIX_EPOCH;
+
+    static LIMITER: RateLimiter<NotKeyed, InMemoryState, SystemClock> =
+        RateLimiter::direct_const(Quota::per_hour(nonzero!(2u32)), SystemClock, UNIX_EPOCH);
+    assert_eq!(Ok(()), LIMITER.check());
+    assert_eq!(Ok(()), LIMITER.check());
+    assert!(LIMITER.check().is_err());
+}
+
+#[test]
+fn bonus_cells_are_free() {
+    let clock = FakeRelativeClock::default();
+    let lim = RateLimiter::direct_with_clock(Quota::per_second(nonzero!(2u32)), &clock);
+    lim.check_n(nonzero!(2u32)).unwrap();
+    assert!(lim.check().is_err());
+
+    lim.grant_bonus(nonzero!(2u32));
+    assert_eq!(lim.state_snapshot().remaining_burst_capacity(), 2);
+    lim.check_n(nonzero!(2u32)).unwrap();
+    assert!(lim.check().is_err());
+
+    // The bonus cells don't delay later cells: The next cell conforms 500ms after them.
+    clock.advance(Duration::from_millis(400));
+    assert!(lim.check().is_err());
+    clock.advance(Duration::from_millis(100));
+    lim.check().unwrap();
+    assert!(lim.check().is_err());
+}
+
+#[test]
+fn bonus_is_granted_once() {
+    let clock = FakeRelativeClock::default();
+    let lim = RateLimiter::direct_with_clock(Quota::per_hour(nonzero!(10u32)), &clock);
+    lim.check_n(nonzero!(10u32)).unwrap();
+    lim.grant_bonus(nonzero!(10u32));
+    lim.check_n(nonzero!(10u32)).unwrap();
+
+    clock.advance(Duration::from_secs(2));
+    assert!(lim.check().is_err());
+}
+
+#[test]
+fn bonus_never_exceeds_a_full_bucket() {
+    let clock = FakeRelativeClock::default();
+    let lim = RateLimiter::direct_with_clock(Quota::per_second(nonzero!(2u32)), &clock);
+    lim.grant_bonus(nonzero!(5u32));
+    assert_eq!(lim.state_snapshot().remaining_burst_capacity(), 2);
+    lim.check_n(nonzero!(2u32)).unwrap();
+    assert!(lim.check().is_err());
+}
+
+#[test]
+fn bonuses_add_up() {
+    let clock = FakeRelativeClock::default();
+    let lim = RateLimiter::direct_with_clock(Quota::per_minute(nonzero!(4u32)), &clock);
+    lim.check_n(nonzero!(4u32)).unwrap();
+    lim.grant_bonus(nonzero!(1u32));
+    lim.grant_bonus(nonzero!(2u32));
+    for _ in 0..3 {
+        lim.check().unwrap();
+    }
+    assert!(lim.check().is_err());
+}
+
+#[test]
+fn no_bonus_for_sentinel_quotas() {
+    let clock = FakeRelativeClock::default();
+    let lim = RateLimiter::direct_with_clock(Quota::none(), &clock);
+    lim.grant_bonus(nonzero!(5u32));
+    assert!(lim.check().is_err());
+
+    let lim = RateLimiter::direct_with_clock(Quota::unlimited(), &clock);
+    lim.grant_bonus(nonzero!(5u32));
+    assert_eq!(Ok(()), lim.check());
+}
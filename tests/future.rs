@@ -1,7 +1,7 @@
 #![cfg(feature = "std")]
 
 use futures::executor::block_on;
-use governor::{Quota, RateLimiter};
+use governor::{clock::Clock, Quota, RateLimiter};
 use more_asserts::*;
 use nonzero_ext::*;
 use std::sync::Arc;
@@ -136,5 +136,153 @@ fn multiple_keyed() {
 fn errors_on_exceeded_capacity() {
     let lim = RateLimiter::direct(Quota::per_second(nonzero!(10u32)));
 
-    block_on(lim.until_n_ready(nonzero!(11u32))).unwrap_err();
+    let insufficient = block_on(lim.until_n_ready(nonzero!(11u32))).unwrap_err();
+    assert_eq!(insufficient.requested().get(), 11);
+    assert_eq!(insufficient.max_burst(), 10);
+    assert_eq!(insufficient.quota(), Quota::per_second(nonzero!(10u32)));
+}
+
+#[test]
+fn fake_clock_advance_wakes_waits() {
+    use futures::task::{noop_waker, Context, Poll};
+    use futures::Future;
+    use governor::clock::FakeRelativeClock;
+
+    let clock = FakeRelativeClock::default();
+    let lim = RateLimiter::direct_with_clock(Quota::per_second(nonzero!(1u32)), &clock);
+    lim.check().unwrap();
+
+    let waker = noop_waker();
+    let mut cx = Context::from_waker(&waker);
+    let mut wait = Box::pin(lim.until_ready());
+    assert_eq!(Poll::Pending, wait.as_mut().poll(&mut cx));
+    clock.advance(Duration::from_millis(500));
+    assert_eq!(Poll::Pending, wait.as_mut().poll(&mut cx));
+    clock.advance(Duration::from_millis(500));
+    assert_eq!(Poll::Ready(()), wait.as_mut().poll(&mut cx));
+}
+
+#[test]
+fn fake_clock_auto_advance_jitter() {
+    use futures::future::join_all;
+    use governor::{clock::FakeRelativeClock, Jitter};
+
+    let clock = FakeRelativeClock::default();
+    let lim = RateLimiter::direct_with_clock(Quota::per_second(nonzero!(10u32)), &clock);
+    let jitter = Jitter::up_to(Duration::from_secs(1));
+    let i = Instant::now();
+    clock.block_on_auto_advance(join_all(
+        (0..30).map(|_| lim.until_ready_with_jitter(jitter.clone())),
+    ));
+    // No real time passed, but enough fake time for all cells:
+    assert_lt!(i.elapsed(), Duration::from_secs(1));
+    assert_ge!(Duration::from(clock.now()), Duration::from_secs(2));
+}
+
+#[test]
+fn fake_clock_auto_advance_keyed() {
+    use governor::clock::FakeRelativeClock;
+
+    let clock = FakeRelativeClock::default();
+    let lim = RateLimiter::hashmap_with_clock(Quota::per_minute(nonzero!(1u32)), &clock);
+    clock.block_on_auto_advance(async {
+        lim.until_key_ready(&1u32).await;
+        lim.until_key_ready(&2u32).await;
+        lim.until_key_ready(&1u32).await;
+    });
+    assert_eq!(Duration::from(clock.now()), Duration::from_secs(60));
+}
+
+#[test]
+fn until_key_n_ready() {
+    use governor::clock::FakeRelativeClock;
+
+    let clock = FakeRelativeClock::default();
+    let lim = RateLimiter::hashmap_with_clock(Quota::per_second(nonzero!(4u32)), &clock);
+    clock.block_on_auto_advance(async {
+        lim.until_key_n_ready(&1u32, nonzero!(4u32)).await.unwrap();
+        lim.until_key_n_ready(&1u32, nonzero!(2u32)).await.unwrap();
+        assert!(lim.until_key_n_ready(&1u32, nonzero!(5u32)).await.is_err());
+    });
+    assert_eq!(Duration::from(clock.now()), Duration::from_millis(500));
+}
+
+#[test]
+fn reports_wait_stats() {
+    use governor::{clock::FakeRelativeClock, Jitter};
+
+    let clock = FakeRelativeClock::default();
+    let lim = RateLimiter::direct_with_clock(Quota::per_second(nonzero!(4u32)), &clock);
+    clock.block_on_auto_advance(async {
+        let stats = lim.until_ready_with_stats(Jitter::default()).await;
+        assert_eq!(stats.waited(), Duration::from_secs(0));
+        assert_eq!(stats.retries(), 0);
+        assert!(!stats.was_delayed());
+
+        lim.until_n_ready(nonzero!(3u32)).await.unwrap();
+        let stats = lim
+            .until_n_ready_with_stats(nonzero!(2u32), Jitter::default())
+            .await
+            .unwrap();
+        assert_eq!(stats.waited(), Duration::from_millis(500));
+        assert_eq!(stats.retries(), 1);
+
+        // Waits that can never succeed don't get stats:
+        assert!(lim
+            .until_n_ready_with_stats(nonzero!(5u32), Jitter::default())
+            .await
+            .is_err());
+    });
+}
+
+#[test]
+fn reports_keyed_wait_stats() {
+    use governor::{clock::FakeRelativeClock, Jitter};
+
+    let clock = FakeRelativeClock::default();
+    let lim = RateLimiter::hashmap_with_clock(Quota::per_second(nonzero!(2u32)), &clock);
+    clock.block_on_auto_advance(async {
+        let stats = lim
+            .until_key_n_ready_with_stats(&1u32, nonzero!(2u32), Jitter::default())
+            .await
+            .unwrap();
+        assert!(!stats.was_delayed());
+        let stats = lim
+            .until_key_ready_with_stats(&1u32, Jitter::default())
+            .await;
+        assert_eq!(stats.waited(), Duration::from_millis(500));
+        assert_eq!(stats.retries(), 1);
+        let stats = lim
+            .until_key_ready_with_stats(&2u32, Jitter::default())
+            .await;
+        assert!(!stats.was_delayed());
+    });
+}
+
+#[test]
+fn counts_retries_of_contended_waits() {
+    use futures::task::{noop_waker, Context, Poll};
+    use futures::Future;
+    use governor::{clock::FakeRelativeClock, Jitter};
+
+    let clock = FakeRelativeClock::default();
+    let lim = RateLimiter::direct_with_clock(Quota::per_second(nonzero!(1u32)), &clock);
+    lim.check().unwrap();
+
+    let waker = noop_waker();
+    let mut cx = Context::from_waker(&waker);
+    let mut wait = Box::pin(lim.until_ready_with_stats(Jitter::default()));
+    assert!(wait.as_mut().poll(&mut cx).is_pending());
+    clock.advance(Duration::from_secs(1));
+    // Another caller takes the cell that the wait was waiting for:
+    lim.check().unwrap();
+    assert!(wait.as_mut().poll(&mut cx).is_pending());
+    clock.advance(Duration::from_secs(1));
+    match wait.as_mut().poll(&mut cx) {
+        Poll::Ready(stats) => {
+            assert_eq!(stats.waited(), Duration::from_secs(2));
+            assert_eq!(stats.retries(), 2);
+        }
+        Poll::Pending => panic!("the wait should have finished"),
+    }
 }
@@ -0,0 +1,177 @@
+use governor::{
+    clock::FakeRelativeClock,
+    state::keyed::{GossipDigest, GossipStateStore},
+    Quota, RateLimiter,
+};
+use nonzero_ext::nonzero;
+use std::time::Duration;
+
+type Limiter =
+    RateLimiter<&'static str, GossipStateStore<&'static str, FakeRelativeClock>, FakeRelativeClock>;
+
+fn node(clock: &FakeRelativeClock, id: u64) -> Limiter {
+    RateLimiter::gossip_with_clock(Quota::per_second(nonzero!(4u32)), id, clock)
+}
+
+fn remaining(lim: &Limiter) -> u32 {
+    lim.key_state_snapshot(&"key").remaining_burst_capacity()
+}
+
+fn allowed(lim: &Limiter) -> u32 {
+    let mut n = 0;
+    while lim.check_key(&"key").is_ok() {
+        n += 1;
+    }
+    n
+}
+
+#[test]
+fn merges_peer_cells() {
+    let clock = FakeRelativeClock::default();
+    let a = node(&clock, 1);
+    let b = node(&clock, 2);
+    for _ in 0..3 {
+        a.check_key(&"key").unwrap();
+    }
+    b.merge_digest(&a.digest());
+    assert_eq!(allowed(&b), 1);
+
+    // Merging the same digest again changes nothing:
+    clock.advance(Duration::from_millis(250));
+    b.merge_digest(&a.digest());
+    assert_eq!(allowed(&b), 1);
+}
+
+#[test]
+fn merge_is_commutative() {
+    let clock = FakeRelativeClock::default();
+    let a = node(&clock, 1);
+    let b = node(&clock, 2);
+    let c = node(&clock, 3);
+    let observer1 = node(&clock, 4);
+    let observer2 = node(&clock, 5);
+    a.check_key(&"key").unwrap();
+    b.check_key(&"key").unwrap();
+    c.check_key(&"key").unwrap();
+
+    observer1.merge_digest(&a.digest());
+    observer1.merge_digest(&b.digest());
+    observer1.merge_digest(&c.digest());
+    observer2.merge_digest(&c.digest());
+    observer2.merge_digest(&a.digest());
+    observer2.merge_digest(&b.digest());
+    assert_eq!(
+        observer1.key_state_snapshot(&"key"),
+        observer2.key_state_snapshot(&"key")
+    );
+    assert_eq!(allowed(&observer1), 1);
+}
+
+#[test]
+fn relays_counts() {
+    let clock = FakeRelativeClock::default();
+    let a = node(&clock, 1);
+    let b = node(&clock, 2);
+    let c = node(&clock, 3);
+    a.check_key(&"key").unwrap();
+    a.check_key(&"key").unwrap();
+    b.merge_digest(&a.digest());
+    c.merge_digest(&b.digest());
+    assert_eq!(remaining(&c), 2);
+    // A's cells don't count against A again:
+    a.merge_digest(&c.digest());
+    assert_eq!(remaining(&a), 2);
+}
+
+#[test]
+fn only_new_cells_count() {
+    let clock = FakeRelativeClock::default();
+    let a = node(&clock, 1);
+    let b = node(&clock, 2);
+    a.check_key(&"key").unwrap();
+    b.merge_digest(&a.digest());
+    a.check_key(&"key").unwrap();
+    b.merge_digest(&a.digest());
+    assert_eq!(allowed(&b), 2);
+}
+
+#[test]
+fn merging_never_exceeds_an_empty_bucket() {
+    let clock = FakeRelativeClock::default();
+    let a = node(&clock, 1);
+    let b = node(&clock, 2);
+    let c = node(&clock, 3);
+    assert_eq!(allowed(&a), 4);
+    assert_eq!(allowed(&b), 4);
+    c.merge_digest(&a.digest());
+    c.merge_digest(&b.digest());
+    assert_eq!(allowed(&c), 0);
+    clock.advance(Duration::from_millis(250));
+    assert_eq!(allowed(&c), 1);
+}
+
+#[test]
+fn new_incarnations_after_retain_recent() {
+    let clock = FakeRelativeClock::default();
+    let a = node(&clock, 1);
+    let b = node(&clock, 2);
+    a.check_key(&"key").unwrap();
+    b.merge_digest(&a.digest());
+
+    clock.advance(Duration::from_secs(5));
+    a.retain_recent();
+    assert!(a.is_empty());
+    a.check_key(&"key").unwrap();
+    a.check_key(&"key").unwrap();
+    let digest = a.digest();
+    assert_eq!(digest.counts().len(), 1);
+    assert_eq!(digest.counts()[0].incarnation, 2);
+
+    b.merge_digest(&digest);
+    assert_eq!(remaining(&b), 3);
+}
+
+#[test]
+fn stale_digests_after_reset_count_nothing() {
+    let clock = FakeRelativeClock::default();
+    let a = node(&clock, 1);
+    let b = node(&clock, 2);
+    a.check_key(&"key").unwrap();
+    a.check_key(&"key").unwrap();
+    b.merge_digest(&a.digest());
+    assert_eq!(remaining(&b), 2);
+
+    clock.advance(Duration::from_secs(10));
+    b.reset_key(&"key");
+    b.merge_digest(&a.digest());
+    assert_eq!(remaining(&b), 4);
+
+    clock.advance(Duration::from_secs(10));
+    b.retain_recent();
+    assert!(b.is_empty());
+    b.merge_digest(&a.digest());
+    assert!(b.is_empty());
+    assert_eq!(allowed(&b), 4);
+}
+
+#[test]
+fn digests_round_trip() {
+    let clock = FakeRelativeClock::default();
+    let a = node(&clock, 1);
+    let b = node(&clock, 2);
+    a.check_key(&"key").unwrap();
+    let sent = std::cell::RefCell::new(None);
+    a.send_digest(&|digest: GossipDigest<&'static str>| {
+        *sent.borrow_mut() = Some(digest.into_counts());
+    });
+    let received = GossipDigest::new(sent.into_inner().unwrap());
+    b.merge_digest(&received);
+    assert_eq!(allowed(&b), 3);
+}
+
+#[test]
+fn limiters_are_send_and_sync() {
+    fn assert_send_sync<T: Send + Sync>(_: &T) {}
+    let clock = FakeRelativeClock::default();
+    assert_send_sync(&node(&clock, 1));
+}
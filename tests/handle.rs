@@ -0,0 +1,49 @@
+use governor::{
+    clock::FakeRelativeClock,
+    state::{InMemoryState, NotKeyed},
+    Quota, RateLimiter, RateLimiterHandle,
+};
+use nonzero_ext::nonzero;
+use std::sync::Arc;
+use std::time::Duration;
+
+struct Service {
+    limiter: RateLimiterHandle<NotKeyed, InMemoryState, FakeRelativeClock>,
+}
+
+fn assert_static<T: 'static>(_: &T) {}
+
+#[test]
+fn clones_share_state() {
+    let clock = FakeRelativeClock::default();
+    let lim =
+        RateLimiter::direct_with_clock(Quota::per_second(nonzero!(2u32)), &clock).into_handle();
+    let service = Service {
+        limiter: lim.clone(),
+    };
+    assert_static(&service);
+    assert!(lim.ptr_eq(&service.limiter));
+
+    assert_eq!(Ok(()), lim.check());
+    assert_eq!(Ok(()), service.limiter.check());
+    assert_ne!(Ok(()), lim.check());
+
+    clock.advance(Duration::from_secs(1));
+    assert_eq!(Ok(()), service.limiter.check());
+}
+
+#[test]
+fn keyed_handles() {
+    let clock = FakeRelativeClock::default();
+    let lim = RateLimiter::hashmap_with_clock(Quota::per_second(nonzero!(1u32)), &clock);
+    let lim: RateLimiterHandle<_, _, _> = Arc::new(lim).into();
+    let other = lim.clone();
+
+    assert_eq!(Ok(()), lim.check_key(&1u32));
+    assert_ne!(Ok(()), other.check_key(&1u32));
+    assert_eq!(Ok(()), other.check_key(&2u32));
+    assert_eq!(other.len(), 2);
+
+    drop(lim);
+    assert_eq!(Arc::strong_count(&other.into_arc()), 1);
+}
@@ -0,0 +1,50 @@
+use governor::{
+    clock::FakeRelativeClock,
+    headers::{RateLimitHeaders, RETRY_AFTER},
+    Quota, RateLimiter,
+};
+use nonzero_ext::nonzero;
+use std::time::Duration;
+
+#[test]
+fn fresh_state() {
+    let clock = FakeRelativeClock::default();
+    let lim = RateLimiter::direct_with_clock(Quota::per_second(nonzero!(5u32)), &clock);
+    let headers = RateLimitHeaders::from_snapshot(&lim.state_snapshot());
+    assert_eq!(headers.limit(), 5);
+    assert_eq!(headers.remaining(), 5);
+    assert_eq!(headers.reset(), Duration::from_secs(0));
+    assert_eq!(headers.window(), Duration::from_secs(1));
+    assert_eq!(headers.policy_value(), "5;w=1");
+    assert!(headers
+        .header_values()
+        .iter()
+        .all(|(name, _)| *name != RETRY_AFTER));
+}
+
+#[test]
+fn rounds_up_to_seconds() {
+    let clock = FakeRelativeClock::default();
+    let lim = RateLimiter::direct_with_clock(Quota::per_second(nonzero!(5u32)), &clock);
+    lim.check_n(nonzero!(5u32)).unwrap();
+    clock.advance(Duration::from_millis(100));
+
+    let headers = RateLimitHeaders::from_not_until(&lim.check().unwrap_err());
+    assert_eq!(headers.retry_after(), Some(Duration::from_millis(100)));
+    assert_eq!(headers.retry_after_value(), Some("1".to_string()));
+    assert_eq!(headers.remaining_value(), "0");
+    assert_eq!(headers.reset(), Duration::from_millis(900));
+    assert_eq!(headers.reset_value(), "1");
+}
+
+#[test]
+fn keyed_snapshot() {
+    let clock = FakeRelativeClock::default();
+    let lim = RateLimiter::hashmap_with_clock(Quota::per_minute(nonzero!(10u32)), &clock);
+    lim.check_key_n(&"alice", nonzero!(3u32)).unwrap();
+
+    let headers = RateLimitHeaders::from_snapshot(&lim.key_state_snapshot(&"alice"));
+    assert_eq!(headers.remaining(), 7);
+    assert_eq!(headers.reset(), Duration::from_secs(18));
+    assert_eq!(headers.policy_value(), "10;w=60");
+}
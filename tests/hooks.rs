@@ -0,0 +1,126 @@
+use governor::{clock::FakeRelativeClock, NegativeMultiDecision, Quota, RateLimiter};
+use nonzero_ext::nonzero;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+type Log = Arc<Mutex<Vec<(u32, u32, Option<Duration>)>>>;
+
+#[test]
+fn reports_keyed_decisions() {
+    let clock = FakeRelativeClock::default();
+    let log: Log = Default::default();
+    let lim = RateLimiter::hashmap_with_clock(Quota::per_second(nonzero!(2u32)), &clock)
+        .on_allowed({
+            let log = log.clone();
+            move |key, n| log.lock().unwrap().push((*key, n.get(), None))
+        })
+        .on_denied({
+            let log = log.clone();
+            move |key, n, negative| {
+                log.lock()
+                    .unwrap()
+                    .push((*key, n.get(), Some(negative.retry_after())))
+            }
+        });
+
+    lim.check_key(&1).unwrap();
+    lim.check_key_n(&2, nonzero!(2u32)).unwrap();
+    assert!(lim.check_key(&2).is_err());
+    assert!(matches!(
+        lim.check_key_n(&1, nonzero!(3u32)),
+        Err(NegativeMultiDecision::InsufficientCapacity(insufficient))
+            if insufficient.max_burst() == 2
+    ));
+
+    let log = log.lock().unwrap();
+    assert_eq!(
+        log[..3],
+        [
+            (1, 1, None),
+            (2, 2, None),
+            (2, 1, Some(Duration::from_millis(500)))
+        ]
+    );
+    let (key, n, retry_after) = log[3];
+    assert_eq!((key, n), (1, 3));
+    assert!(retry_after.unwrap() > Duration::from_secs(100 * 365 * 24 * 60 * 60));
+}
+
+#[test]
+fn reports_direct_decisions() {
+    let clock = FakeRelativeClock::default();
+    let allowed = Arc::new(Mutex::new(0));
+    let denied = Arc::new(Mutex::new(0));
+    let lim = RateLimiter::direct_with_clock(Quota::per_second(nonzero!(1u32)), &clock)
+        .on_allowed({
+            let allowed = allowed.clone();
+            move |_, _| *allowed.lock().unwrap() += 1
+        })
+        .on_allowed({
+            let allowed = allowed.clone();
+            move |_, _| *allowed.lock().unwrap() += 10
+        })
+        .on_denied({
+            let denied = denied.clone();
+            move |_, _, _| *denied.lock().unwrap() += 1
+        });
+    lim.check().unwrap();
+    assert!(lim.check().is_err());
+    assert!(lim.check_n(nonzero!(1u32)).is_err());
+    clock.advance(Duration::from_secs(1));
+    lim.check().unwrap();
+
+    assert_eq!(*allowed.lock().unwrap(), 22);
+    assert_eq!(*denied.lock().unwrap(), 2);
+}
+
+#[test]
+fn reports_decision_context() {
+    let clock = FakeRelativeClock::default();
+    clock.advance(Duration::from_secs(10));
+    let quota = Quota::per_second(nonzero!(2u32));
+    let decisions = Arc::new(Mutex::new(Vec::new()));
+    let lim = RateLimiter::hashmap_with_clock(quota, &clock).on_decision({
+        let decisions = decisions.clone();
+        move |key: &u32, decision| decisions.lock().unwrap().push((*key, *decision))
+    });
+
+    clock.advance(Duration::from_millis(100));
+    lim.check_key_n(&1, nonzero!(2u32)).unwrap();
+    assert!(lim.check_key(&1).is_err());
+    assert!(lim.check_key_n(&1, nonzero!(3u32)).is_err());
+
+    let decisions = decisions.lock().unwrap();
+    assert_eq!(decisions.len(), 3);
+    for (key, decision) in decisions.iter() {
+        assert_eq!(*key, 1);
+        assert_eq!(decision.quota(), quota);
+        assert_eq!(decision.start(), Duration::from_secs(10).into());
+        assert_eq!(
+            decision.time_of_decision(),
+            Duration::from_millis(10_100).into()
+        );
+    }
+
+    let (_, allowed) = decisions[0];
+    assert!(allowed.is_allowed());
+    assert_eq!(allowed.cells().get(), 2);
+    let snapshot = allowed.state_snapshot();
+    assert_eq!(snapshot.remaining_burst_capacity(), 0);
+    // The theoretical arrival time, as an instant of the rate limiter's clock:
+    let tat = snapshot.theoretical_arrival_time().unwrap();
+    assert_eq!(allowed.start() + tat, Duration::from_millis(11_600).into());
+
+    let (_, denied) = decisions[1];
+    assert!(!denied.is_allowed());
+    assert_eq!(
+        denied.negative().unwrap().retry_after(),
+        Duration::from_millis(500)
+    );
+    let (_, insufficient) = decisions[2];
+    assert_eq!(insufficient.cells().get(), 3);
+    assert!(
+        insufficient.negative().unwrap().retry_after()
+            > Duration::from_secs(100 * 365 * 24 * 60 * 60)
+    );
+}
@@ -0,0 +1,107 @@
+#![cfg(feature = "std")]
+
+use governor::{
+    clock::FakeRelativeClock,
+    nanos::Nanos,
+    state::{
+        keyed::HashMapStateStore, InMemoryState, InstrumentedStateStore, NotKeyed, StateStore,
+    },
+    Quota, RateLimiter,
+};
+use nonzero_ext::nonzero;
+use std::sync::Arc;
+use std::thread;
+
+/// A direct state store that pretends to lose the race for the state `races` times on every
+/// decision, running the decision closure again each time.
+#[derive(Default)]
+struct RacyState {
+    races: usize,
+    state: InMemoryState,
+}
+
+impl StateStore for RacyState {
+    type Key = NotKeyed;
+
+    fn measure_and_replace<T, F, E>(&self, key: &Self::Key, f: F) -> Result<T, E>
+    where
+        F: Fn(Option<Nanos>) -> Result<(T, Nanos), E>,
+    {
+        for _ in 0..self.races {
+            let _ = f(self.state.tat());
+        }
+        self.state.measure_and_replace(key, f)
+    }
+}
+
+#[test]
+fn counts_retries() {
+    let clock = FakeRelativeClock::default();
+    let store = InstrumentedStateStore::new(RacyState {
+        races: 2,
+        ..Default::default()
+    });
+    let lim = RateLimiter::new(Quota::per_second(nonzero!(5u32)), store, &clock);
+    for _ in 0..4 {
+        lim.check().unwrap();
+    }
+
+    let stats = lim.contention_stats();
+    assert_eq!(stats.decisions(), 4);
+    assert_eq!(stats.attempts(), 12);
+    assert_eq!(stats.retries(), 8);
+    assert_eq!(stats.contended(), 4);
+    assert_eq!(stats.max_attempts(), 3);
+    assert!((stats.contention_rate() - 1.0).abs() < f64::EPSILON);
+}
+
+#[test]
+fn uncontended_decisions() {
+    let clock = FakeRelativeClock::default();
+    let store = InstrumentedStateStore::new(InMemoryState::default());
+    let lim = RateLimiter::new(Quota::per_second(nonzero!(1u32)), store, &clock);
+    assert!(lim.check().is_ok());
+    assert!(lim.check().is_err());
+
+    let stats = lim.contention_stats();
+    assert_eq!(stats.decisions(), 2);
+    assert_eq!(stats.attempts(), 2);
+    assert_eq!(stats.retries(), 0);
+    assert_eq!(stats.max_attempts(), 1);
+    assert!(stats.contention_rate().abs() < f64::EPSILON);
+
+    let store = lim.into_state_store();
+    store.reset_contention_stats();
+    assert_eq!(store.contention_stats(), Default::default());
+}
+
+#[test]
+fn keyed_across_threads() {
+    let clock = FakeRelativeClock::default();
+    let store = InstrumentedStateStore::new(HashMapStateStore::<u32>::default());
+    let lim = Arc::new(RateLimiter::new(
+        Quota::per_second(nonzero!(1000u32)),
+        store,
+        &clock,
+    ));
+    let threads: Vec<_> = (0..4)
+        .map(|_| {
+            let lim = lim.clone();
+            thread::spawn(move || {
+                for _ in 0..100 {
+                    let _ = lim.check_key(&1);
+                }
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    let stats = lim.contention_stats();
+    assert_eq!(stats.decisions(), 400);
+    assert!(stats.attempts() >= 400);
+    assert_eq!(stats.retries(), stats.attempts() - 400);
+    assert!(stats.contended() <= stats.retries());
+    assert_eq!(lim.len(), 1);
+}
@@ -0,0 +1,131 @@
+#![cfg(feature = "std")]
+
+use futures::executor::block_on;
+use futures::io::{AsyncReadExt, AsyncWriteExt, Cursor};
+use governor::{
+    clock::{Clock, FakeRelativeClock},
+    AsyncRateLimitedReader, AsyncRateLimitedWriter, Quota, RateLimitedReader, RateLimitedWriter,
+    RateLimiter,
+};
+use nonzero_ext::*;
+use std::io::{ErrorKind, Read, Write};
+use std::time::{Duration, Instant};
+
+#[test]
+fn reader() {
+    let i = Instant::now();
+    let lim = RateLimiter::direct(Quota::per_second(nonzero!(100u32)));
+    let data = [1u8; 150];
+    let mut reader = RateLimitedReader::new(&data[..], &lim);
+
+    let mut buf = [0u8; 200];
+    // Reads are capped at the burst size:
+    assert_eq!(reader.read(&mut buf).unwrap(), 100);
+    let mut rest = Vec::new();
+    reader.read_to_end(&mut rest).unwrap();
+    assert_eq!(rest.len(), 50);
+    assert!(
+        i.elapsed() >= Duration::from_millis(400),
+        "elapsed: {:?}",
+        i.elapsed()
+    );
+}
+
+#[test]
+fn writer() {
+    let i = Instant::now();
+    let lim = RateLimiter::direct(Quota::per_second(nonzero!(100u32)));
+    let mut writer = RateLimitedWriter::new(Vec::new(), &lim);
+
+    // Writes are capped at the burst size:
+    assert_eq!(writer.write(&[1u8; 150]).unwrap(), 100);
+    assert!(i.elapsed() <= Duration::from_millis(100));
+    writer.write_all(&[2u8; 50]).unwrap();
+    assert!(
+        i.elapsed() >= Duration::from_millis(400),
+        "elapsed: {:?}",
+        i.elapsed()
+    );
+    assert_eq!(writer.into_inner().len(), 150);
+}
+
+#[test]
+fn writer_owned() {
+    let lim = RateLimiter::direct(Quota::per_second(nonzero!(100u32)));
+    let mut writer = RateLimitedWriter::new_owned(Vec::new(), lim);
+    writer.write_all(b"hello").unwrap();
+    assert_eq!(writer.get_ref(), b"hello");
+}
+
+#[test]
+fn async_reader() {
+    let clock = FakeRelativeClock::default();
+    let lim = RateLimiter::direct_with_clock(Quota::per_second(nonzero!(10u32)), &clock);
+    let mut reader = AsyncRateLimitedReader::new(Cursor::new(vec![1u8; 30]), &lim);
+
+    let mut buf = Vec::new();
+    clock.block_on_auto_advance(async {
+        reader.read_to_end(&mut buf).await.unwrap();
+    });
+    assert_eq!(buf.len(), 30);
+    assert_eq!(Duration::from(clock.now()), Duration::from_secs(2));
+}
+
+#[test]
+fn async_writer() {
+    let clock = FakeRelativeClock::default();
+    let lim = RateLimiter::direct_with_clock(Quota::per_second(nonzero!(10u32)), &clock);
+    let mut writer = AsyncRateLimitedWriter::new_owned(Cursor::new(Vec::new()), lim);
+
+    clock.block_on_auto_advance(async {
+        writer.write_all(&[1u8; 30]).await.unwrap();
+        writer.flush().await.unwrap();
+    });
+    assert_eq!(writer.get_ref().get_ref().len(), 30);
+    assert_eq!(Duration::from(clock.now()), Duration::from_secs(2));
+}
+
+#[test]
+fn reader_none() {
+    let lim = RateLimiter::direct(Quota::none());
+    let data = [1u8; 150];
+    let mut reader = RateLimitedReader::new(&data[..], &lim);
+
+    let mut buf = [0u8; 200];
+    let err = reader.read(&mut buf).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Other);
+    // Nothing was read:
+    assert_eq!(reader.get_ref().len(), 150);
+}
+
+#[test]
+fn writer_none() {
+    let lim = RateLimiter::direct(Quota::none());
+    let mut writer = RateLimitedWriter::new(Vec::new(), &lim);
+
+    let err = writer.write(&[1u8; 150]).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Other);
+    assert!(writer.into_inner().is_empty());
+}
+
+#[test]
+fn async_reader_none() {
+    let lim = RateLimiter::direct(Quota::none());
+    let mut reader = AsyncRateLimitedReader::new(Cursor::new(vec![1u8; 30]), &lim);
+
+    let mut buf = Vec::new();
+    let err = block_on(reader.read_to_end(&mut buf)).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Other);
+    assert!(buf.is_empty());
+    assert_eq!(reader.get_ref().position(), 0);
+}
+
+#[test]
+fn async_writer_none() {
+    let lim = RateLimiter::direct(Quota::none());
+    let mut writer = AsyncRateLimitedWriter::new(Cursor::new(Vec::new()), &lim);
+
+    let err = block_on(writer.write_all(&[1u8; 30])).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Other);
+    assert!(writer.get_ref().get_ref().is_empty());
+}
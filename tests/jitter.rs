@@ -0,0 +1,68 @@
+#![cfg(all(feature = "std", feature = "jitter"))]
+
+use futures::future::join_all;
+use governor::{
+    clock::{Clock, FakeRelativeClock},
+    Jitter, Quota, RateLimiter,
+};
+use more_asserts::*;
+use nonzero_ext::*;
+use rand::distributions::Uniform;
+use std::time::Duration;
+
+const REFERENCE: Duration = Duration::from_secs(10);
+
+#[test]
+fn first_attempt_ranges() {
+    let base = Duration::from_millis(100);
+    let cap = Duration::from_secs(1);
+    for _ in 0..100 {
+        assert_eq!(Jitter::exponential(base, cap) + REFERENCE, REFERENCE + base);
+
+        let full = Jitter::full(base, cap) + REFERENCE;
+        assert_ge!(full, REFERENCE);
+        assert_le!(full, REFERENCE + base);
+
+        let equal = Jitter::equal(base, cap) + REFERENCE;
+        assert_ge!(equal, REFERENCE + base / 2);
+        assert_le!(equal, REFERENCE + base);
+
+        let decorrelated = Jitter::decorrelated(base, cap) + REFERENCE;
+        assert_ge!(decorrelated, REFERENCE + base);
+        assert_le!(decorrelated, REFERENCE + base * 3);
+    }
+}
+
+#[test]
+fn caps_backoff() {
+    let jitter = Jitter::exponential(Duration::from_secs(2), Duration::from_secs(1));
+    assert_eq!(jitter + REFERENCE, REFERENCE + Duration::from_secs(1));
+}
+
+#[test]
+fn custom_distribution() {
+    let dist = Uniform::new(Duration::from_millis(10), Duration::from_millis(20));
+    let jitter = Jitter::from_distribution(dist);
+    assert_eq!(jitter, jitter.clone());
+    assert_ne!(jitter, Jitter::from_distribution(dist));
+    for _ in 0..100 {
+        let result = jitter.clone() + REFERENCE;
+        assert_ge!(result, REFERENCE + Duration::from_millis(10));
+        assert_lt!(result, REFERENCE + Duration::from_millis(20));
+    }
+}
+
+#[test]
+fn exponential_backoff_between_retries() {
+    let clock = FakeRelativeClock::default();
+    let lim = RateLimiter::direct_with_clock(Quota::per_second(nonzero!(1u32)), &clock);
+    lim.check().unwrap();
+
+    let jitter = Jitter::exponential(Duration::from_secs(1), Duration::from_secs(60));
+    clock.block_on_auto_advance(join_all(
+        (0..3).map(|_| lim.until_ready_with_jitter(jitter.clone())),
+    ));
+    // All three tasks wake up after 1s + 1s, when two of them get through. The remaining task
+    // gets rate-limited again, and its second attempt waits 1s + 2s:
+    assert_eq!(Duration::from(clock.now()), Duration::from_secs(5));
+}
@@ -0,0 +1,178 @@
+#![cfg(feature = "std")]
+
+use governor::{
+    clock::FakeRelativeClock,
+    nanos::Nanos,
+    state::keyed::{CasBackend, CasStateStore, FailurePolicy},
+    Quota, RateLimiter,
+};
+use nonzero_ext::nonzero;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[derive(Debug, PartialEq)]
+struct Unavailable;
+
+/// An in-memory backend that can simulate concurrent writers and outages.
+#[derive(Default)]
+struct Backend {
+    map: Mutex<HashMap<u32, (Vec<u8>, u64)>>,
+    conflicts: AtomicUsize,
+    cas_calls: AtomicUsize,
+    down: AtomicBool,
+}
+
+impl CasBackend for Backend {
+    type Key = u32;
+    type Version = u64;
+    type Error = Unavailable;
+
+    fn get(&self, key: &u32) -> Result<Option<(Vec<u8>, u64)>, Unavailable> {
+        if self.down.load(Ordering::SeqCst) {
+            return Err(Unavailable);
+        }
+        Ok(self.map.lock().unwrap().get(key).cloned())
+    }
+
+    fn compare_and_swap(
+        &self,
+        key: &u32,
+        expected: Option<&u64>,
+        blob: Vec<u8>,
+    ) -> Result<bool, Unavailable> {
+        self.cas_calls.fetch_add(1, Ordering::SeqCst);
+        let mut map = self.map.lock().unwrap();
+        if self.conflicts.load(Ordering::SeqCst) > 0 {
+            // Another writer got there first:
+            self.conflicts.fetch_sub(1, Ordering::SeqCst);
+            let entry = map.entry(*key).or_insert_with(|| (vec![], 0));
+            entry.1 += 1;
+            return Ok(false);
+        }
+        let version = map.get(key).map(|(_, version)| *version);
+        if version.as_ref() != expected {
+            return Ok(false);
+        }
+        map.insert(*key, (blob, version.unwrap_or(0) + 1));
+        Ok(true)
+    }
+}
+
+#[test]
+fn shares_state_across_limiters() {
+    let backend = Arc::new(Backend::default());
+    let clock = FakeRelativeClock::default();
+    let quota = Quota::per_second(nonzero!(2u32));
+    let lim1 = RateLimiter::cas_with_clock(quota, backend.clone(), &clock, Nanos::from(0));
+    clock.advance(Duration::from_millis(300));
+    // A limiter created later still agrees on the timeline:
+    let lim2 = RateLimiter::cas_with_clock(quota, backend.clone(), &clock, Nanos::from(0));
+
+    assert!(lim1.check_key(&1).is_ok());
+    assert!(lim2.check_key(&1).is_ok());
+    assert!(lim1.check_key(&1).is_err());
+    assert!(lim2.check_key(&1).is_err());
+    assert!(lim2.check_key(&2).is_ok());
+    assert_eq!(lim1.key_state_snapshot(&1).remaining_burst_capacity(), 0);
+
+    clock.advance(Duration::from_millis(500));
+    assert!(lim2.check_key(&1).is_ok());
+    assert!(lim1.check_key(&1).is_err());
+}
+
+#[test]
+fn retries_on_conflicts() {
+    let backend = Arc::new(Backend::default());
+    let clock = FakeRelativeClock::default();
+    let lim = RateLimiter::cas_with_clock(
+        Quota::per_second(nonzero!(1u32)),
+        backend.clone(),
+        &clock,
+        Nanos::from(0),
+    );
+    backend.conflicts.store(2, Ordering::SeqCst);
+    assert!(lim.check_key(&1).is_ok());
+    assert_eq!(backend.cas_calls.load(Ordering::SeqCst), 3);
+    // The conflicting writes left an undecodable blob, which counts as a fresh state; the
+    // successful write replaced it:
+    assert!(lim.check_key(&1).is_err());
+}
+
+#[test]
+fn failure_policies() {
+    let backend = Arc::new(Backend::default());
+    let errors = Arc::new(AtomicUsize::new(0));
+    let clock = FakeRelativeClock::default();
+    let quota = Quota::per_second(nonzero!(1u32));
+
+    let counter = errors.clone();
+    let closed = RateLimiter::cas_with_clock(
+        quota,
+        CasStateStore::new(backend.clone()).on_error(move |error| {
+            assert_eq!(error, &Unavailable);
+            counter.fetch_add(1, Ordering::SeqCst);
+        }),
+        &clock,
+        Nanos::from(0),
+    );
+    let open = RateLimiter::cas_with_clock(
+        quota,
+        CasStateStore::new(backend.clone()).with_failure_policy(FailurePolicy::Allow),
+        &clock,
+        Nanos::from(0),
+    );
+
+    backend.down.store(true, Ordering::SeqCst);
+    assert!(closed.check_key(&1).is_err());
+    assert!(closed.check_key(&1).is_err());
+    assert_eq!(errors.load(Ordering::SeqCst), 2);
+    assert!(open.check_key(&1).is_ok());
+    assert!(open.check_key(&1).is_ok());
+
+    backend.down.store(false, Ordering::SeqCst);
+    // Nothing was counted during the outage:
+    assert!(closed.check_key(&1).is_ok());
+    assert!(open.check_key(&1).is_err());
+}
+
+#[test]
+fn system_clock() {
+    let backend = Arc::new(Backend::default());
+    let quota = Quota::per_hour(nonzero!(1u32));
+    let lim1 = RateLimiter::cas(quota, backend.clone());
+    let lim2 = RateLimiter::cas(quota, backend);
+    assert!(lim1.check_key(&1).is_ok());
+    assert!(lim2.check_key(&1).is_err());
+}
+
+#[test]
+fn resets_keys_to_fresh_states() {
+    let backend = Arc::new(Backend::default());
+    let clock = FakeRelativeClock::default();
+    let lim = RateLimiter::cas_with_clock(
+        Quota::per_second(nonzero!(1u32)),
+        backend.clone(),
+        &clock,
+        Nanos::from(0),
+    );
+    assert!(lim.check_key_reporting_new(&1).newly_created);
+    assert!(lim.check_key(&1).is_err());
+
+    lim.reset_key(&1);
+    assert_eq!(backend.map.lock().unwrap()[&1].0, Vec::<u8>::new());
+    let check = lim.check_key_reporting_new(&1);
+    assert!(check.newly_created);
+    assert_eq!(Ok(()), check.decision);
+
+    // Fresh states of quotas that start empty have no capacity:
+    let empty = RateLimiter::cas_with_clock(
+        Quota::per_second(nonzero!(1u32)).starting_empty(),
+        backend,
+        &clock,
+        Nanos::from(0),
+    );
+    empty.reset_key(&1);
+    assert!(empty.check_key(&1).is_err());
+}
@@ -0,0 +1,163 @@
+use governor::{
+    clock::FakeRelativeClock,
+    nanos::Nanos,
+    state::{keyed::ShrinkableKeyedStateStore, InMemoryState, StateStore},
+    Quota, RateLimiter,
+};
+use nonzero_ext::nonzero;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// A keyed state store that keeps its states in a vector, like an external crate might.
+#[derive(Default)]
+struct VecStore(Mutex<Vec<(u32, InMemoryState)>>);
+
+impl StateStore for VecStore {
+    type Key = u32;
+
+    fn measure_and_replace<T, F, E>(&self, key: &u32, f: F) -> Result<T, E>
+    where
+        F: Fn(Option<Nanos>) -> Result<(T, Nanos), E>,
+    {
+        let mut states = self.0.lock().unwrap();
+        if let Some((_, state)) = states.iter().find(|(k, _)| k == key) {
+            return state.measure_and_replace_one(f);
+        }
+        states.push((*key, InMemoryState::default()));
+        states.last().unwrap().1.measure_and_replace_one(f)
+    }
+
+    fn peek(&self, key: &u32) -> Option<Nanos> {
+        let states = self.0.lock().unwrap();
+        states
+            .iter()
+            .find(|(k, _)| k == key)
+            .and_then(|(_, state)| state.tat())
+    }
+}
+
+impl ShrinkableKeyedStateStore<u32> for VecStore {
+    fn retain_recent(&self, drop_below: Nanos) {
+        let mut states = self.0.lock().unwrap();
+        states.retain(|(_, state)| !state.is_older_than(drop_below));
+    }
+
+    fn len(&self) -> usize {
+        self.0.lock().unwrap().len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.0.lock().unwrap().is_empty()
+    }
+
+    fn snapshot(&self) -> Vec<(u32, Option<Nanos>)> {
+        let states = self.0.lock().unwrap();
+        states.iter().map(|(k, state)| (*k, state.tat())).collect()
+    }
+}
+
+#[test]
+fn custom_store_rate_limits() {
+    let clock = FakeRelativeClock::default();
+    let lim = RateLimiter::new(
+        Quota::per_second(nonzero!(2u32)),
+        VecStore::default(),
+        &clock,
+    );
+    assert!(lim.check_key(&1).is_ok());
+    assert!(lim.check_key(&1).is_ok());
+    assert!(lim.check_key(&1).is_err());
+    assert!(lim.check_key(&2).is_ok());
+
+    let mut capacities = lim.remaining_capacities();
+    capacities.sort_unstable();
+    assert_eq!(capacities, vec![(1, 0), (2, 1)]);
+    // Peeking doesn't add keys:
+    assert_eq!(lim.key_state_snapshot(&3).remaining_burst_capacity(), 2);
+    assert_eq!(lim.len(), 2);
+}
+
+#[test]
+fn custom_store_housekeeping() {
+    let clock = FakeRelativeClock::default();
+    let lim = RateLimiter::new(
+        Quota::per_second(nonzero!(1u32)),
+        VecStore::default(),
+        &clock,
+    );
+    lim.check_key(&1).unwrap();
+    clock.advance(Duration::from_secs(2));
+    lim.check_key(&2).unwrap();
+
+    lim.retain_recent();
+    assert_eq!(lim.len(), 1);
+    lim.reset_all();
+    assert!(lim.is_empty());
+}
+
+#[test]
+fn in_memory_state_building_blocks() {
+    let state = InMemoryState::default();
+    assert_eq!(state.tat(), None);
+    assert!(state.is_older_than(Nanos::from(0)));
+
+    let res: Result<(), ()> = state.measure_and_replace_one(|tat| {
+        assert_eq!(tat, None);
+        Ok(((), Nanos::from(Duration::from_secs(1))))
+    });
+    assert_eq!(res, Ok(()));
+    assert_eq!(state.tat(), Some(Nanos::from(Duration::from_secs(1))));
+    assert!(!state.is_older_than(Nanos::from(Duration::from_millis(999))));
+    assert!(state.is_older_than(Nanos::from(Duration::from_secs(1))));
+
+    // Negative decisions leave the state alone:
+    let res: Result<(), u32> = state.measure_and_replace_one(|_| Err(5));
+    assert_eq!(res, Err(5));
+    assert_eq!(state.tat(), Some(Nanos::from(Duration::from_secs(1))));
+}
+
+/// A state store that implements only the required housekeeping methods.
+#[derive(Default)]
+struct UnlistedStore(VecStore);
+
+impl StateStore for UnlistedStore {
+    type Key = u32;
+
+    fn measure_and_replace<T, F, E>(&self, key: &u32, f: F) -> Result<T, E>
+    where
+        F: Fn(Option<Nanos>) -> Result<(T, Nanos), E>,
+    {
+        self.0.measure_and_replace(key, f)
+    }
+
+    fn peek(&self, key: &u32) -> Option<Nanos> {
+        self.0.peek(key)
+    }
+}
+
+impl ShrinkableKeyedStateStore<u32> for UnlistedStore {
+    fn retain_recent(&self, drop_below: Nanos) {
+        self.0.retain_recent(drop_below)
+    }
+
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+#[test]
+fn snapshot_is_optional() {
+    let clock = FakeRelativeClock::default();
+    let lim = RateLimiter::new(
+        Quota::per_second(nonzero!(1u32)),
+        UnlistedStore::default(),
+        &clock,
+    );
+    lim.check_key(&1).unwrap();
+    assert_eq!(lim.len(), 1);
+    assert_eq!(lim.remaining_capacities(), vec![]);
+}
@@ -126,7 +126,7 @@ fn actual_threadsafety() {
 fn dashmap_length() {
     let lim = RateLimiter::dashmap(Quota::per_second(nonzero!(1u32)));
     assert_eq!(lim.len(), 0);
-    assert!(lim.is_empty(), true);
+    assert!(lim.is_empty());
 
     lim.check_key(&"foo").unwrap();
     assert_eq!(lim.len(), 1);
@@ -140,3 +140,61 @@ fn dashmap_length() {
     assert_eq!(lim.len(), 3);
     assert!(!lim.is_empty());
 }
+
+#[test]
+fn dashmap_remaining_capacities() {
+    let clock = FakeRelativeClock::default();
+    let lim = RateLimiter::dashmap_with_clock(Quota::per_second(nonzero!(3u32)), &clock);
+    assert_eq!(lim.remaining_capacities(), vec![]);
+
+    lim.check_key(&"foo").unwrap();
+    lim.check_key_n(&"bar", nonzero!(3u32)).unwrap();
+    let mut capacities = lim.remaining_capacities();
+    capacities.sort();
+    assert_eq!(capacities, vec![("bar", 0), ("foo", 2)]);
+
+    // Replenishing one cell:
+    clock.advance(Duration::from_millis(334));
+    let mut capacities = lim.remaining_capacities();
+    capacities.sort();
+    assert_eq!(capacities, vec![("bar", 1), ("foo", 3)]);
+
+    // Eventually, all keys are back at full capacity:
+    clock.advance(Duration::from_secs(1));
+    let mut capacities = lim.remaining_capacities();
+    capacities.sort();
+    assert_eq!(capacities, vec![("bar", 3), ("foo", 3)]);
+}
+
+#[test]
+fn dashmap_reset() {
+    let clock = FakeRelativeClock::default();
+    let lim = RateLimiter::dashmap_with_clock(Quota::per_second(nonzero!(2u32)), &clock);
+    for key in KEYS {
+        lim.check_key_n(key, nonzero!(2u32)).unwrap();
+        assert_ne!(Ok(()), lim.check_key(key));
+    }
+
+    lim.reset_key(&1);
+    assert_eq!(Ok(()), lim.check_key_n(&1, nonzero!(2u32)));
+    assert_ne!(Ok(()), lim.check_key(&2));
+
+    lim.reset_all();
+    assert!(lim.is_empty());
+    for key in KEYS {
+        assert_eq!(Ok(()), lim.check_key_n(key, nonzero!(2u32)));
+    }
+}
+
+#[test]
+fn key_state_snapshot_does_not_insert() {
+    let clock = FakeRelativeClock::default();
+    let lim = RateLimiter::dashmap_with_clock(Quota::per_second(nonzero!(2u32)), &clock);
+    let snapshot = lim.key_state_snapshot(&"foo");
+    assert_eq!(snapshot.theoretical_arrival_time(), None);
+    assert!(lim.is_empty());
+
+    lim.check_key(&"foo").unwrap();
+    let snapshot = lim.key_state_snapshot(&"foo");
+    assert_eq!(snapshot.remaining_burst_capacity(), 1);
+}
@@ -0,0 +1,114 @@
+use governor::state::keyed::{FixedCapacityStateStore, OverflowPolicy};
+use governor::{
+    clock::{Clock, FakeRelativeClock},
+    Quota, RateLimiter,
+};
+use nonzero_ext::nonzero;
+use std::time::Duration;
+
+fn limiter<const N: usize>(
+    quota: Quota,
+    overflow: OverflowPolicy,
+    clock: &FakeRelativeClock,
+) -> RateLimiter<u32, FixedCapacityStateStore<u32, N>, FakeRelativeClock> {
+    RateLimiter::fixed_capacity_with_clock(quota, overflow, clock)
+}
+
+#[test]
+fn rejects_too_many() {
+    let clock = FakeRelativeClock::default();
+    let lb = limiter::<2>(
+        Quota::per_second(nonzero!(2u32)),
+        OverflowPolicy::Deny,
+        &clock,
+    );
+    let ms = Duration::from_millis(1);
+
+    for key in &[1u32, 2u32] {
+        assert_eq!(Ok(()), lb.check_key(key), "Now: {:?}", clock.now());
+        clock.advance(ms);
+        assert_eq!(Ok(()), lb.check_key(key), "Now: {:?}", clock.now());
+        clock.advance(ms);
+        assert_ne!(Ok(()), lb.check_key(key), "Now: {:?}", clock.now());
+    }
+    assert_eq!(lb.len(), 2);
+}
+
+#[test]
+fn denies_keys_on_overflow() {
+    let clock = FakeRelativeClock::default();
+    let lb = limiter::<2>(
+        Quota::per_second(nonzero!(1u32)),
+        OverflowPolicy::Deny,
+        &clock,
+    );
+
+    assert_eq!(Ok(()), lb.check_key(&1));
+    assert_eq!(Ok(()), lb.check_key(&2));
+    let outcome = lb.check_key(&3).unwrap_err();
+    assert!(outcome.wait_time_from(clock.now()) > Duration::from_secs(60 * 60 * 24 * 365));
+    assert_eq!(lb.len(), 2);
+
+    // Keys that have a slot are unaffected:
+    clock.advance(Duration::from_secs(1));
+    assert_eq!(Ok(()), lb.check_key(&1));
+
+    // Once a key's state is stale, it can be removed to make room:
+    clock.advance(Duration::from_secs(1));
+    lb.retain_recent();
+    assert_eq!(lb.len(), 1);
+    assert_eq!(Ok(()), lb.check_key(&3));
+    assert_ne!(Ok(()), lb.check_key(&2));
+}
+
+#[test]
+fn denies_keys_on_overflow_with_huge_bursts() {
+    let clock = FakeRelativeClock::default();
+    let lb = limiter::<1>(
+        Quota::per_hour(nonzero!(1u32)).allow_burst(nonzero!(3_000_000u32)),
+        OverflowPolicy::Deny,
+        &clock,
+    );
+
+    assert_eq!(Ok(()), lb.check_key(&1));
+    assert!(lb.check_key(&2).is_err());
+    assert!(lb.check_key_n(&2, nonzero!(2u32)).is_err());
+    assert_eq!(lb.len(), 1);
+}
+
+#[test]
+fn evicts_most_replenished_key() {
+    let clock = FakeRelativeClock::default();
+    let lb = limiter::<2>(
+        Quota::per_second(nonzero!(2u32)),
+        OverflowPolicy::EvictMostReplenished,
+        &clock,
+    );
+
+    assert_eq!(Ok(()), lb.check_key(&1));
+    assert_eq!(Ok(()), lb.check_key(&2));
+    assert_eq!(Ok(()), lb.check_key(&2));
+
+    // Key 1 has more capacity left than key 2, so it makes room for key 3:
+    assert_eq!(Ok(()), lb.check_key(&3));
+    let mut keys: Vec<u32> = lb
+        .remaining_capacities()
+        .into_iter()
+        .map(|(k, _)| k)
+        .collect();
+    keys.sort_unstable();
+    assert_eq!(keys, vec![2, 3]);
+    assert_ne!(Ok(()), lb.check_key(&2));
+}
+
+#[test]
+fn zero_capacity_denies_everything() {
+    let clock = FakeRelativeClock::default();
+    let lb = limiter::<0>(
+        Quota::per_second(nonzero!(1u32)),
+        OverflowPolicy::EvictMostReplenished,
+        &clock,
+    );
+    assert!(lb.check_key(&1).is_err());
+    assert!(lb.is_empty());
+}
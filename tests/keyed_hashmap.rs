@@ -120,7 +120,7 @@ fn actual_threadsafety() {
 fn hashmap_length() {
     let lim = RateLimiter::hashmap(Quota::per_second(nonzero!(1u32)));
     assert_eq!(lim.len(), 0);
-    assert!(lim.is_empty(), true);
+    assert!(lim.is_empty());
 
     lim.check_key(&"foo").unwrap();
     assert_eq!(lim.len(), 1);
@@ -134,3 +134,122 @@ fn hashmap_length() {
     assert_eq!(lim.len(), 3);
     assert!(!lim.is_empty());
 }
+
+#[test]
+fn hashmap_remaining_capacities() {
+    let clock = FakeRelativeClock::default();
+    let lim = RateLimiter::hashmap_with_clock(Quota::per_second(nonzero!(3u32)), &clock);
+    assert_eq!(lim.remaining_capacities(), vec![]);
+
+    lim.check_key(&"foo").unwrap();
+    lim.check_key_n(&"bar", nonzero!(3u32)).unwrap();
+    let mut capacities = lim.remaining_capacities();
+    capacities.sort();
+    assert_eq!(capacities, vec![("bar", 0), ("foo", 2)]);
+
+    // Replenishing one cell:
+    clock.advance(Duration::from_millis(334));
+    let mut capacities = lim.remaining_capacities();
+    capacities.sort();
+    assert_eq!(capacities, vec![("bar", 1), ("foo", 3)]);
+
+    // Eventually, all keys are back at full capacity:
+    clock.advance(Duration::from_secs(1));
+    let mut capacities = lim.remaining_capacities();
+    capacities.sort();
+    assert_eq!(capacities, vec![("bar", 3), ("foo", 3)]);
+}
+
+#[test]
+fn hashmap_reset() {
+    let clock = FakeRelativeClock::default();
+    let lim = RateLimiter::hashmap_with_clock(Quota::per_second(nonzero!(2u32)), &clock);
+    for key in KEYS {
+        lim.check_key_n(key, nonzero!(2u32)).unwrap();
+        assert_ne!(Ok(()), lim.check_key(key));
+    }
+
+    lim.reset_key(&1);
+    assert_eq!(Ok(()), lim.check_key_n(&1, nonzero!(2u32)));
+    assert_ne!(Ok(()), lim.check_key(&2));
+
+    lim.reset_all();
+    assert!(lim.is_empty());
+    for key in KEYS {
+        assert_eq!(Ok(()), lim.check_key_n(key, nonzero!(2u32)));
+    }
+}
+
+#[test]
+fn starting_empty_keys() {
+    let clock = FakeRelativeClock::default();
+    let quota = Quota::per_second(nonzero!(2u32)).starting_empty();
+    let lim = RateLimiter::hashmap_with_clock(quota, &clock);
+    for key in KEYS {
+        assert_ne!(Ok(()), lim.check_key(key));
+    }
+    assert_eq!(lim.remaining_capacities().len(), KEYS.len());
+    assert!(lim.remaining_capacities().iter().all(|(_, c)| *c == 0));
+
+    clock.advance(Duration::from_millis(500));
+    for key in KEYS {
+        assert_eq!(Ok(()), lim.check_key(key));
+        assert_ne!(Ok(()), lim.check_key(key));
+    }
+}
+
+#[test]
+fn key_state_snapshot_does_not_insert() {
+    let clock = FakeRelativeClock::default();
+    let lim = RateLimiter::hashmap_with_clock(Quota::per_second(nonzero!(2u32)), &clock);
+    let snapshot = lim.key_state_snapshot(&"foo");
+    assert_eq!(snapshot.theoretical_arrival_time(), None);
+    assert_eq!(snapshot.remaining_burst_capacity(), 2);
+    assert!(lim.is_empty());
+
+    lim.check_key(&"foo").unwrap();
+    let snapshot = lim.key_state_snapshot(&"foo");
+    assert_eq!(snapshot.remaining_burst_capacity(), 1);
+    assert!(snapshot.theoretical_arrival_time().is_some());
+}
+
+#[test]
+fn sentinel_quotas_create_no_keys() {
+    let clock = FakeRelativeClock::default();
+    let lb = RateLimiter::hashmap_with_clock(Quota::unlimited(), &clock);
+    for key in KEYS {
+        assert_eq!(Ok(()), lb.check_key(&key));
+        assert_eq!(Ok(()), lb.check_key_n(&key, nonzero!(1000u32)));
+    }
+    assert!(lb.is_empty());
+
+    let lb = RateLimiter::hashmap_with_clock(Quota::none(), &clock);
+    for key in KEYS {
+        assert_ne!(Ok(()), lb.check_key(&key));
+    }
+    assert!(lb.is_empty());
+}
+
+#[test]
+fn reports_new_keys() {
+    let clock = FakeRelativeClock::default();
+    let lim = RateLimiter::hashmap_with_clock(Quota::per_second(nonzero!(2u32)), &clock);
+    let check = lim.check_key_reporting_new(&1);
+    assert!(check.newly_created);
+    assert_eq!(Ok(()), check.decision);
+    assert!(lim.check_key_reporting_new(&2).newly_created);
+    let check = lim.check_key_reporting_new(&1);
+    assert!(!check.newly_created);
+    assert_eq!(Ok(()), check.decision);
+    let check = lim.check_key_reporting_new(&1);
+    assert!(!check.newly_created);
+    assert!(check.decision.is_err());
+
+    // Keys that were reset or removed count as new again:
+    lim.reset_key(&1);
+    assert!(lim.check_key_reporting_new(&1).newly_created);
+    clock.advance(Duration::from_secs(2));
+    lim.retain_recent();
+    assert!(lim.is_empty());
+    assert!(lim.check_key_reporting_new(&2).newly_created);
+}
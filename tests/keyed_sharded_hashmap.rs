@@ -0,0 +1,115 @@
+use governor::state::keyed::ShardedHashMapStateStore;
+use governor::{
+    clock::{Clock, FakeRelativeClock},
+    Quota, RateLimiter,
+};
+use nonzero_ext::nonzero;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+type ShardedLimiter<const SHARDS: usize> =
+    RateLimiter<u32, ShardedHashMapStateStore<u32, SHARDS>, FakeRelativeClock>;
+
+#[test]
+fn rejects_too_many() {
+    let clock = FakeRelativeClock::default();
+    let lb =
+        ShardedLimiter::<4>::sharded_hashmap_with_clock(Quota::per_second(nonzero!(2u32)), &clock);
+    let ms = Duration::from_millis(1);
+
+    for key in 0..16 {
+        assert_eq!(Ok(()), lb.check_key(&key), "Now: {:?}", clock.now());
+        assert_eq!(Ok(()), lb.check_key(&key), "Now: {:?}", clock.now());
+        assert_ne!(Ok(()), lb.check_key(&key), "Now: {:?}", clock.now());
+    }
+    clock.advance(ms * 1000);
+    for key in 0..16 {
+        assert_eq!(Ok(()), lb.check_key(&key), "Now: {:?}", clock.now());
+    }
+}
+
+#[test]
+fn routes_keys_stably() {
+    let first = ShardedHashMapStateStore::<u32, 8>::new();
+    let second = ShardedHashMapStateStore::<u32, 8>::default();
+    assert_eq!(first.shard_count(), 8);
+    for key in 0..100 {
+        let shard = first.shard_index(&key);
+        assert!(shard < 8);
+        assert_eq!(shard, second.shard_index(&key));
+    }
+    // The routing doesn't depend on the process (FNV-1a of the key's hashed bytes):
+    assert_eq!(
+        ShardedHashMapStateStore::<&str, 1000>::new().shard_index(&"governor"),
+        895
+    );
+}
+
+#[test]
+fn spreads_keys_over_shards() {
+    let clock = FakeRelativeClock::default();
+    let lim =
+        ShardedLimiter::<8>::sharded_hashmap_with_clock(Quota::per_second(nonzero!(1u32)), &clock);
+    for key in 0..800 {
+        lim.check_key(&key).unwrap();
+    }
+    let lens = lim.into_state_store().shard_lens();
+    assert_eq!(lens.iter().sum::<usize>(), 800);
+    for len in lens.iter() {
+        assert!(*len > 50, "uneven shards: {:?}", lens);
+    }
+}
+
+#[test]
+fn housekeeping() {
+    let clock = FakeRelativeClock::default();
+    let lim =
+        ShardedLimiter::<4>::sharded_hashmap_with_clock(Quota::per_second(nonzero!(1u32)), &clock);
+    for key in 0..10 {
+        lim.check_key(&key).unwrap();
+    }
+    assert_eq!(lim.len(), 10);
+    clock.advance(Duration::from_secs(1));
+    lim.check_key(&3).unwrap();
+    // All keys but the one used last are now indistinguishable from fresh ones:
+    clock.advance(Duration::from_secs(1));
+    lim.retain_recent();
+    assert_eq!(lim.len(), 1);
+    assert!(!lim.is_empty());
+    lim.shrink_to_fit();
+    let keys: Vec<u32> = lim
+        .remaining_capacities()
+        .into_iter()
+        .map(|(k, _)| k)
+        .collect();
+    assert_eq!(keys, vec![3]);
+}
+
+#[test]
+fn concurrent_keys() {
+    let clock = FakeRelativeClock::default();
+    let lim = Arc::new(ShardedLimiter::<16>::sharded_hashmap_with_clock(
+        Quota::per_second(nonzero!(5u32)),
+        &clock,
+    ));
+    let children: Vec<_> = (0..8)
+        .map(|i| {
+            let lim = Arc::clone(&lim);
+            thread::spawn(move || {
+                let mut allowed = 0;
+                for _ in 0..10 {
+                    for key in 0..20 {
+                        if lim.check_key(&(key + i % 2 * 20)).is_ok() {
+                            allowed += 1;
+                        }
+                    }
+                }
+                allowed
+            })
+        })
+        .collect();
+    let allowed: u32 = children.into_iter().map(|c| c.join().unwrap()).sum();
+    assert_eq!(allowed, 40 * 5);
+    assert_eq!(lim.len(), 40);
+}
@@ -0,0 +1,98 @@
+use governor::{
+    clock::FakeRelativeClock,
+    nanos::Nanos,
+    state::keyed::{HashMapStateStore, StatsStateStore},
+    Quota, RateLimiter,
+};
+use nonzero_ext::nonzero;
+use std::time::Duration;
+
+fn limiter(
+    clock: &FakeRelativeClock,
+) -> RateLimiter<u32, StatsStateStore<u32, HashMapStateStore<u32>>, FakeRelativeClock> {
+    RateLimiter::new(
+        Quota::per_second(nonzero!(2u32)),
+        StatsStateStore::default(),
+        clock,
+    )
+}
+
+#[test]
+fn counts_decisions_per_key() {
+    let clock = FakeRelativeClock::default();
+    let lim = limiter(&clock);
+    assert_eq!(lim.key_stats(&1), None);
+
+    for _ in 0..5 {
+        let _ = lim.check_key(&1);
+    }
+    clock.advance(Duration::from_millis(10));
+    lim.check_key(&2).unwrap();
+    let _ = lim.check_key_n(&2, nonzero!(2u32));
+    let _ = lim.check_key_n(&2, nonzero!(3u32));
+
+    let stats = lim.key_stats(&1).unwrap();
+    assert_eq!((stats.allowed(), stats.denied()), (2, 3));
+    assert_eq!(stats.last_seen(), Nanos::from(0));
+
+    let stats = lim.key_stats(&2).unwrap();
+    assert_eq!((stats.allowed(), stats.denied()), (1, 2));
+    assert_eq!(stats.last_seen(), Nanos::from(Duration::from_millis(10)));
+}
+
+#[test]
+fn most_throttled() {
+    let clock = FakeRelativeClock::default();
+    let lim = limiter(&clock);
+    for (key, attempts) in [(1, 3), (2, 10), (3, 6), (4, 1)] {
+        for _ in 0..attempts {
+            let _ = lim.check_key(&key);
+        }
+    }
+    // Key 5 gets throttled as often as key 1, but more recently:
+    clock.advance(Duration::from_secs(1));
+    for _ in 0..3 {
+        let _ = lim.check_key(&5);
+    }
+
+    let keys: Vec<(u32, u64)> = lim
+        .most_throttled(4)
+        .into_iter()
+        .map(|(key, stats)| (key, stats.denied()))
+        .collect();
+    assert_eq!(keys, vec![(2, 8), (3, 4), (5, 1), (1, 1)]);
+    // Keys that were never throttled aren't reported:
+    assert_eq!(lim.most_throttled(100).len(), 4);
+}
+
+#[test]
+fn stats_outlive_states() {
+    let clock = FakeRelativeClock::default();
+    let lim = limiter(&clock);
+    for _ in 0..3 {
+        let _ = lim.check_key(&1);
+    }
+    clock.advance(Duration::from_secs(10));
+    lim.check_key(&2).unwrap();
+
+    lim.retain_recent();
+    assert_eq!(lim.len(), 1);
+    assert_eq!(lim.key_stats(&1).unwrap().denied(), 1);
+
+    lim.forget_stats_older_than(Duration::from_secs(5));
+    assert_eq!(lim.key_stats(&1), None);
+    assert!(lim.key_stats(&2).is_some());
+
+    let store = lim.into_state_store();
+    store.clear_stats();
+    assert!(store.stats().is_empty());
+}
+
+#[test]
+fn sentinel_quotas_are_counted() {
+    let clock = FakeRelativeClock::default();
+    let lim = RateLimiter::new(Quota::none(), StatsStateStore::<u32>::default(), &clock);
+    let _ = lim.check_key(&1);
+    assert_eq!(lim.key_stats(&1).unwrap().denied(), 1);
+    assert!(lim.is_empty());
+}
@@ -0,0 +1,182 @@
+#![cfg(feature = "std")]
+
+use governor::{
+    clock::{Clock, FakeRelativeClock},
+    nanos::Nanos,
+    state::keyed::CasBackend,
+    Quota, RateLimiter,
+};
+use nonzero_ext::nonzero;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[derive(Debug, PartialEq)]
+struct Unavailable;
+
+#[derive(Default)]
+struct Backend {
+    map: Mutex<HashMap<u32, (Vec<u8>, u64)>>,
+    calls: AtomicUsize,
+    down: AtomicBool,
+}
+
+impl CasBackend for Backend {
+    type Key = u32;
+    type Version = u64;
+    type Error = Unavailable;
+
+    fn get(&self, key: &u32) -> Result<Option<(Vec<u8>, u64)>, Unavailable> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        if self.down.load(Ordering::SeqCst) {
+            return Err(Unavailable);
+        }
+        Ok(self.map.lock().unwrap().get(key).cloned())
+    }
+
+    fn compare_and_swap(
+        &self,
+        key: &u32,
+        expected: Option<&u64>,
+        blob: Vec<u8>,
+    ) -> Result<bool, Unavailable> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        let mut map = self.map.lock().unwrap();
+        let version = map.get(key).map(|(_, version)| *version);
+        if version.as_ref() != expected {
+            return Ok(false);
+        }
+        map.insert(*key, (blob, version.unwrap_or(0) + 1));
+        Ok(true)
+    }
+}
+
+#[test]
+fn decides_locally_until_synced() {
+    let backend = Arc::new(Backend::default());
+    let clock = FakeRelativeClock::default();
+    let quota = Quota::per_second(nonzero!(2u32));
+    let lim1 = RateLimiter::synced_with_clock(quota, backend.clone(), &clock, Nanos::from(0), None)
+        .unwrap();
+    let lim2 = RateLimiter::synced_with_clock(quota, backend.clone(), &clock, Nanos::from(0), None)
+        .unwrap();
+
+    // Without syncing, each rate limiter lets the full burst through:
+    for lim in &[&lim1, &lim2] {
+        assert!(lim.check_key(&1).is_ok());
+        assert!(lim.check_key(&1).is_ok());
+        assert!(lim.check_key(&1).is_err());
+    }
+    assert_eq!(backend.calls.load(Ordering::SeqCst), 0);
+
+    lim1.sync();
+    lim2.sync();
+    lim1.sync();
+    // Both rate limiters now know about the over-admitted cells, and throttle for longer:
+    for lim in &[&lim1, &lim2] {
+        let negative = lim.check_key(&1).unwrap_err();
+        assert_eq!(negative.wait_time_from(clock.now()), Duration::from_secs(2));
+    }
+
+    // Rate limiters that check the backend directly agree:
+    let cas = RateLimiter::cas_with_clock(quota, backend, &clock, Nanos::from(0));
+    clock.advance(Duration::from_millis(1500));
+    assert!(cas.check_key(&1).is_err());
+    clock.advance(Duration::from_millis(500));
+    assert!(cas.check_key(&1).is_ok());
+}
+
+#[test]
+fn keeps_cells_when_the_backend_fails() {
+    let backend = Arc::new(Backend::default());
+    let errors = Arc::new(AtomicUsize::new(0));
+    let clock = FakeRelativeClock::default();
+    let quota = Quota::per_second(nonzero!(1u32));
+    let counter = errors.clone();
+    let lim = RateLimiter::synced_with_clock_and_on_error(
+        quota,
+        backend.clone(),
+        &clock,
+        Nanos::from(0),
+        None,
+        move |error| {
+            assert_eq!(error, &Unavailable);
+            counter.fetch_add(1, Ordering::SeqCst);
+        },
+    )
+    .unwrap();
+
+    assert!(lim.check_key(&1).is_ok());
+    backend.down.store(true, Ordering::SeqCst);
+    lim.sync();
+    assert_eq!(errors.load(Ordering::SeqCst), 1);
+    assert!(backend.map.lock().unwrap().is_empty());
+
+    backend.down.store(false, Ordering::SeqCst);
+    lim.sync();
+    assert_eq!(errors.load(Ordering::SeqCst), 1);
+    let cas = RateLimiter::cas_with_clock(quota, backend, &clock, Nanos::from(0));
+    assert!(cas.check_key(&1).is_err());
+}
+
+#[test]
+fn keeps_unsynced_keys_when_shrinking() {
+    let backend = Arc::new(Backend::default());
+    let clock = FakeRelativeClock::default();
+    let lim = RateLimiter::synced_with_clock(
+        Quota::per_second(nonzero!(1u32)),
+        backend,
+        &clock,
+        Nanos::from(0),
+        None,
+    )
+    .unwrap();
+    lim.check_key(&1).unwrap();
+    clock.advance(Duration::from_secs(5));
+    lim.retain_recent();
+    assert_eq!(lim.len(), 1);
+
+    lim.sync();
+    lim.retain_recent();
+    assert!(lim.is_empty());
+}
+
+#[test]
+fn syncs_in_the_background() {
+    let backend = Arc::new(Backend::default());
+    let lim = RateLimiter::synced(
+        Quota::per_hour(nonzero!(1u32)),
+        backend.clone(),
+        Duration::from_millis(10),
+    )
+    .unwrap();
+    assert!(lim.check_key(&1).is_ok());
+    std::thread::sleep(Duration::from_millis(200));
+    assert!(backend.map.lock().unwrap().contains_key(&1));
+
+    let other = RateLimiter::cas(Quota::per_hour(nonzero!(1u32)), backend);
+    assert!(other.check_key(&1).is_err());
+}
+
+#[test]
+fn resets_shared_states() {
+    let backend = Arc::new(Backend::default());
+    let clock = FakeRelativeClock::default();
+    let quota = Quota::per_second(nonzero!(1u32));
+    let lim = RateLimiter::synced_with_clock(quota, backend.clone(), &clock, Nanos::from(0), None)
+        .unwrap();
+    let cas = RateLimiter::cas_with_clock(quota, backend, &clock, Nanos::from(0));
+
+    assert!(lim.check_key(&1).is_ok());
+    lim.sync();
+    assert!(cas.check_key(&1).is_err());
+
+    lim.reset_key(&1);
+    assert!(lim.check_key_reporting_new(&1).newly_created);
+    lim.sync();
+    // The other rate limiter only sees the cell let through after the reset:
+    assert!(cas.check_key_reporting_new(&1).decision.is_err());
+    clock.advance(Duration::from_secs(1));
+    assert!(cas.check_key(&1).is_ok());
+}
@@ -0,0 +1,56 @@
+use governor::{
+    clock::{Clock, FakeRelativeClock},
+    Quota, RateLimiter,
+};
+use nonzero_ext::nonzero;
+use std::rc::Rc;
+use std::time::Duration;
+
+#[test]
+fn direct_rejects_too_many() {
+    let clock = FakeRelativeClock::default();
+    let lb = RateLimiter::direct_local_with_clock(Quota::per_second(nonzero!(2u32)), &clock);
+    let ms = Duration::from_millis(1);
+
+    assert_eq!(Ok(()), lb.check(), "Now: {:?}", clock.now());
+    clock.advance(ms);
+    assert_eq!(Ok(()), lb.check(), "Now: {:?}", clock.now());
+    clock.advance(ms);
+    assert_ne!(Ok(()), lb.check(), "Now: {:?}", clock.now());
+
+    clock.advance(ms * 1000);
+    assert_eq!(Ok(()), lb.check(), "Now: {:?}", clock.now());
+    assert!(lb.check_n(nonzero!(3u32)).is_err());
+}
+
+#[test]
+fn keyed_with_rc_keys() {
+    let clock = FakeRelativeClock::default();
+    let lb = RateLimiter::local_hashmap_with_clock(Quota::per_second(nonzero!(1u32)), &clock);
+    let (a, b) = (Rc::new("a"), Rc::new("b"));
+
+    assert_eq!(Ok(()), lb.check_key(&a));
+    assert_eq!(Ok(()), lb.check_key(&b));
+    assert_ne!(Ok(()), lb.check_key(&a));
+    assert_eq!(lb.len(), 2);
+
+    clock.advance(Duration::from_secs(2));
+    lb.retain_recent();
+    assert!(lb.is_empty());
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn until_ready() {
+    let clock = FakeRelativeClock::default();
+    let lb = Rc::new(RateLimiter::local_hashmap_with_clock(
+        Quota::per_second(nonzero!(1u32)),
+        &clock,
+    ));
+    let key = Rc::new(1u32);
+    clock.block_on_auto_advance(async {
+        lb.until_key_ready(&key).await;
+        lb.until_key_ready(&key).await;
+    });
+    assert_eq!(Duration::from(clock.now()), Duration::from_secs(1));
+}
@@ -0,0 +1,170 @@
+#![cfg(all(feature = "mmap", unix))]
+
+use governor::{
+    clock::{Clock, FakeRelativeClock},
+    nanos::Nanos,
+    Quota, RateLimiter,
+};
+use nonzero_ext::nonzero;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+/// Returns a fresh path for a state file, removing any leftover from earlier test runs.
+fn state_file(name: &str) -> PathBuf {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let path = std::env::temp_dir().join(format!(
+        "governor-mmap-{}-{}-{}",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed),
+        name
+    ));
+    let _ = fs::remove_file(&path);
+    path
+}
+
+#[test]
+fn state_survives_reopening() {
+    let path = state_file("reopen");
+    let clock = FakeRelativeClock::default();
+    let quota = Quota::per_second(nonzero!(5u32));
+    {
+        let lim =
+            RateLimiter::direct_mmap_with_clock(quota, &path, &clock, Nanos::from(0)).unwrap();
+        for _ in 0..5 {
+            assert_eq!(Ok(()), lim.check());
+        }
+        assert!(lim.check().is_err());
+        lim.into_state_store().flush().unwrap();
+    }
+
+    let lim = RateLimiter::direct_mmap_with_clock(quota, &path, &clock, Nanos::from(0)).unwrap();
+    assert!(lim.check().is_err());
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn rebases_onto_the_clock() {
+    let path = state_file("rebase");
+    let clock = FakeRelativeClock::default();
+    let quota = Quota::per_second(nonzero!(5u32));
+    {
+        let lim =
+            RateLimiter::direct_mmap_with_clock(quota, &path, &clock, Nanos::from(0)).unwrap();
+        for _ in 0..5 {
+            assert_eq!(Ok(()), lim.check());
+        }
+        assert!(lim.check().is_err());
+    }
+
+    // While the process was "stopped", two cells were replenished; the new rate limiter starts
+    // at a different time, but still counts the cells used before:
+    clock.advance(Duration::from_millis(400));
+    let lim = RateLimiter::direct_mmap_with_clock(quota, &path, &clock, Nanos::from(0)).unwrap();
+    assert_eq!(Ok(()), lim.check());
+    assert_eq!(Ok(()), lim.check());
+    let negative = lim.check().unwrap_err();
+    assert_eq!(
+        negative.wait_time_from(clock.now()),
+        Duration::from_millis(200)
+    );
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn shares_states_between_open_files() {
+    let path = state_file("shared");
+    let clock = FakeRelativeClock::default();
+    let quota = Quota::per_second(nonzero!(2u32));
+    let first =
+        RateLimiter::keyed_mmap_with_clock(quota, &path, 2, &clock, Nanos::from(0)).unwrap();
+    let second =
+        RateLimiter::keyed_mmap_with_clock(quota, &path, 2, &clock, Nanos::from(0)).unwrap();
+
+    assert_eq!(Ok(()), first.check_key(&0));
+    assert_eq!(Ok(()), second.check_key(&0));
+    assert!(first.check_key(&0).is_err());
+    assert!(second.check_key(&0).is_err());
+    // The other key is unaffected:
+    assert_eq!(Ok(()), second.check_key(&1));
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn resets_to_fresh_states() {
+    let path = state_file("reset");
+    let clock = FakeRelativeClock::default();
+    let quota = Quota::per_second(nonzero!(2u32)).starting_empty();
+    let lim = RateLimiter::keyed_mmap_with_clock(quota, &path, 2, &clock, Nanos::from(0)).unwrap();
+
+    assert!(lim.check_key(&0).is_err());
+    clock.advance(Duration::from_millis(500));
+    assert_eq!(Ok(()), lim.check_key(&0));
+    lim.reset_key(&0);
+    assert!(lim
+        .key_state_snapshot(&0)
+        .theoretical_arrival_time()
+        .is_none());
+    // The quota starts empty, so a fresh state has no capacity:
+    assert!(lim.check_key(&0).is_err());
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn rejects_out_of_range_keys() {
+    let path = state_file("range");
+    let clock = FakeRelativeClock::default();
+    let lim = RateLimiter::keyed_mmap_with_clock(
+        Quota::per_second(nonzero!(2u32)),
+        &path,
+        2,
+        &clock,
+        Nanos::from(0),
+    )
+    .unwrap();
+    fs::remove_file(&path).unwrap();
+    let negative = lim.check_key(&2).unwrap_err();
+    assert!(negative.wait_time_from(clock.now()) > Duration::from_secs(100 * 365 * 24 * 60 * 60));
+    assert!(lim.check_key_n(&2, nonzero!(2u32)).is_err());
+    assert!(lim
+        .key_state_snapshot(&2)
+        .theoretical_arrival_time()
+        .is_none());
+    lim.reset_key(&2);
+    assert_eq!(Ok(()), lim.check_key(&1));
+}
+
+#[test]
+fn rejects_mismatched_files() {
+    let path = state_file("mismatch");
+    let clock = FakeRelativeClock::default();
+    let quota = Quota::per_second(nonzero!(2u32));
+    drop(RateLimiter::keyed_mmap_with_clock(quota, &path, 2, &clock, Nanos::from(0)).unwrap());
+
+    let err =
+        RateLimiter::keyed_mmap_with_clock(quota, &path, 3, &clock, Nanos::from(0)).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    let err =
+        RateLimiter::direct_mmap_with_clock(quota, &path, &clock, Nanos::from(0)).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+    // Files of the right size that weren't written by governor:
+    fs::write(&path, vec![0xffu8; 8 * 9]).unwrap();
+    let err =
+        RateLimiter::direct_mmap_with_clock(quota, &path, &clock, Nanos::from(0)).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn system_clock() {
+    let path = state_file("system");
+    let lim = RateLimiter::direct_mmap(Quota::per_hour(nonzero!(1u32)), &path).unwrap();
+    assert_eq!(Ok(()), lim.check());
+    drop(lim);
+    let lim = RateLimiter::direct_mmap(Quota::per_hour(nonzero!(1u32)), &path).unwrap();
+    assert!(lim.check().is_err());
+    fs::remove_file(&path).unwrap();
+}
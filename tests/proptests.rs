@@ -22,11 +22,12 @@ impl Arbitrary for Count {
 }
 
 fn test_config() -> ProptestConfig {
-    let mut cfg = ProptestConfig::default();
-    cfg.failure_persistence = Some(Box::new(FileFailurePersistence::WithSource("regressions")));
-    //cfg.timeout = 20;
-    cfg.verbose = 0; // 2 for extra verbosity;
-    cfg
+    ProptestConfig {
+        failure_persistence: Some(Box::new(FileFailurePersistence::WithSource("regressions"))),
+        //timeout: 20,
+        verbose: 0, // 2 for extra verbosity;
+        ..ProptestConfig::default()
+    }
 }
 
 #[test]
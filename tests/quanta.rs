@@ -0,0 +1,49 @@
+#![cfg(all(feature = "std", feature = "quanta"))]
+
+use governor::clock::{Clock, QuantaClock, Reference};
+use governor::{Quota, RateLimiter};
+use nonzero_ext::nonzero;
+use std::time::Duration;
+
+#[test]
+fn mocked_clock() {
+    let (clock, mock) = QuantaClock::mock();
+    let lim = RateLimiter::direct_with_clock(Quota::per_second(nonzero!(1u32)), &clock);
+    assert!(lim.check().is_ok());
+    assert!(lim.check().is_err());
+
+    mock.increment(Duration::from_secs(1));
+    assert!(lim.check().is_ok());
+}
+
+#[test]
+fn shared_quanta_clock() {
+    let (quanta, mock) = quanta::Clock::mock();
+    let c1 = QuantaClock::builder()
+        .with_quanta_clock(quanta.clone())
+        .build();
+    let c2 = QuantaClock::builder().with_quanta_clock(quanta).build();
+    let start = c1.now();
+    mock.increment(Duration::from_millis(5));
+    assert_eq!(
+        Duration::from(c2.now().duration_since(start)),
+        Duration::from_millis(5)
+    );
+}
+
+#[test]
+fn upkeep_clocks() {
+    let c1 = QuantaClock::builder()
+        .build_with_upkeep(Duration::from_millis(1))
+        .unwrap();
+    let c2 = QuantaClock::builder()
+        .recalibrate(true)
+        .build_with_upkeep(Duration::from_millis(10))
+        .unwrap();
+    let start = c2.now();
+    std::thread::sleep(Duration::from_millis(20));
+    // Both clocks read the same recent time, which the first clock's thread keeps updating:
+    assert!(c1.now() > start);
+    let lim = RateLimiter::direct_with_clock(Quota::per_second(nonzero!(1u32)), &c2);
+    assert!(lim.check().is_ok());
+}
@@ -0,0 +1,90 @@
+#![cfg(feature = "std")]
+
+use governor::{
+    clock::{Clock, FakeRelativeClock},
+    retry::MaxRetries,
+    Quota, RateLimiter,
+};
+use nonzero_ext::*;
+use std::cell::Cell;
+use std::time::Duration;
+
+#[test]
+fn attempts_wait_for_the_rate_limiter() {
+    let clock = FakeRelativeClock::default();
+    let lim = RateLimiter::direct_with_clock(Quota::per_second(nonzero!(1u32)), &clock);
+    let attempts = Cell::new(0);
+
+    let result = clock.block_on_auto_advance(lim.retrying(MaxRetries::new(5), || {
+        attempts.set(attempts.get() + 1);
+        let attempt = attempts.get();
+        async move {
+            if attempt < 3 {
+                Err(attempt)
+            } else {
+                Ok(attempt)
+            }
+        }
+    }));
+    assert_eq!(result, Ok(3));
+    assert_eq!(Duration::from(clock.now()), Duration::from_secs(2));
+}
+
+#[test]
+fn gives_up_with_the_last_error() {
+    let clock = FakeRelativeClock::default();
+    let lim = RateLimiter::direct_with_clock(Quota::per_second(nonzero!(10u32)), &clock);
+    let attempts = Cell::new(0);
+
+    let result: Result<(), u32> =
+        clock.block_on_auto_advance(lim.retrying(MaxRetries::new(2), || {
+            attempts.set(attempts.get() + 1);
+            let attempt = attempts.get();
+            async move { Err(attempt) }
+        }));
+    assert_eq!(result, Err(3));
+    assert_eq!(Duration::from(clock.now()), Duration::from_secs(0));
+}
+
+#[test]
+fn policy_delays_add_to_the_rate_limit() {
+    let clock = FakeRelativeClock::default();
+    let lim = RateLimiter::direct_with_clock(Quota::per_second(nonzero!(10u32)), &clock);
+    let policy = |error: &&str, attempt: u32| {
+        assert_eq!(*error, "busy");
+        if attempt < 3 {
+            Some(Duration::from_secs(attempt.into()))
+        } else {
+            None
+        }
+    };
+
+    let result: Result<(), &str> =
+        clock.block_on_auto_advance(lim.retrying(policy, || async { Err("busy") }));
+    assert_eq!(result, Err("busy"));
+    assert_eq!(Duration::from(clock.now()), Duration::from_secs(3));
+}
+
+#[test]
+fn keyed() {
+    let clock = FakeRelativeClock::default();
+    let lim = RateLimiter::hashmap_with_clock(Quota::per_second(nonzero!(1u32)), &clock);
+    let attempts = Cell::new(0);
+
+    let result = clock.block_on_auto_advance(lim.retrying_key(&"a", MaxRetries::new(1), || {
+        attempts.set(attempts.get() + 1);
+        let attempt = attempts.get();
+        async move {
+            if attempt < 2 {
+                Err(())
+            } else {
+                Ok(())
+            }
+        }
+    }));
+    assert_eq!(result, Ok(()));
+    assert_eq!(Duration::from(clock.now()), Duration::from_secs(1));
+    // The retries were counted against key "a" only:
+    assert!(lim.check_key(&"b").is_ok());
+    assert!(lim.check_key(&"a").is_err());
+}
@@ -0,0 +1,120 @@
+use governor::{
+    clock::{Clock, FakeRelativeClock},
+    nanos::Nanos,
+    Quota, ScheduledRateLimiter,
+};
+use nonzero_ext::nonzero;
+use std::time::Duration;
+
+fn minute_schedule(now: Nanos) -> Quota {
+    // Alternate between 2 and 4 cells per second, every minute:
+    let minute: u64 = Nanos::from(Duration::from_secs(60)).into();
+    let now: u64 = now.into();
+    if (now / minute).is_multiple_of(2) {
+        Quota::per_second(nonzero!(2u32))
+    } else {
+        Quota::per_second(nonzero!(4u32))
+    }
+}
+
+#[test]
+fn applies_scheduled_quota() {
+    let clock = FakeRelativeClock::default();
+    let lim = ScheduledRateLimiter::direct_with_clock(minute_schedule, &clock);
+
+    assert_eq!(Ok(()), lim.check());
+    assert_eq!(Ok(()), lim.check());
+    assert_ne!(Ok(()), lim.check(), "Now: {:?}", clock.now());
+
+    clock.advance(Duration::from_secs(60));
+    for _ in 0..4 {
+        assert_eq!(Ok(()), lim.check(), "Now: {:?}", clock.now());
+    }
+    assert_eq!(lim.current_quota(), Quota::per_second(nonzero!(4u32)));
+    // Exhaust the bucket; the next cell is replenished at the faster rate:
+    let negative = loop {
+        if let Err(negative) = lim.check() {
+            break negative;
+        }
+    };
+    assert_eq!(
+        negative.wait_time_from(clock.now()),
+        Duration::from_millis(250)
+    );
+
+    clock.advance(Duration::from_secs(60));
+    assert_eq!(Ok(()), lim.check());
+    assert_eq!(lim.current_quota(), Quota::per_second(nonzero!(2u32)));
+}
+
+#[test]
+fn reevaluates_lazily() {
+    let clock = FakeRelativeClock::default();
+    let lim = ScheduledRateLimiter::direct_with_clock(minute_schedule, &clock);
+    clock.advance(Duration::from_secs(60));
+    // No decision has been made yet, so the previous quota is still in effect:
+    assert_eq!(lim.current_quota(), Quota::per_second(nonzero!(2u32)));
+    lim.check().unwrap();
+    assert_eq!(lim.current_quota(), Quota::per_second(nonzero!(4u32)));
+}
+
+#[test]
+fn keyed_schedule() {
+    let clock = FakeRelativeClock::default();
+    let lim = ScheduledRateLimiter::keyed_with_clock(minute_schedule, &clock);
+    for key in &[1u32, 2u32] {
+        assert_eq!(Ok(()), lim.check_key(key));
+        assert_eq!(Ok(()), lim.check_key(key));
+        assert_ne!(Ok(()), lim.check_key(key));
+    }
+    clock.advance(Duration::from_secs(60));
+    assert_eq!(Ok(()), lim.check_key_n(&1u32, nonzero!(4u32)));
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn daily_schedule_windows() {
+    use governor::schedule::DailySchedule;
+
+    let hour = Duration::from_secs(60 * 60);
+    let day = Quota::per_second(nonzero!(10u32));
+    let night = Quota::per_second(nonzero!(100u32));
+    let lunch = Quota::per_second(nonzero!(1u32));
+    let schedule = DailySchedule::new(day)
+        .with_window(hour * 12, hour * 13, lunch)
+        .with_window(hour * 22, hour * 6, night);
+
+    assert_eq!(schedule.quota_at_time_of_day(hour * 9), day);
+    assert_eq!(schedule.quota_at_time_of_day(hour * 12), lunch);
+    assert_eq!(schedule.quota_at_time_of_day(hour * 13), day);
+    assert_eq!(schedule.quota_at_time_of_day(hour * 23), night);
+    assert_eq!(schedule.quota_at_time_of_day(hour * 2), night);
+    assert_eq!(schedule.quota_at_time_of_day(hour * 6), day);
+}
+
+#[test]
+fn kill_switch() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    static KILLED: AtomicBool = AtomicBool::new(false);
+
+    let clock = FakeRelativeClock::default();
+    let schedule = |_now: Nanos| {
+        if KILLED.load(Ordering::Relaxed) {
+            Quota::none()
+        } else {
+            Quota::per_second(nonzero!(2u32))
+        }
+    };
+    let lim = ScheduledRateLimiter::direct_with_clock(schedule, &clock);
+    assert_eq!(Ok(()), lim.check());
+
+    KILLED.store(true, Ordering::Relaxed);
+    assert_ne!(Ok(()), lim.check());
+    assert_eq!(lim.current_quota(), Quota::none());
+
+    // Flipping the switch back restores the state from before:
+    KILLED.store(false, Ordering::Relaxed);
+    assert_eq!(Ok(()), lim.check());
+    assert_ne!(Ok(()), lim.check());
+    assert_eq!(lim.current_quota(), Quota::per_second(nonzero!(2u32)));
+}
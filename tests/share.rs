@@ -0,0 +1,116 @@
+use governor::{
+    clock::FakeRelativeClock,
+    state::{InMemoryState, NotKeyed},
+    Quota, RateLimiter,
+};
+use nonzero_ext::nonzero;
+use std::num::NonZeroU32;
+use std::time::Duration;
+
+type Limiter = RateLimiter<NotKeyed, InMemoryState, FakeRelativeClock>;
+
+fn drain(lim: &Limiter) -> u32 {
+    let mut n = 0;
+    while lim.check().is_ok() {
+        n += 1;
+    }
+    n
+}
+
+/// Checks that the shares of `quota` together let through as many cells as `quota` itself, at
+/// every point in time.
+fn assert_shares_add_up(quota: Quota, n: NonZeroU32) {
+    let clock = FakeRelativeClock::default();
+    let upstream = RateLimiter::direct_with_clock(quota, &clock);
+    let shares: Vec<_> = quota
+        .divided_by(n)
+        .unwrap()
+        .iter()
+        .map(|share| RateLimiter::direct_share_with_clock(share, &clock))
+        .collect();
+    let step = quota.replenish_interval() / 3;
+    for i in 0..100 {
+        let expected = drain(&upstream);
+        let actual: u32 = shares.iter().map(drain).sum();
+        assert_eq!(
+            expected, actual,
+            "{:?} in {} shares, at step {}",
+            quota, n, i
+        );
+        clock.advance(step);
+    }
+}
+
+#[test]
+fn shares_add_up() {
+    for &burst in &[1u32, 2, 5, 7, 12] {
+        let quota = Quota::per_second(nonzero!(10u32)).allow_burst(NonZeroU32::new(burst).unwrap());
+        for &n in &[1u32, 2, 3, 5] {
+            let n = NonZeroU32::new(n).unwrap();
+            if burst >= n.get() {
+                assert_shares_add_up(quota, n);
+            }
+            assert_shares_add_up(quota.starting_empty(), n);
+        }
+    }
+}
+
+#[test]
+fn divides_burst_and_rate() {
+    let quota = Quota::per_second(nonzero!(10u32)).allow_burst(nonzero!(7u32));
+    let shares = quota.divided_by(nonzero!(3u32)).unwrap();
+    let bursts: Vec<u32> = shares
+        .iter()
+        .map(|s| s.quota().burst_size().get())
+        .collect();
+    assert_eq!(bursts, vec![3, 2, 2]);
+    let cells: Vec<u32> = shares.iter().map(|s| s.initial_cells()).collect();
+    assert_eq!(cells, vec![3, 2, 2]);
+    for share in &shares {
+        assert_eq!(
+            share.quota().replenish_interval(),
+            Duration::from_millis(300)
+        );
+    }
+    let phases: Vec<Duration> = shares.iter().map(|s| s.phase()).collect();
+    assert_eq!(
+        phases,
+        vec![
+            Duration::from_millis(300),
+            Duration::from_millis(100),
+            Duration::from_millis(200)
+        ]
+    );
+}
+
+#[test]
+fn small_bursts_give_every_share_a_cell() {
+    let quota = Quota::per_second(nonzero!(10u32)).allow_burst(nonzero!(2u32));
+    let shares = quota.divided_by(nonzero!(4u32)).unwrap();
+    let cells: Vec<u32> = shares.iter().map(|s| s.initial_cells()).collect();
+    assert_eq!(cells, vec![1, 1, 0, 0]);
+    for share in &shares {
+        assert_eq!(share.quota().burst_size().get(), 1);
+    }
+}
+
+#[test]
+fn sentinel_quotas() {
+    let shares = Quota::none().divided_by(nonzero!(2u32)).unwrap();
+    assert_eq!(shares.len(), 2);
+    assert!(shares.iter().all(|s| s.quota().is_none()));
+    let clock = FakeRelativeClock::default();
+    assert!(RateLimiter::direct_share_with_clock(&shares[0], &clock)
+        .check()
+        .is_err());
+
+    let shares = Quota::unlimited().divided_by(nonzero!(2u32)).unwrap();
+    assert!(shares.iter().all(|s| s.quota().is_unlimited()));
+    assert_eq!(shares[1].phase(), Duration::from_secs(0));
+}
+
+#[test]
+fn overflowing_shares() {
+    let quota = Quota::with_period(Duration::from_secs(u64::MAX / 4)).unwrap();
+    assert_eq!(quota.divided_by(nonzero!(3u32)), None);
+}
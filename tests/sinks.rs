@@ -8,6 +8,7 @@ use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 #[test]
+#[allow(clippy::unit_cmp)]
 fn sink() {
     let i = Instant::now();
     let lim = Arc::new(RateLimiter::direct(Quota::per_second(nonzero!(10u32))));
@@ -42,3 +43,184 @@ fn sink() {
     assert_eq!(result.len(), 12);
     assert!(result.into_iter().all(|elt| elt == ()));
 }
+
+#[cfg(feature = "jitter")]
+#[test]
+fn sink_for_key_with_jitter() {
+    use governor::{
+        clock::{Clock, FakeRelativeClock},
+        Jitter,
+    };
+
+    let clock = FakeRelativeClock::default();
+    let lim = RateLimiter::hashmap_with_clock(Quota::per_second(nonzero!(1u32)), &clock);
+    let jitter = Jitter::new(Duration::from_millis(100), Duration::from_millis(0));
+    let mut sink = Vec::new().ratelimit_sink_for_key_with_jitter(&lim, 1u32, jitter);
+
+    clock.block_on_auto_advance(async {
+        for i in 0..3 {
+            sink.send(i).await.unwrap();
+        }
+    });
+    assert_eq!(sink.get_ref(), &vec![0, 1, 2]);
+    // Each wait is 100ms longer than necessary, but the schedule doesn't drift:
+    assert_eq!(Duration::from(clock.now()), Duration::from_millis(2100));
+}
+
+#[test]
+fn sink_owned() {
+    use futures::Sink;
+    use governor::clock::{Clock, FakeRelativeClock};
+
+    fn make_sink(
+        clock: &FakeRelativeClock,
+    ) -> impl Sink<u32, Error = std::convert::Infallible> + Unpin + 'static {
+        let lim = RateLimiter::direct_with_clock(Quota::per_second(nonzero!(2u32)), clock);
+        Vec::new().ratelimit_sink_owned(lim.into_handle())
+    }
+
+    let clock = FakeRelativeClock::default();
+    let mut sink = make_sink(&clock);
+    clock.block_on_auto_advance(async {
+        for i in 0..4 {
+            sink.send(i).await.unwrap();
+        }
+    });
+    assert_eq!(Duration::from(clock.now()), Duration::from_secs(1));
+}
+
+#[test]
+fn sink_for_key_owned() {
+    use governor::clock::{Clock, FakeRelativeClock};
+
+    let clock = FakeRelativeClock::default();
+    let lim = Arc::new(RateLimiter::hashmap_with_clock(
+        Quota::per_second(nonzero!(2u32)),
+        &clock,
+    ));
+    let mut sink = Vec::new().ratelimit_sink_for_key_owned(lim.clone(), "a");
+    clock.block_on_auto_advance(async {
+        for i in 0..3 {
+            sink.send(i).await.unwrap();
+        }
+    });
+    assert_eq!(Duration::from(clock.now()), Duration::from_millis(500));
+    assert_eq!(sink.into_inner(), vec![0, 1, 2]);
+    assert_eq!(Arc::strong_count(&lim), 1);
+}
+
+#[cfg(feature = "jitter")]
+#[test]
+fn sink_for_key_owned_with_jitter() {
+    use governor::clock::{Clock, FakeRelativeClock};
+    use governor::Jitter;
+
+    fn assert_send<T: Send>(t: T) -> T {
+        t
+    }
+
+    let clock = FakeRelativeClock::default();
+    let lim = RateLimiter::hashmap_with_clock(Quota::per_second(nonzero!(1u32)), &clock);
+    let key: Arc<str> = Arc::from("a");
+    let jitter = Jitter::new(Duration::from_millis(100), Duration::from_millis(0));
+    let mut sink = assert_send(
+        Vec::new()
+            .ratelimit_sink_for_key_owned(lim.into_handle(), key)
+            .with_jitter(jitter),
+    );
+    let spawned = std::thread::spawn({
+        let clock = clock.clone();
+        move || {
+            clock.block_on_auto_advance(async {
+                for i in 0..3 {
+                    sink.send(i).await.unwrap();
+                }
+            });
+            sink.into_inner()
+        }
+    });
+    assert_eq!(spawned.join().unwrap(), vec![0, 1, 2]);
+    assert_eq!(Duration::from(clock.now()), Duration::from_millis(2100));
+}
+
+#[test]
+fn sink_weighted() {
+    use governor::clock::{Clock, FakeRelativeClock};
+    use std::num::NonZeroU32;
+
+    let clock = FakeRelativeClock::default();
+    let lim = RateLimiter::direct_with_clock(Quota::per_second(nonzero!(10u32)), &clock);
+    let mut sink = Vec::new().ratelimit_sink_weighted(&lim, |frame: &Vec<u8>| {
+        NonZeroU32::new(frame.len() as u32).unwrap()
+    });
+
+    let mut times = vec![];
+    clock.block_on_auto_advance(async {
+        for len in &[10, 5, 5, 20] {
+            sink.send(vec![0; *len]).await.unwrap();
+            times.push(Duration::from(clock.now()));
+        }
+    });
+    // The last frame exceeds the burst capacity, and is charged in two instalments, a second
+    // apart:
+    assert_eq!(
+        times,
+        vec![
+            Duration::from_secs(0),
+            Duration::from_millis(500),
+            Duration::from_millis(1000),
+            Duration::from_millis(3000),
+        ]
+    );
+    let sent: Vec<usize> = sink.into_inner().iter().map(Vec::len).collect();
+    assert_eq!(sent, vec![10, 5, 5, 20]);
+}
+
+#[test]
+fn sink_paced() {
+    use governor::clock::{Clock, FakeRelativeClock};
+    use std::num::NonZeroU32;
+
+    let clock = FakeRelativeClock::default();
+    let lim = RateLimiter::direct_with_clock(Quota::per_second(nonzero!(4u32)), &clock);
+    let mut sink = Vec::new().ratelimit_sink(&lim).with_pacing();
+    let mut times = vec![];
+    clock.block_on_auto_advance(async {
+        for i in 0..4u32 {
+            sink.send(i).await.unwrap();
+            times.push(Duration::from(clock.now()));
+        }
+    });
+    // The items are spread out, even though the rate limiter would let them all through at once:
+    assert_eq!(
+        times,
+        vec![
+            Duration::from_secs(0),
+            Duration::from_millis(250),
+            Duration::from_millis(500),
+            Duration::from_millis(750),
+        ]
+    );
+
+    // Weighted items are spaced out by the time it takes to replenish their cost:
+    let lim = RateLimiter::direct_with_clock(Quota::per_second(nonzero!(4u32)), &clock);
+    let start = Duration::from(clock.now());
+    let mut sink = Vec::new()
+        .ratelimit_sink_weighted(&lim, |n: &u32| NonZeroU32::new(*n).unwrap())
+        .with_pacing();
+    let mut times = vec![];
+    clock.block_on_auto_advance(async {
+        for n in &[2u32, 1, 1] {
+            sink.send(*n).await.unwrap();
+            times.push(Duration::from(clock.now()) - start);
+        }
+    });
+    assert_eq!(
+        times,
+        vec![
+            Duration::from_secs(0),
+            Duration::from_millis(500),
+            Duration::from_millis(750),
+        ]
+    );
+}
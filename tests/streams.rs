@@ -26,3 +26,183 @@ fn stream() {
     assert!(i.elapsed() > Duration::from_millis(200));
     assert!(i.elapsed() <= Duration::from_millis(300));
 }
+
+#[test]
+fn stream_for_key() {
+    use governor::clock::{Clock, FakeRelativeClock};
+
+    let clock = FakeRelativeClock::default();
+    let lim = RateLimiter::hashmap_with_clock(Quota::per_second(nonzero!(2u32)), &clock);
+    let mut stream = stream::repeat(()).ratelimit_stream_for_key(&lim, "a");
+
+    clock.block_on_auto_advance(async {
+        for _ in 0..4 {
+            stream.next().await;
+        }
+    });
+    // The burst of 2 goes through immediately, the following items are paced:
+    assert_eq!(Duration::from(clock.now()), Duration::from_secs(1));
+    assert_eq!(lim.len(), 1);
+    assert_eq!(lim.key_state_snapshot(&"a").remaining_burst_capacity(), 0);
+}
+
+#[test]
+fn stream_owned() {
+    use governor::clock::{Clock, FakeRelativeClock};
+
+    let clock = FakeRelativeClock::default();
+    let lim =
+        RateLimiter::direct_with_clock(Quota::per_second(nonzero!(2u32)), &clock).into_handle();
+    let stream = stream::iter(0..4).ratelimit_stream_owned(lim.clone());
+    let items = clock.block_on_auto_advance(stream.collect::<Vec<_>>());
+    assert_eq!(items, vec![0, 1, 2, 3]);
+    assert_eq!(Duration::from(clock.now()), Duration::from_secs(1));
+
+    let spawned = std::thread::spawn({
+        let clock = clock.clone();
+        move || {
+            let stream = stream::iter(0..2).ratelimit_stream_for_key_owned(
+                RateLimiter::hashmap_with_clock(Quota::per_second(nonzero!(1u32)), &clock),
+                "a",
+            );
+            clock.block_on_auto_advance(stream.collect::<Vec<_>>())
+        }
+    });
+    assert_eq!(spawned.join().unwrap(), vec![0, 1]);
+}
+
+#[cfg(feature = "jitter")]
+#[test]
+fn stream_for_key_owned_with_jitter() {
+    use governor::clock::{Clock, FakeRelativeClock};
+    use governor::Jitter;
+
+    fn assert_send<T: Send>(t: T) -> T {
+        t
+    }
+
+    let clock = FakeRelativeClock::default();
+    let lim = Arc::new(RateLimiter::hashmap_with_clock(
+        Quota::per_second(nonzero!(1u32)),
+        &clock,
+    ));
+    let key: Arc<str> = Arc::from("a");
+    let jitter = Jitter::new(Duration::from_millis(100), Duration::from_millis(0));
+    let stream = assert_send(
+        stream::iter(0..3)
+            .ratelimit_stream_for_key_owned(lim, key)
+            .with_jitter(jitter),
+    );
+    let spawned = std::thread::spawn({
+        let clock = clock.clone();
+        move || clock.block_on_auto_advance(stream.collect::<Vec<_>>())
+    });
+    assert_eq!(spawned.join().unwrap(), vec![0, 1, 2]);
+    // Each wait is 100ms longer than necessary, but the schedule doesn't drift:
+    assert_eq!(Duration::from(clock.now()), Duration::from_millis(2100));
+}
+
+#[test]
+fn stream_weighted() {
+    use governor::clock::{Clock, FakeRelativeClock};
+    use std::num::NonZeroU32;
+
+    let clock = FakeRelativeClock::default();
+    let lim = RateLimiter::direct_with_clock(Quota::per_second(nonzero!(4u32)), &clock);
+    let mut stream = stream::iter(vec![4u32, 2, 1, 1])
+        .ratelimit_stream_weighted(&lim, |n| NonZeroU32::new(*n).unwrap());
+
+    let times = clock.block_on_auto_advance(async {
+        let mut times = vec![];
+        while let Some(n) = stream.next().await {
+            times.push((n, Duration::from(clock.now())));
+        }
+        times
+    });
+    assert_eq!(
+        times,
+        vec![
+            (4, Duration::from_secs(0)),
+            (2, Duration::from_millis(500)),
+            (1, Duration::from_millis(750)),
+            (1, Duration::from_millis(1000)),
+        ]
+    );
+}
+
+#[test]
+fn stream_weighted_exceeding_burst() {
+    use governor::clock::{Clock, FakeRelativeClock};
+    use std::num::NonZeroU32;
+
+    let clock = FakeRelativeClock::default();
+    let lim = RateLimiter::direct_with_clock(Quota::per_second(nonzero!(4u32)), &clock);
+    let mut stream = stream::iter(vec![10u32, 1])
+        .ratelimit_stream_weighted(&lim, |n| NonZeroU32::new(*n).unwrap());
+
+    let times = clock.block_on_auto_advance(async {
+        let mut times = vec![];
+        while let Some(n) = stream.next().await {
+            times.push((n, Duration::from(clock.now())));
+        }
+        times
+    });
+    // The first item is charged in instalments of 4, 4 and 2 cells, and the second item waits for
+    // the cell after those:
+    assert_eq!(
+        times,
+        vec![
+            (10, Duration::from_millis(1500)),
+            (1, Duration::from_millis(1750)),
+        ]
+    );
+}
+
+#[test]
+fn stream_paced() {
+    use governor::clock::{Clock, FakeRelativeClock};
+
+    let clock = FakeRelativeClock::default();
+    let lim = RateLimiter::direct_with_clock(Quota::per_second(nonzero!(4u32)), &clock);
+    let mut stream = stream::iter(0..4u32).ratelimit_stream(&lim).with_pacing();
+
+    let times = clock.block_on_auto_advance(async {
+        let mut times = vec![];
+        while let Some(n) = stream.next().await {
+            times.push((n, Duration::from(clock.now())));
+        }
+        times
+    });
+    // The items are spread out, even though the rate limiter would let them all through at once:
+    assert_eq!(
+        times,
+        vec![
+            (0, Duration::from_secs(0)),
+            (1, Duration::from_millis(250)),
+            (2, Duration::from_millis(500)),
+            (3, Duration::from_millis(750)),
+        ]
+    );
+}
+
+#[test]
+fn filter() {
+    use governor::clock::{Clock, FakeRelativeClock};
+    use std::sync::Mutex;
+
+    let clock = FakeRelativeClock::default();
+    let lim = RateLimiter::direct_with_clock(Quota::per_second(nonzero!(2u32)), &clock);
+    let mut stream = stream::iter(0..5).ratelimit_filter(&lim);
+    assert_eq!(block_on(stream.by_ref().collect::<Vec<_>>()), vec![0, 1]);
+    assert_eq!(stream.dropped(), 3);
+
+    clock.advance(Duration::from_secs(1));
+    let overflowed = Arc::new(Mutex::new(vec![]));
+    let stream = stream::iter(5..10).ratelimit_filter_with_overflow(&lim, {
+        let overflowed = overflowed.clone();
+        move |item| overflowed.lock().unwrap().push(item)
+    });
+    assert_eq!(block_on(stream.collect::<Vec<_>>()), vec![5, 6]);
+    assert_eq!(*overflowed.lock().unwrap(), vec![7, 8, 9]);
+    assert_eq!(Duration::from(clock.now()), Duration::from_secs(1));
+}
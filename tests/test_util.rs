@@ -0,0 +1,217 @@
+#![cfg(feature = "std")]
+
+use governor::{
+    nanos::Nanos,
+    state::{keyed::HashMapStateStore, InMemoryState, NotKeyed, StateStore},
+    test_util::{Simulation, ViolationKind},
+    Quota,
+};
+use nonzero_ext::nonzero;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+#[test]
+fn in_memory_state_upholds_invariants() {
+    for seed in 0..4 {
+        let report = Simulation::direct()
+            .seed(seed)
+            .max_cells(nonzero!(3u32))
+            .run(Quota::per_second(nonzero!(5u32)), InMemoryState::default());
+        report.assert_ok();
+        assert_eq!(report.steps(), 1000);
+        assert!(report.allowed_cells() > 0);
+        assert!(report.denied_decisions() > 0);
+    }
+}
+
+#[test]
+fn keyed_stores_uphold_invariants() {
+    let simulation = Simulation::keyed(vec![1u32, 2, 3])
+        .threads(8)
+        .steps(2000)
+        .max_cells(nonzero!(2u32));
+    let quota = Quota::per_second(nonzero!(10u32)).allow_burst(nonzero!(4u32));
+    simulation
+        .run(quota, HashMapStateStore::default())
+        .assert_ok();
+    #[cfg(feature = "dashmap")]
+    simulation
+        .run(quota, governor::state::keyed::DashMapStateStore::default())
+        .assert_ok();
+}
+
+#[test]
+fn sentinel_quotas() {
+    let report = Simulation::direct().run(Quota::unlimited(), InMemoryState::default());
+    report.assert_ok();
+    assert_eq!(report.allowed_cells(), 1000);
+    let report = Simulation::direct().run(Quota::none(), InMemoryState::default());
+    report.assert_ok();
+    assert_eq!(report.denied_decisions(), 1000);
+}
+
+#[test]
+fn stores_uphold_invariants_concurrently() {
+    let quota = Quota::per_second(nonzero!(10u32)).allow_burst(nonzero!(4u32));
+    let report = Simulation::direct()
+        .concurrent()
+        .steps(500)
+        .max_cells(nonzero!(2u32))
+        .run(quota, InMemoryState::default());
+    report.assert_ok();
+    assert_eq!(report.steps(), 500);
+    assert!(report.allowed_cells() > 0);
+    assert!(report.denied_decisions() > 0);
+
+    let simulation = Simulation::keyed(vec![1u32, 2])
+        .threads(8)
+        .steps(500)
+        .concurrent();
+    simulation
+        .run(quota, HashMapStateStore::default())
+        .assert_ok();
+    #[cfg(feature = "dashmap")]
+    simulation
+        .run(quota, governor::state::keyed::DashMapStateStore::default())
+        .assert_ok();
+}
+
+#[test]
+fn simulations_are_deterministic() {
+    let simulation = Simulation::keyed(vec!["a", "b"])
+        .seed(42)
+        .max_advance(Duration::from_millis(150));
+    let quota = Quota::per_second(nonzero!(10u32));
+    let first = simulation.run(quota, HashMapStateStore::default());
+    let second = simulation.run(quota, HashMapStateStore::default());
+    assert_eq!(first, second);
+}
+
+/// A state store that forgets every other state, letting too many cells through.
+#[derive(Default)]
+struct ForgetfulState {
+    state: InMemoryState,
+    decisions: AtomicU64,
+}
+
+impl StateStore for ForgetfulState {
+    type Key = NotKeyed;
+
+    fn measure_and_replace<T, F, E>(&self, key: &Self::Key, f: F) -> Result<T, E>
+    where
+        F: Fn(Option<Nanos>) -> Result<(T, Nanos), E>,
+    {
+        if self.decisions.fetch_add(1, Ordering::Relaxed) & 1 == 0 {
+            f(None).map(|(result, _)| result)
+        } else {
+            self.state.measure_and_replace(key, f)
+        }
+    }
+
+    fn peek(&self, _key: &Self::Key) -> Option<Nanos> {
+        self.state.tat()
+    }
+}
+
+#[test]
+fn detects_over_admission() {
+    let report =
+        Simulation::direct().run(Quota::per_second(nonzero!(5u32)), ForgetfulState::default());
+    assert!(!report.is_ok());
+    assert!(report
+        .violations()
+        .iter()
+        .all(|v| matches!(v.kind(), ViolationKind::OverAdmitted(_))));
+}
+
+/// A state store that occasionally turns back its theoretical arrival time.
+#[derive(Default)]
+struct RewindingState {
+    state: InMemoryState,
+    decisions: AtomicU64,
+}
+
+impl StateStore for RewindingState {
+    type Key = NotKeyed;
+
+    fn measure_and_replace<T, F, E>(&self, key: &Self::Key, f: F) -> Result<T, E>
+    where
+        F: Fn(Option<Nanos>) -> Result<(T, Nanos), E>,
+    {
+        let rewind = self.decisions.fetch_add(1, Ordering::Relaxed) % 10 == 9;
+        self.state.measure_and_replace(key, |tat| {
+            let (result, new_tat) = f(tat)?;
+            if rewind {
+                let rewound = u64::from(new_tat).saturating_sub(1_000_000_000);
+                Ok((result, Nanos::from(rewound)))
+            } else {
+                Ok((result, new_tat))
+            }
+        })
+    }
+
+    fn peek(&self, _key: &Self::Key) -> Option<Nanos> {
+        self.state.tat()
+    }
+}
+
+#[test]
+#[should_panic(expected = "violated the GCRA's invariants")]
+fn detects_rewound_states() {
+    let report = Simulation::direct()
+        .threads(2)
+        .run(Quota::per_second(nonzero!(5u32)), RewindingState::default());
+    assert!(report
+        .violations()
+        .iter()
+        .any(|v| matches!(v.kind(), ViolationKind::TatDecreased { .. })));
+    report.assert_ok();
+}
+
+/// A state store that reads and writes its state in two separate steps, so that concurrent
+/// decisions can overwrite each other's updates.
+#[derive(Default)]
+struct RacyState {
+    tat: Mutex<Option<Nanos>>,
+}
+
+impl StateStore for RacyState {
+    type Key = NotKeyed;
+
+    fn measure_and_replace<T, F, E>(&self, _key: &Self::Key, f: F) -> Result<T, E>
+    where
+        F: Fn(Option<Nanos>) -> Result<(T, Nanos), E>,
+    {
+        let tat = *self.tat.lock().unwrap();
+        // Give other threads a chance to read the same state:
+        thread::sleep(Duration::from_millis(1));
+        let (result, new_tat) = f(tat)?;
+        *self.tat.lock().unwrap() = Some(new_tat);
+        Ok(result)
+    }
+
+    fn peek(&self, _key: &Self::Key) -> Option<Nanos> {
+        *self.tat.lock().unwrap()
+    }
+}
+
+#[test]
+fn detects_races_concurrently() {
+    let quota = Quota::per_second(nonzero!(5u32));
+    // Threads that take turns never race:
+    Simulation::direct()
+        .steps(200)
+        .run(quota, RacyState::default())
+        .assert_ok();
+
+    let report = Simulation::direct()
+        .steps(200)
+        .concurrent()
+        .run(quota, RacyState::default());
+    assert!(report
+        .violations()
+        .iter()
+        .any(|v| matches!(v.kind(), ViolationKind::OverAdmitted(_))));
+}
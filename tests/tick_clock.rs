@@ -0,0 +1,53 @@
+use governor::clock::{Clock, TickClock};
+use governor::{Quota, RateLimiter};
+use nonzero_ext::nonzero;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+#[test]
+fn converts_ticks() {
+    type Clock1k = TickClock<fn() -> u64, 1_000>;
+    assert_eq!(
+        Duration::from(Clock1k::ticks_to_nanos(1_500)),
+        Duration::from_millis(1_500)
+    );
+
+    type ClockGhz = TickClock<fn() -> u64, 4_000_000_000>;
+    assert_eq!(
+        Duration::from(ClockGhz::ticks_to_nanos(u64::MAX)),
+        Duration::from_nanos(4_611_686_018_427_387_903)
+    );
+}
+
+#[test]
+fn drives_rate_limiter() {
+    let ticks = Arc::new(AtomicU64::new(0));
+    let source = {
+        let ticks = ticks.clone();
+        move || ticks.load(Ordering::Relaxed)
+    };
+    let clock = TickClock::<_, 100>::new(source);
+    assert_eq!(clock.frequency(), 100);
+
+    let lim = RateLimiter::direct_with_clock(Quota::per_second(nonzero!(10u32)), &clock);
+    for _ in 0..10 {
+        assert!(lim.check().is_ok());
+    }
+    let negative = lim.check().unwrap_err();
+    assert_eq!(
+        negative.wait_time_from(clock.now()),
+        Duration::from_millis(100)
+    );
+
+    // 10 ticks are 100ms:
+    ticks.fetch_add(10, Ordering::Relaxed);
+    assert!(lim.check().is_ok());
+    assert!(lim.check().is_err());
+}
+
+#[test]
+#[should_panic]
+fn zero_frequency() {
+    TickClock::<_, 0>::new(|| 0);
+}
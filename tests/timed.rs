@@ -0,0 +1,103 @@
+use governor::{
+    clock::{Clock, FakeRelativeClock},
+    nanos::Nanos,
+    CheckAtError, NegativeMultiDecision, Quota, RateLimiter,
+};
+use nonzero_ext::nonzero;
+use std::time::Duration;
+
+#[test]
+fn direct_decisions_at_given_times() {
+    let clock = FakeRelativeClock::default();
+    let lim = RateLimiter::direct_with_clock(Quota::per_second(nonzero!(2u32)), &clock);
+    let start = clock.now();
+    let ms = Duration::from_millis(1);
+
+    assert_eq!(Ok(()), lim.check_n_at(nonzero!(2u32), start + ms * 100));
+    match lim.check_at(start + ms * 100) {
+        Err(CheckAtError::NonConforming(negative)) => {
+            assert_eq!(negative.earliest_possible(), start + ms * 600);
+            assert_eq!(negative.time_of_decision(), start + ms * 100);
+        }
+        other => panic!("unexpected outcome {:?}", other),
+    }
+    assert_eq!(Ok(()), lim.check_at(start + ms * 600));
+    assert!(matches!(
+        lim.check_n_at(nonzero!(3u32), start + ms * 700),
+        Err(CheckAtError::NonConforming(
+            NegativeMultiDecision::InsufficientCapacity(insufficient)
+        )) if insufficient.max_burst() == 2
+    ));
+    // The clock doesn't matter:
+    assert_eq!(clock.now(), start);
+}
+
+#[test]
+fn rejects_times_out_of_order() {
+    let clock = FakeRelativeClock::default();
+    clock.advance(Duration::from_secs(1));
+    let lim = RateLimiter::direct_with_clock(Quota::per_second(nonzero!(20u32)), &clock);
+    let start = clock.now();
+    let ms = Duration::from_millis(1);
+
+    assert_eq!(lim.check_at(start + ms * 10), Ok(()));
+    assert_eq!(lim.check_at(start + ms * 10), Ok(()));
+    assert_eq!(
+        lim.check_at(start + ms * 9),
+        Err(CheckAtError::OutOfOrder(start + ms * 10))
+    );
+    assert_eq!(
+        lim.check_n_at(nonzero!(2u32), start + ms * 9),
+        Err(CheckAtError::OutOfOrder(start + ms * 10))
+    );
+    // Times before the rate limiter's construction are out of order, too:
+    let err = lim.until_ready_at(Nanos::from(ms * 999)).unwrap_err();
+    assert_eq!(err, CheckAtError::OutOfOrder(start + ms * 10));
+    assert_eq!(
+        err.to_string(),
+        "decision time lies before that of an earlier decision, at Nanos(1.01s)"
+    );
+}
+
+#[test]
+fn until_ready_at() {
+    let clock = FakeRelativeClock::default();
+    let lim = RateLimiter::direct_with_clock(Quota::per_minute(nonzero!(2u32)), &clock);
+    let start = clock.now();
+    let s = Duration::from_secs(1);
+    assert_eq!(lim.until_ready_at(start), Ok(start));
+    assert_eq!(lim.until_ready_at(start + s), Ok(start + s));
+    assert_eq!(lim.until_ready_at(start + s * 2), Ok(start + s * 30));
+    assert_eq!(lim.until_ready_at(start + s * 40), Ok(start + s * 60));
+    assert_eq!(lim.until_ready_at(start + s * 200), Ok(start + s * 200));
+
+    let none = RateLimiter::direct_with_clock(Quota::none(), &clock);
+    assert!(matches!(
+        none.until_ready_at(start),
+        Err(CheckAtError::NonConforming(_))
+    ));
+}
+
+#[test]
+fn keyed_decisions_at_given_times() {
+    let clock = FakeRelativeClock::default();
+    let lim = RateLimiter::hashmap_with_clock(Quota::per_second(nonzero!(1u32)), &clock);
+    let start = clock.now();
+    let ms = Duration::from_millis(1);
+
+    assert_eq!(Ok(()), lim.check_key_at(&"a", start + ms * 10));
+    assert_eq!(
+        Ok(()),
+        lim.check_key_n_at(&"b", nonzero!(1u32), start + ms * 20)
+    );
+    assert!(lim.check_key_at(&"a", start + ms * 30).is_err());
+    assert_eq!(
+        lim.until_key_ready_at(&"b", start + ms * 30),
+        Ok(start + ms * 1020)
+    );
+    // Times are ordered across all keys:
+    assert_eq!(
+        lim.check_key_at(&"c", start + ms * 20),
+        Err(CheckAtError::OutOfOrder(start + ms * 30))
+    );
+}
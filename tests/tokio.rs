@@ -0,0 +1,68 @@
+#![cfg(feature = "tokio")]
+
+use governor::{clock::TokioClock, Quota, RateLimiter};
+use nonzero_ext::nonzero;
+use std::time::{Duration, Instant};
+
+#[tokio::test]
+async fn until_ready_uses_tokio_timer() {
+    let lim = RateLimiter::direct(Quota::with_period(Duration::from_millis(100)).unwrap());
+    let start = Instant::now();
+    for _ in 0..3 {
+        lim.until_ready().await;
+    }
+    // The first cell is let through immediately, the following ones 100ms apart:
+    assert!(start.elapsed() >= Duration::from_millis(190));
+}
+
+#[tokio::test]
+async fn until_key_ready_uses_tokio_timer() {
+    let lim = RateLimiter::keyed(Quota::with_period(Duration::from_millis(50)).unwrap());
+    let start = Instant::now();
+    for _ in 0..3 {
+        lim.until_key_ready(&1u32).await;
+    }
+    assert!(start.elapsed() >= Duration::from_millis(90));
+}
+
+#[tokio::test(start_paused = true)]
+async fn paused_time_drives_clock() {
+    let clock = TokioClock;
+    let lim = RateLimiter::direct_with_clock(Quota::per_minute(nonzero!(2u32)), &clock);
+    let start = tokio::time::Instant::now();
+    for _ in 0..4 {
+        lim.until_ready().await;
+    }
+    assert_eq!(start.elapsed(), Duration::from_secs(60));
+}
+
+#[tokio::test(start_paused = true)]
+async fn paused_time_keyed() {
+    let clock = TokioClock;
+    let lim = RateLimiter::hashmap_with_clock(Quota::per_hour(nonzero!(1u32)), &clock);
+    let start = tokio::time::Instant::now();
+    lim.until_key_ready(&"a").await;
+    lim.until_key_ready(&"b").await;
+    assert_eq!(start.elapsed(), Duration::from_secs(0));
+    lim.until_key_ready(&"a").await;
+    assert_eq!(start.elapsed(), Duration::from_secs(60 * 60));
+}
+
+#[tokio::test(start_paused = true)]
+async fn local_limiter_on_local_set() {
+    use std::rc::Rc;
+
+    let clock = TokioClock;
+    let lim = Rc::new(RateLimiter::direct_local_with_clock(
+        Quota::per_second(nonzero!(1u32)),
+        &clock,
+    ));
+    let start = tokio::time::Instant::now();
+    let local = tokio::task::LocalSet::new();
+    for _ in 0..3 {
+        let lim = lim.clone();
+        local.spawn_local(async move { lim.until_ready().await });
+    }
+    local.await;
+    assert_eq!(start.elapsed(), Duration::from_secs(2));
+}
@@ -0,0 +1,83 @@
+#![cfg(feature = "tonic")]
+
+use governor::{clock::FakeRelativeClock, tonic::RateLimitInterceptor, Quota, RateLimiter};
+use nonzero_ext::nonzero;
+use std::time::Duration;
+use tonic::{service::Interceptor, Code, Request};
+use tonic_types::StatusExt;
+
+fn request(token: Option<&'static str>) -> Request<()> {
+    let mut request = Request::new(());
+    if let Some(token) = token {
+        request
+            .metadata_mut()
+            .insert("authorization", token.parse().unwrap());
+    }
+    request
+}
+
+#[test]
+fn limits_by_metadata() {
+    let clock = FakeRelativeClock::default();
+    let lim = RateLimiter::dashmap_with_clock(Quota::per_second(nonzero!(1u32)), &clock);
+    let mut interceptor = RateLimitInterceptor::new(lim, "authorization");
+
+    assert!(interceptor.call(request(Some("a"))).is_ok());
+    assert!(interceptor.call(request(Some("b"))).is_ok());
+    let status = interceptor.call(request(Some("a"))).unwrap_err();
+    assert_eq!(status.code(), Code::ResourceExhausted);
+    assert_eq!(status.metadata().get("retry-after").unwrap(), "1");
+    assert_eq!(status.metadata().get("x-ratelimit-limit").unwrap(), "1");
+    let retry_info = status.get_details_retry_info().unwrap();
+    assert_eq!(retry_info.retry_delay, Some(Duration::from_secs(1)));
+
+    clock.advance(Duration::from_secs(1));
+    assert!(interceptor.call(request(Some("a"))).is_ok());
+}
+
+#[test]
+fn requests_without_metadata_share_a_key() {
+    let clock = FakeRelativeClock::default();
+    let lim = RateLimiter::dashmap_with_clock(Quota::per_second(nonzero!(1u32)), &clock);
+    let mut interceptor = RateLimitInterceptor::new(lim, "authorization");
+
+    assert!(interceptor.call(request(None)).is_ok());
+    assert!(interceptor.call(request(None)).is_err());
+    assert!(interceptor.call(request(Some("a"))).is_ok());
+}
+
+#[test]
+fn limits_requests_without_metadata_by_fallback_key() {
+    let clock = FakeRelativeClock::default();
+    let lim = RateLimiter::dashmap_with_clock(Quota::per_second(nonzero!(1u32)), &clock);
+    let mut interceptor = RateLimitInterceptor::new(lim, "authorization")
+        .with_fallback_key(Some("anonymous".to_string()));
+
+    assert!(interceptor.call(request(None)).is_ok());
+    assert!(interceptor.call(request(Some("a"))).is_ok());
+    assert!(interceptor.call(request(Some("anonymous"))).is_err());
+}
+
+#[test]
+fn rejects_requests_without_metadata() {
+    let clock = FakeRelativeClock::default();
+    let lim = RateLimiter::dashmap_with_clock(Quota::per_second(nonzero!(1u32)), &clock);
+    let mut interceptor = RateLimitInterceptor::new(lim, "authorization").with_fallback_key(None);
+
+    let status = interceptor.call(request(None)).unwrap_err();
+    assert_eq!(status.code(), Code::Unauthenticated);
+    assert!(interceptor.call(request(Some(""))).is_ok());
+    assert_eq!(interceptor.limiter().len(), 1);
+}
+
+#[test]
+fn clones_share_the_limiter() {
+    let clock = FakeRelativeClock::default();
+    let lim = RateLimiter::dashmap_with_clock(Quota::per_second(nonzero!(1u32)), &clock);
+    let mut interceptor = RateLimitInterceptor::new(lim, "authorization");
+    let mut other = interceptor.clone();
+    assert!(interceptor.limiter().ptr_eq(other.limiter()));
+
+    assert!(interceptor.call(request(Some("a"))).is_ok());
+    assert!(other.call(request(Some("a"))).is_err());
+}
@@ -0,0 +1,103 @@
+use governor::{
+    clock::FakeRelativeClock,
+    state::{UsageBucket, UsageTracker},
+    Quota, RateLimiter,
+};
+use nonzero_ext::nonzero;
+use std::time::Duration;
+
+fn counts(buckets: &[UsageBucket]) -> Vec<(u64, u64, u64)> {
+    buckets
+        .iter()
+        .map(|b| (b.start().as_secs(), b.allowed_cells(), b.denied_cells()))
+        .collect()
+}
+
+#[test]
+fn empty_before_decisions() {
+    let usage = UsageTracker::new(Duration::from_secs(1), nonzero!(3usize));
+    let report = usage.report();
+    assert!(report.buckets().is_empty());
+    assert_eq!(report.quota(), None);
+    assert_eq!(report.capacity_per_bucket(), 0.0);
+    assert_eq!(report.peak_utilization(), 0.0);
+    assert_eq!(report.mean_utilization(), 0.0);
+}
+
+#[test]
+fn counts_cells_per_bucket() {
+    let clock = FakeRelativeClock::default();
+    let usage = UsageTracker::new(Duration::from_secs(1), nonzero!(3usize));
+    let lim = RateLimiter::direct_with_clock(Quota::per_second(nonzero!(4u32)), &clock)
+        .track_usage(&usage);
+
+    lim.check_n(nonzero!(3u32)).unwrap();
+    lim.check_n(nonzero!(2u32)).unwrap_err();
+    clock.advance(Duration::from_secs(2));
+    lim.check().unwrap();
+    let report = usage.report();
+    assert_eq!(
+        counts(report.buckets()),
+        vec![(0, 3, 2), (1, 0, 0), (2, 1, 0)]
+    );
+    assert_eq!(report.allowed_cells(), 4);
+    assert_eq!(report.denied_cells(), 2);
+    assert_eq!(report.bucket_width(), Duration::from_secs(1));
+    assert_eq!(report.quota(), Some(Quota::per_second(nonzero!(4u32))));
+    assert_eq!(report.capacity_per_bucket(), 4.0);
+    assert_eq!(report.utilization(&report.buckets()[0]), 0.75);
+    assert_eq!(report.peak_utilization(), 0.75);
+    assert_eq!(report.mean_utilization(), (0.75 + 0.25) / 3.0);
+
+    // Older buckets get dropped:
+    clock.advance(Duration::from_secs(1));
+    lim.check().unwrap();
+    assert_eq!(
+        counts(usage.report().buckets()),
+        vec![(1, 0, 0), (2, 1, 0), (3, 1, 0)]
+    );
+    clock.advance(Duration::from_secs(10));
+    lim.check().unwrap();
+    assert_eq!(
+        counts(usage.report().buckets()),
+        vec![(11, 0, 0), (12, 0, 0), (13, 1, 0)]
+    );
+
+    usage.clear();
+    assert!(usage.report().buckets().is_empty());
+}
+
+#[test]
+fn keyed_limiters_count_all_keys() {
+    let clock = FakeRelativeClock::default();
+    let usage = UsageTracker::new(Duration::from_secs(60), nonzero!(60usize));
+    let lim = RateLimiter::hashmap_with_clock(Quota::per_second(nonzero!(1u32)), &clock)
+        .track_usage(&usage);
+    for key in 0..10u32 {
+        lim.check_key(&key).unwrap();
+        lim.check_key(&key).unwrap_err();
+    }
+    let report = usage.report();
+    assert_eq!(counts(report.buckets()), vec![(0, 10, 10)]);
+    // A single key can use 60 cells a minute:
+    assert_eq!(report.capacity_per_bucket(), 60.0);
+}
+
+#[test]
+fn clones_share_counts() {
+    let clock = FakeRelativeClock::default();
+    let usage = UsageTracker::new(Duration::from_secs(1), nonzero!(2usize));
+    let first = RateLimiter::direct_with_clock(Quota::per_second(nonzero!(5u32)), &clock)
+        .track_usage(&usage);
+    let second = RateLimiter::direct_with_clock(Quota::per_second(nonzero!(5u32)), &clock)
+        .track_usage(&usage.clone());
+    first.check().unwrap();
+    second.check().unwrap();
+    assert_eq!(usage.report().allowed_cells(), 2);
+}
+
+#[test]
+#[should_panic(expected = "must not be empty")]
+fn rejects_empty_buckets() {
+    UsageTracker::new(Duration::from_secs(0), nonzero!(2usize));
+}